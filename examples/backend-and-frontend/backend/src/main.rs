@@ -3,14 +3,21 @@
 use rocket::response::NamedFile;
 use std::path::{Path, PathBuf};
 
+/// The build directory of the frontend: wasm-run gives it to the backend when it runs it.
+fn build_path() -> PathBuf {
+    std::env::var_os("WASM_RUN_BUILD_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new("build").to_owned())
+}
+
 #[rocket::get("/")]
 pub fn index() -> Option<NamedFile> {
-    NamedFile::open(Path::new("build").join("index.html")).ok()
+    NamedFile::open(build_path().join("index.html")).ok()
 }
 
 #[rocket::get("/<file..>")]
 pub fn files(file: PathBuf) -> Option<NamedFile> {
-    NamedFile::open(Path::new("build").join(file)).ok()
+    NamedFile::open(build_path().join(file)).ok()
 }
 
 fn main() {
@@ -5,26 +5,35 @@ use std::process::Command;
 use structopt::StructOpt;
 use wasm_run::prelude::*;
 
-#[wasm_run::main("frontend", "backend", other_cli_commands)]
+#[wasm_run::main("frontend", "backend", other_cli_commands, command_context)]
 #[derive(StructOpt, Debug)]
 enum Cli {
     BuildContainerImage,
 }
 
-fn other_cli_commands(cli: Cli, metadata: &Metadata, _package: &Package) -> anyhow::Result<()> {
+fn other_cli_commands(
+    cli: Cli,
+    context: &CommandContext<DefaultBuildArgs, DefaultServeArgs>,
+) -> anyhow::Result<()> {
     match cli {
         Cli::BuildContainerImage => {
             println!("Building frontend...");
-            Cli::build()?;
+            context.build_with(BuildProfile::Release, |args| {
+                args.no_loader_checks = true;
+            })?;
 
             println!("Building backend...");
-            metadata
+            let backend = context
+                .backend_package()
+                .expect("the backend package is set in the macro");
+            context
+                .metadata()
                 .cargo(|command| {
                     command.args(&[
                         "build",
                         "--release",
                         "-p",
-                        "backend",
+                        &backend.name,
                         "--target",
                         "x86_64-unknown-linux-musl",
                     ]);
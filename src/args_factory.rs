@@ -0,0 +1,105 @@
+use crate::{BuildArgs, BuildOutput, ServeArgs};
+use anyhow::Result;
+use std::ffi::OsString;
+use std::fmt;
+use std::iter;
+use std::marker::PhantomData;
+use structopt::StructOpt;
+
+/// Constructor of the arguments of the `build` and `serve` commands configured in the macro
+/// [`main`](crate::main) (`build_args` and `serve_args`).
+///
+/// It is given to the function `other_cli_commands` when the macro has the argument
+/// `args_factory`, so the custom commands can run a build with the configured types without
+/// naming them:
+///
+/// ```ignore
+/// #[wasm_run::main(other_cli_commands, args_factory)]
+/// #[derive(StructOpt, Debug)]
+/// enum Cli {
+///     Deploy,
+/// }
+///
+/// fn other_cli_commands<B: BuildArgs + StructOpt, S: ServeArgs + StructOpt>(
+///     cli: Cli,
+///     _metadata: &Metadata,
+///     _package: &Package,
+///     args: &ArgsFactory<B, S>,
+/// ) -> anyhow::Result<()> {
+///     match cli {
+///         Cli::Deploy => {
+///             let build_path = args.build(&["--release", "--fingerprint-assets"])?;
+///             upload(&build_path)
+///         }
+///     }
+/// }
+/// ```
+pub struct ArgsFactory<B, S> {
+    bin_name: String,
+    _marker: PhantomData<fn() -> (B, S)>,
+}
+
+impl<B, S> fmt::Debug for ArgsFactory<B, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ArgsFactory")
+            .field("bin_name", &self.bin_name)
+            .finish()
+    }
+}
+
+impl<B: BuildArgs + StructOpt, S: ServeArgs + StructOpt> ArgsFactory<B, S> {
+    /// Parse the arguments with `bin_name` as the name of the binary in the help and the errors.
+    pub fn new(bin_name: impl Into<String>) -> Self {
+        Self {
+            bin_name: bin_name.into(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Arguments of the `build` command parsed from `iter` (without the name of the binary).
+    pub fn build_args<I>(&self, iter: I) -> Result<B>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        Ok(B::from_iter_safe(self.args(iter))?)
+    }
+
+    /// Arguments of the `serve` command parsed from `iter` (without the name of the binary).
+    pub fn serve_args<I>(&self, iter: I) -> Result<S>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        Ok(S::from_iter_safe(self.args(iter))?)
+    }
+
+    /// Run the `build` command with the arguments `iter` and return the files it emitted.
+    pub fn build<I>(&self, iter: I) -> Result<BuildOutput>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        self.build_args(iter)?.run()
+    }
+
+    /// Run the `serve` command with the arguments `iter`.
+    ///
+    /// This function only returns on error.
+    pub fn serve<I>(&self, iter: I) -> Result<()>
+    where
+        S: Sync,
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        self.serve_args(iter)?.run()
+    }
+
+    fn args<I>(&self, iter: I) -> impl Iterator<Item = OsString>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString>,
+    {
+        iter::once(OsString::from(&self.bin_name)).chain(iter.into_iter().map(Into::into))
+    }
+}
@@ -0,0 +1,272 @@
+use crate::BuildArgs;
+use anyhow::{bail, Context, Result};
+use camino::Utf8PathBuf;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// A directory of static files copied to the build directory.
+///
+/// ```ignore
+/// fn asset_rules(&self) -> Vec<AssetRule> {
+///     vec![
+///         AssetRule::new("static", ""),
+///         AssetRule::new("assets", "assets")
+///             .exclude("**/*.psd")
+///             .recompress_images(true),
+///     ]
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetRule {
+    /// Source directory (relative to the directory of the frontend package).
+    pub source: Utf8PathBuf,
+    /// Destination directory (relative to the build directory).
+    pub destination: Utf8PathBuf,
+    /// Only the files matching one of these glob patterns (relative to the source directory) are
+    /// copied. Everything is copied if empty.
+    pub include: Vec<String>,
+    /// The files matching one of these glob patterns (relative to the source directory) are not
+    /// copied.
+    pub exclude: Vec<String>,
+    /// Recompress the PNG and JPEG images with `oxipng` and `jpegoptim` (they must be installed).
+    pub recompress_images: bool,
+    /// What to do with the symbolic links.
+    pub symlinks: SymlinkMode,
+}
+
+/// What to do with the symbolic links found in an asset directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkMode {
+    /// Copy the files and directories the symbolic links point to.
+    #[default]
+    Follow,
+    /// Reproduce the symbolic links as they are in the build directory: relative links must still
+    /// be valid from there.
+    Preserve,
+}
+
+impl AssetRule {
+    /// Copy the directory `source` of the frontend package to the directory `destination` of the
+    /// build directory.
+    pub fn new(source: impl Into<Utf8PathBuf>, destination: impl Into<Utf8PathBuf>) -> Self {
+        Self {
+            source: source.into(),
+            destination: destination.into(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            recompress_images: false,
+            symlinks: SymlinkMode::Follow,
+        }
+    }
+
+    /// Only copy the files matching this glob pattern.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Do not copy the files matching this glob pattern.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Recompress the PNG and JPEG images.
+    pub fn recompress_images(mut self, recompress_images: bool) -> Self {
+        self.recompress_images = recompress_images;
+        self
+    }
+
+    /// Follow or reproduce the symbolic links.
+    pub fn symlinks(mut self, symlinks: SymlinkMode) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+}
+
+/// Copy the files of all the asset rules to the build directory. The permissions of the files are
+/// preserved. All the files are attempted and the errors are reported together.
+pub(crate) fn copy_assets(args: &dyn BuildArgs) -> Result<()> {
+    let frontend_dir = args.frontend_package().manifest_path.parent().unwrap();
+    let mut errors = Vec::new();
+
+    for rule in args.asset_rules() {
+        let source = frontend_dir.join(&rule.source);
+        if !source.exists() {
+            continue;
+        }
+
+        let mut copy = Copy {
+            include: glob_set(&rule.include)?,
+            exclude: glob_set(&rule.exclude)?,
+            destination: args.build_path().join(&rule.destination).into(),
+            rule: &rule,
+            ancestors: Vec::new(),
+            errors: &mut errors,
+        };
+        copy.dir(&source, Path::new(""));
+    }
+
+    if !errors.is_empty() {
+        bail!(
+            "could not copy {} asset(s):\n{}",
+            errors.len(),
+            errors.join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+struct Copy<'a> {
+    rule: &'a AssetRule,
+    include: GlobSet,
+    exclude: GlobSet,
+    destination: PathBuf,
+    /// Canonical paths of the directories being copied, to detect the symbolic link loops.
+    ancestors: Vec<PathBuf>,
+    errors: &'a mut Vec<String>,
+}
+
+impl Copy<'_> {
+    fn dir(&mut self, path: &Path, relative: &Path) {
+        let canonical = match path.canonicalize() {
+            Ok(x) => x,
+            Err(err) => return self.error(path, err),
+        };
+        if self.ancestors.contains(&canonical) {
+            return self.error(path, "symbolic link loop");
+        }
+
+        let mut entries = match fs::read_dir(path).and_then(|x| x.collect::<io::Result<Vec<_>>>()) {
+            Ok(x) => x,
+            Err(err) => return self.error(path, err),
+        };
+        entries.sort_by_key(|x| x.file_name());
+
+        self.ancestors.push(canonical);
+        for entry in entries {
+            self.entry(&entry.path(), &relative.join(entry.file_name()));
+        }
+        self.ancestors.pop();
+    }
+
+    fn entry(&mut self, path: &Path, relative: &Path) {
+        let is_symlink = match fs::symlink_metadata(path) {
+            Ok(x) => x.file_type().is_symlink(),
+            Err(err) => return self.error(path, err),
+        };
+        if is_symlink && self.rule.symlinks == SymlinkMode::Preserve {
+            if self.is_selected(relative) {
+                self.symlink(path, relative);
+            }
+            return;
+        }
+
+        // NOTE: the symbolic links are followed
+        match fs::metadata(path) {
+            Ok(x) if x.is_dir() => self.dir(path, relative),
+            Ok(_) if self.is_selected(relative) => self.file(path, relative),
+            Ok(_) => {}
+            Err(err) => self.error(path, err),
+        }
+    }
+
+    fn is_selected(&self, relative: &Path) -> bool {
+        (self.rule.include.is_empty() || self.include.is_match(relative))
+            && !self.exclude.is_match(relative)
+    }
+
+    fn file(&mut self, path: &Path, relative: &Path) {
+        let target = self.destination.join(relative);
+        // NOTE: fs::copy also copies the permissions
+        let res = target
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::copy(path, &target));
+
+        match res {
+            Ok(_) if self.rule.recompress_images => recompress_image(&target),
+            Ok(_) => {}
+            Err(err) => self.error(path, err),
+        }
+    }
+
+    fn symlink(&mut self, path: &Path, relative: &Path) {
+        let target = self.destination.join(relative);
+        let res = fs::read_link(path).and_then(|link| {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if fs::symlink_metadata(&target).is_ok() {
+                fs::remove_file(&target)?;
+            }
+
+            #[cfg(unix)]
+            return std::os::unix::fs::symlink(&link, &target);
+
+            #[cfg(windows)]
+            return if path.is_dir() {
+                std::os::windows::fs::symlink_dir(&link, &target)
+            } else {
+                std::os::windows::fs::symlink_file(&link, &target)
+            };
+        });
+
+        if let Err(err) = res {
+            self.error(path, err);
+        }
+    }
+
+    fn error(&mut self, path: &Path, err: impl std::fmt::Display) {
+        self.errors.push(format!("  {}: {}", path.display(), err));
+    }
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).with_context(|| format!("invalid glob `{}`", pattern))?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Recompress an image in place with `oxipng` or `jpegoptim`. A failure is only a warning: the
+/// original image is kept.
+fn recompress_image(path: &Path) {
+    let extension = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .map(|x| x.to_lowercase());
+    let mut command = match extension.as_deref() {
+        Some("png") => {
+            let mut command = Command::new("oxipng");
+            command.args(["--quiet", "--strip", "safe"]);
+            command
+        }
+        Some("jpg") | Some("jpeg") => {
+            let mut command = Command::new("jpegoptim");
+            command.args(["--quiet", "--strip-all"]);
+            command
+        }
+        _ => return,
+    };
+
+    match command.arg(path).stdout(Stdio::null()).status() {
+        Ok(status) if status.success() => {}
+        Ok(_) => {
+            log::warn!("Could not recompress `{}`", path.display());
+        }
+        Err(err) => {
+            log::warn!(
+                "Could not recompress `{}`: could not run `{:?}`: {}",
+                path.display(),
+                command.get_program(),
+                err
+            );
+        }
+    }
+}
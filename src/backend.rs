@@ -0,0 +1,316 @@
+// NOTE: the health check is only used by the backend of the `serve` command without the
+//       `dev-server` feature
+#![cfg_attr(feature = "dev-server", allow(dead_code))]
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(windows)]
+use std::process::Stdio;
+use std::process::{Child, Command};
+#[cfg(unix)]
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Interval between two attempts of the health check and between two checks of a process that
+/// is stopping.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The process groups of the background processes that are running. They are not in the
+/// foreground process group of the terminal: the signals that stop wasm-run are forwarded to them.
+#[cfg(unix)]
+static GROUPS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+/// How the `serve` command checks that the backend is ready after (re-)starting it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthCheck {
+    /// A `GET` request to this URL (`http://` only) must succeed (status 2xx or 3xx).
+    Url(String),
+    /// This port of `localhost` must accept connections.
+    Port(u16),
+}
+
+impl HealthCheck {
+    fn check(&self) -> bool {
+        match self {
+            Self::Url(url) => http_ok(url),
+            Self::Port(port) => connect(&format!("localhost:{}", port)).is_some(),
+        }
+    }
+}
+
+fn connect(addr: &str) -> Option<TcpStream> {
+    addr.to_socket_addrs()
+        .ok()?
+        .find_map(|x| TcpStream::connect_timeout(&x, Duration::from_secs(1)).ok())
+}
+
+fn http_ok(url: &str) -> bool {
+    let rest = match url.strip_prefix("http://") {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let addr = if host.contains(':') {
+        host.to_owned()
+    } else {
+        format!("{}:80", host)
+    };
+
+    let mut stream = match connect(&addr) {
+        Some(stream) => stream,
+        None => return false,
+    };
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    if stream.write_all(request.as_bytes()).is_err() {
+        return false;
+    }
+
+    // NOTE: the status line is enough: `HTTP/1.x 200 OK`
+    let mut buf = [0; 12];
+    if stream.read_exact(&mut buf).is_err() {
+        return false;
+    }
+    matches!(&buf[9..10], b"2" | b"3")
+}
+
+/// A process started by the `serve` command (the backend or the command restarted with
+/// `--full-restart`). It is stopped when dropped with all the processes it started: the process
+/// is the leader of its own process group, the group receives `SIGTERM` and it is killed if a
+/// process of the group is still running after the kill timeout.
+///
+/// On Windows, the process is put in a Job Object: the processes it starts are in the job too
+/// and they are all killed immediately when it is dropped, or when wasm-run exits.
+pub(crate) struct BackgroundProcess {
+    child: Child,
+    // NOTE: there is no graceful stop on Windows
+    #[cfg_attr(windows, allow(dead_code))]
+    kill_timeout: Duration,
+    #[cfg(windows)]
+    job: Option<job::Job>,
+}
+
+impl BackgroundProcess {
+    /// Start `command` in its own process group (in a Job Object on Windows).
+    pub(crate) fn spawn(command: &mut Command, kill_timeout: Duration) -> io::Result<Self> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+            // NOTE: the group doesn't receive the Ctrl-C of the terminal: the handlers forward
+            //       the signals to it, whatever the entry point (the macro, `tasks` or `Builder`)
+            crate::shutdown::install();
+        }
+        let child = command.spawn()?;
+        Ok(Self::new(child, kill_timeout))
+    }
+
+    fn new(child: Child, kill_timeout: Duration) -> Self {
+        #[cfg(unix)]
+        GROUPS.lock().unwrap().push(child.id() as i32);
+        #[cfg(windows)]
+        let job = match job::Job::assign(&child) {
+            Ok(job) => Some(job),
+            Err(err) => {
+                log::warn!(
+                    "Could not put the process {} in a job object: {}",
+                    child.id(),
+                    err
+                );
+                None
+            }
+        };
+
+        Self {
+            child,
+            kill_timeout,
+            #[cfg(windows)]
+            job,
+        }
+    }
+
+    pub(crate) fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub(crate) fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Wait until the health check passes, for at most `timeout`. Returns `false` on timeout or
+    /// if the process exited in the meantime.
+    pub(crate) fn wait_ready(&mut self, check: &HealthCheck, timeout: Duration) -> bool {
+        let started = Instant::now();
+        loop {
+            if check.check() {
+                return true;
+            }
+            if !self.is_running() || started.elapsed() >= timeout {
+                return false;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Whether the process or one of the processes it started is running.
+    #[cfg(unix)]
+    fn is_alive(&mut self) -> bool {
+        self.is_running() || signal_group(self.id() as i32, 0)
+    }
+
+    #[cfg(not(unix))]
+    fn is_alive(&mut self) -> bool {
+        self.is_running()
+    }
+
+    #[cfg(unix)]
+    fn terminate(&mut self) {
+        // NOTE: the descendants stay in the group even when they are reparented
+        let group = self.id() as i32;
+        if !self.kill_timeout.is_zero() {
+            signal_group(group, libc::SIGTERM);
+
+            let started = Instant::now();
+            while started.elapsed() < self.kill_timeout {
+                if !self.is_alive() {
+                    return;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            log::warn!(
+                "The process {} did not stop after {:?}: killing it",
+                self.id(),
+                self.kill_timeout
+            );
+        }
+
+        signal_group(group, libc::SIGKILL);
+        let _ = self.child.kill();
+    }
+
+    #[cfg(windows)]
+    fn terminate(&mut self) {
+        match self.job.as_ref() {
+            Some(job) => job.terminate(),
+            None => {
+                let _ = Command::new("taskkill")
+                    .args(["/T", "/F", "/PID", &self.id().to_string()])
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+            }
+        }
+        let _ = self.child.kill();
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn terminate(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+impl Drop for BackgroundProcess {
+    fn drop(&mut self) {
+        if self.is_alive() {
+            self.terminate();
+        }
+        let _ = self.child.wait();
+        #[cfg(unix)]
+        GROUPS
+            .lock()
+            .unwrap()
+            .retain(|x| *x != self.child.id() as i32);
+    }
+}
+
+/// Send `signal` to the process group `group`. Returns `false` if there is no such group (the
+/// signal 0 only checks that the group exists).
+#[cfg(unix)]
+fn signal_group(group: i32, signal: i32) -> bool {
+    // SAFETY: `kill` has no memory safety requirement
+    unsafe { libc::kill(-group, signal) == 0 }
+}
+
+/// Forward the signal that stops wasm-run to the process groups of the background processes.
+#[cfg(unix)]
+pub(crate) fn forward_signal(signal: i32) {
+    for group in GROUPS.lock().unwrap().iter() {
+        signal_group(*group, signal);
+    }
+}
+
+#[cfg(windows)]
+mod job {
+    use std::io;
+    use std::mem;
+    use std::os::windows::io::AsRawHandle;
+    use std::process::Child;
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    /// A Job Object: the processes of the job are killed when it is terminated or when its
+    /// handle is closed (`JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE`), even if wasm-run crashes.
+    pub(crate) struct Job(HANDLE);
+
+    impl Job {
+        /// Create a job with the process `child`. The processes it starts from now on are in
+        /// the job too.
+        pub(crate) fn assign(child: &Child) -> io::Result<Self> {
+            // SAFETY: the handles are checked and the information structure matches its class
+            unsafe {
+                let handle = CreateJobObjectW(ptr::null(), ptr::null());
+                if handle == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let job = Self(handle);
+
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = mem::zeroed();
+                info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+                if SetInformationJobObject(
+                    handle,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const _,
+                    mem::size_of_val(&info) as u32,
+                ) == 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+
+                if AssignProcessToJobObject(handle, child.as_raw_handle() as HANDLE) == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(job)
+            }
+        }
+
+        /// Kill all the processes of the job.
+        pub(crate) fn terminate(&self) {
+            // SAFETY: the handle is valid until the job is dropped
+            unsafe {
+                TerminateJobObject(self.0, 1);
+            }
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: the handle is valid and it is not used after this
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
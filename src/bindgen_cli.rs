@@ -0,0 +1,158 @@
+use crate::{cancel, BuildArgs, BuildProfile};
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::{Metadata, PackageId};
+use std::collections::{HashSet, VecDeque};
+use std::env;
+use std::fs;
+use std::process::Command;
+
+/// Run the `wasm-bindgen` CLI matching the version of `wasm-bindgen` used by the frontend and
+/// return the generated JS, WASM and JS snippets (path relative to the `snippets` directory and
+/// content).
+#[allow(clippy::type_complexity)]
+pub(crate) fn generate(
+    args: &dyn BuildArgs,
+    wasm_path: &Utf8Path,
+    profile: BuildProfile,
+) -> Result<(String, Vec<u8>, Vec<(Utf8PathBuf, String)>)> {
+    let version = frontend_bindgen_version(args)?;
+    let wasm_bindgen = install(args, &version)?;
+
+    let out_dir = args.target_path().join("wasm-run").join("bindgen");
+    let _ = fs::remove_dir_all(&out_dir);
+    fs::create_dir_all(&out_dir)?;
+
+    let mut command = Command::new(&wasm_bindgen);
+    command.arg(wasm_path).arg("--out-dir").arg(&out_dir).args([
+        "--out-name",
+        "app",
+        "--target",
+        "web",
+        "--no-typescript",
+    ]);
+    if !matches!(profile, BuildProfile::Release) {
+        command.arg("--debug");
+    }
+    let settings = args.bindgen_settings();
+    if settings.reference_types {
+        command.arg("--reference-types");
+    }
+    if settings.weak_refs {
+        command.arg("--weak-refs");
+    }
+    if settings.omit_default_module_path {
+        command.arg("--omit-default-module-path");
+    }
+    if settings.remove_name_section {
+        command.arg("--remove-name-section");
+    }
+    if !settings.demangle {
+        command.arg("--no-demangle");
+    }
+    if args.dwarf() {
+        command.arg("--keep-debug");
+    }
+
+    log::info!("Running wasm-bindgen {}", version);
+    let status = cancel::status(&mut command)
+        .with_context(|| format!("could not run `{}`", wasm_bindgen))?;
+    if !status.success() {
+        bail!("wasm-bindgen {} failed", version);
+    }
+
+    let wasm_js = fs::read_to_string(out_dir.join("app.js"))
+        .context("could not read the JS generated by wasm-bindgen")?;
+    let wasm_bin = fs::read(out_dir.join("app_bg.wasm"))
+        .context("could not read the WASM generated by wasm-bindgen")?;
+
+    let snippets_path = out_dir.join("snippets");
+    let mut snippets = Vec::new();
+    if snippets_path.exists() {
+        for (relative, file) in crate::generated::files(&snippets_path)? {
+            let contents = fs::read_to_string(&file)?;
+            snippets.push((Utf8PathBuf::from(relative), contents));
+        }
+    }
+
+    Ok((wasm_js, wasm_bin, snippets))
+}
+
+/// Version of the crate `wasm-bindgen` in the dependencies of the frontend.
+fn frontend_bindgen_version(args: &dyn BuildArgs) -> Result<String> {
+    let metadata = args.metadata();
+    let frontend_id = &args.frontend_package().id;
+
+    dependencies(metadata, frontend_id)
+        .into_iter()
+        .filter_map(|id| metadata.packages.iter().find(|x| &x.id == id))
+        .find(|x| x.name == "wasm-bindgen")
+        .map(|x| x.version.to_string())
+        .context("the frontend does not depend on wasm-bindgen")
+}
+
+/// All the dependencies of a package (breadth-first).
+fn dependencies<'a>(metadata: &'a Metadata, id: &'a PackageId) -> Vec<&'a PackageId> {
+    let resolve = match metadata.resolve.as_ref() {
+        Some(resolve) => resolve,
+        None => return Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from(vec![id]);
+    let mut dependencies = Vec::new();
+
+    while let Some(id) = queue.pop_front() {
+        if !seen.insert(id) {
+            continue;
+        }
+        dependencies.push(id);
+        if let Some(node) = resolve.nodes.iter().find(|x| &x.id == id) {
+            queue.extend(node.dependencies.iter());
+        }
+    }
+
+    dependencies
+}
+
+/// Find the `wasm-bindgen` CLI of this exact version or install it in the target directory.
+fn install(args: &dyn BuildArgs, version: &str) -> Result<Utf8PathBuf> {
+    let root = args
+        .target_path()
+        .join("wasm-bindgen")
+        .join(format!("version_{}", version));
+    let cached = root
+        .join("bin")
+        .join(format!("wasm-bindgen{}", env::consts::EXE_SUFFIX));
+
+    if cached.exists() {
+        return Ok(cached);
+    }
+
+    let expected = format!("wasm-bindgen {}", version);
+    let on_path = Command::new("wasm-bindgen")
+        .arg("--version")
+        .output()
+        .map(|x| String::from_utf8_lossy(&x.stdout).trim() == expected)
+        .unwrap_or(false);
+    if on_path {
+        return Ok(Utf8PathBuf::from("wasm-bindgen"));
+    }
+
+    log::info!("Installing wasm-bindgen-cli {}", version);
+    let mut command = Command::new("cargo");
+    command
+        .args(["install", "wasm-bindgen-cli", "--version"])
+        .arg(format!("={}", version))
+        .arg("--root")
+        .arg(&root);
+    args.verbosity().cargo_args(&mut command);
+    if args.offline() {
+        command.arg("--offline");
+    }
+    let status = command.status().context("could not run cargo install")?;
+    if !status.success() {
+        bail!("could not install wasm-bindgen-cli {}", version);
+    }
+
+    Ok(cached)
+}
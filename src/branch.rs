@@ -0,0 +1,62 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use std::path::Path;
+use std::process::Command;
+
+/// Name of the current git branch of the repository of `dir`, or `detached-<commit>` if the
+/// `HEAD` is detached. The characters that are not allowed in a directory name are replaced by
+/// `-` (`feature/login` becomes `feature-login`).
+fn current_branch(dir: &Path) -> Option<String> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .ok()
+            .filter(|x| x.status.success())
+            .map(|x| String::from_utf8_lossy(&x.stdout).trim().to_string())
+            .filter(|x| !x.is_empty())
+    };
+
+    let branch = match git(&["rev-parse", "--abbrev-ref", "HEAD"])? {
+        branch if branch == "HEAD" => {
+            format!("detached-{}", git(&["rev-parse", "--short", "HEAD"])?)
+        }
+        branch => branch,
+    };
+
+    Some(
+        branch
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Build directory of the current git branch: `<build_path>/<branch>`. The build directory is
+/// not namespaced if the branch cannot be determined (the workspace is not in a git repository).
+pub(crate) fn build_path(build_path: &Utf8Path, workspace_root: &Path) -> Utf8PathBuf {
+    match current_branch(workspace_root) {
+        Some(branch) => {
+            let build_path = build_path.join(&branch);
+            log::info!(
+                "Using the build directory of the branch `{}`: {}",
+                branch,
+                build_path
+            );
+            build_path
+        }
+        None => {
+            log::warn!(
+                "Could not determine the git branch: the build directory is not namespaced by \
+                branch"
+            );
+            build_path.to_path_buf()
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{BuildArgs, BuildProfile};
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::time::SystemTime;
+
+/// Number of entries retained in the cache: the least recently used ones are removed.
+const MAX_ENTRIES: usize = 8;
+
+/// Number of outputs of wasm-opt retained.
+const MAX_OPTIMIZED: usize = 16;
+
+/// File touched every time an entry is used.
+const LAST_USED: &str = "last-used";
+
+/// File of the warnings of wasm-bindgen: they are replayed every time the entry is used.
+const WARNINGS: &str = "warnings.json";
+
+/// The outputs of wasm-bindgen and wasm-opt for a WASM produced by cargo.
+pub(crate) struct Entry {
+    pub wasm_js: String,
+    pub wasm_bin: Vec<u8>,
+    pub snippets: Vec<(Utf8PathBuf, String)>,
+    pub warnings: Vec<String>,
+}
+
+fn cache_path(args: &dyn BuildArgs) -> Utf8PathBuf {
+    args.target_path().join("wasm-run-cache")
+}
+
+/// The wasm-opt used: the backend and its version.
+#[cfg_attr(not(feature = "prebuilt-wasm-opt"), allow(unused_variables))]
+fn wasm_opt_backend(args: &dyn BuildArgs) -> String {
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    let version = Some(args.wasm_opt_version());
+    #[cfg(not(feature = "prebuilt-wasm-opt"))]
+    let version: Option<String> = None;
+
+    format!(
+        "{:?}",
+        (
+            version,
+            env::var_os("WASM_RUN_WASM_OPT"),
+            cfg!(feature = "binaryen"),
+            cfg!(feature = "wasm-opt"),
+        )
+    )
+}
+
+/// Key of the outputs of a build: the WASM produced by cargo (which already reflects the sources,
+/// the `Cargo.lock`, the features and the environment of the frontend) and the settings of
+/// wasm-bindgen and wasm-opt.
+pub(crate) fn key(
+    args: &dyn BuildArgs,
+    profile: BuildProfile,
+    wasm_path: &Utf8Path,
+) -> Result<String> {
+    let wasm = fs::read(wasm_path).with_context(|| format!("could not read `{}`", wasm_path))?;
+    let config = args.profile_config(profile);
+    let settings = format!(
+        "{:?}",
+        (
+            env!("CARGO_PKG_VERSION"),
+            profile,
+            args.bindgen_settings(),
+            args.external_bindgen(),
+            config.wasm_opt_level,
+            config.wasm_opt_shrink_level,
+            wasm_opt_backend(args),
+        )
+    );
+
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(settings.as_bytes());
+    hasher.update(&wasm);
+    Ok(hasher.finalize().to_hex()[..32].to_string())
+}
+
+/// The cached outputs for `key`, if any.
+pub(crate) fn load(args: &dyn BuildArgs, key: &str) -> Option<Entry> {
+    let path = cache_path(args).join(key);
+    let wasm_js = fs::read_to_string(path.join("app.js")).ok()?;
+    let wasm_bin = fs::read(path.join("app_bg.wasm")).ok()?;
+    // NOTE: the entries without the warnings are rebuilt for `--deny-bindgen-warnings`
+    let warnings = serde_json::from_str(&fs::read_to_string(path.join(WARNINGS)).ok()?).ok()?;
+
+    let snippets_path = path.join("snippets");
+    let mut snippets = Vec::new();
+    if snippets_path.exists() {
+        for (relative, file) in crate::generated::files(&snippets_path).ok()? {
+            let contents = fs::read_to_string(&file).ok()?;
+            snippets.push((Utf8PathBuf::from(relative), contents));
+        }
+    }
+
+    let _ = fs::write(path.join(LAST_USED), "");
+    Some(Entry {
+        wasm_js,
+        wasm_bin,
+        snippets,
+        warnings,
+    })
+}
+
+/// Store the outputs for `key`. The cache is only an optimization: the errors are logged.
+pub(crate) fn store(
+    args: &dyn BuildArgs,
+    key: &str,
+    wasm_js: &str,
+    wasm_bin: &[u8],
+    snippets: &[(Utf8PathBuf, String)],
+    warnings: &[String],
+) {
+    if let Err(err) = try_store(args, key, wasm_js, wasm_bin, snippets, warnings) {
+        log::warn!("Could not store the build in the cache: {:#}", err);
+    }
+}
+
+fn try_store(
+    args: &dyn BuildArgs,
+    key: &str,
+    wasm_js: &str,
+    wasm_bin: &[u8],
+    snippets: &[(Utf8PathBuf, String)],
+    warnings: &[String],
+) -> Result<()> {
+    let cache_path = cache_path(args);
+    let path = cache_path.join(key);
+    // NOTE: the entry is written in a temporary directory so an interrupted build never leaves a
+    //       partial entry
+    let tmp_path = cache_path.join(format!("{}.tmp", key));
+    let _ = fs::remove_dir_all(&tmp_path);
+    fs::create_dir_all(&tmp_path)?;
+
+    fs::write(tmp_path.join("app.js"), wasm_js)?;
+    fs::write(tmp_path.join("app_bg.wasm"), wasm_bin)?;
+    for (snippet, contents) in snippets {
+        let snippet = tmp_path.join("snippets").join(snippet);
+        fs::create_dir_all(snippet.parent().unwrap())?;
+        fs::write(snippet, contents)?;
+    }
+    fs::write(tmp_path.join(WARNINGS), serde_json::to_string(warnings)?)?;
+    fs::write(tmp_path.join(LAST_USED), "")?;
+
+    let _ = fs::remove_dir_all(&path);
+    fs::rename(&tmp_path, &path)?;
+
+    let mut entries = fs::read_dir(&cache_path)?
+        .flatten()
+        .filter_map(|x| {
+            let used = fs::metadata(x.path().join(LAST_USED))
+                .ok()?
+                .modified()
+                .ok()?;
+            Some((used, x.path()))
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+    let excess = entries.len().saturating_sub(MAX_ENTRIES);
+    for (_, path) in entries.into_iter().take(excess) {
+        let _ = fs::remove_dir_all(path);
+    }
+
+    Ok(())
+}
+
+/// Run `optimize` on `wasm_bin` or reuse its output for the same WASM and `settings` (stored in
+/// `wasm-run/wasm-opt-cache` in the target directory). This works for every build, including the
+/// ones that are not in the build cache (the WASM optimized in background while serving).
+pub(crate) fn optimized(
+    args: &dyn BuildArgs,
+    wasm_bin: Vec<u8>,
+    settings: impl fmt::Debug,
+    optimize: impl FnOnce(Vec<u8>) -> Result<Vec<u8>>,
+) -> Result<Vec<u8>> {
+    if !args.build_cache() {
+        return optimize(wasm_bin);
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(format!("{:?}", (settings, wasm_opt_backend(args))).as_bytes());
+    hasher.update(&wasm_bin);
+    let cache_path = args.target_path().join("wasm-run").join("wasm-opt-cache");
+    let path = cache_path.join(format!("{}.wasm", &hasher.finalize().to_hex()[..32]));
+
+    if let Ok(file) = fs::File::options().append(true).open(&path) {
+        if let Ok(optimized) = fs::read(&path) {
+            let _ = file.set_modified(SystemTime::now());
+            log::info!("Reusing the output of wasm-opt from the cache");
+            return Ok(optimized);
+        }
+    }
+
+    let optimized = optimize(wasm_bin)?;
+    // NOTE: the file is written at once so an interrupted build never leaves a partial output
+    let tmp_path = path.with_extension("tmp");
+    let written = fs::create_dir_all(&cache_path)
+        .and_then(|_| fs::write(&tmp_path, &optimized))
+        .and_then(|_| fs::rename(&tmp_path, &path));
+    match written {
+        Ok(()) => evict(&cache_path, MAX_OPTIMIZED),
+        Err(err) => {
+            log::warn!(
+                "Could not store the output of wasm-opt in the cache: {}",
+                err
+            );
+        }
+    }
+    Ok(optimized)
+}
+
+/// Remove the least recently used files of `path` beyond `max`.
+fn evict(path: &Utf8Path, max: usize) {
+    let mut files = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|x| Some((x.metadata().ok()?.modified().ok()?, x.path())))
+            .collect::<Vec<_>>(),
+        Err(_) => return,
+    };
+    files.sort();
+    let excess = files.len().saturating_sub(max);
+    for (_, path) in files.into_iter().take(excess) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_keep_the_warnings_of_bindgen() {
+        let root =
+            std::env::temp_dir().join(format!("wasm-run-build-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let args = crate::TestBuildArgs::new(&root).unwrap();
+
+        let warnings = vec!["unused import".to_string()];
+        store(&args, "key", "js", b"wasm", &[], &warnings);
+        let entry = load(&args, "key").unwrap();
+        assert_eq!(entry.warnings, warnings);
+        assert_eq!(entry.wasm_js, "js");
+
+        // NOTE: an entry of a previous version could hide the warnings
+        fs::remove_file(cache_path(&args).join("key").join(WARNINGS)).unwrap();
+        assert!(load(&args, "key").is_none());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
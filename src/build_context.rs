@@ -0,0 +1,151 @@
+use crate::{BuildArgs, BuildProfile};
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::{Metadata, Package};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Information about the build given to the build hooks (`pre_build`, `post_optimize` and
+/// `post_build`).
+///
+/// The hooks of the macro [`main`](crate::main) receive a context with the arguments of the
+/// command (`BuildContext<DefaultBuildArgs>` by default):
+///
+/// ```ignore
+/// fn post_build(
+///     context: &BuildContext<DefaultBuildArgs>,
+///     _wasm_js: String,
+///     _wasm_bin: Vec<u8>,
+/// ) -> anyhow::Result<()> {
+///     if !context.is_rebuild() {
+///         upload_templates(context.build_path())?;
+///     }
+///     log::info!("Built in {:?}", context.elapsed());
+///     Ok(())
+/// }
+/// ```
+pub struct BuildContext<'a, A: ?Sized + 'a = dyn BuildArgs> {
+    args: &'a A,
+    profile: BuildProfile,
+    started: Instant,
+    rebuild: bool,
+}
+
+impl<'a, A: ?Sized> Clone for BuildContext<'a, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, A: ?Sized> Copy for BuildContext<'a, A> {}
+
+impl<'a, A: ?Sized> fmt::Debug for BuildContext<'a, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BuildContext")
+            .field("profile", &self.profile)
+            .field("started", &self.started)
+            .field("rebuild", &self.rebuild)
+            .finish()
+    }
+}
+
+impl<'a> BuildContext<'a> {
+    pub(crate) fn new(
+        args: &'a dyn BuildArgs,
+        profile: BuildProfile,
+        started: Instant,
+        rebuild: bool,
+    ) -> Self {
+        Self {
+            args,
+            profile,
+            started,
+            rebuild,
+        }
+    }
+
+    /// The same context with the concrete type of the arguments, if they are of this type.
+    pub fn downcast<A: BuildArgs>(&self) -> Option<BuildContext<'a, A>> {
+        Some(BuildContext {
+            args: self.args.downcast_ref::<A>()?,
+            profile: self.profile,
+            started: self.started,
+            rebuild: self.rebuild,
+        })
+    }
+}
+
+impl<'a, A: BuildArgs + ?Sized> BuildContext<'a, A> {
+    /// Context of an initial build starting now. This is meant to test the hooks (check
+    /// [`TestBuildArgs`](crate::TestBuildArgs)).
+    pub fn from_args(args: &'a A, profile: BuildProfile) -> Self {
+        Self {
+            args,
+            profile,
+            started: Instant::now(),
+            rebuild: false,
+        }
+    }
+
+    /// The same context for a rebuild triggered by the watcher (`rebuild` is `true`) or for the
+    /// initial build.
+    pub fn with_rebuild(mut self, rebuild: bool) -> Self {
+        self.rebuild = rebuild;
+        self
+    }
+
+    /// Arguments of the command.
+    pub fn args(&self) -> &'a A {
+        self.args
+    }
+
+    /// Profile of the build.
+    pub fn profile(&self) -> BuildProfile {
+        self.profile
+    }
+
+    /// Build directory.
+    pub fn build_path(&self) -> &'a Utf8Path {
+        self.args.build_path()
+    }
+
+    /// Directory of the artifacts (the build directory or its artifact subdirectory).
+    pub fn artifact_path(&self) -> Utf8PathBuf {
+        self.args.artifact_path()
+    }
+
+    /// Path of the JS file of the frontend (`app.js` before the fingerprinting).
+    pub fn wasm_js_path(&self) -> Utf8PathBuf {
+        self.artifact_path().join("app.js")
+    }
+
+    /// Path of the WASM file of the frontend (`app_bg.wasm` before the fingerprinting).
+    pub fn wasm_bin_path(&self) -> Utf8PathBuf {
+        self.artifact_path().join("app_bg.wasm")
+    }
+
+    /// Metadata of the workspace.
+    pub fn metadata(&self) -> &'a Metadata {
+        self.args.metadata()
+    }
+
+    /// Frontend package.
+    pub fn frontend_package(&self) -> &'a Package {
+        self.args.frontend_package()
+    }
+
+    /// When the build started.
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    /// Time elapsed since the build started.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// `true` if the build was triggered by a change of the watched files, `false` for the
+    /// initial build.
+    pub fn is_rebuild(&self) -> bool {
+        self.rebuild
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{generated, BuildArgs, BuildProfile};
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use std::time::Duration;
+
+/// The files emitted by a build, returned by [`BuildArgs::run`] and by `Cli::build()` (for
+/// example to package them, embed them in a backend or deploy them).
+///
+/// ```ignore
+/// let output = Cli::build()?;
+/// upload(&output.wasm_path)?;
+/// for path in &output.assets {
+///     upload(path)?;
+/// }
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct BuildOutput {
+    /// The build directory.
+    pub build_path: Utf8PathBuf,
+    /// The WASM binary (`app_bg-<hash>.wasm` when the artifacts are fingerprinted).
+    pub wasm_path: Utf8PathBuf,
+    /// The JS generated by wasm-bindgen (`app-<hash>.js` when the artifacts are fingerprinted).
+    pub js_path: Utf8PathBuf,
+    /// All the files of the build directory, the artifacts included, sorted by their path
+    /// relative to it.
+    pub assets: Vec<Utf8PathBuf>,
+    /// The profile of the build ([`BuildProfile::Profiling`] with `--profiling`).
+    pub profile: BuildProfile,
+    /// The duration of the build, hooks included.
+    pub duration: Duration,
+}
+
+impl BuildOutput {
+    /// List the files of the build directory once the build is finished. `artifacts` are the
+    /// file names of the JS and of the WASM if they are not `app.js` and `app_bg.wasm`.
+    pub(crate) fn collect(
+        args: &dyn BuildArgs,
+        profile: BuildProfile,
+        artifacts: Option<(String, String)>,
+        duration: Duration,
+    ) -> Result<Self> {
+        let build_path = args.build_path().to_owned();
+        let (js_name, wasm_name) =
+            artifacts.unwrap_or_else(|| ("app.js".to_string(), "app_bg.wasm".to_string()));
+        let assets = generated::files(&build_path)
+            .with_context(|| format!("could not list `{}`", build_path))?
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect();
+
+        Ok(Self {
+            wasm_path: args.artifact_path().join(wasm_name),
+            js_path: args.artifact_path().join(js_name),
+            build_path,
+            assets,
+            profile,
+            duration,
+        })
+    }
+}
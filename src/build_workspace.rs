@@ -0,0 +1,245 @@
+use crate::{build, BuildArgs, BuildProfile, DefaultBuildArgs, Hooks, WorkspaceContext};
+use anyhow::{bail, Result};
+use camino::Utf8PathBuf;
+use cargo_metadata::{DependencyKind, Metadata, Package};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+/// Arguments of the `build-workspace` command (enabled with the argument `build_workspace` of the
+/// macro [`main`](crate::main)): build all the packages of the workspace that have a
+/// `[package.metadata.wasm-run]` section.
+///
+/// The packages are built one at a time, each one after the other selected packages it depends
+/// on. Each package is built with its own configuration in `<out-dir>/<package>` with
+/// `--out-dir`, otherwise in `build_path` of its configuration or `build/<package>`, and with the
+/// hooks of the runner crate. Like cargo, the packages are built with the `dev` profile unless
+/// `--release` or `--profile` is used.
+#[derive(StructOpt, Debug)]
+pub struct BuildWorkspaceArgs {
+    /// Only build these packages.
+    #[structopt(long = "package", short = "p", number_of_values = 1)]
+    pub packages: Vec<String>,
+
+    /// Parent of the output directories: each package is built in `<out-dir>/<package>`. It is
+    /// relative to the current directory. `--build-path` is an alias.
+    #[structopt(long = "out-dir", alias = "build-path")]
+    pub out_dir: Option<Utf8PathBuf>,
+
+    /// Build the packages with the `release` profile.
+    #[structopt(long, conflicts_with = "profile")]
+    pub release: bool,
+
+    /// Build profile of the packages: `dev` (default), `profiling` or `release`.
+    #[structopt(
+        long,
+        possible_values = &["dev", "profiling", "release"],
+        parse(from_str = parse_profile),
+    )]
+    pub profile: Option<BuildProfile>,
+
+    /// Arguments of the `build` command used for every package (after `--`).
+    #[structopt(last = true)]
+    pub build_args: Vec<String>,
+}
+
+impl BuildWorkspaceArgs {
+    /// Run the `build-workspace` command.
+    pub fn run(self) -> Result<()> {
+        build_workspace(&self, WorkspaceContext::global())
+    }
+
+    /// The profile of the builds.
+    pub fn build_profile(&self) -> BuildProfile {
+        match self.profile {
+            Some(profile) => profile,
+            None if self.release => BuildProfile::Release,
+            None => BuildProfile::Dev,
+        }
+    }
+}
+
+fn parse_profile(s: &str) -> BuildProfile {
+    match s {
+        "profiling" => BuildProfile::Profiling,
+        "release" => BuildProfile::Release,
+        _ => BuildProfile::Dev,
+    }
+}
+
+#[derive(Debug)]
+enum Outcome {
+    Built(Utf8PathBuf, Duration),
+    Failed(anyhow::Error),
+    Skipped,
+}
+
+fn build_workspace(args: &BuildWorkspaceArgs, context: &WorkspaceContext) -> Result<()> {
+    let packages = workspace_packages(context.metadata(), &args.packages)?;
+    // NOTE: the arguments are validated before anything is built
+    let build_args = DefaultBuildArgs::from_iter_safe(build_command(&args.build_args))?;
+    if build_args.build_path.is_some() && packages.len() > 1 {
+        bail!(
+            "the packages cannot share the output directory of the build arguments: use \
+            `build-workspace --out-dir <dir>` to build them in `<dir>/<package>`"
+        );
+    }
+
+    let dependencies = packages
+        .iter()
+        .map(|package| {
+            packages
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| {
+                    package
+                        .dependencies
+                        .iter()
+                        .any(|dep| dep.name == x.name && dep.kind != DependencyKind::Development)
+                })
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    // NOTE: the packages are built one at a time: the hooks and the cargo commands of the builds
+    //       are not meant to run concurrently
+    let mut outcomes: Vec<Option<Outcome>> = packages.iter().map(|_| None).collect();
+    while let Some(i) = (0..packages.len())
+        .find(|i| outcomes[*i].is_none() && dependencies[*i].iter().all(|x| outcomes[*x].is_some()))
+    {
+        let package = packages[i];
+        if dependencies[i]
+            .iter()
+            .any(|x| !matches!(outcomes[*x], Some(Outcome::Built(..))))
+        {
+            log::warn!(
+                "Skipping `{}`: one of its dependencies could not be built",
+                package.name
+            );
+            outcomes[i] = Some(Outcome::Skipped);
+            continue;
+        }
+
+        log::info!("Building `{}`", package.name);
+        let start = Instant::now();
+        outcomes[i] = Some(match build_package(context, package, args) {
+            Ok(build_path) => Outcome::Built(build_path, start.elapsed()),
+            Err(err) => Outcome::Failed(err),
+        });
+    }
+
+    log::info!("Summary:");
+    let width = packages.iter().map(|x| x.name.len()).max().unwrap_or(0);
+    let mut failed = 0;
+    for (package, outcome) in packages.iter().zip(outcomes) {
+        match outcome.unwrap_or(Outcome::Skipped) {
+            Outcome::Built(build_path, elapsed) => {
+                log::info!(
+                    "  {:<width$}  built in {:.2?}: {}",
+                    package.name,
+                    elapsed,
+                    build_path,
+                    width = width,
+                );
+            }
+            Outcome::Failed(err) => {
+                failed += 1;
+                log::error!(
+                    "  {:<width$}  failed: {:#}",
+                    package.name,
+                    err,
+                    width = width
+                );
+            }
+            Outcome::Skipped => {
+                failed += 1;
+                log::warn!(
+                    "  {:<width$}  skipped (a dependency could not be built)",
+                    package.name,
+                    width = width,
+                );
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!(
+            "{} of {} packages could not be built",
+            failed,
+            packages.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// The members of the workspace with a `[package.metadata.wasm-run]` section (only the ones named
+/// in `selection` if it is not empty).
+fn workspace_packages<'a>(
+    metadata: &'a Metadata,
+    selection: &[String],
+) -> Result<Vec<&'a Package>> {
+    let packages = metadata
+        .packages
+        .iter()
+        .filter(|x| metadata.workspace_members.contains(&x.id))
+        .filter(|x| !x.metadata["wasm-run"].is_null())
+        .collect::<Vec<_>>();
+
+    if let Some(name) = selection
+        .iter()
+        .find(|name| !packages.iter().any(|x| &x.name == *name))
+    {
+        bail!(
+            "package `{}` not found in the workspace or without a `[package.metadata.wasm-run]` \
+            section",
+            name
+        );
+    }
+    let packages = packages
+        .into_iter()
+        .filter(|x| selection.is_empty() || selection.contains(&x.name))
+        .collect::<Vec<_>>();
+
+    if packages.is_empty() {
+        bail!("no package of the workspace has a `[package.metadata.wasm-run]` section");
+    }
+
+    Ok(packages)
+}
+
+fn build_command(build_args: &[String]) -> impl Iterator<Item = &str> {
+    std::iter::once("build").chain(build_args.iter().map(|x| x.as_str()))
+}
+
+fn build_package(
+    context: &WorkspaceContext,
+    package: &Package,
+    args: &BuildWorkspaceArgs,
+) -> Result<Utf8PathBuf> {
+    let package_context = WorkspaceContext::init(
+        context.metadata().clone(),
+        Some(&package.name),
+        None,
+        Some(Box::new(|metadata, package| {
+            crate::metadata_path(&metadata.workspace_root)
+                .join("build")
+                .join(&package.name)
+        })),
+        false,
+        Hooks::default(),
+    )?
+    .with_verbosity(context.verbosity())
+    .with_shared_hooks(context.shared_hooks());
+    let mut build_args = DefaultBuildArgs::from_iter_safe(build_command(&args.build_args))?
+        .with_context(Arc::new(package_context));
+    if let Some(out_dir) = args.out_dir.as_ref() {
+        build_args.build_path = Some(out_dir.join(&package.name));
+    }
+    build(
+        args.build_profile(),
+        &build_args,
+        build_args.context().hooks(),
+    )?;
+    Ok(build_args.build_path().to_owned())
+}
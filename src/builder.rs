@@ -0,0 +1,132 @@
+use crate::tasks::{self, BuildConfig, ServeConfig};
+use crate::{select_frontend, BuildOutput, BuildProfile, Hooks};
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use cargo_metadata::{Metadata, MetadataCommand};
+
+/// Programmatic entry point of the build pipeline. Unlike the macro [`main`](crate::main), it
+/// doesn't generate a `main` and doesn't use any process-global state: several builders can be
+/// used in the same process (an xtask binary, integration tests, ...).
+///
+/// ```ignore
+/// use wasm_run::prelude::*;
+///
+/// let output = wasm_run::Builder::new(metadata)
+///     .frontend("app")
+///     .profile(BuildProfile::Release)
+///     .hooks(Hooks::default())
+///     .build()?;
+/// ```
+///
+/// The frontend package is detected if it is not provided: it is the only member of the
+/// workspace that can be built to WASM.
+#[allow(clippy::type_complexity)]
+pub struct Builder {
+    metadata: Metadata,
+    frontend: Option<String>,
+    backend: Option<String>,
+    profile: BuildProfile,
+    hooks: Hooks,
+    build_path: Option<Utf8PathBuf>,
+    configure: Vec<Box<dyn FnOnce(&mut BuildConfig)>>,
+}
+
+impl Builder {
+    /// Build a package of the workspace `metadata`.
+    pub fn new(metadata: Metadata) -> Self {
+        Self {
+            metadata,
+            frontend: None,
+            backend: None,
+            profile: BuildProfile::Release,
+            hooks: Hooks::default(),
+            build_path: None,
+            configure: Vec::new(),
+        }
+    }
+
+    /// Build a package of the workspace of the current directory.
+    pub fn from_current_dir() -> Result<Self> {
+        let metadata = MetadataCommand::new()
+            .exec()
+            .context("could not load the metadata of the workspace")?;
+        Ok(Self::new(metadata))
+    }
+
+    /// Name of the frontend package.
+    pub fn frontend(mut self, name: impl Into<String>) -> Self {
+        self.frontend = Some(name.into());
+        self
+    }
+
+    /// Name of the backend package (used by [`Builder::serve`]).
+    pub fn backend(mut self, name: impl Into<String>) -> Self {
+        self.backend = Some(name.into());
+        self
+    }
+
+    /// Build profile ([`BuildProfile::Release`] by default).
+    pub fn profile(mut self, profile: BuildProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Hooks of the pipeline ([`Hooks::default`] by default).
+    pub fn hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Build directory (`build_path` of the configuration or `build/` at the root of the
+    /// workspace by default).
+    pub fn build_path(mut self, build_path: impl Into<Utf8PathBuf>) -> Self {
+        self.build_path = Some(build_path.into());
+        self
+    }
+
+    /// Change any other setting of the [`BuildConfig`].
+    pub fn configure(mut self, f: impl FnOnce(&mut BuildConfig) + 'static) -> Self {
+        self.configure.push(Box::new(f));
+        self
+    }
+
+    /// Resolve the build configuration and the hooks.
+    pub fn into_config(self) -> Result<(BuildConfig, Hooks)> {
+        let frontend = match self.frontend {
+            Some(frontend) => frontend,
+            None => select_frontend(&self.metadata, None)?.name.clone(),
+        };
+        let mut config = BuildConfig::from_metadata(self.metadata, &frontend)?;
+        if let Some(backend) = self.backend {
+            config = config.backend(&backend)?;
+        }
+        if let Some(build_path) = self.build_path {
+            config.build_path = build_path;
+        }
+        for f in self.configure {
+            f(&mut config);
+        }
+        Ok((config, self.hooks))
+    }
+
+    /// Build the frontend and return the files it emitted. It returns once the webhooks of the
+    /// build have been sent.
+    pub fn build(self) -> Result<BuildOutput> {
+        let profile = self.profile;
+        let (config, hooks) = self.into_config()?;
+        tasks::build(profile, &config, &hooks)
+    }
+
+    /// Build the frontend and serve it (or run the backend) with the profile of the builder,
+    /// rebuilding every time a change is detected.
+    ///
+    /// This function only returns on error.
+    pub fn serve(self) -> Result<()> {
+        let profile = self.profile;
+        let (mut config, hooks) = self.into_config()?;
+        config.profiling |= matches!(profile, BuildProfile::Profiling);
+        let mut serve = ServeConfig::new(config);
+        serve.release = matches!(profile, BuildProfile::Release);
+        tasks::serve(serve, hooks)
+    }
+}
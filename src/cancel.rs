@@ -0,0 +1,177 @@
+use crate::state::State;
+use anyhow::Result;
+use std::cell::RefCell;
+use std::fmt;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Interval between two checks of the cancellation while waiting for a process.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+thread_local! {
+    static CURRENT: RefCell<Option<CancelToken>> = const { RefCell::new(None) };
+}
+
+/// Error returned by a build cancelled by a newer change.
+#[derive(Debug)]
+pub(crate) struct BuildCancelled;
+
+impl fmt::Display for BuildCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "build cancelled by a newer change")
+    }
+}
+
+impl std::error::Error for BuildCancelled {}
+
+/// Cancellation of the build running on a worker thread.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub(crate) fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Use this token for the builds run by the current thread.
+    pub(crate) fn install(self) {
+        CURRENT.with(|x| x.replace(Some(self)));
+    }
+}
+
+/// Fail with [`BuildCancelled`] if the build of the current thread has been cancelled.
+pub(crate) fn check() -> Result<()> {
+    if current().is_some_and(|x| x.is_cancelled()) {
+        return Err(BuildCancelled.into());
+    }
+    Ok(())
+}
+
+pub(crate) fn is_cancelled<T>(res: &Result<T>) -> bool {
+    res.as_ref()
+        .err()
+        .is_some_and(|err| err.chain().any(|x| x.is::<BuildCancelled>()))
+}
+
+/// The rebuilds of the `serve` command, run one at a time on a thread of `scope` so the watcher
+/// keeps receiving the changes: a newer change cancels the build in progress.
+pub(crate) struct Worker<'scope> {
+    state: &'scope State,
+    running: Option<(CancelToken, thread::ScopedJoinHandle<'scope, bool>)>,
+    succeeded: bool,
+}
+
+impl<'scope> Worker<'scope> {
+    /// The results of the builds are reported in the overlay of `state`.
+    pub(crate) fn new(state: &'scope State) -> Self {
+        Self {
+            state,
+            running: None,
+            succeeded: true,
+        }
+    }
+
+    /// Run the build `f` on a new thread. Its result is reported like the results of the watch
+    /// loop.
+    pub(crate) fn spawn<'env>(
+        &mut self,
+        scope: &'scope thread::Scope<'scope, 'env>,
+        f: impl FnOnce() -> Result<()> + Send + 'scope,
+    ) {
+        self.cancel();
+        let token = CancelToken::default();
+        let control = crate::serve_handle::current();
+        let state = self.state;
+        let handle = {
+            let token = token.clone();
+            scope.spawn(move || {
+                token.install();
+                if let Some(control) = control {
+                    control.install();
+                }
+                let res = f();
+                let succeeded = res.is_ok();
+                crate::report(state, res);
+                succeeded
+            })
+        };
+        self.running = Some((token, handle));
+    }
+
+    /// Cancel the build in progress and wait for it. Returns `true` if the last build succeeded
+    /// (a cancelled build did not).
+    pub(crate) fn cancel(&mut self) -> bool {
+        if let Some((token, handle)) = self.running.take() {
+            token.cancel();
+            self.succeeded = handle.join().unwrap_or(false);
+        }
+        self.succeeded
+    }
+}
+
+/// Run the command to completion like [`Command::status`] but kill it if the build of the current
+/// thread is cancelled.
+pub(crate) fn status(command: &mut Command) -> Result<ExitStatus> {
+    match current() {
+        Some(token) => wait(command.spawn()?, &token),
+        None => Ok(command.status()?),
+    }
+}
+
+/// Run the command to completion like [`Command::output`] but kill it if the build of the current
+/// thread is cancelled. The standard error is inherited.
+#[cfg(feature = "prebuilt-wasm-opt")]
+pub(crate) fn output(command: &mut Command) -> Result<std::process::Output> {
+    use anyhow::Context;
+    use std::io::Read;
+    use std::process::Stdio;
+
+    let token = match current() {
+        Some(token) => token,
+        None => return Ok(command.output()?),
+    };
+
+    let mut child = command.stdout(Stdio::piped()).spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+    // NOTE: the output must be read while the process runs or it would block on a full pipe
+    let reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        stdout.read_to_end(&mut buf).map(|_| buf)
+    });
+    let status = wait(child, &token)?;
+    let stdout = reader
+        .join()
+        .unwrap()
+        .context("could not read the output of the process")?;
+
+    Ok(std::process::Output {
+        status,
+        stdout,
+        stderr: Vec::new(),
+    })
+}
+
+fn current() -> Option<CancelToken> {
+    CURRENT.with(|x| x.borrow().clone())
+}
+
+fn wait(mut child: Child, token: &CancelToken) -> Result<ExitStatus> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if token.is_cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(BuildCancelled.into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{
+    ArgsFactory, BuildArgs, BuildOutput, BuildProfile, Config, Hooks, ServeArgs, WorkspaceContext,
+};
+use anyhow::Result;
+use cargo_metadata::{Metadata, Package};
+use std::fmt;
+use structopt::StructOpt;
+
+/// Context of the custom commands given to the function `other_cli_commands` when the macro
+/// [`main`](crate::main) has the argument `command_context`: the workspace, the frontend and
+/// backend packages, the hooks and the build pipeline with the configured `build_args` and
+/// `serve_args` types.
+///
+/// ```ignore
+/// #[wasm_run::main(other_cli_commands, command_context)]
+/// #[derive(StructOpt, Debug)]
+/// enum Cli {
+///     Deploy,
+/// }
+///
+/// fn other_cli_commands(
+///     cli: Cli,
+///     context: &CommandContext<DefaultBuildArgs, DefaultServeArgs>,
+/// ) -> anyhow::Result<()> {
+///     match cli {
+///         Cli::Deploy => {
+///             let output = context.build_with(BuildProfile::Release, |args| {
+///                 args.fingerprint_assets = true;
+///             })?;
+///             upload(&output.assets, context.backend_package())
+///         }
+///     }
+/// }
+/// ```
+pub struct CommandContext<'a, B, S> {
+    workspace: &'a WorkspaceContext,
+    args: ArgsFactory<B, S>,
+}
+
+impl<B, S> fmt::Debug for CommandContext<'_, B, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CommandContext")
+            .field("workspace", &self.workspace)
+            .field("args", &self.args)
+            .finish()
+    }
+}
+
+impl<'a, B: BuildArgs + StructOpt, S: ServeArgs + StructOpt> CommandContext<'a, B, S> {
+    /// Context of the workspace `workspace`. The arguments are parsed with `bin_name` as the
+    /// name of the binary in the help and the errors.
+    pub fn new(workspace: &'a WorkspaceContext, bin_name: impl Into<String>) -> Self {
+        Self {
+            workspace,
+            args: ArgsFactory::new(bin_name),
+        }
+    }
+
+    /// Context of the workspace.
+    pub fn workspace(&self) -> &'a WorkspaceContext {
+        self.workspace
+    }
+
+    /// Metadata of the workspace.
+    pub fn metadata(&self) -> &'a Metadata {
+        self.workspace.metadata()
+    }
+
+    /// Frontend package.
+    pub fn frontend_package(&self) -> &'a Package {
+        self.workspace.frontend_package()
+    }
+
+    /// Backend package, if any.
+    pub fn backend_package(&self) -> Option<&'a Package> {
+        self.workspace.backend_package()
+    }
+
+    /// Settings of `WasmRun.toml` and of the metadata of the frontend.
+    pub fn config(&self) -> &'a Config {
+        self.workspace.config()
+    }
+
+    /// Hooks of the macro.
+    pub fn hooks(&self) -> &'a Hooks {
+        self.workspace.hooks()
+    }
+
+    /// Constructor of the arguments of the `build` and `serve` commands from a command-line.
+    pub fn args(&self) -> &ArgsFactory<B, S> {
+        &self.args
+    }
+
+    /// Run the build pipeline (with the hooks of the context of the arguments) and return the
+    /// files it emitted. Unlike [`BuildArgs::run`], the profile is not always
+    /// [`BuildProfile::Release`].
+    pub fn build(&self, profile: BuildProfile, args: &B) -> Result<BuildOutput>
+    where
+        B: 'static,
+    {
+        crate::build(profile, args, args.context().hooks())
+    }
+
+    /// Run the build pipeline with the default arguments of the `build` command changed by
+    /// `configure` and return the files it emitted.
+    pub fn build_with(
+        &self,
+        profile: BuildProfile,
+        configure: impl FnOnce(&mut B),
+    ) -> Result<BuildOutput>
+    where
+        B: 'static,
+    {
+        let mut args = self.args.build_args(std::iter::empty::<&str>())?;
+        configure(&mut args);
+        self.build(profile, &args)
+    }
+
+    /// Run the `serve` command with its default arguments changed by `configure`.
+    ///
+    /// This function only returns on error.
+    pub fn serve_with(&self, configure: impl FnOnce(&mut S)) -> Result<()>
+    where
+        S: Sync + 'static,
+    {
+        let mut args = self.args.serve_args(std::iter::empty::<&str>())?;
+        configure(&mut args);
+        args.run()
+    }
+}
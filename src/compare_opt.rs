@@ -0,0 +1,282 @@
+use crate::frontend_target::FrontendTarget;
+use crate::{events, BuildArgs, BuildProfile, DefaultBuildArgs};
+use anyhow::{bail, Context, Result};
+use camino::Utf8PathBuf;
+use serde_json::json;
+use std::fs;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+/// Arguments of the `compare-opt` command: optimize the same WASM with several settings of
+/// wasm-opt and report the size and the duration of each of them.
+///
+/// Every optimization level is compared with and without the debug information (`-g`, the names
+/// section), and with the settings of the release profile of the configuration. The variants run
+/// at the same time in their own directory of `wasm-run/compare-opt` in the target directory: it
+/// is cleared when the command starts, so the files of an interrupted run don't accumulate, and
+/// removed at the end unless `--keep` is used.
+#[derive(StructOpt, Debug)]
+pub struct CompareOptArgs {
+    /// WASM to optimize (default: the output of wasm-bindgen for the WASM of the last release
+    /// build of the frontend by cargo, as it is given to wasm-opt by the `build` command).
+    #[structopt(long)]
+    pub input: Option<Utf8PathBuf>,
+
+    /// Optimization levels compared (default: `O2`, `O3` and `Oz`).
+    #[structopt(
+        long = "level",
+        number_of_values = 1,
+        possible_values = &["O0", "O1", "O2", "O3", "O4", "Os", "Oz"],
+    )]
+    pub levels: Vec<String>,
+
+    /// Number of variants optimized at the same time (default: the number of CPUs).
+    #[structopt(long, short = "j")]
+    pub jobs: Option<usize>,
+
+    /// Keep the optimized WASM of every variant in `wasm-run/compare-opt` in the target directory.
+    #[structopt(long)]
+    pub keep: bool,
+
+    /// Build arguments.
+    #[structopt(flatten)]
+    pub build_args: DefaultBuildArgs,
+}
+
+impl CompareOptArgs {
+    /// Run the `compare-opt` command.
+    pub fn run(self) -> Result<()> {
+        compare(&self)
+    }
+}
+
+/// Settings of wasm-opt of a variant.
+struct Variant {
+    name: String,
+    optimization_level: u32,
+    shrink_level: u32,
+    debug_info: bool,
+}
+
+impl Variant {
+    fn new(level: &str, debug_info: bool) -> Self {
+        let (optimization_level, shrink_level) = match level {
+            "Os" => (2, 1),
+            "Oz" => (2, 2),
+            _ => (level[1..].parse().unwrap_or(2), 0),
+        };
+        Self {
+            name: if debug_info {
+                format!("{} -g", level)
+            } else {
+                level.to_owned()
+            },
+            optimization_level,
+            shrink_level,
+            debug_info,
+        }
+    }
+
+    /// The settings used by the release builds (`wasm_opt` of the configuration).
+    fn configured(args: &dyn BuildArgs) -> Self {
+        let config = args.profile_config(BuildProfile::Release);
+        Self {
+            name: "configured".to_owned(),
+            optimization_level: config.wasm_opt_level.unwrap_or(2),
+            shrink_level: config.wasm_opt_shrink_level.unwrap_or(1),
+            debug_info: false,
+        }
+    }
+
+    fn dir_name(&self) -> String {
+        self.name.replace(' ', "")
+    }
+}
+
+/// Temporary directory of the variants. It is removed when dropped, including on error.
+struct Workspace {
+    path: Utf8PathBuf,
+    keep: bool,
+}
+
+impl Workspace {
+    fn create(args: &dyn BuildArgs, keep: bool) -> Result<Self> {
+        let path = args.target_path().join("wasm-run").join("compare-opt");
+        if path.exists() {
+            fs::remove_dir_all(&path).with_context(|| format!("could not remove `{}`", path))?;
+        }
+        fs::create_dir_all(&path).with_context(|| format!("could not create `{}`", path))?;
+        Ok(Self { path, keep })
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+fn compare(args: &CompareOptArgs) -> Result<()> {
+    let build_args = &args.build_args;
+    let wasm_bin = input(args)?;
+    let levels = if args.levels.is_empty() {
+        vec!["O2".to_owned(), "O3".to_owned(), "Oz".to_owned()]
+    } else {
+        args.levels.clone()
+    };
+    let variants = levels
+        .iter()
+        .flat_map(|x| [Variant::new(x, false), Variant::new(x, true)])
+        .chain(std::iter::once(Variant::configured(build_args)))
+        .collect::<Vec<_>>();
+    let jobs = args
+        .jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |x| x.get()))
+        .max(1);
+
+    // NOTE: wasm-opt is downloaded once before the variants run at the same time
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    crate::prebuilt_wasm_opt::install_wasm_opt(build_args)?;
+
+    let workspace = Workspace::create(build_args, args.keep)?;
+    fs::write(workspace.path.join("input.wasm"), &wasm_bin)?;
+    log::info!(
+        "Comparing {} variants of wasm-opt on a WASM of {} bytes",
+        variants.len(),
+        wasm_bin.len()
+    );
+
+    let queue = Mutex::new(variants.iter().enumerate());
+    let mut outcomes: Vec<Option<Result<(usize, Duration)>>> =
+        variants.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..jobs.min(variants.len()) {
+            let tx = tx.clone();
+            let (queue, wasm_bin, workspace) = (&queue, &wasm_bin, &workspace);
+            scope.spawn(move || loop {
+                let next = queue.lock().unwrap().next();
+                let Some((i, variant)) = next else {
+                    break;
+                };
+                log::info!("Optimizing with {}", variant.name);
+                let started = Instant::now();
+                let outcome = crate::wasm_opt(
+                    wasm_bin.clone(),
+                    variant.shrink_level,
+                    variant.optimization_level,
+                    variant.debug_info,
+                    build_args,
+                )
+                .and_then(|optimized| {
+                    let path = workspace.path.join(variant.dir_name());
+                    fs::create_dir_all(&path)?;
+                    fs::write(path.join("app_bg.wasm"), &optimized)?;
+                    Ok((optimized.len(), started.elapsed()))
+                });
+                let _ = tx.send((i, outcome));
+            });
+        }
+        drop(tx);
+        for (i, outcome) in rx {
+            outcomes[i] = Some(outcome);
+        }
+    });
+
+    report(args, &variants, outcomes, wasm_bin.len())?;
+    if args.keep {
+        log::info!("The optimized WASM are in `{}`", workspace.path);
+    }
+
+    Ok(())
+}
+
+/// The WASM given with `--input`, otherwise the output of wasm-bindgen for the WASM of the last
+/// release build.
+fn input(args: &CompareOptArgs) -> Result<Vec<u8>> {
+    if let Some(path) = args.input.as_deref() {
+        return fs::read(path).with_context(|| format!("could not read `{}`", path));
+    }
+
+    let target = FrontendTarget::resolve(&args.build_args)?;
+    let wasm_path = crate::cargo_wasm_path(&args.build_args, &target, BuildProfile::Release);
+    if !wasm_path.exists() {
+        bail!(
+            "`{}` does not exist: build the frontend first (`build`) or give a WASM with `--input`",
+            wasm_path
+        );
+    }
+    log::info!("Running wasm-bindgen on `{}`", wasm_path);
+    let (_, wasm_bin, _) =
+        crate::generate_bindings(&args.build_args, &wasm_path, BuildProfile::Release)?;
+    Ok(wasm_bin)
+}
+
+fn report(
+    args: &CompareOptArgs,
+    variants: &[Variant],
+    outcomes: Vec<Option<Result<(usize, Duration)>>>,
+    size_before: usize,
+) -> Result<()> {
+    let mut results = Vec::new();
+    let mut failed = 0;
+    for (variant, outcome) in variants.iter().zip(outcomes) {
+        match outcome {
+            Some(Ok((size, elapsed))) => results.push((variant, size, elapsed)),
+            Some(Err(err)) => {
+                failed += 1;
+                log::error!("{} failed: {:#}", variant.name, err);
+            }
+            None => failed += 1,
+        }
+    }
+    results.sort_by_key(|(_, size, elapsed)| (*size, *elapsed));
+
+    log::info!("Summary (input: {} bytes):", size_before);
+    let width = variants.iter().map(|x| x.name.len()).max().unwrap_or(0);
+    for (variant, size, elapsed) in &results {
+        log::info!(
+            "  {:<width$}  {:>10} bytes ({:+.1}%) in {:.2?}",
+            variant.name,
+            size,
+            percent(*size, size_before),
+            elapsed,
+            width = width,
+        );
+    }
+    events::emit(
+        &args.build_args,
+        "compare-opt-finished",
+        json!({
+            "size_before": size_before,
+            "variants": results
+                .iter()
+                .map(|(variant, size, elapsed)| json!({
+                    "name": variant.name,
+                    "optimization_level": variant.optimization_level,
+                    "shrink_level": variant.shrink_level,
+                    "debug_info": variant.debug_info,
+                    "size": size,
+                    "duration": elapsed.as_secs_f64(),
+                }))
+                .collect::<Vec<_>>(),
+        }),
+    );
+
+    if failed > 0 {
+        bail!("{} of {} variants failed", failed, variants.len());
+    }
+
+    Ok(())
+}
+
+fn percent(size: usize, size_before: usize) -> f64 {
+    if size_before == 0 {
+        return 0.0;
+    }
+    (size as f64 - size_before as f64) * 100.0 / size_before as f64
+}
@@ -0,0 +1,446 @@
+use crate::config_schema::{self, ConfigIssue};
+use crate::{
+    BuildProfile, CriticalCss, ExternalDependency, Fingerprint, ProxyRule, PwaConfig, Redirect,
+};
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::{Metadata, Package};
+use serde_json::{Map, Value};
+use std::fs;
+
+/// Name of the configuration file looked up in the frontend package and then at the root of the
+/// workspace.
+pub const CONFIG_FILE: &str = "WasmRun.toml";
+
+/// Settings of the project declared in `WasmRun.toml` or in `[package.metadata.wasm-run]` of the
+/// frontend package. The keys of `WasmRun.toml` take precedence over the metadata and the
+/// command-line arguments take precedence over both.
+///
+/// The sections `dev`, `profiling` and `release` override the settings for a build profile
+/// (check [`Config::profile`]).
+///
+/// ```toml
+/// build_path = "dist"
+/// port = 8080
+/// overlay = false
+/// watch_ignore = ["*.log"]
+/// redirects = ["/old -> /new"]
+///
+/// [wasm_opt]
+/// version = "101"
+/// mirror = "https://mirror.example.com/binaryen"
+///
+/// [proxy]
+/// "/api" = "http://localhost:8000"
+///
+/// [env]
+/// API_URL = "http://localhost:8000"
+///
+/// [pwa]
+/// name = "My App"
+///
+/// [release]
+/// base_url = "/my-app/"
+/// features = ["analytics"]
+/// wasm_opt = { level = 3, shrink_level = 2 }
+/// env = { API_URL = "https://api.example.com" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// Default output directory (relative to the file that declares it). It can also be set with
+    /// `out_dir`.
+    pub build_path: Option<Utf8PathBuf>,
+    /// Default port of the development server.
+    pub port: Option<u16>,
+    /// Patterns (`.gitignore` syntax) of the files whose changes are ignored when watching, in
+    /// addition to the ones of the command-line.
+    pub watch_ignore: Vec<String>,
+    /// Version of binaryen used for wasm-opt.
+    pub wasm_opt_version: Option<String>,
+    /// Expected SHA-256 of the binaryen archive.
+    pub wasm_opt_sha256: Option<String>,
+    /// Mirror of the binaryen releases.
+    pub wasm_opt_mirror: Option<String>,
+    /// Optimization level of wasm-opt (`-O<level>`).
+    pub wasm_opt_level: Option<u32>,
+    /// Shrink level of wasm-opt (`-s <level>`).
+    pub wasm_opt_shrink_level: Option<u32>,
+    /// URL path prefix of the artifacts in the default `index.html` (for example `/my-app/`).
+    pub base_url: Option<String>,
+    /// Source of the `index.html` of the build directory (relative to the file that declares it).
+    pub index: Option<Utf8PathBuf>,
+    /// Features of the frontend package enabled when building it.
+    pub features: Vec<String>,
+    /// Environment variables of the cargo commands that build the frontend and the backend.
+    pub env: Vec<(String, String)>,
+    /// All the settings, including the ones that are read by other parts of `wasm-run`
+    /// (`redirects`, `proxy`, `pwa`, ...).
+    pub values: Map<String, Value>,
+    profiles: Vec<ProfileSection>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ProfileSection {
+    profile: &'static str,
+    value: Value,
+    base: Utf8PathBuf,
+    source: &'static str,
+}
+
+impl Config {
+    /// Load `WasmRun.toml` (from the frontend package or the root of the workspace) and
+    /// `[package.metadata.wasm-run]` of the frontend package.
+    pub fn load(metadata: &Metadata, frontend_package: &Package) -> Result<Self> {
+        let package_dir = crate::metadata_path(frontend_package.manifest_path.parent().unwrap());
+        let mut config = Self::default();
+        config.merge(
+            &frontend_package.metadata["wasm-run"],
+            package_dir,
+            "Cargo.toml",
+        )?;
+
+        if let Some(config_path) = config_file(metadata, frontend_package) {
+            let content = fs::read_to_string(&config_path)
+                .with_context(|| format!("could not read `{}`", config_path))?;
+            let value: toml::Value = toml::from_str(&content)
+                .with_context(|| format!("could not parse `{}`", config_path))?;
+            log::debug!("Using the configuration file {}", config_path);
+            config.merge(
+                &serde_json::to_value(value)?,
+                config_path.parent().unwrap(),
+                CONFIG_FILE,
+            )?;
+        }
+
+        Ok(config)
+    }
+
+    /// The JSON Schema of the settings of `WasmRun.toml` and `[package.metadata.wasm-run]`. It can
+    /// be used by the editors to complete and check the configuration.
+    pub fn schema() -> Value {
+        config_schema::schema()
+    }
+
+    /// Check `WasmRun.toml` and `[package.metadata.wasm-run]` of the frontend package: the
+    /// unknown settings, the values of the wrong type and the values rejected by the parts of
+    /// `wasm-run` that use them (an invalid redirect, ...) are returned with their location.
+    pub fn validate(metadata: &Metadata, frontend_package: &Package) -> Result<Vec<ConfigIssue>> {
+        let mut sources = Vec::new();
+        let manifest_path = crate::metadata_path(&frontend_package.manifest_path).to_owned();
+        sources.push((
+            fs::read_to_string(&manifest_path)
+                .with_context(|| format!("could not read `{}`", manifest_path))?,
+            manifest_path,
+            &["package", "metadata", "wasm-run"][..],
+            frontend_package.metadata["wasm-run"].clone(),
+        ));
+
+        let mut issues = Vec::new();
+        if let Some(config_path) = config_file(metadata, frontend_package) {
+            let content = fs::read_to_string(&config_path)
+                .with_context(|| format!("could not read `{}`", config_path))?;
+            match toml::from_str::<toml::Value>(&content) {
+                Ok(value) => {
+                    let value = serde_json::to_value(value)?;
+                    sources.push((content, config_path, &[][..], value));
+                }
+                Err(err) => issues.push(ConfigIssue {
+                    line: err
+                        .span()
+                        .map(|x| content[..x.start].matches('\n').count() + 1),
+                    file: config_path,
+                    key: String::new(),
+                    message: err.message().trim().replace('\n', ": "),
+                }),
+            }
+        }
+
+        let schema = Self::schema();
+        for (content, file, root, value) in &sources {
+            if value.is_null() {
+                continue;
+            }
+            let mut errors = Vec::new();
+            config_schema::check(&schema, value, "", &mut errors);
+            issues.extend(errors.into_iter().map(|(key, message)| ConfigIssue {
+                line: config_schema::locate(content, root, &key),
+                file: file.clone(),
+                key,
+                message,
+            }));
+        }
+        if !issues.is_empty() {
+            issues.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+            return Ok(issues);
+        }
+
+        // NOTE: the types are valid, the parsers of the settings check the values
+        let config = Self::load(metadata, frontend_package)?;
+        let mut checks = vec![
+            (
+                "critical_css".to_string(),
+                CriticalCss::from_config(&config).map(drop),
+            ),
+            (
+                "fingerprint".to_string(),
+                Fingerprint::from_config(&config).map(drop),
+            ),
+            (
+                "pwa".to_string(),
+                PwaConfig::from_config(&config, &frontend_package.name).map(drop),
+            ),
+            (
+                "proxy".to_string(),
+                ProxyRule::from_value(config.get("proxy")).map(drop),
+            ),
+            (
+                "external".to_string(),
+                ExternalDependency::from_config(&config).map(drop),
+            ),
+            (
+                "js_replace".to_string(),
+                crate::js_replace::from_config(&config).map(drop),
+            ),
+        ];
+        #[cfg(feature = "webhooks")]
+        checks.push((
+            "webhooks".to_string(),
+            crate::Webhook::from_config(&config).map(drop),
+        ));
+        for (i, redirect) in config
+            .get("redirects")
+            .as_array()
+            .iter()
+            .flat_map(|x| x.iter())
+            .enumerate()
+        {
+            checks.push((
+                format!("redirects[{}]", i),
+                redirect
+                    .as_str()
+                    .unwrap_or_default()
+                    .parse::<Redirect>()
+                    .map(drop)
+                    .map_err(|err| anyhow::anyhow!(err)),
+            ));
+        }
+
+        // NOTE: the target directory is the same for all the profiles
+        for section in &config.profiles {
+            for name in ["CARGO_TARGET_DIR", "CARGO_BUILD_TARGET_DIR"] {
+                if !section.value["env"][name].is_null() {
+                    checks.push((
+                        format!("{}.env.{}", section.profile, name),
+                        Err(anyhow::anyhow!(
+                            "the target directory can only be set in the top-level `env`"
+                        )),
+                    ));
+                }
+            }
+        }
+
+        for (key, result) in checks {
+            if let Err(err) = result {
+                // NOTE: the setting comes from the last file that declares it
+                let name = key.split(['[', '.']).next().unwrap_or_default();
+                let (content, file, root, _) = sources
+                    .iter()
+                    .rev()
+                    .find(|(_, _, _, value)| !value[name].is_null())
+                    .unwrap_or(&sources[0]);
+                issues.push(ConfigIssue {
+                    line: config_schema::locate(content, root, &key),
+                    file: file.clone(),
+                    key,
+                    message: format!("{:#}", err),
+                });
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// A setting by name.
+    pub fn get(&self, key: &str) -> &Value {
+        self.values.get(key).unwrap_or(&Value::Null)
+    }
+
+    /// The settings resolved for a build profile: the section `dev`, `profiling` or `release`
+    /// (`[package.metadata.wasm-run.release]` or `[release]` in `WasmRun.toml`) is merged over
+    /// the other settings.
+    pub fn profile(&self, profile: BuildProfile) -> Self {
+        let name = profile_section(profile);
+        let mut config = self.clone();
+        for section in self.profiles.iter().filter(|x| x.profile == name) {
+            config
+                .merge(&section.value, &section.base, section.source)
+                .expect("the sections are validated when the configuration is loaded; qed");
+        }
+        config.profiles.clear();
+        config
+    }
+
+    /// The settings as they are declared: `WasmRun.toml` merged over the metadata, with the
+    /// sections of the profiles.
+    pub(crate) fn declared(&self) -> Value {
+        let mut values = self.values.clone();
+        for section in &self.profiles {
+            let table = values
+                .entry(section.profile)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let (Value::Object(table), Value::Object(section)) = (table, &section.value) {
+                table.extend(section.clone());
+            }
+        }
+        Value::Object(values)
+    }
+
+    fn merge(&mut self, value: &Value, base: &Utf8Path, source: &'static str) -> Result<()> {
+        let values = match value {
+            Value::Null => return Ok(()),
+            Value::Object(values) => values,
+            _ => anyhow::bail!(
+                "invalid `{}`: the settings of wasm-run must be a table",
+                source
+            ),
+        };
+
+        let string = |value: &Value, key: &str| -> Result<String> {
+            value
+                .as_str()
+                .map(|x| x.to_string())
+                .with_context(|| format!("invalid `{}`: `{}` must be a string", source, key))
+        };
+
+        let number = |value: &Value, key: &str| -> Result<u32> {
+            value
+                .as_u64()
+                .map(|x| x as u32)
+                .with_context(|| format!("invalid `{}`: `{}` must be a number", source, key))
+        };
+
+        for (key, value) in values {
+            match key.as_str() {
+                "dev" | "profiling" | "release" => {
+                    let profile = match key.as_str() {
+                        "dev" => "dev",
+                        "profiling" => "profiling",
+                        _ => "release",
+                    };
+                    let section = ProfileSection {
+                        profile,
+                        value: value.clone(),
+                        base: base.to_path_buf(),
+                        source,
+                    };
+                    Self::default()
+                        .merge(value, base, source)
+                        .with_context(|| format!("invalid section `{}`", key))?;
+                    self.profiles.push(section);
+                    continue;
+                }
+                "base_url" => self.base_url = Some(string(value, key)?),
+                "index" => self.index = Some(base.join(string(value, key)?)),
+                "features" => {
+                    self.features = value
+                        .as_array()
+                        .with_context(|| {
+                            format!("invalid `{}`: `features` must be an array", source)
+                        })?
+                        .iter()
+                        .map(|x| string(x, "features"))
+                        .collect::<Result<_>>()?;
+                }
+                "build_path" | "out_dir" => {
+                    if values.contains_key("build_path") && values.contains_key("out_dir") {
+                        anyhow::bail!(
+                            "invalid `{}`: `out_dir` is an alias of `build_path`, only one can \
+                            be set",
+                            source
+                        );
+                    }
+                    self.build_path = Some(base.join(string(value, key)?));
+                }
+                "port" => {
+                    self.port = Some(
+                        value
+                            .as_u64()
+                            .filter(|x| *x <= u16::MAX as u64)
+                            .with_context(|| {
+                                format!("invalid `{}`: `port` must be a port number", source)
+                            })? as u16,
+                    )
+                }
+                "watch_ignore" => {
+                    self.watch_ignore = value
+                        .as_array()
+                        .with_context(|| {
+                            format!("invalid `{}`: `watch_ignore` must be an array", source)
+                        })?
+                        .iter()
+                        .map(|x| string(x, "watch_ignore"))
+                        .collect::<Result<_>>()?;
+                }
+                "wasm_opt" => {
+                    let table = value.as_object().with_context(|| {
+                        format!("invalid `{}`: `wasm_opt` must be a table", source)
+                    })?;
+                    for (key, value) in table {
+                        let name = format!("wasm_opt.{}", key);
+                        match key.as_str() {
+                            "version" => self.wasm_opt_version = Some(string(value, &name)?),
+                            "sha256" => self.wasm_opt_sha256 = Some(string(value, &name)?),
+                            "mirror" => self.wasm_opt_mirror = Some(string(value, &name)?),
+                            "level" => self.wasm_opt_level = Some(number(value, &name)?),
+                            "shrink_level" => {
+                                self.wasm_opt_shrink_level = Some(number(value, &name)?)
+                            }
+                            _ => {
+                                log::warn!("Unknown setting `wasm_opt.{}` in `{}`", key, source);
+                            }
+                        }
+                    }
+                }
+                "env" => {
+                    let table = value
+                        .as_object()
+                        .with_context(|| format!("invalid `{}`: `env` must be a table", source))?;
+                    for (name, value) in table {
+                        let value = match value {
+                            Value::String(x) => x.clone(),
+                            Value::Number(_) | Value::Bool(_) => value.to_string(),
+                            _ => anyhow::bail!(
+                                "invalid `{}`: `env.{}` must be a string",
+                                source,
+                                name
+                            ),
+                        };
+                        self.env.retain(|(x, _)| x != name);
+                        self.env.push((name.clone(), value));
+                    }
+                }
+                _ => {}
+            }
+            self.values.insert(key.clone(), value.clone());
+        }
+
+        Ok(())
+    }
+}
+
+/// The file `WasmRun.toml` of the frontend package or of the root of the workspace.
+fn config_file(metadata: &Metadata, frontend_package: &Package) -> Option<Utf8PathBuf> {
+    let package_dir = frontend_package.manifest_path.parent().unwrap();
+    [package_dir, metadata.workspace_root.as_path()]
+        .iter()
+        .map(|x| crate::metadata_path(x).join(CONFIG_FILE))
+        .find(|x| x.exists())
+}
+
+fn profile_section(profile: BuildProfile) -> &'static str {
+    match profile {
+        BuildProfile::Dev => "dev",
+        BuildProfile::Profiling => "profiling",
+        BuildProfile::Release => "release",
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{BuildArgs, BuildProfile, Config, DefaultBuildArgs, WorkspaceContext};
+use anyhow::{bail, Result};
+use cargo_metadata::{Metadata, Package};
+use serde_json::{json, Value};
+use structopt::StructOpt;
+
+/// Arguments of the `config` command: check and display the configuration of wasm-run
+/// (`WasmRun.toml` and `[package.metadata.wasm-run]` of the frontend package, check [`Config`]).
+#[derive(StructOpt, Debug)]
+pub struct ConfigArgs {
+    /// Subcommand.
+    #[structopt(subcommand)]
+    pub command: ConfigCommand,
+}
+
+/// Subcommands of the `config` command.
+#[derive(StructOpt, Debug)]
+pub enum ConfigCommand {
+    /// Check the configuration: every unknown setting and invalid value is reported with its
+    /// file and line.
+    Validate,
+    /// Print the settings as JSON.
+    Print(Box<ConfigPrintArgs>),
+    /// Print the JSON Schema of the configuration (for the completion and the validation in the
+    /// editors).
+    Schema,
+}
+
+/// Arguments of `config print`.
+#[derive(StructOpt, Debug)]
+pub struct ConfigPrintArgs {
+    /// Print the settings resolved for a build profile: the section of the profile, the
+    /// environment variables and the command-line arguments are applied.
+    #[structopt(long)]
+    pub resolved: bool,
+
+    /// Build profile of the resolved settings.
+    #[structopt(
+        long,
+        default_value = "release",
+        possible_values = &["dev", "profiling", "release"],
+    )]
+    pub profile: String,
+
+    /// Build arguments (for the resolved settings).
+    #[structopt(flatten)]
+    pub build_args: DefaultBuildArgs,
+}
+
+impl ConfigArgs {
+    /// Run the `config` command.
+    pub fn run(self) -> Result<()> {
+        match self.command {
+            ConfigCommand::Validate => {
+                let context = WorkspaceContext::global();
+                validate(context.metadata(), context.frontend_package())?;
+            }
+            ConfigCommand::Print(args) => {
+                let value = if args.resolved {
+                    let profile = match args.profile.as_str() {
+                        "dev" => BuildProfile::Dev,
+                        "profiling" => BuildProfile::Profiling,
+                        _ => BuildProfile::Release,
+                    };
+                    resolve(&args.build_args, profile)?
+                } else {
+                    args.build_args.config().declared()
+                };
+                println!("{}", serde_json::to_string_pretty(&value)?);
+            }
+            ConfigCommand::Schema => {
+                println!("{}", serde_json::to_string_pretty(&Config::schema())?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Report the issues of the configuration of `frontend_package`. It is also run before the
+/// workspace context is initialized (which fails if the configuration is invalid).
+pub(crate) fn validate(metadata: &Metadata, frontend_package: &Package) -> Result<()> {
+    let issues = Config::validate(metadata, frontend_package)?;
+    for issue in &issues {
+        log::error!("{}", issue);
+    }
+    if !issues.is_empty() {
+        bail!(
+            "the configuration is invalid ({} error{})",
+            issues.len(),
+            if issues.len() == 1 { "" } else { "s" }
+        );
+    }
+    log::info!("The configuration is valid");
+    Ok(())
+}
+
+/// The settings used by a build: the ones of the profile where the values overridden by the
+/// environment and the command-line are replaced by the effective ones.
+fn resolve(args: &dyn BuildArgs, profile: BuildProfile) -> Result<Value> {
+    let config = args.profile_config(profile);
+    let mut settings = config.values.clone();
+
+    settings.insert("build_path".to_string(), json!(args.build_path()));
+    settings.insert(
+        "env".to_string(),
+        Value::Object(
+            config
+                .env
+                .iter()
+                .map(|(name, value)| (name.clone(), json!(value)))
+                .collect(),
+        ),
+    );
+
+    let fingerprint = args.fingerprint()?;
+    settings.insert(
+        "fingerprint".to_string(),
+        json!({
+            "algorithm": fingerprint.algorithm.to_string(),
+            "length": fingerprint.length,
+            "assets": args.fingerprint_assets(profile),
+        }),
+    );
+    settings.insert(
+        "critical_css".to_string(),
+        match args.critical_css()? {
+            Some(critical_css) => json!({
+                "selectors": critical_css.selectors,
+                "inline_size": critical_css.inline_size,
+            }),
+            None => json!(false),
+        },
+    );
+
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    {
+        let mut wasm_opt = match settings.remove("wasm_opt") {
+            Some(Value::Object(table)) => table,
+            _ => serde_json::Map::new(),
+        };
+        wasm_opt.insert("version".to_string(), json!(args.wasm_opt_version()));
+        for (key, value) in [
+            ("sha256", args.wasm_opt_sha256()),
+            ("mirror", args.wasm_opt_mirror()),
+        ] {
+            match value {
+                Some(value) => wasm_opt.insert(key.to_string(), json!(value)),
+                None => wasm_opt.remove(key),
+            };
+        }
+        settings.insert("wasm_opt".to_string(), Value::Object(wasm_opt));
+    }
+
+    #[cfg(feature = "webhooks")]
+    settings.insert(
+        "webhooks".to_string(),
+        Value::Array(
+            args.webhooks()?
+                .into_iter()
+                .map(|webhook| {
+                    let mut on = Vec::new();
+                    if webhook.on_success {
+                        on.push("success");
+                    }
+                    if webhook.on_failure {
+                        on.push("failure");
+                    }
+                    json!({ "url": webhook.url, "on": on })
+                })
+                .collect(),
+        ),
+    );
+
+    Ok(Value::Object(settings))
+}
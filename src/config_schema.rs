@@ -0,0 +1,429 @@
+use camino::Utf8PathBuf;
+use serde_json::{json, Map, Value};
+use std::fmt;
+
+/// A problem found in a configuration file by [`Config::validate`](crate::Config::validate).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    /// File that declares the setting (`WasmRun.toml` or the `Cargo.toml` of the frontend).
+    pub file: Utf8PathBuf,
+    /// Line of the setting in the file, if it could be found.
+    pub line: Option<usize>,
+    /// Path of the setting (for example `wasm_opt.level` or `webhooks[1].on`).
+    pub key: String,
+    /// What is wrong with the setting.
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if self.key.is_empty() {
+            write!(f, ": {}", self.message)
+        } else {
+            write!(f, ": `{}`: {}", self.key, self.message)
+        }
+    }
+}
+
+fn strings() -> Value {
+    json!({ "type": "array", "items": { "type": "string" } })
+}
+
+/// The settings that can be used at the top level and in the profile sections.
+fn settings() -> Map<String, Value> {
+    let properties = json!({
+        "build_path": {
+            "type": "string",
+            "description": "Default output directory (relative to the file that declares it).",
+        },
+        "out_dir": {
+            "type": "string",
+            "description": "Alias of `build_path`.",
+        },
+        "port": {
+            "type": "integer",
+            "minimum": 0,
+            "maximum": 65535,
+            "description": "Default port of the development server.",
+        },
+        "overlay": {
+            "type": "boolean",
+            "description": "Display the build errors in an overlay in the browser (`serve`).",
+        },
+        "watch_ignore": {
+            "description": "Patterns (`.gitignore` syntax) of the files whose changes are ignored.",
+            "type": "array",
+            "items": { "type": "string" },
+        },
+        "wasm_opt": {
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "version": { "type": "string", "description": "Version of binaryen." },
+                "sha256": { "type": "string", "description": "SHA-256 of the binaryen archive." },
+                "mirror": { "type": "string", "description": "Mirror of the binaryen releases." },
+                "level": { "type": "integer", "minimum": 0, "maximum": 4 },
+                "shrink_level": { "type": "integer", "minimum": 0, "maximum": 2 },
+            },
+        },
+        "base_url": {
+            "type": "string",
+            "description": "URL path prefix of the artifacts (for example `/my-app/`).",
+        },
+        "index": {
+            "type": "string",
+            "description": "Source of the `index.html` (relative to the file that declares it).",
+        },
+        "features": strings(),
+        "env": {
+            "type": "object",
+            "description": "Environment variables of the cargo commands.",
+            "additionalProperties": { "type": ["string", "number", "boolean"] },
+        },
+        "redirects": {
+            "description": "Redirections of the development server (`<from> -> <to> [status]`).",
+            "type": "array",
+            "items": { "type": "string" },
+        },
+        "proxy": {
+            "description": "Path prefixes forwarded by the development server to `http://` URLs.",
+            "type": "object",
+            "additionalProperties": { "type": "string" },
+        },
+        "host_globals": strings(),
+        "js_replace": {
+            "description": "Replacements in the JS generated by wasm-bindgen.",
+            "type": "array",
+            "items": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["from", "to"],
+                "properties": {
+                    "from": { "type": "string" },
+                    "to": { "type": "string" },
+                },
+            },
+        },
+        "critical_css": {
+            "anyOf": [
+                { "type": "boolean" },
+                {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "selectors": strings(),
+                        "inline_size": { "type": "integer", "minimum": 0 },
+                    },
+                },
+            ],
+        },
+        "fingerprint": {
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "algorithm": { "enum": ["blake3", "sha256", "xxh3"] },
+                "length": { "type": "integer", "minimum": 1 },
+                "assets": { "type": "boolean" },
+            },
+        },
+        "external": {
+            "description": "JavaScript dependencies loaded from a CDN by the `index.html`.",
+            "type": "array",
+            "items": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["url"],
+                "properties": {
+                    "name": { "type": "string", "description": "Specifier of the import map." },
+                    "url": { "type": "string" },
+                    "integrity": { "type": "string", "description": "Subresource Integrity." },
+                    "module": { "type": "boolean" },
+                },
+            },
+        },
+        "webhooks": {
+            "type": "array",
+            "items": {
+                "anyOf": [
+                    { "type": "string" },
+                    {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["url"],
+                        "properties": {
+                            "url": { "type": "string" },
+                            "on": {
+                                "type": "array",
+                                "items": { "enum": ["success", "failure"] },
+                            },
+                        },
+                    },
+                ],
+            },
+        },
+    });
+    match properties {
+        Value::Object(properties) => properties,
+        _ => unreachable!(),
+    }
+}
+
+/// The progressive web app, only declared at the top level.
+fn pwa() -> Value {
+    json!({
+        "type": "object",
+        "description": "Web app manifest and service worker.",
+        "additionalProperties": false,
+        "properties": {
+            "name": { "type": "string" },
+            "short_name": { "type": "string" },
+            "description": { "type": "string" },
+            "start_url": { "type": "string" },
+            "display": { "type": "string" },
+            "background_color": { "type": "string" },
+            "theme_color": { "type": "string" },
+            "icons": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "required": ["src", "sizes"],
+                    "properties": {
+                        "src": { "type": "string" },
+                        "sizes": { "type": "string" },
+                        "type": { "type": "string" },
+                    },
+                },
+            },
+            "precache_exclude": strings(),
+        },
+    })
+}
+
+pub(crate) fn schema() -> Value {
+    let profile = json!({
+        "type": "object",
+        "additionalProperties": false,
+        "properties": settings(),
+    });
+    let mut properties = settings();
+    for name in &["dev", "profiling", "release"] {
+        properties.insert(name.to_string(), profile.clone());
+    }
+    properties.insert("pwa".to_owned(), pwa());
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "wasm-run",
+        "description": "Settings of wasm-run (`WasmRun.toml` or `[package.metadata.wasm-run]`).",
+        "type": "object",
+        "additionalProperties": false,
+        "properties": properties,
+    })
+}
+
+/// Check `value` against the subset of JSON Schema used by [`schema`]. The errors are returned
+/// with the path of the invalid value.
+pub(crate) fn check(schema: &Value, value: &Value, key: &str, errors: &mut Vec<(String, String)>) {
+    if let Some(choices) = schema.get("anyOf").and_then(Value::as_array) {
+        let mut candidates = Vec::new();
+        for choice in choices {
+            let mut choice_errors = Vec::new();
+            check(choice, value, key, &mut choice_errors);
+            if choice_errors.is_empty() {
+                return;
+            }
+            candidates.push(choice_errors);
+        }
+        // NOTE: the errors of the choice of the right type are the most precise ones
+        match candidates
+            .into_iter()
+            .find(|x| x.iter().all(|(x, _)| x != key))
+        {
+            Some(choice_errors) => errors.extend(choice_errors),
+            None => errors.push((key.to_owned(), format!("expected {}", describe(schema)))),
+        }
+        return;
+    }
+
+    if let Some(choices) = schema.get("enum").and_then(Value::as_array) {
+        if !choices.contains(value) {
+            errors.push((key.to_owned(), format!("expected {}", describe(schema))));
+        }
+        return;
+    }
+
+    if let Some(types) = schema.get("type") {
+        let matches = |ty: &Value| match ty.as_str() {
+            Some("string") => value.is_string(),
+            Some("integer") => value.is_u64() || value.is_i64(),
+            Some("number") => value.is_number(),
+            Some("boolean") => value.is_boolean(),
+            Some("array") => value.is_array(),
+            Some("object") => value.is_object(),
+            _ => false,
+        };
+        let ok = match types {
+            Value::Array(types) => types.iter().any(matches),
+            ty => matches(ty),
+        };
+        if !ok {
+            errors.push((key.to_owned(), format!("expected {}", describe(schema))));
+            return;
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        let minimum = schema.get("minimum").and_then(Value::as_f64);
+        let maximum = schema.get("maximum").and_then(Value::as_f64);
+        if minimum.is_some_and(|x| number < x) || maximum.is_some_and(|x| number > x) {
+            errors.push((
+                key.to_owned(),
+                format!(
+                    "expected a number between {} and {}",
+                    minimum.map_or("-".to_owned(), |x| x.to_string()),
+                    maximum.map_or("-".to_owned(), |x| x.to_string()),
+                ),
+            ));
+        }
+    }
+
+    match value {
+        Value::Array(items) => {
+            if let Some(schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(schema, item, &format!("{}[{}]", key, i), errors);
+                }
+            }
+        }
+        Value::Object(table) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for required in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                if !table.contains_key(required) {
+                    errors.push((key.to_owned(), format!("missing `{}`", required)));
+                }
+            }
+            for (name, value) in table {
+                let child = if key.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", key, name)
+                };
+                match (
+                    properties.and_then(|x| x.get(name)),
+                    schema.get("additionalProperties"),
+                ) {
+                    (Some(schema), _) => check(schema, value, &child, errors),
+                    (None, Some(Value::Bool(false))) => {
+                        errors.push((child, "unknown setting".to_owned()))
+                    }
+                    (None, Some(schema)) if schema.is_object() => {
+                        check(schema, value, &child, errors)
+                    }
+                    (None, _) => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn describe(schema: &Value) -> String {
+    if let Some(choices) = schema.get("anyOf").and_then(Value::as_array) {
+        return choices
+            .iter()
+            .map(describe)
+            .collect::<Vec<_>>()
+            .join(" or ");
+    }
+    if let Some(choices) = schema.get("enum").and_then(Value::as_array) {
+        return format!(
+            "one of {}",
+            choices
+                .iter()
+                .map(|x| format!("`{}`", x.as_str().unwrap_or_default()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    let describe_type = |ty: &str| match ty {
+        "string" => "a string",
+        "integer" => "an integer",
+        "number" => "a number",
+        "boolean" => "a boolean",
+        "array" => "an array",
+        "object" => "a table",
+        _ => "a value",
+    };
+    match schema.get("type") {
+        Some(Value::String(ty)) => describe_type(ty).to_owned(),
+        Some(Value::Array(types)) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .map(describe_type)
+            .collect::<Vec<_>>()
+            .join(" or "),
+        _ => "a value".to_owned(),
+    }
+}
+
+/// Line (1-based) of the setting `key` (a path like `wasm_opt.level` or `webhooks[1]`) in the
+/// TOML `content`, where the settings are in the table `root` (empty for `WasmRun.toml`). The
+/// line of the closest parent that can be found is used for the values of inline tables and
+/// arrays.
+pub(crate) fn locate(content: &str, root: &[&str], key: &str) -> Option<usize> {
+    let wanted = root
+        .iter()
+        .map(|x| x.to_string())
+        .chain(
+            key.split('.')
+                .map(|x| x.split('[').next().unwrap_or(x).to_owned()),
+        )
+        .collect::<Vec<_>>();
+
+    let mut table = Vec::new();
+    let mut best: Option<(usize, usize)> = None;
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        let path = if let Some(header) = line.strip_prefix('[') {
+            let header = header.trim_start_matches('[');
+            let header = header.split(']').next().unwrap_or_default();
+            table = split_key(header);
+            table.clone()
+        } else if let Some((name, _)) = line.split_once('=') {
+            if line.starts_with('#') {
+                continue;
+            }
+            table.iter().cloned().chain(split_key(name)).collect()
+        } else {
+            continue;
+        };
+
+        let len = path.len();
+        if len <= wanted.len()
+            && wanted[..len] == path[..]
+            && len > root.len()
+            && best.is_none_or(|(_, x)| len > x)
+        {
+            best = Some((i + 1, len));
+        }
+    }
+
+    best.map(|(line, _)| line)
+}
+
+fn split_key(key: &str) -> Vec<String> {
+    key.split('.')
+        .map(|x| x.trim().trim_matches('"').trim_matches('\'').to_owned())
+        .collect()
+}
@@ -0,0 +1,136 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tide::{Middleware, Next, Request, Server};
+
+/// A container of typed state shared by the endpoints of the development server.
+///
+/// The state is initialized in the hook [`Hooks::serve_init`](crate::Hooks::serve_init) and can
+/// be retrieved in the endpoints with [`ServeContext::from_request`]:
+///
+/// ```ignore
+/// fn serve_init(_args: &DefaultServeArgs, context: &mut ServeContext) -> anyhow::Result<()> {
+///     context.insert(MockDatabase::new());
+///     Ok(())
+/// }
+///
+/// fn serve(_args: &DefaultServeArgs, server: &mut Server<()>) -> anyhow::Result<()> {
+///     server.at("/api/users").get(|req: Request<()>| async move {
+///         let db = ServeContext::from_request(&req).state::<MockDatabase>().unwrap();
+///         Ok(db.users())
+///     });
+///     Ok(())
+/// }
+/// ```
+#[derive(Default)]
+pub struct ServeContext {
+    state: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    wrapper: Option<Wrapper>,
+}
+
+type Wrapper = Box<dyn FnOnce(Server<()>) -> Server<()> + Send + Sync>;
+
+impl ServeContext {
+    /// Store a value. It replaces the value of the same type if there was one.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.state
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|x| x.downcast().ok())
+            .map(|x| *x)
+    }
+
+    /// Get the value of this type.
+    pub fn state<T: 'static>(&self) -> Option<&T> {
+        self.state
+            .get(&TypeId::of::<T>())
+            .and_then(|x| x.downcast_ref())
+    }
+
+    /// Get the value of this type mutably. This is only possible during the initialization.
+    pub fn state_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.state
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|x| x.downcast_mut())
+    }
+
+    /// Serve the development server through `server`: the routes of the development server are
+    /// mounted at the root of `server`, after its own routes and behind its own middlewares.
+    ///
+    /// The hook [`Hooks::serve`](crate::Hooks::serve) only receives a `Server<()>`: this allows
+    /// the endpoints of the development server to use their own state, sessions and middlewares:
+    ///
+    /// ```ignore
+    /// fn serve_init(_args: &DefaultServeArgs, context: &mut ServeContext) -> anyhow::Result<()> {
+    ///     let mut server = tide::with_state(AppState::new());
+    ///     server.with(tide::sessions::SessionMiddleware::new(MemoryStore::new(), SECRET));
+    ///     server.at("/api/users").get(|req: Request<AppState>| async move {
+    ///         Ok(req.state().users())
+    ///     });
+    ///     context.wrap_server(server);
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// It replaces the server given previously if there was one.
+    pub fn wrap_server<S: Clone + Send + Sync + 'static>(&mut self, mut server: Server<S>) {
+        self.wrapper = Some(Box::new(move |app: Server<()>| {
+            server.at("/").all(app.clone());
+            server.at("/*").all(app);
+            let mut outer = tide::new();
+            outer.at("/").all(server.clone());
+            outer.at("/*").all(server);
+            outer
+        }));
+    }
+
+    /// Get the context of the development server from a request.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the request has not been received by the development server of `wasm-run`.
+    pub fn from_request<State>(req: &Request<State>) -> &ServeContext {
+        req.ext::<Arc<ServeContext>>()
+            .expect("the request has not been received by the development server")
+    }
+}
+
+impl fmt::Debug for ServeContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ServeContext")
+            .field("state", &self.state.len())
+            .field("wrapper", &self.wrapper.is_some())
+            .finish()
+    }
+}
+
+/// Middleware that makes the context available to all the requests of the server.
+struct ContextMiddleware(Arc<ServeContext>);
+
+#[tide::utils::async_trait]
+impl Middleware<()> for ContextMiddleware {
+    async fn handle(&self, mut req: Request<()>, next: Next<'_, ()>) -> tide::Result {
+        req.set_ext(Arc::clone(&self.0));
+        Ok(next.run(req).await)
+    }
+}
+
+/// Register the context on the server. The function returned mounts the server in the one given
+/// to [`ServeContext::wrap_server`] if there is one, once all the routes have been added.
+pub(crate) fn register(
+    server: &mut Server<()>,
+    mut context: ServeContext,
+) -> impl FnOnce(Server<()>) -> Server<()> {
+    let wrapper = context.wrapper.take();
+    let context = Arc::new(context);
+    server.with(ContextMiddleware(Arc::clone(&context)));
+    move |server| match wrapper {
+        Some(wrapper) => {
+            // NOTE: the endpoints of the wrapper can also get the context
+            let mut server = wrapper(server);
+            server.with(ContextMiddleware(context));
+            server
+        }
+        None => server,
+    }
+}
@@ -0,0 +1,486 @@
+use crate::{find_ignore_case, BuildArgs, Config};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::fs;
+
+/// Inlining of the critical CSS in the `index.html` of the build directory.
+///
+/// Every local stylesheet linked by the `index.html` (`<link rel="stylesheet" href="...">`) is
+/// processed after the `post_build` hook compiled the styles:
+///
+///  *  if the stylesheet is smaller than `inline_size`, it is entirely inlined in a `<style>`;
+///  *  otherwise the rules matching the `selectors` (and the `@media` and `@supports` blocks
+///     containing them) are inlined and the stylesheet is loaded without blocking the rendering.
+///
+/// A selector of the allowlist matches the selectors that start with it: `.header` matches
+/// `.header`, `.header .logo` and `.header:hover` but not `.header-title`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CriticalCss {
+    /// Selectors of the above-the-fold rules.
+    pub selectors: Vec<String>,
+    /// Stylesheets smaller than this size (in bytes) are entirely inlined.
+    pub inline_size: usize,
+}
+
+impl Default for CriticalCss {
+    fn default() -> Self {
+        Self {
+            selectors: Vec::new(),
+            inline_size: 8192,
+        }
+    }
+}
+
+impl CriticalCss {
+    /// Inline the stylesheets smaller than 8 KiB and nothing of the other ones.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inline the rules matching this selector.
+    pub fn selector(mut self, selector: impl Into<String>) -> Self {
+        self.selectors.push(selector.into());
+        self
+    }
+
+    /// Entirely inline the stylesheets smaller than this size (in bytes).
+    pub fn inline_size(mut self, inline_size: usize) -> Self {
+        self.inline_size = inline_size;
+        self
+    }
+
+    /// Read `critical_css` of the configuration: `true` or a table with the keys `selectors` and
+    /// `inline_size`.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let value = config.get("critical_css");
+        let table = match value {
+            Value::Null | Value::Bool(false) => return Ok(None),
+            Value::Bool(true) => return Ok(Some(Self::default())),
+            Value::Object(table) => table,
+            _ => anyhow::bail!("invalid `critical_css`: expected a boolean or a table"),
+        };
+
+        let mut critical_css = Self::default();
+        if let Some(selectors) = table.get("selectors") {
+            critical_css.selectors = selectors
+                .as_array()
+                .and_then(|x| x.iter().map(|x| x.as_str().map(String::from)).collect())
+                .context("invalid `critical_css.selectors`: expected an array of strings")?;
+        }
+        if let Some(inline_size) = table.get("inline_size") {
+            critical_css.inline_size = inline_size
+                .as_u64()
+                .context("invalid `critical_css.inline_size`: expected a number of bytes")?
+                as usize;
+        }
+
+        Ok(Some(critical_css))
+    }
+}
+
+/// Inline the critical CSS of the stylesheets linked by the `index.html` of the build directory.
+pub(crate) fn inline(args: &dyn BuildArgs, options: &CriticalCss) -> Result<()> {
+    let index_path = args.build_path().join("index.html");
+    let html = match fs::read_to_string(&index_path) {
+        Ok(html) => html,
+        Err(_) => return Ok(()),
+    };
+
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html.as_str();
+    let mut count = 0;
+    while let Some(start) = find_ignore_case(rest, "<link") {
+        let end = match rest[start..].find('>') {
+            Some(i) => start + i + 1,
+            None => break,
+        };
+        let tag = &rest[start..end];
+        output.push_str(&rest[..start]);
+        rest = &rest[end..];
+
+        let href = match stylesheet_href(tag) {
+            Some(href) => href,
+            None => {
+                output.push_str(tag);
+                continue;
+            }
+        };
+        let file = href.split(['?', '#']).next().unwrap();
+        let css_path = args.build_path().join(file.trim_start_matches('/'));
+        let css = match fs::read_to_string(&css_path) {
+            Ok(css) => css,
+            Err(err) => {
+                log::warn!(
+                    "Could not read the stylesheet `{}` for the critical CSS: {}",
+                    css_path,
+                    err
+                );
+                output.push_str(tag);
+                continue;
+            }
+        };
+        let base_url = match file.rfind('/') {
+            Some(i) => &file[..=i],
+            None => "",
+        };
+
+        if css.len() <= options.inline_size {
+            output.push_str(&format!("<style>{}</style>", rebase_urls(&css, base_url)));
+        } else if !options.selectors.is_empty() {
+            let critical = extract(&css, &options.selectors);
+            output.push_str(&format!(
+                "<style>{}</style><link rel=\"preload\" href=\"{href}\" as=\"style\" \
+                onload=\"this.onload=null;this.rel='stylesheet'\">\
+                <noscript><link rel=\"stylesheet\" href=\"{href}\"></noscript>",
+                rebase_urls(&critical, base_url),
+                href = href,
+            ));
+        } else {
+            output.push_str(tag);
+            continue;
+        }
+        count += 1;
+    }
+    output.push_str(rest);
+
+    if count > 0 {
+        log::info!("Inlined the critical CSS of {} stylesheet(s)", count);
+        fs::write(&index_path, output)
+            .with_context(|| format!("could not write `{}`", index_path))?;
+    }
+
+    Ok(())
+}
+
+/// The `href` of a `<link>` tag if it links a local stylesheet for all the media.
+fn stylesheet_href(tag: &str) -> Option<String> {
+    let rel = attribute(tag, "rel")?;
+    if !rel.eq_ignore_ascii_case("stylesheet") || attribute(tag, "media").is_some() {
+        return None;
+    }
+    let href = attribute(tag, "href")?;
+    if href.starts_with("//") || href.contains("://") || href.starts_with("data:") {
+        return None;
+    }
+    Some(href)
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag.trim_start_matches('<').trim_end_matches('>');
+    rest = rest.trim_start_matches(|c: char| c.is_ascii_alphanumeric());
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return None;
+        }
+        let key_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = rest[key_end..].trim_start();
+        let value = if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (value, len) = match after.chars().next() {
+                Some(quote @ '"') | Some(quote @ '\'') => match after[1..].find(quote) {
+                    Some(i) => (&after[1..=i], i + 2),
+                    None => (&after[1..], after.len()),
+                },
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], end)
+                }
+            };
+            rest = &after[len..];
+            value
+        } else {
+            ""
+        };
+        if key.eq_ignore_ascii_case(name) {
+            return Some(value.to_string());
+        }
+    }
+}
+
+/// Prefix the relative `url()` of a stylesheet with its directory so they still resolve once
+/// the CSS is inlined in the `index.html` at the root of the build directory.
+fn rebase_urls(css: &str, base_url: &str) -> String {
+    if base_url.is_empty() {
+        return css.to_string();
+    }
+
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(i) = rest.find("url(") {
+        output.push_str(&rest[..i + 4]);
+        rest = &rest[i + 4..];
+        let trimmed = rest.trim_start();
+        output.push_str(&rest[..rest.len() - trimmed.len()]);
+        rest = trimmed;
+        let quote = match rest.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => {
+                output.push(quote);
+                rest = &rest[1..];
+                Some(quote)
+            }
+            _ => None,
+        };
+        let end = rest
+            .find(|c| Some(c) == quote || c == ')')
+            .unwrap_or(rest.len());
+        let url = &rest[..end];
+        if !(url.starts_with('/')
+            || url.starts_with('#')
+            || url.starts_with("data:")
+            || url.contains("://"))
+        {
+            output.push_str(base_url);
+        }
+        output.push_str(url);
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+enum Item<'a> {
+    Statement,
+    Block { prelude: &'a str, body: &'a str },
+}
+
+/// Split a stylesheet in its top-level statements (`@import ...;`) and blocks (rules and at-rules
+/// with their body).
+fn parse(css: &str) -> Vec<Item<'_>> {
+    let bytes = css.as_bytes();
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    let mut depth = 0;
+    let mut body_start = 0;
+    let mut prelude = "";
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = css[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |x| i + 2 + x + 2);
+                if depth == 0 && css[start..i].trim().is_empty() {
+                    start = end;
+                }
+                i = end;
+                continue;
+            }
+            quote @ b'"' | quote @ b'\'' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'{' => {
+                if depth == 0 {
+                    prelude = css[start..i].trim();
+                    body_start = i + 1;
+                }
+                depth += 1;
+            }
+            b'}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    items.push(Item::Block {
+                        prelude,
+                        body: &css[body_start..i],
+                    });
+                    start = i + 1;
+                }
+            }
+            b';' if depth == 0 => {
+                items.push(Item::Statement);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    items
+}
+
+/// The rules of `css` matching the selectors of the allowlist.
+fn extract(css: &str, allowlist: &[String]) -> String {
+    let mut output = String::new();
+    for item in parse(css) {
+        match item {
+            // NOTE: `@charset` and `@import` are not allowed or useful in an inline style
+            Item::Statement => {}
+            Item::Block { prelude, body } => {
+                if prelude.starts_with("@media") || prelude.starts_with("@supports") {
+                    let inner = extract(body, allowlist);
+                    if !inner.is_empty() {
+                        output.push_str(&format!("{}{{{}}}", prelude, inner));
+                    }
+                } else if !prelude.starts_with('@') {
+                    let selectors = prelude
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|x| allowlist.iter().any(|allowed| matches(x, allowed)))
+                        .collect::<Vec<_>>();
+                    if !selectors.is_empty() {
+                        output.push_str(&format!("{}{{{}}}", selectors.join(","), body.trim()));
+                    }
+                }
+            }
+        }
+    }
+    output
+}
+
+fn matches(selector: &str, allowed: &str) -> bool {
+    match selector.strip_prefix(allowed) {
+        Some(rest) => rest
+            .chars()
+            .next()
+            .is_none_or(|c| !(c.is_ascii_alphanumeric() || c == '-' || c == '_')),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(value: Value) -> Config {
+        let mut config = Config::default();
+        config.values.insert("critical_css".to_string(), value);
+        config
+    }
+
+    #[test]
+    fn from_config() {
+        assert_eq!(CriticalCss::from_config(&Config::default()).unwrap(), None);
+        assert_eq!(
+            CriticalCss::from_config(&config(json!(false))).unwrap(),
+            None
+        );
+        assert_eq!(
+            CriticalCss::from_config(&config(json!(true))).unwrap(),
+            Some(CriticalCss::new())
+        );
+        assert_eq!(
+            CriticalCss::from_config(&config(json!({
+                "selectors": [".header", "nav"],
+                "inline_size": 1024,
+            })))
+            .unwrap(),
+            Some(
+                CriticalCss::new()
+                    .selector(".header")
+                    .selector("nav")
+                    .inline_size(1024)
+            )
+        );
+        for value in [
+            json!("yes"),
+            json!({ "selectors": ".header" }),
+            json!({ "selectors": [1] }),
+            json!({ "inline_size": "8k" }),
+        ] {
+            assert!(CriticalCss::from_config(&config(value)).is_err());
+        }
+    }
+
+    #[test]
+    fn attributes() {
+        let tag = r#"<link rel="stylesheet" href='/style.css?v=1' data-x=a disabled>"#;
+        assert_eq!(attribute(tag, "rel").as_deref(), Some("stylesheet"));
+        assert_eq!(attribute(tag, "HREF").as_deref(), Some("/style.css?v=1"));
+        assert_eq!(attribute(tag, "data-x").as_deref(), Some("a"));
+        assert_eq!(attribute(tag, "disabled").as_deref(), Some(""));
+        assert_eq!(attribute(tag, "media"), None);
+        assert_eq!(
+            attribute("<link href = \"a.css\"/>", "href").as_deref(),
+            Some("a.css")
+        );
+        assert_eq!(
+            attribute("<link href=\"a.css", "href").as_deref(),
+            Some("a.css")
+        );
+    }
+
+    #[test]
+    fn stylesheet_hrefs() {
+        let href = stylesheet_href;
+        assert_eq!(
+            href(r#"<link rel="stylesheet" href="style.css">"#).as_deref(),
+            Some("style.css")
+        );
+        assert_eq!(
+            href(r#"<LINK REL=StyleSheet HREF=/css/app.css>"#).as_deref(),
+            Some("/css/app.css")
+        );
+        assert_eq!(href(r#"<link rel="icon" href="favicon.ico">"#), None);
+        assert_eq!(
+            href(r#"<link rel="stylesheet" href="print.css" media="print">"#),
+            None
+        );
+        assert_eq!(href(r#"<link rel="stylesheet">"#), None);
+        for remote in [
+            "https://cdn.example.com/a.css",
+            "//cdn.example.com/a.css",
+            "data:text/css,a{}",
+        ] {
+            let tag = format!(r#"<link rel="stylesheet" href="{}">"#, remote);
+            assert_eq!(href(&tag), None);
+        }
+    }
+
+    #[test]
+    fn rebase_relative_urls() {
+        let css = r#"a{background:url(img/a.png)}b{background:url( "b.png" )}
+            c{background:url('/abs.png')}d{background:url(data:image/png;base64,AA)}
+            e{background:url(https://example.com/e.png)}f{filter:url(#svg)}"#;
+        assert_eq!(rebase_urls(css, ""), css);
+        assert_eq!(
+            rebase_urls(css, "css/"),
+            r#"a{background:url(css/img/a.png)}b{background:url( "css/b.png" )}
+            c{background:url('/abs.png')}d{background:url(data:image/png;base64,AA)}
+            e{background:url(https://example.com/e.png)}f{filter:url(#svg)}"#
+        );
+    }
+
+    #[test]
+    fn extract_critical_rules() {
+        let css = r#"@charset "utf-8";
+            @import url("other.css");
+            /* .header { color: red } */
+            .header, .footer { color: blue; }
+            .header-title { color: green }
+            .header .logo:hover { content: "}" }
+            @media (max-width: 600px) { .header { display: none } .footer { margin: 0 } }
+            @media print { .footer { display: none } }
+            @font-face { font-family: x; src: url(x.woff) }
+            nav > a { color: black }"#;
+        let allowlist = [".header".to_string(), "nav".to_string()];
+        assert_eq!(
+            extract(css, &allowlist),
+            ".header{color: blue;}\
+            .header .logo:hover{content: \"}\"}\
+            @media (max-width: 600px){.header{display: none}}\
+            nav > a{color: black}"
+        );
+        assert_eq!(extract(css, &[]), "");
+    }
+
+    #[test]
+    fn selector_prefixes() {
+        assert!(matches(".header", ".header"));
+        assert!(matches(".header .logo", ".header"));
+        assert!(matches(".header:hover", ".header"));
+        assert!(matches(".header>a", ".header"));
+        assert!(!matches(".header-title", ".header"));
+        assert!(!matches(".header_title", ".header"));
+        assert!(!matches(".headers", ".header"));
+        assert!(!matches("nav .header", ".header"));
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{events, BuildArgs, MessageFormat, Verbosity};
+use anyhow::{bail, Context, Result};
+use cargo_metadata::diagnostic::{Diagnostic, DiagnosticLevel};
+use cargo_metadata::{Artifact, Message};
+use serde_json::json;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+/// Error returned when cargo could not compile the frontend.
+///
+/// It contains all the diagnostics of the compiler (errors and warnings).
+#[derive(Debug)]
+pub struct CompileError {
+    /// Diagnostics reported by the compiler during the build.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CompileError {
+    /// Errors reported by the compiler.
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|x| matches!(x.level, DiagnosticLevel::Error | DiagnosticLevel::Ice))
+    }
+
+    /// Rendered errors, as displayed by cargo, without the terminal colors.
+    pub fn rendered(&self) -> String {
+        self.errors()
+            .filter_map(|x| x.rendered.as_deref())
+            .map(strip_ansi)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = self.errors().count();
+        write!(
+            f,
+            "could not compile the frontend: {} error{}",
+            count,
+            if count == 1 { "" } else { "s" },
+        )?;
+
+        if let Some(error) = self.errors().next() {
+            write!(f, "\nfirst error: {}", error.message)?;
+            if let Some(span) = error.spans.iter().find(|x| x.is_primary) {
+                write!(
+                    f,
+                    "\n  --> {}:{}:{}",
+                    span.file_name, span.line_start, span.column_start
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Run the cargo build command of the frontend and collect the diagnostics of the compiler.
+/// Returns the artifacts reported by cargo (compiled or fresh).
+///
+/// The rendered diagnostics are displayed on the standard error. With the JSON message format,
+/// the messages of cargo are also passed through on the standard output.
+///
+/// With [`Verbosity::Quiet`], only the errors are displayed and the warnings are summarized.
+/// With [`Verbosity::Silent`], nothing is displayed: the first error is in the [`CompileError`].
+pub(crate) fn run_cargo(args: &dyn BuildArgs, command: &mut Command) -> Result<Vec<Artifact>> {
+    let verbosity = args.verbosity();
+    verbosity.cargo_args(command);
+    command
+        .arg("--message-format=json-diagnostic-rendered-ansi")
+        .stdout(Stdio::piped());
+
+    let mut child = command.spawn().context("could not start build process")?;
+    let reader = BufReader::new(child.stdout.take().unwrap());
+    let json = args.message_format() == MessageFormat::Json;
+    let color = crate::ui::colored();
+    let mut diagnostics = Vec::new();
+    let mut artifacts = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+
+        if json {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            let _ = writeln!(stdout, "{}", line);
+        }
+
+        match serde_json::from_str::<Message>(&line) {
+            Ok(Message::CompilerMessage(message)) => {
+                let displayed = match verbosity {
+                    Verbosity::Silent => false,
+                    Verbosity::Quiet => matches!(
+                        message.message.level,
+                        DiagnosticLevel::Error | DiagnosticLevel::Ice
+                    ),
+                    _ => true,
+                };
+                if let Some(rendered) = message.message.rendered.as_deref().filter(|_| displayed) {
+                    if color {
+                        eprint!("{}", rendered);
+                    } else {
+                        eprint!("{}", strip_ansi(rendered));
+                    }
+                }
+                diagnostics.push(message.message);
+            }
+            Ok(Message::CompilerArtifact(artifact)) => artifacts.push(artifact),
+            Ok(_) => {}
+            Err(_) if !json && verbosity > Verbosity::Silent => println!("{}", line),
+            Err(_) => {}
+        }
+    }
+
+    let status = child.wait()?;
+
+    let warnings = diagnostics
+        .iter()
+        .filter(|x| matches!(x.level, DiagnosticLevel::Warning))
+        .count();
+    if verbosity == Verbosity::Quiet && warnings > 0 {
+        log::warn!(
+            "the compiler emitted {} warning{} (run without `--quiet` to display them)",
+            warnings,
+            if warnings == 1 { "" } else { "s" },
+        );
+    }
+
+    if !status.success() {
+        let error = CompileError { diagnostics };
+        if error.errors().next().is_some() {
+            events::emit(
+                args,
+                "build-failed",
+                json!({ "errors": error.errors().count(), "message": error.to_string() }),
+            );
+            return Err(error.into());
+        } else if let Some(code) = status.code() {
+            bail!("build process exit with code {}", code);
+        } else {
+            bail!("build process has been terminated by a signal");
+        }
+    }
+
+    Ok(artifacts)
+}
+
+/// Remove the escape sequences used for the terminal colors.
+fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+
+    stripped
+}
+
+thread_local! {
+    /// The warnings of `wasm-bindgen` captured for the build running on the thread.
+    static BINDGEN_WARNINGS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// A logger that forwards the records to `env_logger` and keeps the warnings of `wasm-bindgen`.
+struct Logger(env_logger::Logger);
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        is_bindgen_warning(metadata) || self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if is_bindgen_warning(record.metadata()) {
+            BINDGEN_WARNINGS.with(|x| {
+                if let Some(warnings) = x.borrow_mut().as_mut() {
+                    warnings.push(record.args().to_string());
+                }
+            });
+        }
+        crate::ui::suspend(|| self.0.log(record));
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+}
+
+fn is_bindgen_warning(metadata: &log::Metadata) -> bool {
+    metadata.level() <= log::Level::Warn && metadata.target().starts_with("wasm_bindgen")
+}
+
+/// Install the logger of `wasm-run`: `env_logger` (the filter of `verbosity` by default) with the
+/// capture of the warnings of `wasm-bindgen`. Only the first call has an effect.
+pub(crate) fn init_logger(verbosity: Verbosity) {
+    let filter = verbosity.default_filter();
+    let logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(filter))
+            .write_style(crate::ui::write_style())
+            .build();
+    let max_level = logger.filter().max(log::LevelFilter::Warn);
+
+    if log::set_boxed_logger(Box::new(Logger(logger))).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Run `f` and return the warnings emitted by `wasm-bindgen` on the current thread meanwhile.
+pub(crate) fn capture_bindgen_warnings<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let previous = BINDGEN_WARNINGS.with(|x| x.borrow_mut().replace(Vec::new()));
+    let res = f();
+    let warnings = BINDGEN_WARNINGS.with(|x| std::mem::replace(&mut *x.borrow_mut(), previous));
+    (res, warnings.unwrap_or_default())
+}
@@ -0,0 +1,160 @@
+//! The handoff of the build directory of a frontend to its backend, for the backends that embed
+//! the static files in their executable (for example with the `folder` attribute of
+//! `RustEmbed`).
+//!
+//! After every build, wasm-run writes a manifest `wasm-run/embed/<frontend package>.json` in the
+//! target directory with the absolute path of the build directory. The cargo commands of the
+//! backend run by wasm-run (`serve` and `package`) also receive it in the environment variable
+//! [`BUILD_PATH_ENV`].
+//!
+//! The build script of the backend locates the directory and gives it to the compiler:
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     wasm_run::embed::build_script("frontend").unwrap();
+//! }
+//!
+//! // src/main.rs
+//! #[derive(rust_embed::RustEmbed)]
+//! #[folder = "$WASM_RUN_BUILD_PATH"]
+//! struct Assets;
+//! ```
+//!
+//! (The feature `interpolate-folder-path` of `rust-embed` is required and wasm-run can be a
+//! build dependency without its default features.)
+
+use crate::{BuildArgs, BuildProfile};
+use anyhow::{anyhow, bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable with the absolute path of the build directory: it is set on the cargo
+/// commands of the backend and by [`build_script`] for the compiler.
+pub const BUILD_PATH_ENV: &str = "WASM_RUN_BUILD_PATH";
+
+/// Environment variable with the name of the frontend package whose build directory is in
+/// [`BUILD_PATH_ENV`].
+pub const FRONTEND_ENV: &str = "WASM_RUN_FRONTEND";
+
+fn manifest_path(target_path: &Utf8Path, package: &str) -> Utf8PathBuf {
+    target_path
+        .join("wasm-run")
+        .join("embed")
+        .join(format!("{}.json", package))
+}
+
+/// Write the manifest of the build. It is only used by the backend: the errors are logged.
+pub(crate) fn write_manifest(args: &dyn BuildArgs, profile: BuildProfile) {
+    let path = manifest_path(args.target_path(), &args.frontend_package().name);
+    let manifest = json!({
+        "package": args.frontend_package().name,
+        "profile": format!("{:?}", profile).to_lowercase(),
+        "build_path": args.build_path(),
+        "artifact_path": args.artifact_path(),
+    });
+    let written = fs::create_dir_all(path.parent().unwrap())
+        .with_context(|| format!("could not create `{}`", path.parent().unwrap()))
+        .and_then(|_| crate::generated::write_json(&path, &manifest, true));
+    if let Err(err) = written {
+        log::warn!("Could not write the manifest of the build: {:#}", err);
+    }
+}
+
+/// The absolute path of the build directory of the frontend `package`.
+///
+/// It is [`BUILD_PATH_ENV`] when the command is run by wasm-run for this frontend. Otherwise it
+/// is read from the manifest of the last build in the target directory: the one of
+/// `CARGO_TARGET_DIR`, of `OUT_DIR` (in a build script) or the one of the workspace (relocated by
+/// the `env` of the configuration of the frontend like for the builds).
+pub fn locate_ui_out_dir(package: &str) -> Result<Utf8PathBuf> {
+    if let Some(path) = env::var_os(BUILD_PATH_ENV).filter(|x| !x.is_empty()) {
+        if env::var_os(FRONTEND_ENV).is_none_or(|x| x == package) {
+            return Utf8PathBuf::from_path_buf(path.into())
+                .map_err(|x| anyhow!("`{}` is not a UTF-8 path", x.display()));
+        }
+    }
+
+    let manifest_path = find_manifest(package)?;
+    let manifest: Value = serde_json::from_str(
+        &fs::read_to_string(&manifest_path)
+            .with_context(|| format!("could not read `{}`", manifest_path))?,
+    )
+    .with_context(|| format!("could not parse `{}`", manifest_path))?;
+    let path = manifest["build_path"]
+        .as_str()
+        .map(Utf8PathBuf::from)
+        .with_context(|| format!("invalid manifest `{}`", manifest_path))?;
+
+    if !path.exists() {
+        bail!(
+            "the build directory `{}` of `{}` does not exist anymore: build the frontend again",
+            path,
+            package
+        );
+    }
+    Ok(path)
+}
+
+/// Locate the build directory of the frontend `package` from a build script and make it
+/// available to the compiler in [`BUILD_PATH_ENV`]. The build script is run again when the
+/// frontend is built again.
+pub fn build_script(package: &str) -> Result<Utf8PathBuf> {
+    let path = locate_ui_out_dir(package)?;
+    println!("cargo:rustc-env={}={}", BUILD_PATH_ENV, path);
+    println!("cargo:rerun-if-env-changed={}", BUILD_PATH_ENV);
+    println!("cargo:rerun-if-env-changed={}", FRONTEND_ENV);
+    if let Ok(manifest_path) = find_manifest(package) {
+        println!("cargo:rerun-if-changed={}", manifest_path);
+    }
+    Ok(path)
+}
+
+fn find_manifest(package: &str) -> Result<Utf8PathBuf> {
+    let mut candidates = Vec::new();
+    if let Some(path) = env::var_os("CARGO_TARGET_DIR") {
+        candidates.push(PathBuf::from(path));
+    }
+    // NOTE: `OUT_DIR` is `<target>[/<triple>]/<profile>/build/<package>-<hash>/out`
+    if let Some(path) = env::var_os("OUT_DIR") {
+        candidates.extend(Path::new(&path).ancestors().skip(3).map(Path::to_owned));
+    }
+    // NOTE: the target directories that are not UTF-8 can't be the one of wasm-run
+    for path in candidates
+        .into_iter()
+        .filter_map(|x| Utf8PathBuf::from_path_buf(x).ok())
+    {
+        let manifest_path = manifest_path(&path, package);
+        if manifest_path.exists() {
+            return Ok(manifest_path);
+        }
+    }
+
+    let mut command = cargo_metadata::MetadataCommand::new();
+    command.no_deps();
+    if let Some(path) = env::var_os("CARGO_MANIFEST_DIR") {
+        command.current_dir(path);
+    }
+    let metadata = command.exec().context("could not get the cargo metadata")?;
+    // NOTE: the target directory of the frontend may be relocated by its configuration
+    let target_path = match metadata.packages.iter().find(|x| x.name == package) {
+        Some(frontend) => crate::workspace::target_directory(
+            &metadata,
+            &crate::Config::load(&metadata, frontend)?,
+        ),
+        None => metadata.target_directory.clone(),
+    };
+    let manifest_path = manifest_path(crate::metadata_path(&target_path), package);
+    if !manifest_path.exists() {
+        bail!(
+            "the frontend `{}` has not been built: `{}` is missing (run the command `build` of \
+            the frontend first)",
+            package,
+            manifest_path
+        );
+    }
+    Ok(manifest_path)
+}
@@ -0,0 +1,55 @@
+use crate::BuildArgs;
+use serde_json::Value;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Format of the messages printed on the standard output.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum MessageFormat {
+    /// Only human-oriented logs are printed (on the standard error).
+    #[default]
+    Human,
+    /// JSON messages are printed on the standard output, one per line, alongside the messages of
+    /// cargo (`--message-format json`). Every message of `wasm-run` has a `reason` starting with
+    /// `wasm-run-`.
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("invalid message format `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for MessageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Human => write!(f, "human"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Print a JSON message on the standard output if the JSON message format is enabled.
+pub(crate) fn emit(args: &dyn BuildArgs, reason: &str, message: Value) {
+    if args.message_format() == MessageFormat::Json {
+        print(reason, message);
+    }
+}
+
+/// Print a JSON message on the standard output regardless of the message format.
+pub(crate) fn print(reason: &str, mut message: Value) {
+    message["reason"] = Value::String(format!("wasm-run-{}", reason));
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    let _ = writeln!(stdout, "{}", message);
+    let _ = stdout.flush();
+}
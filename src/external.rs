@@ -0,0 +1,397 @@
+use crate::{escape_html, BuildArgs, Config};
+use anyhow::{bail, Context, Result};
+use camino::Utf8PathBuf;
+use serde_json::{json, Map, Value};
+#[cfg(feature = "sha2")]
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::fs;
+
+/// URL path of the local copies of the external dependencies (`serve --cache-external`).
+#[cfg(feature = "dev-server")]
+pub(crate) const CACHE_URL: &str = "/__wasm_run/external";
+
+/// Markers of the tags inserted in the `index.html`: they are replaced by the next builds.
+const BEGIN: &str = "<!-- wasm-run: external -->";
+const END: &str = "<!-- /wasm-run: external -->";
+
+/// A JavaScript dependency loaded from a CDN by the `index.html`: an entry of the import map if
+/// it has a name (the specifier imported by the modules), a `<script>` tag otherwise. The tags are
+/// inserted at the beginning of the `<head>` after the `post_build` hook with the integrity of
+/// the files (Subresource Integrity).
+///
+/// It is read from `external` in `WasmRun.toml` or in `[package.metadata.wasm-run]`:
+///
+/// ```toml
+/// [[package.metadata.wasm-run.external]]
+/// name = "lit"
+/// url = "https://cdn.jsdelivr.net/npm/lit@3.1.0/+esm"
+/// integrity = "sha384-..."
+///
+/// [[package.metadata.wasm-run.external]]
+/// url = "https://cdn.jsdelivr.net/npm/chart.js@4.4.0/dist/chart.umd.js"
+/// ```
+///
+/// The files are downloaded once to `target/wasm-run/external`. When the integrity is missing,
+/// it is computed from the file and logged so it can be pinned; otherwise the file is checked
+/// against it and the build fails if the file changed on the CDN.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalDependency {
+    /// Specifier of the import map (`import { html } from "lit"`). A `<script>` tag is used if
+    /// this is `None`.
+    pub name: Option<String>,
+    /// URL of the file.
+    pub url: String,
+    /// Integrity of the file (`sha384-<base64>`, `sha256-...` or `sha512-...`). It is computed
+    /// from the file if this is `None`.
+    pub integrity: Option<String>,
+    /// Load the `<script>` tag as a module (`type="module"`).
+    pub module: bool,
+}
+
+impl ExternalDependency {
+    /// A `<script>` tag loading `url`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            name: None,
+            url: url.into(),
+            integrity: None,
+            module: false,
+        }
+    }
+
+    /// Add the file to the import map with this specifier instead of using a `<script>` tag.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the expected integrity of the file.
+    pub fn integrity(mut self, integrity: impl Into<String>) -> Self {
+        self.integrity = Some(integrity.into());
+        self
+    }
+
+    /// Load the `<script>` tag as a module.
+    pub fn module(mut self, module: bool) -> Self {
+        self.module = module;
+        self
+    }
+
+    pub(crate) fn from_config(config: &Config) -> Result<Vec<Self>> {
+        let values = match config.get("external") {
+            Value::Null => return Ok(Vec::new()),
+            Value::Array(values) => values,
+            _ => bail!("invalid `external`: expected an array of tables"),
+        };
+
+        values
+            .iter()
+            .map(|value| {
+                let table = value
+                    .as_object()
+                    .context("invalid `external`: expected an array of tables")?;
+                let string =
+                    |key: &str| -> Result<Option<String>> {
+                        match table.get(key) {
+                            None => Ok(None),
+                            Some(value) => value
+                                .as_str()
+                                .map(|x| Some(x.to_string()))
+                                .with_context(|| {
+                                    format!("invalid `external`: `{}` must be a string", key)
+                                }),
+                        }
+                    };
+
+                let mut dependency =
+                    Self::new(string("url")?.context("invalid `external`: `url` is missing")?);
+                dependency.name = string("name")?;
+                dependency.integrity = string("integrity")?;
+                if let Some(integrity) = dependency.integrity.as_deref() {
+                    algorithm(integrity)?;
+                }
+                if let Some(module) = table.get("module") {
+                    dependency.module = module
+                        .as_bool()
+                        .context("invalid `external`: `module` must be a boolean")?;
+                }
+                Ok(dependency)
+            })
+            .collect()
+    }
+
+    /// Name of the local copy of the file. The extension is kept so the development server
+    /// serves it with the right MIME type (JavaScript if it has none).
+    pub(crate) fn cache_name(&self) -> String {
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        let extension = path
+            .rsplit('/')
+            .next()
+            .and_then(|x| x.rsplit_once('.'))
+            .map(|(_, extension)| extension)
+            .filter(|x| ["js", "mjs", "css", "json", "wasm"].contains(x))
+            .unwrap_or("js");
+        format!(
+            "{}.{}",
+            &blake3::hash(self.url.as_bytes()).to_hex()[..16],
+            extension
+        )
+    }
+}
+
+/// Directory of the local copies of the files.
+pub(crate) fn cache_dir(args: &dyn BuildArgs) -> Utf8PathBuf {
+    args.target_path().join("wasm-run").join("external")
+}
+
+/// Insert the import map and the `<script>` tags of the dependencies in the `index.html` of the
+/// build directory.
+pub(crate) fn inject(args: &dyn BuildArgs, dependencies: &[ExternalDependency]) -> Result<()> {
+    let index_path = args.build_path().join("index.html");
+    let mut html = match fs::read_to_string(&index_path) {
+        Ok(html) => html,
+        Err(_) => return Ok(()),
+    };
+
+    let mut imports = Map::new();
+    let mut integrities = Map::new();
+    let mut scripts = String::new();
+    for dependency in dependencies {
+        let integrity = resolve_integrity(args, dependency)?;
+        match dependency.name.as_ref() {
+            Some(name) => {
+                imports.insert(name.clone(), json!(dependency.url));
+                integrities.insert(dependency.url.clone(), json!(integrity));
+            }
+            None => scripts.push_str(&format!(
+                "<script src=\"{}\" integrity=\"{}\" crossorigin=\"anonymous\"{}></script>",
+                escape_html(&dependency.url),
+                escape_html(&integrity),
+                if dependency.module {
+                    " type=\"module\""
+                } else {
+                    ""
+                },
+            )),
+        }
+    }
+
+    let mut block = String::from(BEGIN);
+    if !imports.is_empty() {
+        let import_map = json!({ "imports": imports, "integrity": integrities });
+        block.push_str(&format!(
+            "<script type=\"importmap\">{}</script>",
+            import_map.to_string().replace("</", "<\\/")
+        ));
+    }
+    block.push_str(&scripts);
+    block.push_str(END);
+
+    // NOTE: the import map must come before the module scripts
+    match (html.find(BEGIN), html.find(END)) {
+        (Some(start), Some(end)) if start < end => {
+            html.replace_range(start..end + END.len(), &block)
+        }
+        _ => {
+            let i = html
+                .to_ascii_lowercase()
+                .find("<head")
+                .and_then(|start| html[start..].find('>').map(|x| start + x + 1))
+                .unwrap_or(0);
+            html.insert_str(i, &block);
+        }
+    }
+
+    fs::write(&index_path, html).with_context(|| format!("could not write `{}`", index_path))?;
+    Ok(())
+}
+
+/// The integrity of the dependency: the one of the configuration, checked against the file, or
+/// the one computed from the file.
+fn resolve_integrity(args: &dyn BuildArgs, dependency: &ExternalDependency) -> Result<String> {
+    let content = fetch(args, dependency);
+    match (dependency.integrity.as_deref(), content) {
+        (Some(expected), Some(content)) => {
+            let algorithm = algorithm(expected)?;
+            // NOTE: without the feature `sha2` only the browser checks the integrity
+            let actual = integrity(algorithm, &content).unwrap_or_else(|| expected.to_string());
+            if actual != expected {
+                bail!(
+                    "the integrity of `{}` does not match: expected {}, got {} (the file changed \
+                    on the CDN or the copy `{}` is corrupted)",
+                    dependency.url,
+                    expected,
+                    actual,
+                    cache_dir(args).join(dependency.cache_name()),
+                );
+            }
+            Ok(actual)
+        }
+        (Some(expected), None) => Ok(expected.to_string()),
+        (None, Some(content)) => {
+            let integrity = integrity("sha384", &content).with_context(|| {
+                format!(
+                    "the integrity of `{}` is not set and computing it requires the feature \
+                    `sha2`",
+                    dependency.url
+                )
+            })?;
+            log::info!(
+                "Integrity of {}: {} (add `integrity = \"{}\"` to pin it)",
+                dependency.url,
+                integrity,
+                integrity
+            );
+            Ok(integrity)
+        }
+        (None, None) => bail!(
+            "the integrity of `{}` is not set and the file could not be downloaded to compute it \
+            (it can be copied to `{}`)",
+            dependency.url,
+            cache_dir(args).join(dependency.cache_name()),
+        ),
+    }
+}
+
+/// The content of the file: the local copy, downloaded if it is missing.
+fn fetch(args: &dyn BuildArgs, dependency: &ExternalDependency) -> Option<Vec<u8>> {
+    let path = cache_dir(args).join(dependency.cache_name());
+    if let Ok(content) = fs::read(&path) {
+        return Some(content);
+    }
+    if args.offline() {
+        return None;
+    }
+
+    log::info!("Downloading {}", dependency.url);
+    let content = match download(&dependency.url) {
+        Ok(content) => content,
+        Err(err) => {
+            log::warn!("Could not download {}: {:#}", dependency.url, err);
+            return None;
+        }
+    };
+    // NOTE: the copy is written at once so an interrupted download is never used
+    let tmp_path = path.with_extension("tmp");
+    let written = fs::create_dir_all(cache_dir(args))
+        .and_then(|_| fs::write(&tmp_path, &content))
+        .and_then(|_| fs::rename(&tmp_path, &path));
+    if let Err(err) = written {
+        log::warn!("Could not write `{}`: {}", path, err);
+    }
+    Some(content)
+}
+
+#[cfg(feature = "curl")]
+fn download(url: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut easy = curl::easy::Easy::new();
+    easy.url(url)?;
+    easy.follow_location(true)?;
+    easy.fail_on_error(true)?;
+    {
+        let mut transfer = easy.transfer();
+        transfer.write_function(|chunk| {
+            data.extend_from_slice(chunk);
+            Ok(chunk.len())
+        })?;
+        transfer.perform()?;
+    }
+    Ok(data)
+}
+
+#[cfg(not(feature = "curl"))]
+fn download(_url: &str) -> Result<Vec<u8>> {
+    bail!("downloading requires the feature `prebuilt-wasm-opt` or `webhooks`")
+}
+
+fn algorithm(integrity: &str) -> Result<&str> {
+    match integrity.split_once('-') {
+        Some((algorithm @ ("sha256" | "sha384" | "sha512"), _)) => Ok(algorithm),
+        _ => bail!(
+            "invalid integrity `{}`: expected `sha256-`, `sha384-` or `sha512-` followed by the \
+            digest in base64",
+            integrity
+        ),
+    }
+}
+
+/// The integrity of the content (`None` without the feature `sha2`).
+#[cfg(feature = "sha2")]
+fn integrity(algorithm: &str, content: &[u8]) -> Option<String> {
+    let digest = match algorithm {
+        "sha256" => Sha256::digest(content).to_vec(),
+        "sha512" => Sha512::digest(content).to_vec(),
+        _ => Sha384::digest(content).to_vec(),
+    };
+    Some(format!("{}-{}", algorithm, base64(&digest)))
+}
+
+#[cfg(not(feature = "sha2"))]
+fn integrity(_algorithm: &str, _content: &[u8]) -> Option<String> {
+    None
+}
+
+#[cfg(feature = "sha2")]
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, x)| n | (*x as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_padding() {
+        // NOTE: the test vectors of RFC 4648
+        for (input, output) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(base64(input.as_bytes()), output);
+        }
+    }
+
+    #[test]
+    fn base64_alphabet() {
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+        assert_eq!(base64(&[0, 0, 0]), "AAAA");
+        assert_eq!(base64(&[0xff; 4]), "/////w==");
+    }
+
+    #[test]
+    fn integrity_digests() {
+        assert_eq!(
+            integrity("sha256", b"").unwrap(),
+            "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+        );
+        assert_eq!(
+            integrity("sha384", b"alert('Hello, world.');").unwrap(),
+            "sha384-H8BRh8j48O9oYatfu5AZzq6A9RINhZO5H16dQZngK7T62em8MUt1FLm52t+eX6xO"
+        );
+        assert_eq!(algorithm("sha512-abc").unwrap(), "sha512");
+        assert!(algorithm("md5-abc").is_err());
+        assert!(algorithm("sha256").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use crate::escape_html;
+use crate::status_page::time;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tide::{Response, Server, StatusCode};
+
+pub(crate) const LISTING_PATH: &str = "/__wasm_run/files";
+
+/// Extensions of the precompressed variants of the files (served by the development server
+/// instead of the file when the browser accepts them).
+const COMPRESSED: &[&str] = &["br", "gz"];
+
+/// Number of hexadecimal characters of the hashes displayed.
+const HASH_LEN: usize = 16;
+
+/// Register the listing of the build directory at `/__wasm_run/files/<directory>`: the files
+/// with their size, their hash (BLAKE3), their modification time and the sizes of their
+/// precompressed variants.
+pub(crate) fn register(server: &mut Server<()>, build_path: PathBuf) {
+    for route in &[
+        LISTING_PATH.to_owned(),
+        format!("{}/", LISTING_PATH),
+        format!("{}/*path", LISTING_PATH),
+    ] {
+        let build_path = build_path.clone();
+        server.at(route).get(move |req: tide::Request<()>| {
+            let build_path = build_path.clone();
+            async move {
+                let dir = req.param("path").unwrap_or("").trim_matches('/').to_owned();
+                let relative = Path::new(&dir);
+                if relative
+                    .components()
+                    .any(|x| !matches!(x, Component::Normal(_)))
+                {
+                    return Ok(Response::new(StatusCode::BadRequest));
+                }
+                let html = match render(&build_path, relative) {
+                    Some(html) => html,
+                    None => return Ok(Response::new(StatusCode::NotFound)),
+                };
+
+                let mut res = Response::from(html);
+                res.set_content_type(tide::http::mime::HTML);
+                res.insert_header("Cache-Control", "no-store");
+                Ok(res)
+            }
+        });
+    }
+}
+
+struct Entry {
+    name: String,
+    size: u64,
+    modified: Option<SystemTime>,
+    is_dir: bool,
+}
+
+fn render(build_path: &Path, relative: &Path) -> Option<String> {
+    let dir = build_path.join(relative);
+    let mut entries = fs::read_dir(&dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some(Entry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+                is_dir: metadata.is_dir(),
+            })
+        })
+        .collect::<Vec<_>>();
+    // NOTE: the directories first, then by name
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+    let url_prefix = relative
+        .iter()
+        .map(|x| format!("{}/", x.to_string_lossy()))
+        .collect::<String>();
+
+    let mut html = String::from(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>wasm-run: files</title><style>\
+        body { font: 14px sans-serif; margin: 2em; } \
+        table { border-collapse: collapse; } \
+        td, th { padding: 0.2em 1em 0.2em 0; text-align: left; } \
+        code { font-size: 12px; } .number { text-align: right; }\
+        </style></head><body>",
+    );
+    let _ = write!(
+        html,
+        "<h1>/{}</h1><p>{}</p><table><tr><th>Name</th><th>Size</th><th>BLAKE3</th>\
+        <th>Modified</th><th>Compressed</th></tr>",
+        escape_html(&url_prefix),
+        escape_html(&dir.display().to_string()),
+    );
+    if !url_prefix.is_empty() {
+        let parent = url_prefix
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .map(|(parent, _)| format!("{}/", parent))
+            .unwrap_or_default();
+        let _ = write!(
+            html,
+            "<tr><td><a href=\"{}/{}\">..</a></td></tr>",
+            LISTING_PATH,
+            escape_html(&parent),
+        );
+    }
+
+    for entry in entries {
+        let url = format!("{}{}", url_prefix, entry.name);
+        if entry.is_dir {
+            let _ = write!(
+                html,
+                "<tr><td><a href=\"{}/{}/\">{}/</a></td></tr>",
+                LISTING_PATH,
+                escape_html(&url),
+                escape_html(&entry.name),
+            );
+            continue;
+        }
+
+        let path = dir.join(&entry.name);
+        let hash = fs::read(&path)
+            .map(|x| blake3::hash(&x).to_hex()[..HASH_LEN].to_owned())
+            .unwrap_or_default();
+        let compressed = COMPRESSED
+            .iter()
+            .filter_map(|ext| {
+                let size = fs::metadata(dir.join(format!("{}.{}", entry.name, ext)))
+                    .ok()?
+                    .len();
+                Some(format!("{}: {}", ext, size))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = write!(
+            html,
+            "<tr><td><a href=\"/{}\">{}</a></td><td class=\"number\">{}</td><td><code>{}</code></td>\
+            <td>{}</td><td>{}</td></tr>",
+            escape_html(&url),
+            escape_html(&entry.name),
+            entry.size,
+            hash,
+            entry.modified.map(date_time).unwrap_or_default(),
+            compressed,
+        );
+    }
+    html.push_str("</table></body></html>");
+
+    Some(html)
+}
+
+/// Date (`YYYY-MM-DD`) and time of the day (UTC).
+fn date_time(time_: SystemTime) -> String {
+    let days = time_
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+        / 86400;
+    // NOTE: civil date from the number of days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{}-{:02}-{:02} {}", year, month, day, time(time_))
+}
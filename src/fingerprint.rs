@@ -0,0 +1,473 @@
+use crate::{find_ignore_case, BuildArgs, Config};
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+#[cfg(feature = "sha2")]
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+/// Name of the manifest of the fingerprinted assets in the build directory.
+pub(crate) const ASSET_MANIFEST: &str = "asset-manifest.json";
+
+/// A hashing algorithm used for the fingerprints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// BLAKE3 (the default: fast and stable).
+    Blake3,
+    /// SHA-256 (feature `sha2`).
+    #[cfg(feature = "sha2")]
+    Sha256,
+    /// XXH3 (128 bits): the fastest, but it is not a cryptographic hash.
+    Xxh3,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "blake3" => Ok(Self::Blake3),
+            #[cfg(feature = "sha2")]
+            "sha256" => Ok(Self::Sha256),
+            #[cfg(not(feature = "sha2"))]
+            "sha256" => bail!("the hashing algorithm `sha256` requires the feature `sha2`"),
+            "xxh3" => Ok(Self::Xxh3),
+            _ => bail!(
+                "unknown hashing algorithm `{}` (expected `blake3`, `sha256` or `xxh3`)",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Blake3 => "blake3",
+            #[cfg(feature = "sha2")]
+            Self::Sha256 => "sha256",
+            Self::Xxh3 => "xxh3",
+        })
+    }
+}
+
+/// Hashing of the content of the files for the fingerprinted assets (`app-<hash>.js`) and the
+/// build identifiers (cache of the service worker, cache busting of the development server).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// Hashing algorithm.
+    pub algorithm: HashAlgorithm,
+    /// Number of hexadecimal digits of the digests (the whole digest is used if it is shorter).
+    pub length: usize,
+}
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self {
+            algorithm: HashAlgorithm::Blake3,
+            length: 16,
+        }
+    }
+}
+
+impl Fingerprint {
+    /// Digests of `length` hexadecimal digits with the algorithm `algorithm`.
+    pub fn new(algorithm: HashAlgorithm, length: usize) -> Self {
+        Self { algorithm, length }
+    }
+
+    /// Read `fingerprint` of the configuration (the keys `algorithm` and `length`).
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut fingerprint = Self::default();
+        let table = match config.get("fingerprint") {
+            Value::Null => return Ok(fingerprint),
+            Value::Object(table) => table,
+            _ => bail!("invalid `fingerprint`: expected a table"),
+        };
+        if let Some(algorithm) = table.get("algorithm") {
+            fingerprint.algorithm = algorithm
+                .as_str()
+                .context("invalid `fingerprint.algorithm`: expected a string")?
+                .parse()?;
+        }
+        if let Some(length) = table.get("length") {
+            fingerprint.length = length
+                .as_u64()
+                .context("invalid `fingerprint.length`: expected a number")?
+                as usize;
+        }
+        Ok(fingerprint)
+    }
+
+    /// Hexadecimal digest of `data`.
+    pub fn digest(&self, data: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    pub(crate) fn hasher(&self) -> FingerprintHasher {
+        FingerprintHasher {
+            state: match self.algorithm {
+                HashAlgorithm::Blake3 => State::Blake3(Box::new(blake3::Hasher::new())),
+                #[cfg(feature = "sha2")]
+                HashAlgorithm::Sha256 => State::Sha256(Sha256::new()),
+                HashAlgorithm::Xxh3 => State::Xxh3(Box::new(xxhash_rust::xxh3::Xxh3::new())),
+            },
+            length: self.length,
+        }
+    }
+}
+
+enum State {
+    Blake3(Box<blake3::Hasher>),
+    #[cfg(feature = "sha2")]
+    Sha256(Sha256),
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+/// Incremental hashing of several files for a single fingerprint.
+pub(crate) struct FingerprintHasher {
+    state: State,
+    length: usize,
+}
+
+impl FingerprintHasher {
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match &mut self.state {
+            State::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            #[cfg(feature = "sha2")]
+            State::Sha256(hasher) => hasher.update(data),
+            State::Xxh3(hasher) => hasher.update(data),
+        }
+    }
+
+    pub(crate) fn finish(self) -> String {
+        let mut digest = match self.state {
+            State::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            #[cfg(feature = "sha2")]
+            State::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            State::Xxh3(hasher) => format!("{:032x}", hasher.digest128()),
+        };
+        digest.truncate(self.length);
+        digest
+    }
+}
+
+/// Rename the artifacts after the fingerprint of their content (`app-<hash>.js` and
+/// `app_bg-<hash>.wasm`), update the references of the `index.html` and the JS and write the
+/// mapping in [`ASSET_MANIFEST`]. Returns the new file names of the JS and of the WASM.
+pub(crate) fn fingerprint_artifacts(
+    args: &dyn BuildArgs,
+    fingerprint: &Fingerprint,
+) -> Result<(String, String)> {
+    let artifact_path = args.artifact_path();
+    let wasm_path = artifact_path.join("app_bg.wasm");
+    let js_path = artifact_path.join("app.js");
+
+    let wasm = fs::read(&wasm_path).with_context(|| format!("could not read `{}`", wasm_path))?;
+    let wasm_name = format!("app_bg-{}.wasm", fingerprint.digest(&wasm));
+    fs::rename(&wasm_path, artifact_path.join(&wasm_name))
+        .with_context(|| format!("could not rename `{}`", wasm_path))?;
+
+    // NOTE: the JS refers to the WASM so its fingerprint depends on the one of the WASM
+    let js =
+        fs::read_to_string(&js_path).with_context(|| format!("could not read `{}`", js_path))?;
+    let js = rewrite_literals(&js, "app_bg.wasm", &wasm_name, |_| true);
+    let js_name = format!("app-{}.js", fingerprint.digest(js.as_bytes()));
+    fs::write(artifact_path.join(&js_name), js)
+        .with_context(|| format!("could not write `{}`", js_name))?;
+    fs::remove_file(&js_path).with_context(|| format!("could not remove `{}`", js_path))?;
+
+    let index_path = args.build_path().join("index.html");
+    if let Ok(index) = fs::read_to_string(&index_path) {
+        let index = rewrite_index(&index, &js_name, &wasm_name);
+        fs::write(&index_path, index)
+            .with_context(|| format!("could not write `{}`", index_path))?;
+    }
+
+    let url = |file_name: &str| args.artifact_url(file_name)[1..].to_string();
+    let manifest = json!({
+        "algorithm": fingerprint.algorithm.to_string(),
+        "length": fingerprint.length,
+        "files": {
+            url("app.js"): url(&js_name),
+            url("app_bg.wasm"): url(&wasm_name),
+        },
+    });
+    let manifest_path = args.build_path().join(ASSET_MANIFEST);
+    crate::generated::write_json(&manifest_path, &manifest, true)?;
+
+    log::info!("Fingerprinted the artifacts: {} and {}", js_name, wasm_name);
+
+    Ok((js_name, wasm_name))
+}
+
+/// Rewrite the references to the artifacts in the `<script>` tags of the `index.html`: the `src`
+/// and the import specifiers of `app.js`, and the strings of the inline scripts that refer to
+/// `app_bg.wasm` (`new URL("app_bg.wasm", import.meta.url)`). The rest of the page is kept
+/// as is.
+fn rewrite_index(index: &str, js_name: &str, wasm_name: &str) -> String {
+    let mut output = String::with_capacity(index.len());
+    let mut rest = index;
+    while let Some(start) = find_ignore_case(rest, "<script") {
+        let tag_end = match rest[start..].find('>') {
+            Some(i) => start + i + 1,
+            None => break,
+        };
+        let body_end =
+            find_ignore_case(&rest[tag_end..], "</script").map_or(rest.len(), |x| tag_end + x);
+        output.push_str(&rest[..start]);
+        output.push_str(&rewrite_literals(
+            &rest[start..tag_end],
+            "app.js",
+            js_name,
+            is_src,
+        ));
+        let body = rewrite_literals(&rest[tag_end..body_end], "app.js", js_name, is_import);
+        output.push_str(&rewrite_literals(&body, "app_bg.wasm", wasm_name, |_| true));
+        rest = &rest[body_end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Add the query `?v=<build_id>` to the references to the artifacts in the `<script>` tags of the
+/// `index.html` (the ones rewritten by [`rewrite_index`]) so the browser doesn't use a stale
+/// copy of the JS or of the WASM after a rebuild.
+#[cfg(feature = "dev-server")]
+pub(crate) fn cache_bust_index(index: &str, build_id: &str) -> String {
+    rewrite_index(
+        index,
+        &format!("app.js?v={}", build_id),
+        &format!("app_bg.wasm?v={}", build_id),
+    )
+}
+
+/// The string is the value of the attribute `src` (`<code before>src=`).
+fn is_src(before: &str) -> bool {
+    match before.trim_end().strip_suffix('=') {
+        Some(name) => {
+            let name = name.trim_end();
+            name.len() > 3
+                && name[name.len() - 3..].eq_ignore_ascii_case("src")
+                && name[..name.len() - 3].ends_with(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+/// The string is an import specifier (`import "..."`, `from "..."` or `import("...")`).
+fn is_import(before: &str) -> bool {
+    let before = before.trim_end();
+    let before = before
+        .strip_suffix('(')
+        .map_or(before, str::trim_end)
+        .trim_end();
+    ["import", "from"].iter().any(|keyword| {
+        before.strip_suffix(keyword).is_some_and(|x| {
+            !x.ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$' || c == '.')
+        })
+    })
+}
+
+/// Replace the file name `from` by `to` in the string literals of `source` that refer to it
+/// (`"app.js"`, `'./app.js'`, `"/pkg/app.js?v=1"`) when `filter` accepts the code before them.
+/// The comments are skipped.
+fn rewrite_literals(source: &str, from: &str, to: &str, filter: impl Fn(&str) -> bool) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut copied = 0;
+    let mut chars = source.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '/' if chars.peek().map(|x| x.1) == Some('/') => {
+                chars.find(|x| x.1 == '\n');
+            }
+            '/' if chars.peek().map(|x| x.1) == Some('*') => {
+                chars.next();
+                let mut previous = ' ';
+                chars.find(|x| std::mem::replace(&mut previous, x.1) == '*' && x.1 == '/');
+            }
+            '"' | '\'' | '`' => {
+                let mut end = None;
+                while let Some((j, x)) = chars.next() {
+                    if x == '\\' {
+                        chars.next();
+                    } else if x == c {
+                        end = Some(j);
+                        break;
+                    }
+                }
+                let end = match end {
+                    Some(end) => end,
+                    None => break,
+                };
+                if let Some(renamed) = rename(&source[i + 1..end], from, to) {
+                    if filter(&source[..i]) {
+                        output.push_str(&source[copied..=i]);
+                        output.push_str(&renamed);
+                        copied = end;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    output.push_str(&source[copied..]);
+    output
+}
+
+/// The specifier with the file name `from` replaced by `to` if it refers to `from`. The query of
+/// `to`, if any, is merged with the one of the specifier.
+fn rename(specifier: &str, from: &str, to: &str) -> Option<String> {
+    let (path, suffix) = specifier.split_at(specifier.find(['?', '#']).unwrap_or(specifier.len()));
+    let directory = path.strip_suffix(from)?;
+    if !directory.is_empty() && !directory.ends_with('/') {
+        return None;
+    }
+    match suffix.strip_prefix('?') {
+        Some(query) if to.contains('?') => Some(format!("{}{}&{}", directory, to, query)),
+        _ => Some(format!("{}{}{}", directory, to, suffix)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_algorithms() {
+        for algorithm in ["blake3", "xxh3"] {
+            assert_eq!(
+                algorithm.parse::<HashAlgorithm>().unwrap().to_string(),
+                algorithm
+            );
+        }
+        #[cfg(feature = "sha2")]
+        assert_eq!(
+            "sha256".parse::<HashAlgorithm>().unwrap(),
+            HashAlgorithm::Sha256
+        );
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn digests() {
+        let digest = |algorithm, length| Fingerprint::new(algorithm, length).digest(b"abc");
+        assert_eq!(
+            digest(HashAlgorithm::Blake3, 64),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(digest(HashAlgorithm::Blake3, 16), "6437b3ac38465133");
+        assert_eq!(digest(HashAlgorithm::Xxh3, 64).len(), 32);
+        assert_eq!(
+            digest(HashAlgorithm::Xxh3, 8),
+            digest(HashAlgorithm::Xxh3, 32)[..8]
+        );
+        #[cfg(feature = "sha2")]
+        assert_eq!(
+            digest(HashAlgorithm::Sha256, 64),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn incremental_digests() {
+        for algorithm in ["blake3", "xxh3"] {
+            let fingerprint = Fingerprint::new(algorithm.parse().unwrap(), 16);
+            let mut hasher = fingerprint.hasher();
+            hasher.update(b"ab");
+            hasher.update(b"c");
+            assert_eq!(hasher.finish(), fingerprint.digest(b"abc"));
+        }
+    }
+
+    #[test]
+    fn rewrite_the_default_index() {
+        assert_eq!(
+            rewrite_index(crate::DEFAULT_INDEX, "app-1234.js", "app_bg-5678.wasm"),
+            crate::DEFAULT_INDEX
+                .replace("/app.js", "/app-1234.js")
+                .replace("'app_bg.wasm'", "'app_bg-5678.wasm'")
+        );
+    }
+
+    #[test]
+    fn rewrite_only_the_references() {
+        let index = r#"<html><head>
+<title>app.js demo</title>
+<meta name="description" content="app.js">
+<link rel="stylesheet" href="/app.js.css">
+<SCRIPT type="module" SRC="/pkg/app.js?v=1"></SCRIPT>
+<script data-src="app.js" src='./app.js'></script>
+<script type="module">
+    // import "app.js" in a comment
+    import init, { run } from "./app.js";
+    const other = import ( '/myapp.js' );
+    const lazy = import("/static/app.js");
+    console.log("app.js");
+    init(new URL("app_bg.wasm", import.meta.url));
+</script>
+</head><body>Download app.js or app_bg.wasm</body></html>"#;
+        assert_eq!(
+            rewrite_index(index, "app-1234.js", "app_bg-5678.wasm"),
+            r#"<html><head>
+<title>app.js demo</title>
+<meta name="description" content="app.js">
+<link rel="stylesheet" href="/app.js.css">
+<SCRIPT type="module" SRC="/pkg/app-1234.js?v=1"></SCRIPT>
+<script data-src="app.js" src='./app-1234.js'></script>
+<script type="module">
+    // import "app.js" in a comment
+    import init, { run } from "./app-1234.js";
+    const other = import ( '/myapp.js' );
+    const lazy = import("/static/app-1234.js");
+    console.log("app.js");
+    init(new URL("app_bg-5678.wasm", import.meta.url));
+</script>
+</head><body>Download app.js or app_bg.wasm</body></html>"#
+        );
+    }
+
+    #[cfg(feature = "dev-server")]
+    #[test]
+    fn cache_bust_the_references() {
+        let index = r#"<html><head>
+<link rel="stylesheet" href="/app.js.css">
+<script type="module" src="/pkg/app.js?x=1#top"></script>
+<script type="module">
+    import init from "/app.js";
+    console.log("app.js");
+    init(new URL('app_bg.wasm', import.meta.url));
+</script>
+</head><body>Download app.js</body></html>"#;
+        assert_eq!(
+            cache_bust_index(index, "1234"),
+            r#"<html><head>
+<link rel="stylesheet" href="/app.js.css">
+<script type="module" src="/pkg/app.js?v=1234&x=1#top"></script>
+<script type="module">
+    import init from "/app.js?v=1234";
+    console.log("app.js");
+    init(new URL('app_bg.wasm?v=1234', import.meta.url));
+</script>
+</head><body>Download app.js</body></html>"#
+        );
+    }
+
+    #[test]
+    fn rewrite_the_wasm_of_the_js() {
+        let js = "/* app_bg.wasm */\nconst s = 'it\\'s \"app_bg.wasm\"';\n\
+            input = new URL('app_bg.wasm', import.meta.url);\n\
+            other = fetch(`/wasm/app_bg.wasm`) + 'my_app_bg.wasm';\n";
+        assert_eq!(
+            rewrite_literals(js, "app_bg.wasm", "app_bg-5678.wasm", |_| true),
+            "/* app_bg.wasm */\nconst s = 'it\\'s \"app_bg.wasm\"';\n\
+            input = new URL('app_bg-5678.wasm', import.meta.url);\n\
+            other = fetch(`/wasm/app_bg-5678.wasm`) + 'my_app_bg.wasm';\n"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use crate::BuildArgs;
+use anyhow::{bail, Result};
+use camino::Utf8PathBuf;
+use cargo_metadata::{Artifact, Package, Target};
+
+/// The target of the frontend package compiled to WASM: its library, or one of its binaries (a
+/// `fn main()` or `#[wasm_bindgen(start)]` frontend) with [`BuildArgs::frontend_bin`] or when the
+/// package has no library.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) enum FrontendTarget {
+    Lib(String),
+    Bin(String),
+}
+
+impl FrontendTarget {
+    /// Select the target from the targets of the frontend package in the metadata.
+    pub(crate) fn resolve(args: &dyn BuildArgs) -> Result<Self> {
+        let package = args.frontend_package();
+        let is_lib = |x: &&Target| {
+            x.kind
+                .iter()
+                .any(|x| x == "lib" || x == "cdylib" || x == "rlib")
+        };
+        let bins: Vec<_> = package
+            .targets
+            .iter()
+            .filter(|x| x.kind.iter().any(|x| x == "bin"))
+            .map(|x| x.name.as_str())
+            .collect();
+        let list = || {
+            bins.iter()
+                .map(|x| format!("`{}`", x))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        if let Some(name) = args.frontend_bin() {
+            if !bins.contains(&name.as_str()) {
+                bail!(
+                    "the frontend package `{}` has no binary `{}` (binaries: {})",
+                    package.name,
+                    name,
+                    if bins.is_empty() {
+                        "none".to_string()
+                    } else {
+                        list()
+                    },
+                );
+            }
+            return Ok(Self::Bin(name));
+        }
+
+        if let Some(lib) = package.targets.iter().find(is_lib) {
+            return Ok(Self::Lib(lib.name.clone()));
+        }
+
+        match bins.as_slice() {
+            [name] => Ok(Self::Bin(name.to_string())),
+            [] => bail!(
+                "the frontend package `{}` has neither a library nor a binary",
+                package.name
+            ),
+            _ => bail!(
+                "the frontend package `{}` has no library and several binaries: {}. Select the \
+                frontend with `--frontend-bin <name>`",
+                package.name,
+                list(),
+            ),
+        }
+    }
+
+    /// The WASM of the target of the frontend `package` among the artifacts reported by cargo
+    /// (whatever the target directory, the name of the library or the profile).
+    pub(crate) fn wasm_artifact(
+        &self,
+        package: &Package,
+        artifacts: &[Artifact],
+    ) -> Option<Utf8PathBuf> {
+        artifacts
+            .iter()
+            .filter(|x| x.package_id == package.id)
+            .filter(|x| match self {
+                Self::Lib(name) => {
+                    x.target.name == *name && x.target.kind.iter().any(|x| x != "bin")
+                }
+                Self::Bin(name) => {
+                    x.target.name == *name && x.target.kind.iter().any(|x| x == "bin")
+                }
+            })
+            .flat_map(|x| x.filenames.iter())
+            .find(|x| x.extension().is_some_and(|x| x == "wasm"))
+            .map(|x| crate::metadata_path(x).to_owned())
+    }
+
+    /// The arguments of `cargo build` that select the target.
+    pub(crate) fn cargo_args(&self) -> Vec<&str> {
+        match self {
+            Self::Lib(_) => vec!["--lib"],
+            Self::Bin(name) => vec!["--bin", name],
+        }
+    }
+
+    /// The name of the WASM file produced by cargo: the libraries are named after their crate
+    /// name, the binaries keep their name.
+    pub(crate) fn wasm_file_name(&self) -> String {
+        match self {
+            Self::Lib(name) => format!("{}.wasm", name.replace('-', "_")),
+            Self::Bin(name) => format!("{}.wasm", name),
+        }
+    }
+}
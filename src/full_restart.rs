@@ -0,0 +1,77 @@
+use crate::backend::BackgroundProcess;
+use crate::{call_hook, report, watch_loop, FileWatcher, Hooks, RunContext, ServeArgs};
+use anyhow::{Context, Result};
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Environment variable set on the command run by [`supervise`].
+const CHILD_ENV: &str = "WASM_RUN_FULL_RESTART_CHILD";
+
+/// Time given to the command to exit after `SIGTERM` before it is killed.
+const KILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether this process is the command restarted by [`supervise`]: it doesn't react to the
+/// changes, the supervisor restarts it instead.
+pub(crate) fn is_child() -> bool {
+    env::var_os(CHILD_ENV).is_some()
+}
+
+/// Run the `serve` command in a child process and restart it entirely (with all the processes
+/// it started) when a watched file changes: the runner is run again with `cargo run` so the
+/// changes of the runner and of the backend are compiled too.
+pub(crate) fn supervise(args: &dyn ServeArgs, hooks: &Hooks) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = FileWatcher::for_serve(args, tx)?;
+
+    call_hook("frontend_watch", || {
+        hooks.call_frontend_watch(&RunContext::from_args(args), &mut watcher)
+    })?;
+    #[cfg(not(feature = "dev-server"))]
+    call_hook("backend_watch", || {
+        hooks.call_backend_watch(&RunContext::from_args(args), &mut watcher)
+    })?;
+
+    let mut child = Some(spawn()?);
+    watch_loop(args, hooks, &mut watcher, rx, |path, _| {
+        log::info!("{} changed: restarting the command", path.display());
+        drop(child.take());
+        report(args.build_args().state(), spawn().map(|x| child = Some(x)));
+    });
+
+    Ok(())
+}
+
+/// The runner with the same arguments: with `cargo run` when it has been started by cargo and
+/// directly otherwise.
+fn spawn() -> Result<BackgroundProcess> {
+    let executable = env::current_exe().context("could not find the current executable")?;
+    let mut command = match env::var_os("CARGO_MANIFEST_DIR") {
+        Some(manifest_dir) => {
+            let mut command = Command::new("cargo");
+            command
+                .arg("run")
+                .arg("--manifest-path")
+                .arg(Path::new(&manifest_dir).join("Cargo.toml"));
+            if let Some(name) = executable.file_stem() {
+                command.arg("--bin").arg(name);
+            }
+            // NOTE: the executable is in `target/<profile directory>/`
+            match executable.parent().and_then(|x| x.file_name()) {
+                Some(dir) if dir == "debug" => {}
+                Some(dir) => {
+                    command.arg("--profile").arg(dir);
+                }
+                None => {}
+            }
+            command.arg("--");
+            command
+        }
+        None => Command::new(&executable),
+    };
+    command.args(env::args_os().skip(1)).env(CHILD_ENV, "1");
+
+    BackgroundProcess::spawn(&mut command, KILL_TIMEOUT).context("could not restart the command")
+}
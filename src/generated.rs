@@ -0,0 +1,65 @@
+//! The listings and the files generated by wasm-run (manifests, service worker, translations,
+//! ...) must be identical across the platforms and the runs of the same build: the files are
+//! listed in the byte order of their relative path with `/` separators (not in the order of the
+//! file system nor of the locale) and the text files are written with LF line endings.
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde_json::Value;
+use std::fs;
+use std::io;
+
+/// The files of `dir` and its subdirectories (the symbolic links are followed) with their path
+/// relative to `dir` (see [`relative_path`]), sorted by this path.
+pub(crate) fn files(dir: &Utf8Path) -> io::Result<Vec<(String, Utf8PathBuf)>> {
+    let mut files = Vec::new();
+    collect(dir, dir, &mut files)?;
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(files)
+}
+
+fn collect(
+    root: &Utf8Path,
+    dir: &Utf8Path,
+    files: &mut Vec<(String, Utf8PathBuf)>,
+) -> io::Result<()> {
+    for entry in dir.read_dir_utf8()? {
+        let path = entry?.into_path();
+        if path.is_dir() {
+            collect(root, &path, files)?;
+        } else {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            files.push((relative_path(relative), path));
+        }
+    }
+    Ok(())
+}
+
+/// A relative path with `/` separators on every platform.
+pub(crate) fn relative_path(path: &Utf8Path) -> String {
+    path.components()
+        .map(|x| x.as_str())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Write a generated text file with LF line endings and a final line ending.
+pub(crate) fn write(path: &Utf8Path, contents: &str) -> Result<()> {
+    let mut contents = contents.replace("\r\n", "\n");
+    if !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    fs::write(path, contents).with_context(|| format!("could not write `{}`", path))
+}
+
+/// Write a generated JSON file, indented if `pretty`. The keys of the objects are sorted.
+pub(crate) fn write_json(path: &Utf8Path, value: &Value, pretty: bool) -> Result<()> {
+    // NOTE: the objects of serde_json are sorted maps (the feature `preserve_order` is not
+    //       enabled)
+    let json = if pretty {
+        serde_json::to_string_pretty(value)?
+    } else {
+        serde_json::to_string(value)?
+    };
+    write(path, &json)
+}
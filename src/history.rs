@@ -0,0 +1,143 @@
+use crate::{BuildArgs, BuildProfile};
+#[cfg(feature = "dev-server")]
+use anyhow::bail;
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use serde_json::json;
+#[cfg(feature = "dev-server")]
+use serde_json::Value;
+use std::fs;
+use std::process::Command;
+use std::time;
+
+/// Directory of the retained builds in the target directory.
+fn history_path(args: &dyn BuildArgs) -> Utf8PathBuf {
+    args.target_path().join("wasm-run").join("history")
+}
+
+/// Identifiers of the retained builds, oldest first.
+fn ids(args: &dyn BuildArgs) -> Vec<u64> {
+    let mut ids: Vec<u64> = fs::read_dir(history_path(args))
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.ok())
+        .filter_map(|x| x.file_name().to_str().and_then(|x| x.parse().ok()))
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Copy the build directory to the history and remove the oldest builds to keep only
+/// [`BuildArgs::keep_builds`] of them.
+pub(crate) fn record(args: &dyn BuildArgs, profile: BuildProfile) -> Result<()> {
+    let keep = args.keep_builds();
+    if keep == 0 {
+        return Ok(());
+    }
+
+    let mut ids = ids(args);
+    let id = ids.last().map_or(1, |x| x + 1);
+    let path = history_path(args).join(id.to_string());
+    fs::create_dir_all(&path).with_context(|| format!("could not create directory `{}`", path))?;
+
+    let mut options = fs_extra::dir::CopyOptions::new();
+    options.content_only = true;
+    fs_extra::dir::copy(args.build_path(), &path, &options)
+        .with_context(|| format!("could not copy the build to `{}`", path))?;
+
+    let created = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default();
+    let info = json!({
+        "id": id,
+        "created": created,
+        "profile": format!("{:?}", profile).to_lowercase(),
+        "commit": git_commit(args),
+    });
+    let info_path = history_path(args).join(format!("{}.json", id));
+    crate::generated::write_json(&info_path, &info, true)?;
+
+    ids.push(id);
+    for old in &ids[..ids.len().saturating_sub(keep)] {
+        let _ = fs::remove_dir_all(history_path(args).join(old.to_string()));
+        let _ = fs::remove_file(history_path(args).join(format!("{}.json", old)));
+    }
+
+    log::info!("Build retained in the history as {}", id);
+
+    Ok(())
+}
+
+/// Replace the content of the build directory by the retained build `id`.
+#[cfg(feature = "dev-server")]
+pub(crate) fn restore(args: &dyn BuildArgs, id: u64) -> Result<()> {
+    let path = history_path(args).join(id.to_string());
+    if !path.is_dir() {
+        let available = ids(args)
+            .iter()
+            .map(|id| describe(args, *id))
+            .collect::<Vec<_>>();
+        if available.is_empty() {
+            bail!(
+                "build {} not found: there is no build in the history (use `--keep-builds`)",
+                id
+            );
+        }
+        bail!(
+            "build {} not found in the history. Available builds:\n  {}",
+            id,
+            available.join("\n  ")
+        );
+    }
+
+    let build_path = args.build_path();
+    crate::out_dir::prepare(build_path, args.metadata())?;
+    let mut options = fs_extra::dir::CopyOptions::new();
+    options.content_only = true;
+    fs_extra::dir::copy(&path, build_path, &options)
+        .with_context(|| format!("could not restore the build `{}`", path))?;
+
+    log::info!("Serving the build {}", describe(args, id));
+
+    Ok(())
+}
+
+/// A line describing a retained build: its identifier, profile and commit.
+#[cfg(feature = "dev-server")]
+fn describe(args: &dyn BuildArgs, id: u64) -> String {
+    let info: Value = fs::read_to_string(history_path(args).join(format!("{}.json", id)))
+        .ok()
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default();
+    let mut line = id.to_string();
+    if let Some(profile) = info["profile"].as_str() {
+        line.push_str(&format!(" ({})", profile));
+    }
+    if let Some(commit) = info["commit"].as_str() {
+        line.push_str(&format!(" at commit {}", commit));
+    }
+    line
+}
+
+/// Short hash of the git commit of the frontend, with a `+` if there are uncommitted changes.
+fn git_commit(args: &dyn BuildArgs) -> Option<String> {
+    let frontend_dir = args.frontend_package().manifest_path.parent().unwrap();
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(frontend_dir)
+            .output()
+            .ok()
+            .filter(|x| x.status.success())
+            .map(|x| String::from_utf8_lossy(&x.stdout).trim().to_string())
+    };
+
+    let commit = git(&["rev-parse", "--short", "HEAD"])?;
+    let dirty = git(&["status", "--porcelain"]).is_some_and(|x| !x.is_empty());
+    Some(if dirty {
+        format!("{}+", commit)
+    } else {
+        commit
+    })
+}
@@ -0,0 +1,570 @@
+use crate::BuildArgs;
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options of the compilation of the translations (feature `i18n`).
+///
+/// The translations are read from the directory `source` of the frontend package: either one file
+/// per locale (`i18n/en-US.ftl`, `i18n/fr.po`) or one directory per locale whose files are merged
+/// (`i18n/en-US/main.ftl`, `i18n/en-US/errors.ftl`). Every locale is compiled to a JSON object
+/// `<destination>/<locale>.json` in the build directory:
+///
+///  *  Fluent (`.ftl`): the messages, terms (`-brand`) and attributes (`login.placeholder`) are
+///     mapped to their pattern. The patterns are not resolved: they are meant to be formatted by
+///     a Fluent implementation at runtime.
+///  *  gettext (`.po`): the `msgid` are mapped to their `msgstr` (or to the array of the plural
+///     forms). The `msgctxt` is prepended to the `msgid` with the separator `\u0004` like gettext
+///     does. The untranslated and fuzzy entries are skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct I18nOptions {
+    /// Directory of the translations (relative to the directory of the frontend package).
+    pub source: Utf8PathBuf,
+    /// Directory of the JSON bundles (relative to the build directory).
+    pub destination: Utf8PathBuf,
+    /// Only these locales are compiled. All of them are compiled if empty.
+    pub locales: Vec<String>,
+    /// Indent the JSON bundles.
+    pub pretty: bool,
+}
+
+impl Default for I18nOptions {
+    fn default() -> Self {
+        Self::new("i18n", "i18n")
+    }
+}
+
+impl I18nOptions {
+    /// Compile the translations of the directory `source` of the frontend package to the
+    /// directory `destination` of the build directory.
+    pub fn new(source: impl Into<Utf8PathBuf>, destination: impl Into<Utf8PathBuf>) -> Self {
+        Self {
+            source: source.into(),
+            destination: destination.into(),
+            locales: Vec::new(),
+            pretty: false,
+        }
+    }
+
+    /// Only compile this locale.
+    pub fn locale(mut self, locale: impl Into<String>) -> Self {
+        self.locales.push(locale.into());
+        self
+    }
+
+    /// Indent the JSON bundles.
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+}
+
+/// Compile the translations to JSON bundles in the build directory.
+pub(crate) fn compile(args: &dyn BuildArgs, options: &I18nOptions) -> Result<()> {
+    let frontend_dir = args.frontend_package().manifest_path.parent().unwrap();
+    let source = frontend_dir.join(&options.source);
+    if !source.is_dir() {
+        return Ok(());
+    }
+
+    let destination = args.build_path().join(&options.destination);
+    let mut count = 0;
+
+    log::info!("Compiling translations from {:?}", source);
+    for path in sorted_entries(&source)? {
+        let (locale, files) = if path.is_dir() {
+            let files = sorted_entries(&path)?
+                .into_iter()
+                .filter(|x| translation_format(x).is_some())
+                .collect();
+            (file_name(&path), files)
+        } else if translation_format(&path).is_some() {
+            (file_stem(&path), vec![path.clone()])
+        } else {
+            continue;
+        };
+
+        if !options.locales.is_empty() && !options.locales.contains(&locale) {
+            continue;
+        }
+
+        let mut bundle = Map::new();
+        for file in files {
+            let content = fs::read_to_string(&file)
+                .with_context(|| format!("could not read `{}`", file.display()))?;
+            let messages = match translation_format(&file) {
+                Some(Format::Fluent) => parse_fluent(&content),
+                Some(Format::Gettext) => parse_po(&content),
+                None => unreachable!("the files are filtered by format; qed"),
+            }
+            .map_err(|(line, err)| anyhow::anyhow!("{}:{}: {}", file.display(), line, err))?;
+            bundle.extend(messages);
+        }
+
+        fs::create_dir_all(&destination)
+            .with_context(|| format!("could not create directory `{}`", destination))?;
+        let bundle_path = destination.join(format!("{}.json", locale));
+        crate::generated::write_json(&bundle_path, &Value::Object(bundle), options.pretty)?;
+        count += 1;
+    }
+
+    log::info!("Compiled the translations of {} locale(s)", count);
+
+    Ok(())
+}
+
+enum Format {
+    Fluent,
+    Gettext,
+}
+
+fn translation_format(path: &Path) -> Option<Format> {
+    if !path.is_file() {
+        return None;
+    }
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("ftl") => Some(Format::Fluent),
+        Some("po") => Some(Format::Gettext),
+        _ => None,
+    }
+}
+
+fn sorted_entries(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(path)
+        .with_context(|| format!("could not read directory `{}`", path.display()))?
+        .map(|x| x.map(|x| x.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem().unwrap().to_string_lossy().into_owned()
+}
+
+type ParseResult = Result<Vec<(String, Value)>, (usize, String)>;
+
+/// Parse the messages, terms and attributes of a Fluent resource.
+fn parse_fluent(content: &str) -> ParseResult {
+    fn is_identifier(s: &str) -> bool {
+        let s = s.strip_prefix('-').unwrap_or(s);
+        s.starts_with(|c: char| c.is_ascii_alphabetic())
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+
+    struct Entry {
+        id: String,
+        line: usize,
+        value: String,
+        attributes: Vec<(String, String)>,
+    }
+
+    let mut messages = Vec::new();
+    let mut current: Option<Entry> = None;
+
+    let mut flush = |current: &mut Option<Entry>| {
+        if let Some(Entry {
+            id,
+            line,
+            value,
+            attributes,
+        }) = current.take()
+        {
+            if value.is_empty() && attributes.is_empty() {
+                return Err((line, format!("the message `{}` has no value", id)));
+            }
+            if !value.is_empty() {
+                messages.push((id.clone(), Value::String(value)));
+            }
+            for (name, value) in attributes {
+                messages.push((format!("{}.{}", id, name), Value::String(value)));
+            }
+        }
+        Ok(())
+    };
+
+    for (i, line) in content.lines().enumerate() {
+        let line_number = i + 1;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if line.starts_with(' ') {
+            let Entry {
+                value, attributes, ..
+            } = current
+                .as_mut()
+                .ok_or((line_number, "unexpected indented line".to_string()))?;
+            // NOTE: the indentation of the continuation lines is kept so the patterns can be
+            //       parsed again by a Fluent implementation
+            let line = line.trim_end();
+            match line
+                .trim_start()
+                .strip_prefix('.')
+                .and_then(|x| x.split_once('='))
+            {
+                Some((name, attribute)) if is_identifier(name.trim()) => {
+                    attributes.push((name.trim().to_string(), attribute.trim().to_string()));
+                }
+                _ => match attributes.last_mut() {
+                    Some((_, attribute)) => push_line(attribute, line),
+                    None => push_line(value, line),
+                },
+            }
+            continue;
+        }
+
+        flush(&mut current)?;
+
+        if line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((id, value)) if is_identifier(id.trim()) => {
+                current = Some(Entry {
+                    id: id.trim().to_string(),
+                    line: line_number,
+                    value: value.trim().to_string(),
+                    attributes: Vec::new(),
+                });
+            }
+            _ => return Err((line_number, "expected a message `id = value`".to_string())),
+        }
+    }
+    flush(&mut current)?;
+
+    Ok(messages)
+}
+
+fn push_line(pattern: &mut String, line: &str) {
+    if !pattern.is_empty() {
+        pattern.push('\n');
+    }
+    pattern.push_str(line);
+}
+
+/// Parse the entries of a gettext catalog.
+fn parse_po(content: &str) -> ParseResult {
+    #[derive(Default)]
+    struct Entry {
+        fuzzy: bool,
+        msgctxt: Option<String>,
+        msgid: Option<String>,
+        msgstr: Vec<String>,
+        plural: bool,
+    }
+
+    fn unquote(s: &str, line: usize) -> Result<String, (usize, String)> {
+        let s = s
+            .trim()
+            .strip_prefix('"')
+            .and_then(|x| x.strip_suffix('"'))
+            .ok_or((line, "expected a quoted string".to_string()))?;
+        let mut unquoted = String::with_capacity(s.len());
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unquoted.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => unquoted.push('\n'),
+                Some('t') => unquoted.push('\t'),
+                Some('r') => unquoted.push('\r'),
+                Some(c @ ('"' | '\\')) => unquoted.push(c),
+                _ => return Err((line, "invalid escape sequence".to_string())),
+            }
+        }
+        Ok(unquoted)
+    }
+
+    let mut messages = Vec::new();
+    let mut flush = |entry: Entry| {
+        let msgid = match entry.msgid {
+            // NOTE: the entry without msgid is the header of the catalog
+            Some(msgid) if !msgid.is_empty() => msgid,
+            _ => return,
+        };
+        if entry.fuzzy || entry.msgstr.iter().all(|x| x.is_empty()) {
+            return;
+        }
+        let key = match entry.msgctxt {
+            Some(msgctxt) => format!("{}\u{4}{}", msgctxt, msgid),
+            None => msgid,
+        };
+        let value = if entry.plural {
+            Value::Array(entry.msgstr.into_iter().map(Value::String).collect())
+        } else {
+            Value::String(entry.msgstr.into_iter().next().unwrap_or_default())
+        };
+        messages.push((key, value));
+    };
+
+    /// Field of the entry continued by the lines that only contain a quoted string.
+    #[derive(Clone, Copy)]
+    enum Field {
+        Msgctxt,
+        Msgid,
+        Msgstr(usize),
+    }
+
+    impl Entry {
+        fn field(&mut self, field: Field) -> Option<&mut String> {
+            match field {
+                Field::Msgctxt => self.msgctxt.as_mut(),
+                Field::Msgid => self.msgid.as_mut(),
+                Field::Msgstr(index) => self.msgstr.get_mut(index),
+            }
+        }
+    }
+
+    let mut entry = Entry::default();
+    let mut last = None;
+
+    for (i, line) in content.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('"') {
+            let continued = unquote(line, line_number)?;
+            match last.and_then(|x| entry.field(x)) {
+                Some(last) => last.push_str(&continued),
+                None => return Err((line_number, "unexpected string".to_string())),
+            }
+            continue;
+        }
+
+        // NOTE: a comment, a context or a second msgid starts the next entry
+        let starts_entry =
+            line.starts_with('#') || line.starts_with("msgctxt") || line.starts_with("msgid ");
+        if starts_entry && entry.msgid.is_some() {
+            flush(std::mem::take(&mut entry));
+        }
+        last = None;
+
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(flags) = comment.strip_prefix(',') {
+                entry.fuzzy |= flags.split(',').any(|x| x.trim() == "fuzzy");
+            }
+            continue;
+        }
+
+        let (keyword, value) = line
+            .split_once(char::is_whitespace)
+            .ok_or((line_number, "expected a keyword and a string".to_string()))?;
+        let value = unquote(value, line_number)?;
+
+        match keyword {
+            "msgctxt" => {
+                entry.msgctxt = Some(value);
+                last = Some(Field::Msgctxt);
+            }
+            "msgid" => {
+                entry.msgid = Some(value);
+                last = Some(Field::Msgid);
+            }
+            "msgid_plural" => entry.plural = true,
+            "msgstr" => {
+                entry.msgstr = vec![value];
+                last = Some(Field::Msgstr(0));
+            }
+            _ => match keyword
+                .strip_prefix("msgstr[")
+                .and_then(|x| x.strip_suffix(']'))
+                .and_then(|x| x.parse::<usize>().ok())
+            {
+                Some(index) => {
+                    if entry.msgstr.len() <= index {
+                        entry.msgstr.resize(index + 1, String::new());
+                    }
+                    entry.msgstr[index] = value;
+                    last = Some(Field::Msgstr(index));
+                }
+                None => return Err((line_number, format!("unknown keyword `{}`", keyword))),
+            },
+        }
+    }
+
+    flush(entry);
+
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn string(key: &str, value: &str) -> (String, Value) {
+        (key.to_string(), Value::String(value.to_string()))
+    }
+
+    #[test]
+    fn parse_fluent_messages() {
+        let content = "\
+# Comment
+hello = Hello, { $name }!
+
+-brand = Firefox
+login = Log in
+    .placeholder = email@example.com
+    .title = Log in to { -brand }
+";
+        assert_eq!(
+            parse_fluent(content),
+            Ok(vec![
+                string("hello", "Hello, { $name }!"),
+                string("-brand", "Firefox"),
+                string("login", "Log in"),
+                string("login.placeholder", "email@example.com"),
+                string("login.title", "Log in to { -brand }"),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_fluent_multiline_patterns() {
+        let content = "\
+emails =
+    { $count ->
+        [one] One email
+       *[other] { $count } emails
+    }
+about = First line
+    second line
+    .title = Title
+        continued
+";
+        assert_eq!(
+            parse_fluent(content),
+            Ok(vec![
+                string(
+                    "emails",
+                    "    { $count ->\n        [one] One email\n       *[other] { $count } \
+                    emails\n    }"
+                ),
+                string("about", "First line\n    second line"),
+                string("about.title", "Title\n        continued"),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_fluent_attributes_only() {
+        assert_eq!(
+            parse_fluent("button =\n    .label = OK\n"),
+            Ok(vec![string("button.label", "OK")])
+        );
+    }
+
+    #[test]
+    fn parse_fluent_errors() {
+        assert_eq!(
+            parse_fluent("    indented = value"),
+            Err((1, "unexpected indented line".to_string()))
+        );
+        assert_eq!(
+            parse_fluent("hello = Hello\nnot a message\n"),
+            Err((2, "expected a message `id = value`".to_string()))
+        );
+        assert_eq!(
+            parse_fluent("1st = invalid identifier"),
+            Err((1, "expected a message `id = value`".to_string()))
+        );
+        assert_eq!(
+            parse_fluent("# Comment\n\nempty =\nother = value\n"),
+            Err((3, "the message `empty` has no value".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_po_entries() {
+        let content = r#"
+msgid ""
+msgstr ""
+"Content-Type: text/plain; charset=UTF-8\n"
+
+# Translator comment
+msgid "Hello"
+msgstr "Bonjour"
+
+msgctxt "menu"
+msgid "Open"
+msgstr "Ouvrir"
+
+msgid "Long"
+msgstr ""
+"First line\n"
+"\"second\" line"
+
+msgid "Untranslated"
+msgstr ""
+
+#, fuzzy
+msgid "Fuzzy"
+msgstr "Flou"
+
+msgid "One file"
+msgid_plural "{count} files"
+msgstr[0] "Un fichier"
+msgstr[1] "{count} fichiers"
+"#;
+        assert_eq!(
+            parse_po(content),
+            Ok(vec![
+                string("Hello", "Bonjour"),
+                string("menu\u{4}Open", "Ouvrir"),
+                string("Long", "First line\n\"second\" line"),
+                (
+                    "One file".to_string(),
+                    json!(["Un fichier", "{count} fichiers"])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_po_escapes() {
+        assert_eq!(
+            parse_po("msgid \"a\\tb\"\nmsgstr \"c\\\\d\\re\""),
+            Ok(vec![string("a\tb", "c\\d\re")])
+        );
+    }
+
+    #[test]
+    fn parse_po_errors() {
+        assert_eq!(
+            parse_po("\"orphan\""),
+            Err((1, "unexpected string".to_string()))
+        );
+        assert_eq!(
+            parse_po("msgid \"a\"\nmsgstr unquoted"),
+            Err((2, "expected a quoted string".to_string()))
+        );
+        assert_eq!(
+            parse_po("msgid \"a\\x\""),
+            Err((1, "invalid escape sequence".to_string()))
+        );
+        assert_eq!(
+            parse_po("msgid \"a\"\nmsgfoo \"b\""),
+            Err((2, "unknown keyword `msgfoo`".to_string()))
+        );
+        assert_eq!(
+            parse_po("msgid"),
+            Err((1, "expected a keyword and a string".to_string()))
+        );
+    }
+}
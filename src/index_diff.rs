@@ -0,0 +1,125 @@
+use crate::ui::paint;
+
+/// Number of unchanged lines displayed around the changes.
+const CONTEXT: usize = 3;
+
+/// Maximum size of the comparison table: the diff is not computed for bigger files.
+const MAX_TABLE_SIZE: usize = 4_000_000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Log a unified diff between the previous and the new `index.html` (debug level only).
+pub(crate) fn log_diff(old: &str, new: &str) {
+    if !log::log_enabled!(log::Level::Debug) || old == new {
+        return;
+    }
+
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+
+    if (old.len() + 1) * (new.len() + 1) > MAX_TABLE_SIZE {
+        log::debug!("index.html changed (too large to display the diff)");
+        return;
+    }
+
+    let lines = diff(&old, &new);
+    let mut output = String::from("index.html changed:\n--- previous build\n+++ current build");
+
+    for hunk in hunks(&lines) {
+        let (old_start, new_start) = lines[..hunk.start]
+            .iter()
+            .fold((1, 1), |(o, n), x| match x {
+                Line::Same(_) => (o + 1, n + 1),
+                Line::Removed(_) => (o + 1, n),
+                Line::Added(_) => (o, n + 1),
+            });
+        let old_len = lines[hunk.clone()]
+            .iter()
+            .filter(|x| !matches!(x, Line::Added(_)))
+            .count();
+        let new_len = lines[hunk.clone()]
+            .iter()
+            .filter(|x| !matches!(x, Line::Removed(_)))
+            .count();
+
+        output.push_str(&paint(
+            "36",
+            &format!(
+                "\n@@ -{},{} +{},{} @@",
+                old_start, old_len, new_start, new_len
+            ),
+        ));
+        for line in &lines[hunk] {
+            output.push('\n');
+            match line {
+                Line::Same(x) => output.push_str(&format!(" {}", x)),
+                Line::Removed(x) => output.push_str(&paint("31", &format!("-{}", x))),
+                Line::Added(x) => output.push_str(&paint("32", &format!("+{}", x))),
+            }
+        }
+    }
+
+    log::debug!("{}", output);
+}
+
+/// Compute the line diff using the longest common subsequence.
+fn diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let mut table = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            table[i][j] = if old[i] == new[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                table[i + 1][j].max(table[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if table[i + 1][j] >= table[i][j + 1] {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|x| Line::Removed(x)));
+    lines.extend(new[j..].iter().map(|x| Line::Added(x)));
+
+    lines
+}
+
+/// Group the changes with their context lines.
+fn hunks(lines: &[Line]) -> Vec<std::ops::Range<usize>> {
+    let mut hunks: Vec<std::ops::Range<usize>> = Vec::new();
+
+    for (i, _) in lines
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| !matches!(x, Line::Same(_)))
+    {
+        let start = i.saturating_sub(CONTEXT);
+        let end = (i + CONTEXT + 1).min(lines.len());
+
+        match hunks.last_mut() {
+            Some(hunk) if hunk.end >= start => hunk.end = end,
+            _ => hunks.push(start..end),
+        }
+    }
+
+    hunks
+}
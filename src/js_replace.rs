@@ -0,0 +1,47 @@
+use crate::Config;
+use anyhow::{bail, Context, Result};
+
+/// The replacements `js_replace` of the configuration, in the order of declaration:
+///
+/// ```toml
+/// [package.metadata.wasm-run]
+/// js_replace = [{ from = "__PUBLIC_PATH__", to = "/" }]
+///
+/// [package.metadata.wasm-run.release]
+/// js_replace = [{ from = "__PUBLIC_PATH__", to = "/my-app/" }]
+/// ```
+pub(crate) fn from_config(config: &Config) -> Result<Vec<(String, String)>> {
+    let value = config.get("js_replace");
+    if value.is_null() {
+        return Ok(Vec::new());
+    }
+
+    value
+        .as_array()
+        .context("`js_replace` must be an array of `{ from = \"...\", to = \"...\" }`")?
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let (from, to) = match (x["from"].as_str(), x["to"].as_str()) {
+                (Some(from), Some(to)) => (from, to),
+                _ => bail!("`js_replace[{}]` must have the strings `from` and `to`", i),
+            };
+            if from.is_empty() {
+                bail!("`js_replace[{}].from` cannot be empty", i);
+            }
+            Ok((from.to_owned(), to.to_owned()))
+        })
+        .collect()
+}
+
+/// Apply the replacements one after the other on the JS generated by wasm-bindgen.
+pub(crate) fn apply(mut js: String, replacements: &[(String, String)]) -> String {
+    for (from, to) in replacements {
+        if !js.contains(from.as_str()) {
+            log::warn!("`{}` of `js_replace` is not in the generated JS", from);
+            continue;
+        }
+        js = js.replace(from.as_str(), to);
+    }
+    js
+}
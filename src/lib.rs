@@ -55,14 +55,218 @@
 //!     ["run-an-example"](https://github.com/IMI-eRnD-Be/wasm-run/blob/main/examples/run-an-example.rs)
 //!     example.
 //!  *  If you want to use your own backend you will need to disable the `dev-server` feature
-//!     by disabling the default features. You can use the `full-restart` feature to force the
+//!     by disabling the default features. You can use `serve --full-restart` to force the
 //!     backend to also be recompiled when a file changes (otherwise only the frontend is
 //!     re-compiled). You will also need to specify `run_server` to the macro arguments to run your
 //!     backend.
 //!  *  You can add commands to the CLI by adding variants in the `enum`.
+//!  *  `Cli::build()` and `Cli::build_with_args()` (generated by the macro) return a
+//!     [`BuildOutput`]: the build directory, the paths of the WASM and of the JS (fingerprinted
+//!     or not), every file of the build directory, the profile and the duration of the build, for
+//!     the commands that package, embed or deploy the frontend.
+//!  *  `Cli::serve()` and `Cli::serve_with_args()` (generated by the macro like `Cli::build()`)
+//!     start the `serve` command in the background, for example in an integration test: the
+//!     `ServeHandle` returned gives the address actually bound (with `--bind-random-port`) and
+//!     stops the server, the watchers and the backend with `shutdown()` or when it is dropped.
+//!  *  `ServeHandle::rebuild().await` rebuilds the frontend and waits for the end of the build
+//!     and `ServeHandle::wait_idle().await` waits until the changes made before the call have been
+//!     reported by the file watcher and rebuilt: an end-to-end test can change a source file and
+//!     check the served output without arbitrary sleeps. Both return the error of the build if it failed.
+//!  *  The hook `shutdown` ([`Hooks::shutdown`] or the argument `shutdown` of the macro) runs once
+//!     when the process stops: at the end of a command or on Ctrl-C and termination (`SIGINT`,
+//!     `SIGTERM`, `SIGHUP` or the console events on Windows). It does not run on a hard exit.
+//!  *  If you don't want to use the macro and the CLI (for example with the xtask pattern), the
+//!     module [`tasks`] provides plain functions to build, watch and serve your project and
+//!     [`Builder`] drives the pipeline without any global state:
+//!     `Builder::new(metadata).frontend("app").profile(BuildProfile::Release).build()?`.
+//!  *  The arguments of the commands use a global [`WorkspaceContext`] initialized by the macro. A
+//!     context can be created explicitly and given to the arguments with `with_context` to build
+//!     several projects in the same process.
+//!  *  If you have a backend, `cargo run -- package-image` builds the frontend and the backend
+//!     (for `x86_64-unknown-linux-musl` by default) and packages them in a container image
+//!     using Docker. This can be customized by overriding [`PackageArgs`].
+//!  *  With `#[wasm_run::main(build_workspace)]`, `cargo run -- build-workspace` builds all the
+//!     packages of the workspace that have a `[package.metadata.wasm-run]` section, one at a time
+//!     and after the packages they depend on, and prints a summary. They are built with the
+//!     `dev` profile unless `--release` or `--profile <name>` is used, and the arguments after
+//!     `--` are given to the build of every package.
+//!  *  `cargo run -- config validate` checks `WasmRun.toml` and `[package.metadata.wasm-run]`
+//!     and reports every invalid setting with its file and line. `config print --resolved`
+//!     prints the settings of a profile with the environment and the arguments applied, and
+//!     `config schema` prints the JSON Schema of the configuration for the editors.
 //!  *  You can add parameters to the `Build` and `Serve` commands by overriding them. Please check
 //!     the documentation on the macro `main`.
+//!  *  The hooks can be async functions: `#[wasm_run::main(post_build = async my_post_build)]`
+//!     runs them to completion on the runtime of the development server (`wasm_run::block_on`).
+//!  *  A hook can extend the default one instead of replacing it:
+//!     `Hooks::default().after_post_build(copy_more_files)` runs `copy_more_files` after the static
+//!     files were copied.
 //!  *  If you run `cargo run -- serve --profiling`, the WASM will be optimized.
+//!  *  If you run `cargo run -- serve --release`, the WASM is built with the release profile
+//!     and optimized on every rebuild, so you can test the final artifact with the development
+//!     server.
+//!  *  With `--background-wasm-opt`, the unoptimized build of `serve --release` (or `--profiling`)
+//!     is served immediately and replaced when wasm-opt is finished. The hook `post_optimize` only
+//!     receives the optimized WASM.
+//!  *  If the debug logs are enabled (`RUST_LOG=wasm_run=debug`), a diff of the `index.html` is
+//!     displayed when it changed between two builds.
+//!  *  The paths watched in the hooks `frontend_watch` and `backend_watch` that don't exist
+//!     yet are watched as soon as they are created. The ignored directories (the build and target
+//!     directories, `node_modules`, the patterns of the ignore files and `watch_ignore`) of the
+//!     directories watched recursively are not watched at all.
+//!  *  To debug the WASM at the source level in the browser, use `--dwarf`: the DWARF debugging
+//!     information is kept in the WASM and wasm-opt is skipped. In Chrome, this requires the
+//!     extension "C/C++ DevTools Support (DWARF)". If a source map `<file>.wasm.map` is next to a
+//!     WASM file, the development server advertises it with the header `SourceMap`.
+//!  *  The frontend can be a binary (a `fn main()` or `#[wasm_bindgen(start)]` frontend) instead
+//!     of a library: the only binary of a package without library is built,
+//!     `--frontend-bin <name>` selects one.
+//!  *  Redirects and rewrites (`/old -> /new`, `/docs/* -> https://docs.example.com/*`) can be
+//!     declared in `[package.metadata.wasm-run]` of the frontend. They are applied by the
+//!     development server and written to the file `_redirects` of the build directory (Netlify,
+//!     Cloudflare Pages). Check [`BuildArgs::redirects`].
+//!  *  The directory `static` of the frontend is copied to the build directory. More directories,
+//!     destinations and filters can be configured with [`BuildArgs::asset_rules`]. If no
+//!     `index.html` is provided, a default one is generated.
+//!  *  To test the loading states of your app on a slow network, `--latency <ms>` delays the
+//!     responses of the development server and `--throttle <kbps>` limits their bandwidth.
+//!     Both can be limited to some paths with a glob pattern: `--throttle '*.wasm=1000'`.
+//!  *  To make a Progressive Web App, add a `[pwa]` table to `WasmRun.toml` or
+//!     `[package.metadata.wasm-run.pwa]` to the frontend (check [`PwaConfig`]): a web app
+//!     manifest and a service worker that precaches the build
+//!     are generated, and the default `index.html` registers them in release builds.
+//!  *  The outputs of wasm-bindgen and wasm-opt of the optimized builds are cached in
+//!     `target/wasm-run-cache/`, keyed on the WASM produced by cargo and the settings: building
+//!     the same sources again (for example from `package-image` or a deployment script) doesn't
+//!     run wasm-opt again. The outputs of wasm-opt are also cached by input WASM and settings in
+//!     `target/wasm-run/wasm-opt-cache/`, for all the builds (switching between `--profiling` and
+//!     a release build, the background optimization of `serve`). `--no-build-cache` disables both.
+//!  *  `--timings` logs the duration of every stage after each build (cargo, wasm-bindgen,
+//!     wasm-opt, the hooks, the assets, SASS...) and `--timings html` also writes a report in
+//!     `target/wasm-run/timings/`, like `cargo build --timings`.
+//!  *  `cargo run -- compare-opt` optimizes the WASM of the last release build with several
+//!     settings of wasm-opt (`O2`, `O3` and `Oz`, with and without `-g`, and the configured ones)
+//!     at the same time and reports their sizes and durations, to choose the `wasm_opt` settings
+//!     of the profiles. `--level <level>` selects the levels and `--input <file>` another WASM.
+//!  *  The global flags `-q` and `-v` adjust the logs of all the commands and the output of
+//!     cargo: `-q` only logs the warnings and summarizes the warnings of the compiler, `-qq`
+//!     hides everything but the errors, `-v` and `-vv` log the details and run cargo with
+//!     `--verbose`. `RUST_LOG` still takes precedence over them.
+//!  *  The steps of the builds are displayed as `[1/5] cargo build`, `[2/5] wasm-bindgen`... with a
+//!     spinner on a terminal and plain lines otherwise. `--color auto|always|never` sets the use of
+//!     the colors of the output, `NO_COLOR` disables them with `auto`.
+//!  *  With `--keep-builds <n>`, the last builds are retained in the target directory and
+//!     `cargo run -- serve --from-history <id>` serves one of them without building anything
+//!     (with the `dev-server` feature). This makes it easy to find when a regression appeared.
+//!  *  `index` of the configuration (or `--index`) is the source of the `index.html`, copied
+//!     after the assets. It is relative to the file that declares it, so the build doesn't depend
+//!     on the directory the command is run from.
+//!  *  The output directory is `--out-dir` (`--build-path` is an alias) relative to the current
+//!     directory, otherwise `build_path` (or `out_dir`) of the configuration. `build-workspace
+//!     --out-dir <dir>` builds every package in `<dir>/<package>`. A directory that contains the
+//!     workspace or one of its packages is never emptied.
+//!  *  The target directory is the one of cargo (`CARGO_TARGET_DIR`, `build.target-dir`) or
+//!     `CARGO_TARGET_DIR` (or `CARGO_BUILD_TARGET_DIR`) of the `env` of the configuration. It is
+//!     given to every cargo command run by wasm-run, so the artifacts and the caches are found in
+//!     a shared target directory too, and it is excluded from the watchers. The build scripts
+//!     that locate the build directory of a frontend use the same resolution.
+//!  *  Backends that embed the static files locate the build directory with
+//!     `wasm_run::embed::build_script("<frontend>")` in their build script, then use
+//!     `#[folder = "$WASM_RUN_BUILD_PATH"]` for `RustEmbed`. The cargo commands of the backend
+//!     run by wasm-run also receive it in `WASM_RUN_BUILD_PATH`.
+//!  *  With `--per-branch`, the build directory is namespaced by git branch (`build/<branch>/`)
+//!     so switching branches doesn't overwrite the artifacts of another branch. The resolved
+//!     path is logged and written in the `status.json` of the development server.
+//!  *  With `#[wasm_run::main(detect_frontend)]`, the frontend package is not hard-coded: the
+//!     package of the workspace that can be built to WASM is detected at runtime, or selected
+//!     with `--package <name>` if there are several of them.
+//!  *  The build directory, the port, the watch ignores, the redirects, the settings of wasm-opt
+//!     and the environment variables of the cargo commands can be declared in a `WasmRun.toml`
+//!     file (in the frontend package or at the root of the workspace) or in
+//!     `[package.metadata.wasm-run]` of the frontend. The command-line arguments take precedence.
+//!  *  The development server forwards the path prefixes of `proxy` in `WasmRun.toml` to other
+//!     HTTP servers: `[proxy]` with `"/api" = "http://localhost:8000"` sends `/api/users` to
+//!     `http://localhost:8000/api/users` (check [`ProxyRule`]).
+//!  *  If the app relies on JS globals provided by the host page (analytics, bridge objects of a
+//!     webview), declare them with `host_globals = ["analytics.track"]` in `WasmRun.toml`: the
+//!     development server defines the missing ones with shims that log a warning when they are
+//!     used.
+//!  *  With `--critical-css` (or `critical_css` in `WasmRun.toml`), the small stylesheets
+//!     linked by the `index.html` are inlined. Only the rules matching a selector allowlist are
+//!     inlined for the bigger ones, which are then loaded without blocking the rendering.
+//!  *  The JavaScript dependencies of `external` in `WasmRun.toml` (a CDN URL, an optional
+//!     import map specifier and an optional integrity) are added to the `index.html` as an import
+//!     map or `<script>` tags with Subresource Integrity. `serve --cache-external` serves the
+//!     local copies of the files instead, for working offline.
+//!  *  The JS generated by wasm-bindgen can be modified before the hook `post_build` writes it:
+//!     `js_replace = [{ from = "__PUBLIC_PATH__", to = "/my-app/" }]` in `WasmRun.toml` replaces
+//!     strings (in the order of declaration, also in the sections of the profiles) and the
+//!     hook `js_transform` receives the result to transform it further.
+//!  *  The sections `dev`, `profiling` and `release` of the configuration override the settings
+//!     for a build profile: for example `base_url`, the `features` of the frontend, the level of
+//!     wasm-opt or the environment variables. The resolved settings are available to the hooks
+//!     with [`BuildArgs::profile_config`].
+//!  *  With `--fingerprint-assets` (or `fingerprint.assets = true` in the `release` section of
+//!     the configuration), the artifacts are renamed after the fingerprint of their content
+//!     (`app-<hash>.js`) and the mapping is written to `asset-manifest.json`. The hashing
+//!     algorithm (`blake3`, `sha256` or `xxh3`) and the length of the fingerprints can be changed
+//!     with `--hash-algorithm` and `--hash-length`.
+//!  *  The files generated by wasm-run (`asset-manifest.json`, the web app manifest and the
+//!     service worker, the translation bundles, the manifest of `embed`, ...) are identical across
+//!     the platforms and the runs: the files are listed in the byte order of their path with `/`
+//!     separators, the keys of the JSON objects are sorted and the line endings are LF.
+//!  *  The development server resolves the unhashed paths (`app.js`) with `asset-manifest.json`,
+//!     serves the precompressed files (`<file>.br` and `<file>.gz`) to the browsers that accept
+//!     them and, with `serve --release`, caches the fingerprinted files forever.
+//!  *  A request of a missing `.wasm` or `.js` file gets a `404` with a JSON body instead of the
+//!     `index.html`: the closest file of the build directory is logged and displayed in the
+//!     overlay (a wrong `out_name` or fingerprint). The `.wasm` files are always served as
+//!     `application/wasm` for `WebAssembly.instantiateStreaming`, the precompressed ones too.
+//!  *  The endpoints of the development server can use their own `tide::Server<State>` (custom
+//!     state, sessions and middlewares) with `ServeContext::wrap_server` in the hook
+//!     `serve_init`: the routes of the development server are mounted in it.
+//!  *  The `index.html` served for `/` and the other routes of the app answers the HEAD
+//!     requests and has a `Last-Modified` header: the conditional requests (`If-Modified-Since`)
+//!     get a `304 Not Modified` while the build has not changed.
+//!  *  The files of the build directory can be requested partially with a `Range` header (the
+//!     audio and video elements of the browsers need it to seek in the media files).
+//!  *  The hooks can be unit tested without the macro with `TestBuildArgs` and `TestServeArgs`:
+//!     they describe a fake frontend package in a directory and the `BuildContext` is created
+//!     with `BuildContext::from_args`.
+//!  *  With `serve --status-page`, the development server has a status page at `/__wasm_run/`:
+//!     the last builds with their duration and their errors, the files of the build directory
+//!     with their sizes and the last changes detected by the watcher (including the ignored ones).
+//!  *  With `serve --file-listing`, the development server has a browsable listing of the build
+//!     directory at `/__wasm_run/files` with the size, the hash, the modification time and the
+//!     compressed sizes (`.br` and `.gz`) of each file.
+//!  *  With `serve --no-watch` (or if the file watcher stops), the sources are checked
+//!     periodically instead: a source modified since the last build is reported in the logs and
+//!     in the overlay, and pressing Enter in the terminal rebuilds.
+//!  *  Additional watch tasks (`Hooks::watch_tasks` or the argument `watch_tasks` of the macro)
+//!     run a function when the files of some paths change, with their own watcher and delay,
+//!     instead of rebuilding the frontend: to run a code generator or a CSS pipeline for example.
+//!  *  The `Cargo.lock` and the toolchain file (`rust-toolchain` or `rust-toolchain.toml`) of
+//!     the root of the workspace are watched by the `serve` command: a change of the dependencies
+//!     or of the toolchain triggers a full rebuild (the toolchain is checked again), even if
+//!     `Cargo.lock` is ignored by git.
+//!  *  Partial rebuilds (`ServeArgs::partial_rebuilds`, enabled with `--partial-rebuilds`): a
+//!     change of a static file, of a SASS or SCSS file or of a translation only runs the stage of
+//!     the build it feeds instead of building the frontend with cargo again. The `post_build`
+//!     hook is not run by these rebuilds.
+//!  *  The rebuilds of the `serve` command run on a worker thread: a change that happens during
+//!     a rebuild cancels it after cargo or wasm-bindgen, or kills wasm-opt, instead of waiting for
+//!     the optimization of a stale build.
+//!  *  The hooks of the `serve` command have `run_*` versions (`Hooks::run_serve`,
+//!     `Hooks::run_frontend_watch`, ... or the arguments `run_serve`, ... of the macro) that
+//!     receive a `RunContext` with the profile of the builds: the previous hooks are deprecated
+//!     but still work.
+//!  *  Without the `dev-server` feature, `serve` can wait for a health check of the backend
+//!     (`--backend-health-url` or `--backend-health-port`) before reporting that it has
+//!     (re-)started, and stops the backend with `SIGTERM` before killing it after
+//!     `--backend-kill-timeout`.
+//!  *  With `--fast-backend-restart`, a change of a file that is not a source of the backend
+//!     (according to the dep-info file written by cargo) restarts the backend executable directly
+//!     without running cargo.
 //!
 //! # Features
 //!
@@ -70,56 +274,200 @@
 //!     of wasm-opt will be downloaded from GitHub and used to optimize the WASM. By default,
 //!     wasm-opt is compiled among the dependencies (`binaryen`). This is useful if you run into
 //!     troubles for building `binaryen-sys`. (`binaryen` cannot be built on Netlify at the
-//!     moment.)
+//!     moment.) The binaries exist for Linux (x86_64 and aarch64, glibc or musl), macOS (x86_64
+//!     and Apple Silicon, which falls back to the x86_64 binary under Rosetta for the older
+//!     versions) and Windows (x86_64). On the other hosts, set `WASM_RUN_WASM_OPT` to the path of
+//!     a wasm-opt installed on the system.
+//!  *  `wasm-opt`: the WASM is optimized in the process with the `wasm-opt` crate, which compiles
+//!     binaryen from its bundled sources with the C++ compiler of the host: nothing is downloaded
+//!     and CMake is not needed (air-gapped CI, Netlify). It takes precedence over
+//!     `prebuilt-wasm-opt`, which can be disabled with `default-features = false`.
+//!  *  `sha2`: the SHA-2 digests of the fingerprints with the algorithm `sha256` and of the
+//!     integrity of the external dependencies (without it, their integrity must be set in the
+//!     configuration). It is enabled by `prebuilt-wasm-opt`, which checks the archive of binaryen.
 //!  *  `sass`: support for SASS and SCSS. All SASS and SCSS files found in the directories
 //!     `styles/`, `assets/`, `sass/` and `css/` will be automatically transpiled to CSS and placed
 //!     in the build directory. This can be configured by overriding:
 //!     [`BuildArgs::build_sass_from_dir`], [`BuildArgs::sass_lookup_directories`],
 //!     [`BuildArgs::sass_options`] or completely overriden in the [`Hooks::post_build`] hook.
 //!     `sass-rs` is re-exported in the prelude of `wasm-run` for this purpose.
-//!  *  `full-restart`: when this feature is active, the command is entirely restarted when changes
-//!     are detected when serving files for development (`cargo run -- serve`). This is useful with
-//!     custom `serve` command that uses a custom backend and if you need to detect changes in the
-//!     backend code itself.
+//!  *  `i18n`: the translations (Fluent `.ftl` and gettext `.po` files) found in the directory
+//!     `i18n/` of the frontend are compiled to JSON bundles (`i18n/<locale>.json`) in the build
+//!     directory. This can be configured by overriding [`BuildArgs::i18n_options`].
+//!  *  `webhooks`: the URLs of `webhooks` in `WasmRun.toml` (and of the environment variable
+//!     `WASM_RUN_WEBHOOKS`) receive a POST with a JSON summary at the end of every build, when
+//!     building and when serving. The requests are sent in the background without delaying the
+//!     builds. This can be configured by overriding [`BuildArgs::webhooks`].
 
 #![warn(missing_docs)]
 
+mod args_factory;
+mod assets;
+mod backend;
+mod bindgen_cli;
+mod branch;
+mod build_cache;
+mod build_context;
+mod build_output;
+mod build_workspace;
+mod builder;
+mod cancel;
+mod command_context;
+mod compare_opt;
+mod config;
+mod config_command;
+mod config_schema;
+#[cfg(feature = "dev-server")]
+mod context;
+mod critical_css;
+mod diagnostics;
+pub mod embed;
+mod events;
+mod external;
+#[cfg(feature = "dev-server")]
+mod file_listing;
+mod fingerprint;
+mod frontend_target;
+mod full_restart;
+mod generated;
+mod history;
+#[cfg(feature = "i18n")]
+mod i18n;
+mod index_diff;
+mod js_replace;
+mod out_dir;
+#[cfg(feature = "dev-server")]
+mod overlay;
+mod package;
+#[cfg(feature = "dev-server")]
+mod port;
 #[cfg(feature = "prebuilt-wasm-opt")]
 mod prebuilt_wasm_opt;
+mod proxy;
+mod pwa;
+mod rebuild;
+mod redirects;
+mod run_context;
+mod serve_handle;
+#[cfg(feature = "dev-server")]
+mod shims;
+mod shutdown;
+mod stale;
+mod state;
+#[cfg(feature = "dev-server")]
+mod static_files;
+#[cfg(feature = "dev-server")]
+mod status_page;
+pub mod tasks;
+mod test_args;
+#[cfg(feature = "dev-server")]
+mod throttle;
+mod timings;
+mod toolchain;
+mod ui;
+mod verbosity;
+mod watch_task;
+#[cfg(feature = "webhooks")]
+mod webhooks;
+mod workspace;
 
 use anyhow::{anyhow, bail, Context, Result};
-use cargo_metadata::{Metadata, MetadataCommand, Package};
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::{DependencyKind, Metadata, MetadataCommand, Package};
 use downcast_rs::*;
-use fs_extra::dir;
-use notify::RecommendedWatcher;
+use frontend_target::FrontendTarget;
+use notify::{PollWatcher, RecommendedWatcher};
 use once_cell::sync::OnceCell;
+use serde_json::json;
+use state::State;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
 use std::io::BufReader;
 use std::iter;
 use std::iter::FromIterator;
+use std::mem;
+use std::panic;
 use std::path::{Path, PathBuf};
 #[cfg(feature = "dev-server")]
 use std::pin::Pin;
 use std::process::{Child, ChildStdout, Command, Stdio};
+use std::str::FromStr;
 use std::sync::mpsc;
+use std::sync::Arc;
 use std::time;
 use structopt::StructOpt;
 #[cfg(feature = "dev-server")]
 use tide::Server;
 
+pub use args_factory::ArgsFactory;
+pub use assets::{AssetRule, SymlinkMode};
+#[cfg(not(feature = "dev-server"))]
+pub use backend::HealthCheck;
+pub use build_context::BuildContext;
+pub use build_output::BuildOutput;
+pub use build_workspace::BuildWorkspaceArgs;
+pub use builder::Builder;
+pub use command_context::CommandContext;
+pub use compare_opt::CompareOptArgs;
+pub use config::Config;
+pub use config_command::{ConfigArgs, ConfigCommand, ConfigPrintArgs};
+pub use config_schema::ConfigIssue;
+#[cfg(feature = "dev-server")]
+pub use context::ServeContext;
+pub use critical_css::CriticalCss;
+pub use diagnostics::CompileError;
+pub use events::MessageFormat;
+pub use external::ExternalDependency;
+pub use fingerprint::{Fingerprint, HashAlgorithm};
+#[cfg(feature = "i18n")]
+pub use i18n::I18nOptions;
+pub use package::{DefaultPackageArgs, PackageArgs};
+pub use proxy::ProxyRule;
+pub use pwa::{PwaConfig, PwaIcon};
+pub use redirects::Redirect;
+pub use run_context::RunContext;
+pub use serve_handle::ServeHandle;
+pub use shutdown::ShutdownReason;
+pub use test_args::{TestBuildArgs, TestServeArgs};
+#[cfg(feature = "dev-server")]
+pub use throttle::PathRule;
+pub use timings::Timings;
+pub use ui::ColorChoice;
+pub use verbosity::Verbosity;
 pub use wasm_run_proc_macro::*;
+pub use watch_task::WatchTask;
+#[cfg(feature = "webhooks")]
+pub use webhooks::Webhook;
+pub use workspace::WorkspaceContext;
 
 #[doc(hidden)]
 pub use structopt;
 
 const DEFAULT_INDEX: &str = r#"<!DOCTYPE html><html><head><meta charset="utf-8"/><script type="module">import init from "/app.js";init(new URL('app_bg.wasm', import.meta.url));</script></head><body></body></html>"#;
 
-static METADATA: OnceCell<Metadata> = OnceCell::new();
-static DEFAULT_BUILD_PATH: OnceCell<PathBuf> = OnceCell::new();
-static FRONTEND_PACKAGE: OnceCell<&Package> = OnceCell::new();
-static BACKEND_PACKAGE: OnceCell<Option<&Package>> = OnceCell::new();
-static HOOKS: OnceCell<Hooks> = OnceCell::new();
+const DEFAULT_INDEX_WITH_CHECKS: &str = r#"<!DOCTYPE html><html><head><meta charset="utf-8"/><script type="module">
+import init from "/app.js";
+const wasm = new URL('app_bg.wasm', import.meta.url);
+init(wasm).catch(async (err) => {
+    console.error(err);
+    let hint = "";
+    try {
+        const res = await fetch(wasm, { method: "HEAD" });
+        const mime = res.headers.get("content-type");
+        if (!res.ok) {
+            const body = await fetch(wasm).then((x) => x.json()).catch(() => ({}));
+            hint = body.error || `${wasm} could not be fetched: HTTP ${res.status}.`;
+        } else if (mime !== "application/wasm") {
+            hint = `${wasm} is served with the MIME type "${mime}" instead of "application/wasm".`;
+        }
+    } catch (_) {}
+    const pre = document.createElement("pre");
+    pre.style = "color: #b00; white-space: pre-wrap; padding: 1em;";
+    pre.textContent = `The WASM application failed to start: ${err}\n${hint}`;
+    document.body.appendChild(pre);
+});
+</script></head><body></body></html>"#;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 /// A build profile for the WASM.
@@ -132,141 +480,665 @@ pub enum BuildProfile {
     Profiling,
 }
 
+/// Options of the generation of the JS bindings by `wasm-bindgen`.
+///
+/// The default values are the defaults of `wasm-bindgen`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindgenSettings {
+    /// Use the reference types proposal (`externref`). Recent versions of `wasm-bindgen` enable
+    /// it automatically when the WASM is compiled with `-Ctarget-feature=+reference-types`.
+    pub reference_types: bool,
+    /// Use weak references to free the Rust objects owned by JS. This is only used with the
+    /// `wasm-bindgen` CLI (`--external-bindgen`): the library linked in wasm-run always uses them.
+    pub weak_refs: bool,
+    /// Don't add the default path of the WASM file in the generated `init` function.
+    pub omit_default_module_path: bool,
+    /// Remove the name section (the function names) of the WASM.
+    pub remove_name_section: bool,
+    /// Demangle the Rust symbols in the name section.
+    pub demangle: bool,
+}
+
+impl Default for BindgenSettings {
+    fn default() -> Self {
+        Self {
+            reference_types: false,
+            weak_refs: false,
+            omit_default_module_path: true,
+            remove_name_section: false,
+            demangle: true,
+        }
+    }
+}
+
 /// This function is called early before any command starts. This is not part of the public API.
 #[doc(hidden)]
+#[allow(clippy::type_complexity)]
 pub fn wasm_run_init(
-    pkg_name: &str,
+    pkg_name: Option<&str>,
     backend_pkg_name: Option<&str>,
-    default_build_path: Option<Box<dyn FnOnce(&Metadata, &Package) -> PathBuf>>,
+    default_build_path: Option<Box<dyn FnOnce(&Metadata, &Package) -> Utf8PathBuf>>,
+    per_package_defaults: bool,
+    verbosity: Verbosity,
     hooks: Hooks,
 ) -> Result<(&'static Metadata, &'static Package)> {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    diagnostics::init_logger(verbosity);
 
     let metadata = MetadataCommand::new()
         .exec()
         .context("this binary is not meant to be ran outside of its workspace")?;
 
-    METADATA
-        .set(metadata)
-        .expect("the cell is initially empty; qed");
+    let context = WorkspaceContext::set_global(
+        WorkspaceContext::init(
+            metadata,
+            pkg_name,
+            backend_pkg_name,
+            default_build_path,
+            per_package_defaults,
+            hooks,
+        )?
+        .with_verbosity(verbosity),
+    )?;
+    shutdown::install();
+
+    Ok((context.metadata(), context.frontend_package()))
+}
+
+/// This function is called when the command ends: it waits for the webhooks and runs the hook
+/// `shutdown`. This is not part of the public API.
+#[doc(hidden)]
+pub fn wasm_run_shutdown() {
+    #[cfg(feature = "webhooks")]
+    webhooks::wait();
+    shutdown::run(ShutdownReason::Exit);
+}
 
-    let metadata = METADATA.get().unwrap();
+/// This function is called first with the [`Verbosity`] of the global flags `-v` and `-q` and
+/// with `--color`: it sets the [`ColorChoice`] and installs the logger. This is not part of the
+/// public API.
+#[doc(hidden)]
+pub fn wasm_run_init_logger(verbosity: Verbosity, color: ColorChoice) {
+    color.set_global();
+    diagnostics::init_logger(verbosity);
+}
 
-    let frontend_package = METADATA
-        .get()
-        .unwrap()
-        .packages
-        .iter()
-        .find(|x| x.name == pkg_name)
-        .expect("the frontend package existence has been checked during compile time; qed");
+/// This function runs `config validate` before [`wasm_run_init`], which fails on the first
+/// invalid setting. This is not part of the public API.
+#[doc(hidden)]
+pub fn wasm_run_validate_config(pkg_name: Option<&str>) -> Result<()> {
+    diagnostics::init_logger(Verbosity::default());
 
-    FRONTEND_PACKAGE
-        .set(frontend_package)
-        .expect("the cell is initially empty; qed");
+    let metadata = MetadataCommand::new()
+        .exec()
+        .context("this binary is not meant to be ran outside of its workspace")?;
+    let frontend_package = select_frontend(&metadata, pkg_name)?;
 
-    let frontend_package = FRONTEND_PACKAGE.get().unwrap();
+    config_command::validate(&metadata, frontend_package)
+}
 
-    if let Some(name) = backend_pkg_name {
-        let backend_package = METADATA
-            .get()
-            .unwrap()
+/// Find the frontend package by name or, if no name is provided, detect the only member of the
+/// workspace that can be built to WASM.
+fn select_frontend<'a>(metadata: &'a Metadata, pkg_name: Option<&str>) -> Result<&'a Package> {
+    if let Some(pkg_name) = pkg_name {
+        return metadata
             .packages
             .iter()
-            .find(|x| x.name == name)
-            .expect("the backend package existence has been checked during compile time; qed");
+            .find(|x| x.name == pkg_name)
+            .with_context(|| format!("package `{}` not found in the workspace", pkg_name));
+    }
 
-        BACKEND_PACKAGE
-            .set(Some(backend_package))
-            .expect("the cell is initially empty; qed");
-    } else {
-        BACKEND_PACKAGE
-            .set(None)
-            .expect("the cell is initially empty; qed");
+    let candidates = wasm_packages(metadata);
+    match candidates.as_slice() {
+        [package] => {
+            log::info!("Using the frontend package `{}`", package.name);
+            Ok(package)
+        }
+        [] => bail!(
+            "no package of the workspace can be built to WASM (a library with the crate type \
+            `cdylib` or depending on `wasm-bindgen`, or a binary without library depending on \
+            `wasm-bindgen`): select the frontend with `--package <name>`"
+        ),
+        _ => bail!(
+            "several packages of the workspace can be built to WASM: {}. Select the frontend \
+            with `--package <name>`",
+            candidates
+                .iter()
+                .map(|x| format!("`{}`", x.name))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
     }
+}
 
-    DEFAULT_BUILD_PATH
-        .set(if let Some(default_build_path) = default_build_path {
-            default_build_path(metadata, frontend_package)
-        } else {
-            metadata.workspace_root.join("build")
+/// Members of the workspace that can be built to WASM: libraries with the crate type `cdylib`
+/// or depending on `wasm-bindgen`, and packages without library with a binary depending on
+/// `wasm-bindgen`.
+fn wasm_packages(metadata: &Metadata) -> Vec<&Package> {
+    metadata
+        .packages
+        .iter()
+        .filter(|x| metadata.workspace_members.contains(&x.id))
+        .filter(|x| {
+            let depends_on_bindgen = x
+                .dependencies
+                .iter()
+                .any(|x| x.kind == DependencyKind::Normal && x.name == "wasm-bindgen");
+            let lib = x
+                .targets
+                .iter()
+                .find(|x| x.kind.iter().any(|x| x == "lib" || x == "cdylib"));
+            match lib {
+                Some(lib) => lib.crate_types.iter().any(|x| x == "cdylib") || depends_on_bindgen,
+                None => {
+                    depends_on_bindgen
+                        && x.targets.iter().any(|x| x.kind.iter().any(|x| x == "bin"))
+                }
+            }
         })
-        .expect("the cell is initially empty; qed");
+        .collect()
+}
 
-    if HOOKS.set(hooks).is_err() {
-        panic!("the cell is initially empty; qed");
-    }
+/// Members of the workspace that depend on `wasm-run` (not only for their tests).
+fn runner_packages(metadata: &Metadata) -> Vec<&Package> {
+    metadata
+        .packages
+        .iter()
+        .filter(|x| metadata.workspace_members.contains(&x.id))
+        .filter(|x| {
+            x.dependencies
+                .iter()
+                .any(|x| x.name == "wasm-run" && x.kind != DependencyKind::Development)
+        })
+        .collect()
+}
 
-    Ok((metadata, frontend_package))
+/// Derive a port between 3000 and 3999 from the name of a package (FNV-1a hash).
+fn package_port(name: &str) -> u16 {
+    let hash = name.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    });
+    3000 + (hash % 1000) as u16
 }
 
 /// Build arguments.
 #[derive(StructOpt, Debug)]
 pub struct DefaultBuildArgs {
-    /// Build directory output.
+    /// Output directory (default: `build_path` of the configuration or `build/`). It is relative
+    /// to the current directory. `--build-path` is an alias.
+    #[structopt(long = "out-dir", alias = "build-path")]
+    pub build_path: Option<Utf8PathBuf>,
+
+    /// Namespace the build directory by git branch (`build/<branch>/`) so switching branches
+    /// doesn't overwrite the artifacts of another branch.
     #[structopt(long)]
-    pub build_path: Option<PathBuf>,
+    pub per_branch: bool,
 
     /// Create a profiling build. Enable optimizations and debug info.
     #[structopt(long)]
     pub profiling: bool,
+
+    /// Do not add runtime checks to the default loader in non-release builds.
+    #[structopt(long)]
+    pub no_loader_checks: bool,
+
+    /// Subdirectory of the build directory where the WASM and JS files are written.
+    #[structopt(long)]
+    pub artifact_subdir: Option<Utf8PathBuf>,
+
+    /// Source of the `index.html` of the build directory (default: `index` of the
+    /// configuration).
+    #[structopt(long)]
+    pub index: Option<Utf8PathBuf>,
+
+    /// Format of the messages printed on the standard output: `human` or `json` (JSON events
+    /// mirroring the messages of cargo).
+    #[structopt(long, default_value = "human", possible_values = &["human", "json"])]
+    pub message_format: MessageFormat,
+
+    /// Install the target `wasm32-unknown-unknown` with rustup if it is missing.
+    #[structopt(long)]
+    pub auto_install_target: bool,
+
+    /// Fail the build if wasm-bindgen emits warnings.
+    #[structopt(long)]
+    pub deny_bindgen_warnings: bool,
+
+    /// Build this binary of the frontend package instead of its library.
+    #[structopt(long)]
+    pub frontend_bin: Option<String>,
+
+    /// Do not access the network: cargo runs with `--offline` and wasm-opt must be in the cache.
+    #[structopt(long)]
+    pub offline: bool,
+
+    /// Use the `wasm-bindgen` CLI of the exact version used by the frontend instead of the
+    /// library linked in wasm-run. It is installed with `cargo install` if needed.
+    #[structopt(long)]
+    pub external_bindgen: bool,
+
+    /// Keep the DWARF debugging information in the WASM and skip wasm-opt, for source-level
+    /// debugging in the browser DevTools.
+    #[structopt(long)]
+    pub dwarf: bool,
+
+    /// Always run wasm-bindgen and wasm-opt instead of reusing their outputs from the caches.
+    #[structopt(long)]
+    pub no_build_cache: bool,
+
+    /// Log the duration of every stage of the builds (cargo, wasm-bindgen, wasm-opt, hooks,
+    /// assets...). With `--timings html`, a report is also written in `wasm-run/timings` in the
+    /// target directory.
+    #[structopt(long, possible_values = &["summary", "html"])]
+    pub timings: Option<Option<Timings>>,
+
+    /// Keep a copy of the last <keep-builds> builds in the target directory so they can be served
+    /// again with `serve --from-history <id>`.
+    #[structopt(long, default_value = "0")]
+    pub keep_builds: usize,
+
+    /// Inline the critical CSS of the stylesheets linked by the `index.html`.
+    #[structopt(long)]
+    pub critical_css: bool,
+
+    /// Hashing algorithm of the fingerprints: `blake3`, `sha256` or `xxh3` (default: `blake3`).
+    #[structopt(long)]
+    pub hash_algorithm: Option<HashAlgorithm>,
+
+    /// Number of hexadecimal digits of the fingerprints (default: 16).
+    #[structopt(long)]
+    pub hash_length: Option<usize>,
+
+    /// Rename the artifacts after the fingerprint of their content (`app-<hash>.js`).
+    #[structopt(long)]
+    pub fingerprint_assets: bool,
+
+    /// Version of binaryen used for wasm-opt (default: `WASM_RUN_WASM_OPT_VERSION` or 97).
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    #[structopt(long)]
+    pub wasm_opt_version: Option<String>,
+
+    /// Expected SHA-256 of the binaryen archive (default: `WASM_RUN_WASM_OPT_SHA256` or the
+    /// checksum published with the release).
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    #[structopt(long)]
+    pub wasm_opt_sha256: Option<String>,
+
+    /// Mirror of the binaryen releases used if GitHub is not reachable (default:
+    /// `WASM_RUN_WASM_OPT_MIRROR`).
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    #[structopt(long)]
+    pub wasm_opt_mirror: Option<String>,
+
+    #[structopt(skip)]
+    context: Option<Arc<WorkspaceContext>>,
+
+    #[structopt(skip)]
+    out_dir: OnceCell<Utf8PathBuf>,
+}
+
+impl DefaultBuildArgs {
+    /// Use this context instead of the global context initialized by the macro
+    /// [`main`](crate::main).
+    pub fn with_context(mut self, context: Arc<WorkspaceContext>) -> Self {
+        self.context = Some(context);
+        self
+    }
 }
 
 /// A trait that allows overriding the `build` command.
 pub trait BuildArgs: Downcast {
     /// Build directory output.
-    fn build_path(&self) -> &PathBuf;
+    fn build_path(&self) -> &Utf8PathBuf;
+
+    /// Subdirectory of the build directory where the WASM and JS files are written. They are
+    /// written at the root of the build directory by default.
+    fn artifact_subdir(&self) -> Option<&Utf8Path> {
+        None
+    }
+
+    /// Source of the `index.html` of the build directory. It is copied after the assets and
+    /// replaces the one they may provide. When it is `None`, the `index.html` of the assets is
+    /// used, or the default one.
+    ///
+    /// By default, it is `index` in `WasmRun.toml` or in the metadata of the frontend package,
+    /// relative to the file that declares it: it doesn't depend on the current directory.
+    fn index_source(&self) -> Option<Utf8PathBuf> {
+        self.config().index.clone()
+    }
+
+    /// Directory where the WASM and JS files are written.
+    fn artifact_path(&self) -> Utf8PathBuf {
+        match self.artifact_subdir() {
+            Some(subdir) => self.build_path().join(subdir),
+            None => self.build_path().to_owned(),
+        }
+    }
+
+    /// URL path of an artifact (WASM and JS files) from the root of the web server.
+    fn artifact_url(&self, file_name: &str) -> String {
+        let mut url = String::from("/");
+        for component in self.artifact_subdir().into_iter().flat_map(|x| x.iter()) {
+            url.push_str(component);
+            url.push('/');
+        }
+        url.push_str(file_name);
+        url
+    }
 
     /// Default path for the build/public directory.
-    fn default_build_path(&self) -> &PathBuf {
-        DEFAULT_BUILD_PATH
-            .get()
-            .expect("default_build_path has been initialized on startup; qed")
+    ///
+    /// This is `build_path` of the configuration ([`BuildArgs::config`]) if any. Otherwise it is
+    /// `build/` at the root of the workspace, or `build/<frontend package>/` when the workspace
+    /// contains several runner crates and the macro [`main`](crate::main) has the argument
+    /// `per_package_defaults`.
+    fn default_build_path(&self) -> &Utf8PathBuf {
+        self.context().default_build_path()
+    }
+
+    /// Path to the `target` directory: the one of cargo, or `CARGO_TARGET_DIR` (or
+    /// `CARGO_BUILD_TARGET_DIR`) of the `env` of the configuration. The cargo commands run by
+    /// wasm-run use it whatever their current directory, and so do the caches, the history and
+    /// the files of the development server written in the target directory.
+    fn target_path(&self) -> &Utf8Path {
+        metadata_path(&self.metadata().target_directory)
+    }
+
+    /// Context of the workspace: the global context initialized by the macro
+    /// [`main`](crate::main) by default.
+    fn context(&self) -> &WorkspaceContext {
+        WorkspaceContext::global()
+    }
+
+    /// State of the builds, the watchers and the development server: the one of the context. This
+    /// is not part of the public API.
+    #[doc(hidden)]
+    fn state(&self) -> &State {
+        self.context().state()
     }
 
-    /// Path to the `target` directory.
-    fn target_path(&self) -> &PathBuf {
-        &self.metadata().target_directory
+    /// Verbosity of the commands (`-q`, `-qq`, `-v` and `-vv`): the one of the context.
+    fn verbosity(&self) -> Verbosity {
+        self.context().verbosity()
     }
 
     /// Metadata of the project.
     fn metadata(&self) -> &Metadata {
-        METADATA
-            .get()
-            .expect("metadata has been initialized on startup; qed")
+        self.context().metadata()
+    }
+
+    /// Settings of `WasmRun.toml` and `[package.metadata.wasm-run]` (check [`Config`]).
+    fn config(&self) -> &Config {
+        self.context().config()
+    }
+
+    /// Settings of the configuration resolved for a build profile: the section `dev`,
+    /// `profiling` or `release` is merged over the other settings (check [`Config::profile`]).
+    fn profile_config(&self, profile: BuildProfile) -> Config {
+        self.config().profile(profile)
     }
 
     /// Package metadata.
     fn frontend_package(&self) -> &Package {
-        FRONTEND_PACKAGE
-            .get()
-            .expect("frontend_package has been initialized on startup; qed")
+        self.context().frontend_package()
     }
 
     /// Backend frontend_package metadata.
     fn backend_package(&self) -> Option<&Package> {
-        BACKEND_PACKAGE
-            .get()
-            .expect("frontend_package has been initialized on startup; qed")
-            .to_owned()
+        self.context().backend_package()
     }
 
     /// Create a profiling build. Enable optimizations and debug info.
     fn profiling(&self) -> bool;
 
+    /// Binary of the frontend package compiled to WASM instead of its library, for the
+    /// frontends with a `fn main()` (or `#[wasm_bindgen(start)]`). By default, the library is
+    /// built, or the only binary of the package if it has no library.
+    fn frontend_bin(&self) -> Option<String> {
+        None
+    }
+
+    /// Format of the messages printed on the standard output.
+    fn message_format(&self) -> MessageFormat {
+        MessageFormat::Human
+    }
+
+    /// Install the target `wasm32-unknown-unknown` with rustup if it is missing. Otherwise the
+    /// build fails with the instruction to install it.
+    fn auto_install_target(&self) -> bool {
+        false
+    }
+
+    /// Fail the build if wasm-bindgen emits warnings. They are displayed in the logs (and in the
+    /// JSON messages) anyway, also when the outputs of wasm-bindgen come from the build cache.
+    fn deny_bindgen_warnings(&self) -> bool {
+        false
+    }
+
+    /// Do not access the network: cargo runs with `--offline` and wasm-opt must already be in the
+    /// cache.
+    fn offline(&self) -> bool {
+        false
+    }
+
+    /// Use the `wasm-bindgen` CLI of the exact version of the crate `wasm-bindgen` used by the
+    /// frontend instead of the library linked in wasm-run. This avoids the schema version
+    /// mismatch errors when the versions differ.
+    ///
+    /// The CLI is taken from the `PATH` if the version matches, otherwise it is installed with
+    /// `cargo install` in the target directory.
+    fn external_bindgen(&self) -> bool {
+        false
+    }
+
+    /// Options of the generation of the JS bindings by `wasm-bindgen` (reference types, weak
+    /// references, name section, ...).
+    fn bindgen_settings(&self) -> BindgenSettings {
+        BindgenSettings::default()
+    }
+
+    /// Keep the DWARF debugging information in the WASM: the frontend is compiled with debug
+    /// information in every profile, wasm-bindgen does not strip it and wasm-opt is skipped
+    /// (it would drop or corrupt it).
+    ///
+    /// The WASM can then be debugged at the source level in Chrome with the extension
+    /// "C/C++ DevTools Support (DWARF)".
+    fn dwarf(&self) -> bool {
+        false
+    }
+
+    /// Reuse the outputs of wasm-bindgen and wasm-opt (in `wasm-run-cache` in the target
+    /// directory) when the WASM produced by cargo and the settings of the optimized builds didn't
+    /// change: building again the same sources, from another command for example, doesn't run
+    /// wasm-opt again. The outputs of wasm-opt are also cached on their own, by input WASM and
+    /// optimization settings (in `wasm-run/wasm-opt-cache`). It is enabled by default.
+    fn build_cache(&self) -> bool {
+        true
+    }
+
+    /// Report the duration of every stage of the builds (check [`Timings`]). Only a line with the
+    /// total duration is logged at the level `debug` by default.
+    fn timings(&self) -> Option<Timings> {
+        None
+    }
+
+    /// Number of builds retained in the history (`wasm-run/history` in the target directory).
+    /// Every successful build is copied there with an incremental identifier and the oldest ones
+    /// are removed. Nothing is retained by default.
+    fn keep_builds(&self) -> usize {
+        0
+    }
+
+    /// Inline the critical CSS of the stylesheets linked by the `index.html` after the
+    /// `post_build` hook (check [`CriticalCss`]).
+    ///
+    /// By default, it is read from `critical_css` in `WasmRun.toml` or in the metadata of the
+    /// frontend package and nothing is inlined if it is missing:
+    ///
+    /// ```toml
+    /// [package.metadata.wasm-run.critical_css]
+    /// selectors = ["html", "body", ".header", "#app"]
+    /// inline_size = 8192
+    /// ```
+    fn critical_css(&self) -> Result<Option<CriticalCss>> {
+        CriticalCss::from_config(self.config())
+    }
+
+    /// Hashing algorithm and length of the fingerprints of the assets and of the build
+    /// identifiers (cache of the service worker, cache busting of the development server).
+    ///
+    /// By default, it is read from `fingerprint` in `WasmRun.toml` or in the metadata of the
+    /// frontend package (BLAKE3 and 16 hexadecimal digits if it is missing). The algorithm is
+    /// `blake3`, `sha256` (feature `sha2`) or `xxh3`:
+    ///
+    /// ```toml
+    /// [package.metadata.wasm-run.fingerprint]
+    /// algorithm = "sha256"
+    /// length = 32
+    /// ```
+    fn fingerprint(&self) -> Result<Fingerprint> {
+        Fingerprint::from_config(self.config())
+    }
+
+    /// Rename the artifacts after the fingerprint of their content (`app-<hash>.js` and
+    /// `app_bg-<hash>.wasm`) after the `post_build` hook. The references of the `index.html` are
+    /// updated and the mapping is written to `asset-manifest.json` in the build directory.
+    ///
+    /// By default, it is enabled by `fingerprint.assets = true` in the configuration of the
+    /// profile (for example in `[package.metadata.wasm-run.release.fingerprint]`).
+    fn fingerprint_assets(&self, profile: BuildProfile) -> bool {
+        self.profile_config(profile).get("fingerprint")["assets"]
+            .as_bool()
+            .unwrap_or(false)
+    }
+
+    /// Directories of static files copied to the build directory by the default `post_build`
+    /// hook. By default, the directory `static` of the frontend package is copied to the root of
+    /// the build directory.
+    fn asset_rules(&self) -> Vec<AssetRule> {
+        vec![AssetRule::new("static", "")]
+    }
+
+    /// Generate a web app manifest (`manifest.webmanifest`) and a service worker
+    /// (`service-worker.js`) that precaches all the files of the build directory. They are
+    /// generated after the `post_build` hook and the default `index.html` registers them in
+    /// release builds.
+    ///
+    /// By default, the configuration is read from `pwa` in `WasmRun.toml` or in the metadata of
+    /// the frontend package (check [`PwaConfig`]) and nothing is generated if it is missing.
+    fn pwa(&self) -> Result<Option<PwaConfig>> {
+        PwaConfig::from_config(self.config(), &self.frontend_package().name)
+    }
+
+    /// Redirects and rewrites applied by the development server. They are written to the file
+    /// `_redirects` of the build directory (the format of Netlify and Cloudflare Pages, check
+    /// [`Redirect::to_netlify`]) unless the assets or the `post_build` hook provide one.
+    ///
+    /// By default, they are read from `redirects` in `WasmRun.toml` or in the metadata of the
+    /// frontend package (check [`Config`]):
+    ///
+    /// ```toml
+    /// [package.metadata.wasm-run]
+    /// redirects = [
+    ///     "/old -> /new",
+    ///     "/docs/* -> https://docs.example.com/* 302",
+    ///     "/app/* -> /index.html 200",
+    /// ]
+    /// ```
+    fn redirects(&self) -> Result<Vec<Redirect>> {
+        let redirects = self.config().get("redirects");
+        if redirects.is_null() {
+            return Ok(Vec::new());
+        }
+
+        redirects
+            .as_array()
+            .context("`package.metadata.wasm-run.redirects` must be an array of strings")?
+            .iter()
+            .map(|x| {
+                x.as_str()
+                    .context("`package.metadata.wasm-run.redirects` must be an array of strings")?
+                    .parse()
+                    .map_err(|err: String| anyhow!(err))
+            })
+            .collect()
+    }
+
+    /// JavaScript dependencies loaded from a CDN by the `index.html` (check
+    /// [`ExternalDependency`]): they are added to an import map or as `<script>` tags, with their
+    /// integrity, after the `post_build` hook.
+    ///
+    /// By default, they are read from `external` in `WasmRun.toml` or in the metadata of the
+    /// frontend package.
+    fn external_dependencies(&self) -> Result<Vec<ExternalDependency>> {
+        ExternalDependency::from_config(self.config())
+    }
+
+    /// URLs notified of the end of the builds (check [`Webhook`]), in build and in serve mode. The
+    /// notifications are sent in the background: the command only waits for them before exiting.
+    ///
+    /// By default, they are read from `webhooks` in `WasmRun.toml` or in the metadata of the
+    /// frontend package (check [`Config`]) and from the environment variable `WASM_RUN_WEBHOOKS`
+    /// (URLs separated by commas or spaces):
+    ///
+    /// ```toml
+    /// [package.metadata.wasm-run]
+    /// webhooks = [
+    ///     "https://ci.example.com/hooks/wasm-run",
+    ///     { url = "https://chat.example.com/hooks/abc", on = ["failure"] },
+    /// ]
+    /// ```
+    #[cfg(feature = "webhooks")]
+    fn webhooks(&self) -> Result<Vec<Webhook>> {
+        Webhook::from_config(self.config())
+    }
+
+    /// Version of binaryen used for wasm-opt.
+    ///
+    /// By default it is the value of the environment variable `WASM_RUN_WASM_OPT_VERSION`, then
+    /// `wasm_opt.version` of the configuration ([`BuildArgs::config`]) or 97.
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    fn wasm_opt_version(&self) -> String {
+        prebuilt_wasm_opt::default_version(self.config())
+    }
+
+    /// Expected SHA-256 of the binaryen archive.
+    ///
+    /// By default it is the value of the environment variable `WASM_RUN_WASM_OPT_SHA256` or
+    /// `wasm_opt.sha256` of the configuration. If `None`, the checksum published with the
+    /// release is downloaded and used.
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    fn wasm_opt_sha256(&self) -> Option<String> {
+        prebuilt_wasm_opt::default_sha256(self.config())
+    }
+
+    /// Base URL of a mirror of the binaryen releases
+    /// (`<mirror>/version_<version>/binaryen-version_<version>-<arch>-<os>.tar.gz`) used if the
+    /// download from GitHub fails.
+    ///
+    /// By default it is the value of the environment variable `WASM_RUN_WASM_OPT_MIRROR` or
+    /// `wasm_opt.mirror` of the configuration.
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    fn wasm_opt_mirror(&self) -> Option<String> {
+        prebuilt_wasm_opt::default_mirror(self.config())
+    }
+
+    /// Add runtime checks to the default loader (friendly error message when the WASM fails to
+    /// instantiate, hint when it is served with the wrong MIME type). Enabled for all the profiles
+    /// except [`BuildProfile::Release`] by default.
+    fn loader_checks(&self, profile: BuildProfile) -> bool {
+        !matches!(profile, BuildProfile::Release)
+    }
+
     /// Transpile SASS and SCSS files to CSS in the build directory.
     #[cfg(feature = "sass")]
-    fn build_sass_from_dir(
-        &self,
-        input_dir: &std::path::Path,
-        options: sass_rs::Options,
-    ) -> Result<()> {
+    fn build_sass_from_dir(&self, input_dir: &Utf8Path, options: sass_rs::Options) -> Result<()> {
         use walkdir::{DirEntry, WalkDir};
 
         let build_path = self.build_path();
 
         fn is_sass(entry: &DirEntry) -> bool {
             matches!(
-                entry.path().extension().map(|x| x.to_str()).flatten(),
+                entry.path().extension().and_then(|x| x.to_str()),
                 Some("sass") | Some("scss")
             )
         }
@@ -281,16 +1153,12 @@ pub trait BuildArgs: Downcast {
 
         log::info!("Building SASS from {:?}", input_dir);
 
-        let walker = WalkDir::new(&input_dir).into_iter();
+        let walker = WalkDir::new(input_dir).into_iter();
         for entry in walker
             .filter_map(|x| match x {
                 Ok(x) => Some(x),
                 Err(err) => {
-                    log::warn!(
-                        "Could not walk into directory `{}`: {}",
-                        input_dir.display(),
-                        err,
-                    );
+                    log::warn!("Could not walk into directory `{}`: {}", input_dir, err);
                     None
                 }
             })
@@ -298,7 +1166,8 @@ pub trait BuildArgs: Downcast {
         {
             let file_path = entry.path();
             let css_path = build_path
-                .join(file_path.strip_prefix(&input_dir).unwrap())
+                .as_std_path()
+                .join(file_path.strip_prefix(input_dir).unwrap())
                 .with_extension("css");
 
             match sass_rs::compile_file(file_path, options.clone()) {
@@ -322,10 +1191,10 @@ pub trait BuildArgs: Downcast {
 
     /// Returns a list of directories to lookup to transpile SASS and SCSS files to CSS.
     #[cfg(feature = "sass")]
-    fn sass_lookup_directories(&self, _profile: BuildProfile) -> Vec<PathBuf> {
+    fn sass_lookup_directories(&self, _profile: BuildProfile) -> Vec<Utf8PathBuf> {
         const STYLE_CANDIDATES: &[&str] = &["assets", "styles", "css", "sass"];
 
-        let package_path = self.frontend_package().manifest_path.parent().unwrap();
+        let package_path = metadata_path(self.frontend_package().manifest_path.parent().unwrap());
 
         STYLE_CANDIDATES
             .iter()
@@ -346,29 +1215,149 @@ pub trait BuildArgs: Downcast {
         }
     }
 
-    /// Run the `build` command.
-    fn run(self) -> Result<PathBuf>
+    /// Options of the compilation of the translations (Fluent `.ftl` and gettext `.po` files) to
+    /// JSON bundles in the build directory. By default, the directory `i18n` of the frontend
+    /// package is compiled to the directory `i18n` of the build directory, indented in the
+    /// non-release builds.
+    #[cfg(feature = "i18n")]
+    fn i18n_options(&self, profile: BuildProfile) -> I18nOptions {
+        I18nOptions::default().pretty(!matches!(profile, BuildProfile::Release))
+    }
+
+    /// Run the `build` command and return the files it emitted.
+    fn run(self) -> Result<BuildOutput>
     where
         Self: Sized + 'static,
     {
-        let hooks = HOOKS.get().expect("wasm_run_init() has not been called");
-        build(BuildProfile::Release, &self, hooks)?;
-        Ok(self.build_path().to_owned())
+        let hooks = self.context().hooks();
+        build(BuildProfile::Release, &self, hooks)
     }
 }
 
 impl_downcast!(BuildArgs);
 
 impl BuildArgs for DefaultBuildArgs {
-    fn build_path(&self) -> &PathBuf {
-        self.build_path
-            .as_ref()
-            .unwrap_or_else(|| self.default_build_path())
+    fn context(&self) -> &WorkspaceContext {
+        match &self.context {
+            Some(context) => context,
+            None => WorkspaceContext::global(),
+        }
+    }
+
+    fn build_path(&self) -> &Utf8PathBuf {
+        self.out_dir.get_or_init(|| {
+            out_dir::resolve(
+                self.build_path.as_deref(),
+                self.default_build_path(),
+                self.per_branch,
+                &self.metadata().workspace_root,
+            )
+        })
     }
 
     fn profiling(&self) -> bool {
         self.profiling
     }
+
+    fn message_format(&self) -> MessageFormat {
+        self.message_format
+    }
+
+    fn auto_install_target(&self) -> bool {
+        self.auto_install_target
+    }
+
+    fn deny_bindgen_warnings(&self) -> bool {
+        self.deny_bindgen_warnings
+    }
+
+    fn frontend_bin(&self) -> Option<String> {
+        self.frontend_bin.clone()
+    }
+
+    fn offline(&self) -> bool {
+        self.offline
+    }
+
+    fn external_bindgen(&self) -> bool {
+        self.external_bindgen
+    }
+
+    fn dwarf(&self) -> bool {
+        self.dwarf
+    }
+
+    fn build_cache(&self) -> bool {
+        !self.no_build_cache
+    }
+
+    fn timings(&self) -> Option<Timings> {
+        self.timings.map(|x| x.unwrap_or(Timings::Summary))
+    }
+
+    fn keep_builds(&self) -> usize {
+        self.keep_builds
+    }
+
+    fn critical_css(&self) -> Result<Option<CriticalCss>> {
+        let critical_css = CriticalCss::from_config(self.config())?;
+        if self.critical_css {
+            Ok(Some(critical_css.unwrap_or_default()))
+        } else {
+            Ok(critical_css)
+        }
+    }
+
+    fn fingerprint(&self) -> Result<Fingerprint> {
+        let mut fingerprint = Fingerprint::from_config(self.config())?;
+        if let Some(algorithm) = self.hash_algorithm {
+            fingerprint.algorithm = algorithm;
+        }
+        if let Some(length) = self.hash_length {
+            fingerprint.length = length;
+        }
+        Ok(fingerprint)
+    }
+
+    fn fingerprint_assets(&self, profile: BuildProfile) -> bool {
+        self.fingerprint_assets
+            || self.profile_config(profile).get("fingerprint")["assets"]
+                .as_bool()
+                .unwrap_or(false)
+    }
+
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    fn wasm_opt_version(&self) -> String {
+        self.wasm_opt_version
+            .clone()
+            .unwrap_or_else(|| prebuilt_wasm_opt::default_version(self.config()))
+    }
+
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    fn wasm_opt_sha256(&self) -> Option<String> {
+        self.wasm_opt_sha256
+            .clone()
+            .or_else(|| prebuilt_wasm_opt::default_sha256(self.config()))
+    }
+
+    #[cfg(feature = "prebuilt-wasm-opt")]
+    fn wasm_opt_mirror(&self) -> Option<String> {
+        self.wasm_opt_mirror
+            .clone()
+            .or_else(|| prebuilt_wasm_opt::default_mirror(self.config()))
+    }
+
+    fn loader_checks(&self, profile: BuildProfile) -> bool {
+        !self.no_loader_checks && !matches!(profile, BuildProfile::Release)
+    }
+
+    fn artifact_subdir(&self) -> Option<&Utf8Path> {
+        self.artifact_subdir.as_deref()
+    }
+
+    fn index_source(&self) -> Option<Utf8PathBuf> {
+        self.index.clone().or_else(|| self.config().index.clone())
+    }
 }
 
 /// Serve arguments.
@@ -384,18 +1373,177 @@ pub struct DefaultServeArgs {
     #[structopt(long, short = "h", default_value = "127.0.0.1")]
     pub ip: String,
 
-    /// Port number.
-    #[structopt(long, short = "p", default_value = "3000")]
-    pub port: u16,
+    /// Port number (default: 3000, or derived from the name of the package with
+    /// `per_package_defaults` when the workspace contains several runner crates).
+    ///
+    /// Use 0 to pick a free port.
+    #[structopt(long, short = "p")]
+    pub port: Option<u16>,
 
-    /// Build arguments.
-    #[structopt(flatten)]
-    pub build_args: DefaultBuildArgs,
-}
+    /// Pick a free port if the port is already in use.
+    #[structopt(long)]
+    pub auto_port: bool,
 
-/// A trait that allows overriding the `serve` command.
-pub trait ServeArgs: Downcast + Send {
-    /// Activate HTTP logs.
+    /// Bind a random free port and announce it on the standard output with a JSON line
+    /// (`"reason": "wasm-run-server-started"`) and in `status.json`.
+    #[structopt(long)]
+    pub bind_random_port: bool,
+
+    /// Write the URL of the development server to this file once the port is bound.
+    #[structopt(long)]
+    pub port_file: Option<Utf8PathBuf>,
+
+    /// Open the development server in the default browser once it has started.
+    #[structopt(long)]
+    pub open: bool,
+
+    /// Append a `?v=<build-id>` query string to the script and WASM URLs of the served
+    /// `index.html` so browsers and proxies never reuse a stale build.
+    #[structopt(long)]
+    pub cache_bust: bool,
+
+    /// Do not display the build errors in an overlay in the browser (`overlay = false` in the
+    /// configuration).
+    #[structopt(long)]
+    pub no_overlay: bool,
+
+    /// Serve a status page at `/__wasm_run/` with the last builds, the files of the build
+    /// directory and the last events of the watcher.
+    #[structopt(long)]
+    pub status_page: bool,
+
+    /// Serve a listing of the build directory at `/__wasm_run/files` with the sizes, the hashes,
+    /// the modification times and the compressed sizes of the files.
+    #[structopt(long)]
+    pub file_listing: bool,
+
+    /// Serve the external dependencies (`external` of the configuration) from their local copies
+    /// in the target directory instead of the CDN, for working offline.
+    #[structopt(long)]
+    pub cache_external: bool,
+
+    /// Delay the responses of the development server by this number of milliseconds. It can be
+    /// limited to the paths matching a glob pattern with `<pattern>=<ms>` (for example
+    /// `/api/*=500`).
+    #[structopt(long, number_of_values = 1)]
+    pub latency: Vec<String>,
+
+    /// Limit the bandwidth of the responses of the development server to this number of
+    /// kilobits per second. It can be limited to the paths matching a glob pattern with
+    /// `<pattern>=<kbps>` (for example `*.wasm=1000`).
+    #[structopt(long, number_of_values = 1)]
+    pub throttle: Vec<String>,
+
+    /// Ignore the changes on the files matching this pattern (`.gitignore` syntax) when watching.
+    #[structopt(long = "watch-ignore", number_of_values = 1)]
+    pub watch_ignore: Vec<String>,
+
+    /// Use a polling watcher that checks for changes every <poll> milliseconds instead of the
+    /// native file watcher (useful on network file systems, Docker bind mounts and WSL). This is
+    /// a shortcut for `--watcher poll --watch-poll-interval <poll>`.
+    #[structopt(long)]
+    pub poll: Option<u64>,
+
+    /// File watcher: `native` (the watcher of the platform), `inotify`, `fsevents`, `windows` or
+    /// `poll`.
+    #[structopt(
+        long,
+        default_value = "native",
+        possible_values = &["native", "inotify", "fsevents", "windows", "poll"],
+    )]
+    pub watcher: WatcherKind,
+
+    /// Interval in milliseconds between two checks of the polling watcher.
+    #[structopt(long, default_value = "1000")]
+    pub watch_poll_interval: u64,
+
+    /// Only run the stages of the build fed by the files that changed (copy of the static files,
+    /// SASS, translations) instead of the whole build pipeline. The `post_build` hook is not run
+    /// by these rebuilds.
+    #[structopt(long)]
+    pub partial_rebuilds: bool,
+
+    /// Run the tests after every successful rebuild of the frontend.
+    #[structopt(long)]
+    pub test_on_change: bool,
+
+    /// Command used to run the tests with `--test-on-change` (default: `cargo test` in the
+    /// frontend crate). It is run by the shell.
+    #[structopt(long)]
+    pub test_command: Option<String>,
+
+    /// Build and rebuild the frontend with the release profile (fully optimized) instead of the
+    /// development profile.
+    #[structopt(long)]
+    pub release: bool,
+
+    /// Serve the unoptimized build immediately and replace it when wasm-opt is finished (with
+    /// `--release` or `--profiling`).
+    #[structopt(long)]
+    pub background_wasm_opt: bool,
+
+    /// Wait until a GET request to this URL succeeds before reporting that the backend has
+    /// (re-)started.
+    #[cfg(not(feature = "dev-server"))]
+    #[structopt(long)]
+    pub backend_health_url: Option<String>,
+
+    /// Wait until this port accepts connections before reporting that the backend has
+    /// (re-)started.
+    #[cfg(not(feature = "dev-server"))]
+    #[structopt(long, conflicts_with = "backend-health-url")]
+    pub backend_health_port: Option<u16>,
+
+    /// Maximum time in milliseconds waited for the health check of the backend.
+    #[cfg(not(feature = "dev-server"))]
+    #[structopt(long, default_value = "30000")]
+    pub backend_health_timeout: u64,
+
+    /// Time in milliseconds given to the backend to exit after SIGTERM before it is killed (0
+    /// kills it immediately).
+    #[cfg(not(feature = "dev-server"))]
+    #[structopt(long, default_value = "5000")]
+    pub backend_kill_timeout: u64,
+
+    /// Do not watch the sources: a source modified since the last build is reported and pressing
+    /// Enter rebuilds.
+    #[structopt(long)]
+    pub no_watch: bool,
+
+    /// Restart the whole command (with `cargo run`) when a change is detected instead of
+    /// rebuilding the frontend.
+    #[structopt(long)]
+    pub full_restart: bool,
+
+    /// Restart the backend executable without running cargo when a changed file is not one of
+    /// its sources (templates, configuration, ...).
+    #[cfg(not(feature = "dev-server"))]
+    #[structopt(long)]
+    pub fast_backend_restart: bool,
+
+    /// Serve the build <from-history> of the history (retained with `--keep-builds`) instead of
+    /// building the frontend. Nothing is watched.
+    #[cfg(feature = "dev-server")]
+    #[structopt(long)]
+    pub from_history: Option<u64>,
+
+    /// Build arguments.
+    #[structopt(flatten)]
+    pub build_args: DefaultBuildArgs,
+}
+
+impl DefaultServeArgs {
+    /// Use this context instead of the global context initialized by the macro
+    /// [`main`](crate::main).
+    pub fn with_context(mut self, context: Arc<WorkspaceContext>) -> Self {
+        self.build_args = self.build_args.with_context(context);
+        self
+    }
+}
+
+/// A trait that allows overriding the `serve` command.
+pub trait ServeArgs: Downcast + Send {
+    /// Activate HTTP logs.
     #[cfg(feature = "dev-server")]
     fn log(&self) -> bool;
 
@@ -406,9 +1554,300 @@ pub trait ServeArgs: Downcast + Send {
     fn ip(&self) -> &str;
 
     /// Port number.
+    ///
+    /// Use 0 to pick a free port.
     #[cfg(feature = "dev-server")]
     fn port(&self) -> u16;
 
+    /// Default port number.
+    ///
+    /// This is `port` of the configuration ([`BuildArgs::config`]) if any. Otherwise it is 3000
+    /// unless the macro [`main`](crate::main) has the argument `per_package_defaults` and the
+    /// workspace contains several runner crates (crates depending on `wasm-run`): then it is
+    /// derived from the name of the frontend package so the development servers don't collide.
+    #[cfg(feature = "dev-server")]
+    fn default_port(&self) -> u16 {
+        self.build_args().context().default_port()
+    }
+
+    /// Pick a free port if the port is already in use.
+    #[cfg(feature = "dev-server")]
+    fn auto_port(&self) -> bool {
+        false
+    }
+
+    /// Bind a random free port and announce it on the standard output with a JSON line
+    /// (`"reason": "wasm-run-server-started"`) even if the JSON message format is not used.
+    #[cfg(feature = "dev-server")]
+    fn bind_random_port(&self) -> bool {
+        false
+    }
+
+    /// Path to the `status.json` file that is written once the development server is started.
+    /// It contains the PID of the process, the IP address, the port and the URL of the server and
+    /// the build directory.
+    ///
+    /// By default it is `wasm-run/status.json` in the target directory.
+    #[cfg(feature = "dev-server")]
+    fn status_path(&self) -> Utf8PathBuf {
+        self.build_args()
+            .target_path()
+            .join("wasm-run")
+            .join("status.json")
+    }
+
+    /// Write the URL of the development server to this file once the port is bound.
+    #[cfg(feature = "dev-server")]
+    fn port_file(&self) -> Option<&Utf8Path> {
+        None
+    }
+
+    /// Open the development server in the default browser once it has started.
+    #[cfg(feature = "dev-server")]
+    fn open(&self) -> bool {
+        false
+    }
+
+    /// Append a `?v=<build-id>` query string to the script and WASM URLs of the served
+    /// `index.html`. The files written to the build directory are not affected.
+    #[cfg(feature = "dev-server")]
+    fn cache_bust(&self) -> bool {
+        false
+    }
+
+    /// Display the errors of the builds triggered by the watcher in an overlay in the browser.
+    ///
+    /// By default, it is enabled unless `overlay = false` is set in the configuration
+    /// ([`BuildArgs::config`]).
+    #[cfg(feature = "dev-server")]
+    fn overlay(&self) -> bool {
+        self.build_args().config().get("overlay").as_bool() != Some(false)
+    }
+
+    /// Serve a status page at `/__wasm_run/`: the last builds (time, duration and errors), the
+    /// files of the build directory with their sizes and the last events of the watcher.
+    #[cfg(feature = "dev-server")]
+    fn status_page(&self) -> bool {
+        false
+    }
+
+    /// Serve a browsable listing of the build directory at `/__wasm_run/files`: the size, the hash
+    /// (BLAKE3), the modification time and the sizes of the precompressed variants (`.br` and
+    /// `.gz`) of each file. It is disabled by default.
+    #[cfg(feature = "dev-server")]
+    fn file_listing(&self) -> bool {
+        false
+    }
+
+    /// Serve the external dependencies of the `index.html` (check [`ExternalDependency`]) from
+    /// their local copies at `/__wasm_run/external/` instead of the CDN, so the app works
+    /// offline. Only the served `index.html` is rewritten, the one of the build directory keeps
+    /// the URLs of the CDN. It is disabled by default.
+    #[cfg(feature = "dev-server")]
+    fn cache_external(&self) -> bool {
+        false
+    }
+
+    /// Artificial latency (in milliseconds) of the responses of the development server, per path
+    /// glob pattern. The first matching rule is used.
+    #[cfg(feature = "dev-server")]
+    fn latency(&self) -> Result<Vec<PathRule<u64>>> {
+        Ok(Vec::new())
+    }
+
+    /// Bandwidth limit (in kilobits per second) of the responses of the development server, per
+    /// path glob pattern. The first matching rule is used.
+    #[cfg(feature = "dev-server")]
+    fn throttle(&self) -> Result<Vec<PathRule<u64>>> {
+        Ok(Vec::new())
+    }
+
+    /// Path prefixes forwarded by the development server to other HTTP servers (check
+    /// [`ProxyRule`]), for example to reach the API of a backend that is not served by the hooks.
+    ///
+    /// By default, they are read from `proxy` in `WasmRun.toml` or in the metadata of the
+    /// frontend package (check [`Config`]):
+    ///
+    /// ```toml
+    /// [package.metadata.wasm-run.proxy]
+    /// "/api" = "http://localhost:8000"
+    /// ```
+    #[cfg(feature = "dev-server")]
+    fn proxy(&self) -> Result<Vec<ProxyRule>> {
+        ProxyRule::from_value(self.build_args().config().get("proxy"))
+    }
+
+    /// JS globals provided by the host page (for example `analytics.track` or `AndroidBridge`).
+    /// The development server defines the missing ones with shims that log a warning when they
+    /// are used so the app runs standalone during development. The files written to the build
+    /// directory are not affected.
+    ///
+    /// By default, they are read from `host_globals` in `WasmRun.toml` or in the metadata of the
+    /// frontend package (check [`Config`]):
+    ///
+    /// ```toml
+    /// [package.metadata.wasm-run]
+    /// host_globals = ["analytics.track", "AndroidBridge"]
+    /// ```
+    #[cfg(feature = "dev-server")]
+    fn host_globals(&self) -> Result<Vec<String>> {
+        let globals = self.build_args().config().get("host_globals");
+        if globals.is_null() {
+            return Ok(Vec::new());
+        }
+
+        globals
+            .as_array()
+            .and_then(|x| x.iter().map(|x| x.as_str().map(String::from)).collect())
+            .context("invalid `host_globals`: expected an array of strings")
+    }
+
+    /// Patterns (`.gitignore` syntax, relative to the workspace root) of the files whose changes
+    /// are ignored when watching, in addition to `watch_ignore` of the configuration.
+    ///
+    /// The `.gitignore` and `.ignore` files of the workspace and its members, the
+    /// [excluded paths](ServeArgs::watch_excluded_paths) and the temporary files of the editors
+    /// are always ignored.
+    fn watch_ignore(&self) -> &[String] {
+        &[]
+    }
+
+    /// Directories whose changes never trigger a rebuild: the build directory and the target
+    /// directory by default.
+    ///
+    /// They are compared after resolving the relative paths and the symbolic links so a build
+    /// directory inside a watched crate doesn't trigger a rebuild after every build.
+    fn watch_excluded_paths(&self) -> Vec<Utf8PathBuf> {
+        vec![
+            self.build_args().build_path().to_owned(),
+            self.build_args().target_path().to_owned(),
+        ]
+    }
+
+    /// File watcher used to detect the changes.
+    fn watcher_kind(&self) -> WatcherKind {
+        WatcherKind::Native
+    }
+
+    /// Interval between two checks of the polling watcher.
+    fn watch_poll_interval(&self) -> time::Duration {
+        time::Duration::from_secs(1)
+    }
+
+    /// Configuration of the file watchers. It is made of [`ServeArgs::watcher_kind`] and
+    /// [`ServeArgs::watch_poll_interval`] by default.
+    fn watcher_config(&self) -> WatcherConfig {
+        WatcherConfig {
+            kind: self.watcher_kind(),
+            poll_interval: self.watch_poll_interval(),
+            ..WatcherConfig::default()
+        }
+    }
+
+    /// Only run the stages of the build fed by the files that changed: a change of a static file
+    /// only copies the static files again, a change of a SASS or SCSS file only transpiles the
+    /// styles and a change of a translation only compiles the translations. The whole pipeline is
+    /// run for the other files, after a failed build and when the build directory is rewritten
+    /// after the `post_build` hook (fingerprints, critical CSS, service worker).
+    ///
+    /// This is disabled by default: the `post_build` hook is not run by a partial rebuild, enable
+    /// it only if your hook doesn't process these files.
+    fn partial_rebuilds(&self) -> bool {
+        false
+    }
+
+    /// Run the tests after every successful rebuild of the frontend.
+    fn test_on_change(&self) -> bool {
+        false
+    }
+
+    /// Build and rebuild the frontend with the release profile instead of the development
+    /// profile.
+    fn release(&self) -> bool {
+        false
+    }
+
+    /// With an optimized profile ([`ServeArgs::release`] or [`BuildArgs::profiling`]), serve the
+    /// unoptimized build immediately and replace the file `app_bg.wasm` of the artifact directory
+    /// when wasm-opt is finished. The browser is notified when the optimized build is ready if the
+    /// overlay is enabled.
+    fn background_wasm_opt(&self) -> bool {
+        false
+    }
+
+    /// Serve this build of the history (check [`BuildArgs::keep_builds`]) as it is: the frontend
+    /// is not built and nothing is watched. This is useful to find when a regression appeared
+    /// without checking out the old commits.
+    #[cfg(feature = "dev-server")]
+    fn history_build(&self) -> Option<u64> {
+        None
+    }
+
+    /// Check that the backend is ready after (re-)starting it: the backend is reported as started
+    /// once the check passes (or after [`ServeArgs::backend_health_timeout`]). There is none by
+    /// default.
+    #[cfg(not(feature = "dev-server"))]
+    fn backend_health_check(&self) -> Option<HealthCheck> {
+        None
+    }
+
+    /// Maximum time waited for the health check of the backend. It is 30 seconds by default.
+    #[cfg(not(feature = "dev-server"))]
+    fn backend_health_timeout(&self) -> time::Duration {
+        time::Duration::from_secs(30)
+    }
+
+    /// Time given to the backend to exit after `SIGTERM` before it is killed with `SIGKILL` when
+    /// it is restarted. It is 5 seconds by default. The backend is killed immediately if it is
+    /// zero and on Windows.
+    #[cfg(not(feature = "dev-server"))]
+    fn backend_kill_timeout(&self) -> time::Duration {
+        time::Duration::from_secs(5)
+    }
+
+    /// Watch the sources and rebuild when they change. It is enabled by default. Otherwise (or
+    /// if the watcher stops) the sources are checked periodically: a source modified since the
+    /// last build is reported in the logs and in the overlay, and pressing Enter rebuilds.
+    fn watch(&self) -> bool {
+        true
+    }
+
+    /// Restart the command entirely when a change is detected: the command runs in a child
+    /// process (`cargo run` of the runner with the same arguments, so the changes of the runner
+    /// and of a custom backend are compiled too) that is stopped and started again. This is
+    /// useful with a custom `serve` hook that runs a custom backend. The paths of the hooks
+    /// `frontend_watch` and `backend_watch` are watched. It is disabled by default.
+    fn full_restart(&self) -> bool {
+        false
+    }
+
+    /// Restart the executable of the backend directly, without running cargo, when a changed file
+    /// is not one of its inputs according to the dep-info file written by cargo next to it. This
+    /// only applies when the `backend_command` hook builds the backend (`cargo build`, not
+    /// `cargo run`). It is disabled by default.
+    #[cfg(not(feature = "dev-server"))]
+    fn fast_backend_restart(&self) -> bool {
+        false
+    }
+
+    /// Build profile used to build and rebuild the frontend while serving. It is
+    /// [`BuildProfile::Release`] if [`ServeArgs::release`] is enabled and [`BuildProfile::Dev`]
+    /// otherwise.
+    fn profile(&self) -> BuildProfile {
+        if self.release() {
+            BuildProfile::Release
+        } else {
+            BuildProfile::Dev
+        }
+    }
+
+    /// Command used to run the tests when [`ServeArgs::test_on_change`] is enabled.
+    ///
+    /// By default it will do `cargo test` in the frontend crate.
+    fn test_command(&self) -> Command {
+        default_test_command(self.build_args())
+    }
+
     /// Build arguments.
     fn build_args(&self) -> &dyn BuildArgs;
 
@@ -417,39 +1856,19 @@ pub trait ServeArgs: Downcast + Send {
     where
         Self: Sync + Sized + 'static,
     {
-        let hooks = HOOKS.get().expect("wasm_run_init() has not been called");
-        // NOTE: the first step for serving is to call `build` a first time. The build directory
-        //       must be present before we start watching files there.
-        build(BuildProfile::Dev, self.build_args(), hooks)?;
-        #[cfg(feature = "dev-server")]
-        {
-            async_std::task::block_on(async {
-                let t1 = async_std::task::spawn(serve_frontend(&self, hooks)?);
-                let t2 = async_std::task::spawn_blocking(move || watch_frontend(&self, hooks));
-                futures::try_join!(t1, t2)?;
-                Err(anyhow!("server and watcher unexpectedly exited"))
-            })
-        }
-        #[cfg(not(feature = "dev-server"))]
-        {
-            use std::sync::Arc;
-            use std::thread;
-
-            if self.build_args().backend_package().is_none() {
-                bail!("missing backend crate name");
-            }
-
-            let args = Arc::new(self);
-            let t1 = {
-                let args = Arc::clone(&args);
-                thread::spawn(move || watch_frontend(&*args, hooks))
-            };
-            let t2 = thread::spawn(move || watch_backend(&*args, hooks));
-            let _ = t1.join();
-            let _ = t2.join();
+        let hooks = self.build_args().context().shared_hooks();
+        serve(self, hooks)
+    }
 
-            Err(anyhow!("server and watcher unexpectedly exited"))
-        }
+    /// Start the `serve` command in the background and return a handle on the development server
+    /// (check [`ServeHandle`]). Unlike [`ServeArgs::run`], the command is never restarted
+    /// entirely (`--full-restart`).
+    fn start(self) -> Result<ServeHandle>
+    where
+        Self: Sync + Sized + 'static,
+    {
+        let hooks = self.build_args().context().shared_hooks();
+        serve_handle::start(self, hooks)
     }
 }
 
@@ -468,399 +1887,2853 @@ impl ServeArgs for DefaultServeArgs {
 
     #[cfg(feature = "dev-server")]
     fn port(&self) -> u16 {
-        self.port
+        self.port.unwrap_or_else(|| self.default_port())
     }
 
-    fn build_args(&self) -> &dyn BuildArgs {
-        &self.build_args
+    #[cfg(feature = "dev-server")]
+    fn auto_port(&self) -> bool {
+        self.auto_port
     }
-}
 
-/// Hooks.
-///
-/// Check the code of [`Hooks::default()`] implementation to see what they do by default.
-///
-/// If you don't provide your own hook, the default code will be executed. But if you do provide a
-/// hook, the code will be *replaced*.
-pub struct Hooks {
-    /// This hook will be run before the WASM is compiled. It does nothing by default.
-    /// You can tweak the command-line arguments of the build command here or create additional
-    /// files in the build directory.
-    pub pre_build:
-        Box<dyn Fn(&dyn BuildArgs, BuildProfile, &mut Command) -> Result<()> + Send + Sync>,
+    #[cfg(feature = "dev-server")]
+    fn bind_random_port(&self) -> bool {
+        self.bind_random_port
+    }
 
-    /// This hook will be run after the WASM is compiled and optimized.
-    /// By default it copies the static files to the build directory.
-    #[allow(clippy::type_complexity)]
-    pub post_build:
-        Box<dyn Fn(&dyn BuildArgs, BuildProfile, String, Vec<u8>) -> Result<()> + Send + Sync>,
+    #[cfg(feature = "dev-server")]
+    fn port_file(&self) -> Option<&Utf8Path> {
+        self.port_file.as_deref()
+    }
 
-    /// This hook will be run before running the HTTP server.
-    /// By default it will add routes to the files in the build directory.
     #[cfg(feature = "dev-server")]
-    #[allow(clippy::type_complexity)]
-    pub serve: Box<dyn Fn(&dyn ServeArgs, &mut Server<()>) -> Result<()> + Send + Sync>,
+    fn open(&self) -> bool {
+        self.open
+    }
 
-    /// This hook will be run before starting to watch for changes in files.
-    /// By default it will add all the `src/` directories and `Cargo.toml` files of all the crates
-    /// in the workspace plus the `static/` directory if it exists in the frontend crate.
-    pub frontend_watch:
-        Box<dyn Fn(&dyn ServeArgs, &mut RecommendedWatcher) -> Result<()> + Send + Sync>,
+    #[cfg(feature = "dev-server")]
+    fn cache_bust(&self) -> bool {
+        self.cache_bust
+    }
 
-    /// This hook will be run before starting to watch for changes in files.
-    /// By default it will add the backend crate directory and all its dependencies. But it
-    /// excludes the target directory.
-    pub backend_watch:
-        Box<dyn Fn(&dyn ServeArgs, &mut RecommendedWatcher) -> Result<()> + Send + Sync>,
+    #[cfg(feature = "dev-server")]
+    fn overlay(&self) -> bool {
+        !self.no_overlay && self.build_args().config().get("overlay").as_bool() != Some(false)
+    }
 
-    /// This hook will be run before (re-)starting the backend.
-    /// You can tweak the cargo command that is run here: adding/removing environment variables or
-    /// adding arguments.
-    /// By default it will do `cargo run -p <backend_crate>`.
-    pub backend_command: Box<dyn Fn(&dyn ServeArgs, &mut Command) -> Result<()> + Send + Sync>,
-}
+    #[cfg(feature = "dev-server")]
+    fn status_page(&self) -> bool {
+        self.status_page
+    }
 
-impl Default for Hooks {
-    fn default() -> Self {
-        Self {
-            backend_command: Box::new(|args, command| {
-                command.args(&[
-                    "run",
-                    "-p",
-                    &args
-                        .build_args()
-                        .backend_package()
-                        .context("missing backend crate name")?
-                        .name,
-                ]);
-                Ok(())
-            }),
-            backend_watch: Box::new(|args, watcher| {
-                use notify::{RecursiveMode, Watcher};
+    #[cfg(feature = "dev-server")]
+    fn file_listing(&self) -> bool {
+        self.file_listing
+    }
 
-                let metadata = args.build_args().metadata();
-                let backend = args
-                    .build_args()
-                    .backend_package()
-                    .context("missing backend crate name")?;
-                let packages: HashMap<_, _> = metadata
-                    .packages
-                    .iter()
-                    .map(|x| (x.name.as_str(), x))
-                    .collect();
-                let members: HashSet<_> = HashSet::from_iter(&metadata.workspace_members);
+    #[cfg(feature = "dev-server")]
+    fn cache_external(&self) -> bool {
+        self.cache_external
+    }
 
-                backend
-                    .dependencies
-                    .iter()
-                    .map(|x| packages.get(x.name.as_str()).unwrap())
-                    .filter(|x| members.contains(&x.id))
-                    .map(|x| x.manifest_path.parent().unwrap())
-                    .chain(iter::once(backend.manifest_path.parent().unwrap()))
-                    .try_for_each(|x| watcher.watch(x, RecursiveMode::Recursive))?;
+    #[cfg(feature = "dev-server")]
+    fn latency(&self) -> Result<Vec<PathRule<u64>>> {
+        self.latency
+            .iter()
+            .map(|x| {
+                x.parse()
+                    .map_err(|err| anyhow!("invalid `--latency`: {}", err))
+            })
+            .collect()
+    }
 
-                Ok(())
-            }),
-            frontend_watch: Box::new(|args, watcher| {
-                use notify::{RecursiveMode, Watcher};
+    #[cfg(feature = "dev-server")]
+    fn throttle(&self) -> Result<Vec<PathRule<u64>>> {
+        self.throttle
+            .iter()
+            .map(|x| {
+                x.parse()
+                    .map_err(|err| anyhow!("invalid `--throttle`: {}", err))
+            })
+            .collect()
+    }
 
-                let metadata = args.build_args().metadata();
-                let frontend = args.build_args().frontend_package();
-                let packages: HashMap<_, _> = metadata
-                    .packages
-                    .iter()
-                    .map(|x| (x.name.as_str(), x))
-                    .collect();
-                let members: HashSet<_> = HashSet::from_iter(&metadata.workspace_members);
+    fn watch_ignore(&self) -> &[String] {
+        &self.watch_ignore
+    }
 
-                frontend
-                    .dependencies
-                    .iter()
-                    .filter_map(|x| packages.get(x.name.as_str()))
-                    .filter(|x| members.contains(&x.id))
-                    .map(|x| x.manifest_path.parent().unwrap())
-                    .chain(iter::once(frontend.manifest_path.parent().unwrap()))
-                    .try_for_each(|x| watcher.watch(x, RecursiveMode::Recursive))?;
+    fn watcher_kind(&self) -> WatcherKind {
+        if self.poll.is_some() {
+            WatcherKind::Poll
+        } else {
+            self.watcher
+        }
+    }
 
-                Ok(())
-            }),
-            pre_build: Box::new(|_, _, _| Ok(())),
-            post_build: Box::new(
-                |args, #[allow(unused_variables)] profile, wasm_js, wasm_bin| {
-                    let build_path = args.build_path();
-                    let wasm_js_path = build_path.join("app.js");
-                    let wasm_bin_path = build_path.join("app_bg.wasm");
+    fn watch_poll_interval(&self) -> time::Duration {
+        time::Duration::from_millis(self.poll.unwrap_or(self.watch_poll_interval))
+    }
 
-                    fs::write(&wasm_js_path, wasm_js).with_context(|| {
-                        format!("could not write JS file to `{}`", wasm_js_path.display())
-                    })?;
-                    fs::write(&wasm_bin_path, wasm_bin).with_context(|| {
-                        format!("could not write WASM file to `{}`", wasm_bin_path.display())
-                    })?;
+    fn partial_rebuilds(&self) -> bool {
+        self.partial_rebuilds
+    }
 
-                    let index_path = build_path.join("index.html");
-                    let static_dir = args
-                        .frontend_package()
-                        .manifest_path
-                        .parent()
-                        .unwrap()
-                        .join("static");
-
-                    if index_path.exists() {
-                        fs::copy("index.html", &index_path).context(format!(
-                            "could not copy index.html to `{}`",
-                            index_path.display()
-                        ))?;
-                    } else if static_dir.exists() {
-                        dir::copy(
-                            &static_dir,
-                            &build_path,
-                            &dir::CopyOptions {
-                                content_only: true,
-                                ..dir::CopyOptions::new()
-                            },
-                        )
-                        .with_context(|| {
-                            format!(
-                                "could not copy content of directory static: `{}` to `{}`",
-                                static_dir.display(),
-                                build_path.display()
-                            )
-                        })?;
-                    } else {
-                        fs::write(&index_path, DEFAULT_INDEX).with_context(|| {
-                            format!(
-                                "could not write default index.html to `{}`",
-                                index_path.display()
-                            )
-                        })?;
-                    }
+    fn test_on_change(&self) -> bool {
+        self.test_on_change
+    }
 
-                    #[cfg(feature = "sass")]
-                    {
-                        let options = args.sass_options(profile);
-                        for style_path in args.sass_lookup_directories(profile) {
-                            args.build_sass_from_dir(&style_path, options.clone())?;
-                        }
-                    }
+    fn release(&self) -> bool {
+        self.release
+    }
 
-                    Ok(())
-                },
-            ),
-            #[cfg(feature = "dev-server")]
-            serve: Box::new(|args, server| {
-                use tide::{Body, Request, Response};
+    fn background_wasm_opt(&self) -> bool {
+        self.background_wasm_opt
+    }
 
-                let build_path = args.build_args().build_path().to_owned();
-                let index_path = build_path.join("index.html");
+    #[cfg(not(feature = "dev-server"))]
+    fn backend_health_check(&self) -> Option<HealthCheck> {
+        self.backend_health_url
+            .clone()
+            .map(HealthCheck::Url)
+            .or_else(|| self.backend_health_port.map(HealthCheck::Port))
+    }
 
-                server.at("/").serve_dir(args.build_args().build_path())?;
-                server.at("/").get(move |_| {
-                    let index_path = index_path.clone();
-                    async move { Ok(Response::from(Body::from_file(index_path).await?)) }
-                });
-                server.at("/*path").get(move |req: Request<()>| {
-                    let build_path = build_path.clone();
-                    async move {
-                        match Body::from_file(build_path.join(req.param("path").unwrap())).await {
-                            Ok(body) => Ok(Response::from(body)),
-                            Err(_) => Ok(Response::from(
-                                Body::from_file(build_path.join("index.html")).await?,
-                            )),
-                        }
+    #[cfg(not(feature = "dev-server"))]
+    fn backend_health_timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.backend_health_timeout)
+    }
+
+    #[cfg(not(feature = "dev-server"))]
+    fn backend_kill_timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.backend_kill_timeout)
+    }
+
+    fn watch(&self) -> bool {
+        !self.no_watch
+    }
+
+    fn full_restart(&self) -> bool {
+        self.full_restart
+    }
+
+    #[cfg(not(feature = "dev-server"))]
+    fn fast_backend_restart(&self) -> bool {
+        self.fast_backend_restart
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn history_build(&self) -> Option<u64> {
+        self.from_history
+    }
+
+    fn test_command(&self) -> Command {
+        match self.test_command.as_ref() {
+            #[cfg(unix)]
+            Some(test_command) => {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg(test_command);
+                command
+            }
+            #[cfg(windows)]
+            Some(test_command) => {
+                let mut command = Command::new("cmd");
+                command.arg("/C").arg(test_command);
+                command
+            }
+            _ => default_test_command(self.build_args()),
+        }
+    }
+
+    fn build_args(&self) -> &dyn BuildArgs {
+        &self.build_args
+    }
+}
+
+/// Configuration of a file watcher.
+#[derive(Debug, Clone, Copy)]
+pub struct WatcherConfig {
+    /// Kind of file watcher.
+    pub kind: WatcherKind,
+
+    /// Interval between two checks of the polling watcher.
+    pub poll_interval: time::Duration,
+
+    /// Delay used to debounce the events of the native file watcher.
+    pub delay: time::Duration,
+}
+
+impl Default for WatcherConfig {
+    fn default() -> Self {
+        Self {
+            kind: WatcherKind::Native,
+            poll_interval: time::Duration::from_secs(1),
+            delay: time::Duration::from_secs(2),
+        }
+    }
+}
+
+/// Kind of file watcher.
+///
+/// The native file watchers are only available on their platform: `inotify` on Linux, `fsevents`
+/// on macOS and `windows` on Windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WatcherKind {
+    /// The native file watcher of the platform.
+    #[default]
+    Native,
+    /// inotify (Linux).
+    Inotify,
+    /// FSEvents (macOS).
+    FsEvents,
+    /// ReadDirectoryChangesW (Windows).
+    Windows,
+    /// A file watcher that polls the file system.
+    Poll,
+}
+
+impl WatcherKind {
+    /// The native file watcher of this platform.
+    pub fn native() -> Self {
+        if cfg!(target_os = "linux") {
+            Self::Inotify
+        } else if cfg!(target_os = "macos") {
+            Self::FsEvents
+        } else if cfg!(windows) {
+            Self::Windows
+        } else {
+            Self::Poll
+        }
+    }
+}
+
+impl FromStr for WatcherKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native" => Ok(Self::Native),
+            "inotify" => Ok(Self::Inotify),
+            "fsevents" => Ok(Self::FsEvents),
+            "windows" => Ok(Self::Windows),
+            "poll" => Ok(Self::Poll),
+            _ => Err(format!("invalid file watcher `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for WatcherKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Native => write!(f, "native"),
+            Self::Inotify => write!(f, "inotify"),
+            Self::FsEvents => write!(f, "fsevents"),
+            Self::Windows => write!(f, "windows"),
+            Self::Poll => write!(f, "poll"),
+        }
+    }
+}
+
+/// A file watcher: the native file watcher of the platform or a polling watcher.
+///
+/// Use the trait [`notify::Watcher`] to add paths to watch. The paths that don't exist yet are
+/// watched as soon as they are created: their closest existing parent directory is watched in the
+/// meantime. The watcher of the `serve` command skips the ignored directories (the build and
+/// target directories, `node_modules`, ...) of the directories watched recursively.
+pub struct FileWatcher {
+    backend: WatcherBackend,
+    watched: Vec<(PathBuf, notify::RecursiveMode)>,
+    pending: Vec<PendingWatch>,
+    filter: Option<WatchFilter>,
+    /// The directories watched non-recursively because they contain ignored directories: their
+    /// other subdirectories are watched recursively.
+    split: Vec<PathBuf>,
+}
+
+enum WatcherBackend {
+    Recommended(RecommendedWatcher),
+    Poll(PollWatcher),
+    /// The paths are only recorded (`serve --no-watch`).
+    Disabled,
+}
+
+/// A path that will be watched when it is created.
+struct PendingWatch {
+    path: PathBuf,
+    recursive_mode: notify::RecursiveMode,
+    /// The closest existing parent directory, watched until the path is created.
+    parent: PathBuf,
+}
+
+impl FileWatcher {
+    /// Create a file watcher from its configuration.
+    pub fn from_config(
+        config: WatcherConfig,
+        tx: mpsc::Sender<notify::DebouncedEvent>,
+    ) -> Result<Self> {
+        use notify::Watcher;
+
+        let kind = match config.kind {
+            WatcherKind::Native => WatcherKind::native(),
+            kind => kind,
+        };
+
+        let backend = if kind == WatcherKind::Poll {
+            WatcherBackend::Poll(
+                PollWatcher::new(tx, config.poll_interval)
+                    .context("could not initialize polling watcher")?,
+            )
+        } else if kind == WatcherKind::native() {
+            WatcherBackend::Recommended(RecommendedWatcher::new(tx, config.delay).context(
+                "could not initialize the native file watcher (you can use a polling watcher \
+                instead with `--poll <interval>`)",
+            )?)
+        } else {
+            bail!(
+                "the file watcher `{}` is not available on this platform (the native file \
+                watcher is `{}`)",
+                kind,
+                WatcherKind::native(),
+            );
+        };
+
+        if kind == WatcherKind::Poll {
+            log::info!(
+                "Using the polling file watcher (interval: {} ms)",
+                config.poll_interval.as_millis()
+            );
+        } else {
+            log::info!("Using the {} file watcher", kind);
+        }
+
+        Ok(Self::from_backend(backend))
+    }
+
+    /// The watcher of the `serve` command: it only records the paths with `--no-watch`.
+    fn for_serve(args: &dyn ServeArgs, tx: mpsc::Sender<notify::DebouncedEvent>) -> Result<Self> {
+        let mut watcher = if args.watch() {
+            Self::from_config(args.watcher_config(), tx)?
+        } else {
+            Self::from_backend(WatcherBackend::Disabled)
+        };
+        watcher.filter = Some(WatchFilter::new(args));
+        Ok(watcher)
+    }
+
+    fn from_backend(backend: WatcherBackend) -> Self {
+        Self {
+            backend,
+            watched: Vec::new(),
+            pending: Vec::new(),
+            filter: None,
+            split: Vec::new(),
+        }
+    }
+
+    /// Paths that are not watched yet because they don't exist.
+    pub fn pending_paths(&self) -> impl Iterator<Item = &Path> {
+        self.pending.iter().map(|x| x.path.as_path())
+    }
+
+    fn watch_or_defer(
+        &mut self,
+        path: &Path,
+        recursive_mode: notify::RecursiveMode,
+    ) -> notify::Result<()> {
+        if path.exists() {
+            return self.attach(path, recursive_mode);
+        }
+
+        let parent = match path.ancestors().skip(1).find(|x| x.is_dir()) {
+            Some(parent) => parent.to_path_buf(),
+            None => return Err(notify::Error::PathNotFound),
+        };
+        self.watch_parent(&parent)?;
+        log::info!(
+            "{} does not exist yet: it will be watched when it is created",
+            path.display()
+        );
+        self.pending.push(PendingWatch {
+            path: path.to_path_buf(),
+            recursive_mode,
+            parent,
+        });
+
+        Ok(())
+    }
+
+    /// Watch an existing path. The ignored directories of a directory watched recursively are
+    /// skipped.
+    fn attach(&mut self, path: &Path, recursive_mode: notify::RecursiveMode) -> notify::Result<()> {
+        let plan = match &self.filter {
+            Some(filter)
+                if matches!(recursive_mode, notify::RecursiveMode::Recursive) && path.is_dir() =>
+            {
+                watch_plan(filter, path)
+            }
+            _ => vec![(path.to_path_buf(), recursive_mode)],
+        };
+        let is_recursive = matches!(recursive_mode, notify::RecursiveMode::Recursive);
+        for (path, recursive_mode) in plan {
+            self.backend.watch(&path, recursive_mode)?;
+            if is_recursive && matches!(recursive_mode, notify::RecursiveMode::NonRecursive) {
+                log::debug!(
+                    "Watching {} without its ignored directories",
+                    path.display()
+                );
+                self.split.push(path.clone());
+            }
+            self.watched.push((path, recursive_mode));
+        }
+        Ok(())
+    }
+
+    /// Watch a directory created in a directory that is watched without its ignored directories.
+    fn attach_created(&mut self, path: &Path) {
+        let is_ignored = match &self.filter {
+            Some(filter) => filter.is_ignored_dir(path),
+            None => return,
+        };
+        if is_ignored
+            || !path.is_dir()
+            || !path
+                .parent()
+                .is_some_and(|x| self.split.iter().any(|y| y == x))
+            || self.watched.iter().any(|(x, _)| x == path)
+        {
+            return;
+        }
+        if let Err(err) = self.attach(path, notify::RecursiveMode::Recursive) {
+            log::error!("Could not watch {}: {}", path.display(), err);
+        }
+    }
+
+    // NOTE: a parent directory that is already watched recursively is left untouched
+    fn watch_parent(&mut self, parent: &Path) -> notify::Result<()> {
+        if self.pending.iter().any(|x| x.parent == parent) || self.is_watched_recursively(parent) {
+            Ok(())
+        } else {
+            self.backend
+                .watch(parent, notify::RecursiveMode::NonRecursive)
+        }
+    }
+
+    fn unwatch_parent(&mut self, parent: &Path) {
+        if !self.pending.iter().any(|x| x.parent == parent) && !self.is_watched_recursively(parent)
+        {
+            let _ = self.backend.unwatch(parent);
+        }
+    }
+
+    /// Attach the watches of the pending paths that have been created. Returns `true` if the
+    /// change must be ignored: it happened in a directory watched only while waiting for a pending
+    /// path to be created.
+    fn update_pending(&mut self, changed: &Path) -> bool {
+        self.attach_created(changed);
+        if self.pending.is_empty() {
+            return false;
+        }
+
+        let ignored = !self.is_watched(changed)
+            && self.pending.iter().all(|x| !changed.starts_with(&x.path))
+            && self
+                .pending
+                .iter()
+                .any(|x| changed == x.parent || changed.parent() == Some(&x.parent));
+
+        for pending in std::mem::take(&mut self.pending) {
+            if pending.path.exists() {
+                match self.attach(&pending.path, pending.recursive_mode) {
+                    Ok(()) => {
+                        log::info!("Watching {}", pending.path.display());
+                        self.unwatch_parent(&pending.parent);
+                        continue;
                     }
-                });
+                    Err(err) => {
+                        log::error!("Could not watch {}: {}", pending.path.display(), err);
+                    }
+                }
+            } else if let Some(parent) = pending
+                .path
+                .ancestors()
+                .skip(1)
+                .find(|x| x.is_dir())
+                .filter(|x| *x != pending.parent)
+                .map(Path::to_path_buf)
+            {
+                // NOTE: an intermediate directory has been created or removed
+                match self.watch_parent(&parent) {
+                    Ok(()) => {
+                        log::debug!(
+                            "Waiting for {} in {}",
+                            pending.path.display(),
+                            parent.display()
+                        );
+                        let old_parent = pending.parent.clone();
+                        self.pending.push(PendingWatch { parent, ..pending });
+                        self.unwatch_parent(&old_parent);
+                        continue;
+                    }
+                    Err(err) => {
+                        log::error!("Could not watch {}: {}", parent.display(), err);
+                    }
+                }
+            }
+            self.pending.push(pending);
+        }
 
-                Ok(())
-            }),
+        ignored
+    }
+
+    fn is_watched_recursively(&self, path: &Path) -> bool {
+        self.watched.iter().any(|(watched, mode)| {
+            matches!(mode, notify::RecursiveMode::Recursive) && path.starts_with(watched)
+        })
+    }
+
+    fn is_watched(&self, path: &Path) -> bool {
+        self.watched.iter().any(|(watched, mode)| match mode {
+            notify::RecursiveMode::Recursive => path.starts_with(watched),
+            notify::RecursiveMode::NonRecursive => {
+                path == watched || path.parent() == Some(watched.as_path())
+            }
+        })
+    }
+}
+
+impl WatcherBackend {
+    fn watch(&mut self, path: &Path, recursive_mode: notify::RecursiveMode) -> notify::Result<()> {
+        use notify::Watcher;
+
+        match self {
+            Self::Recommended(watcher) => watcher.watch(path, recursive_mode),
+            Self::Poll(watcher) => watcher.watch(path, recursive_mode),
+            Self::Disabled => Ok(()),
+        }
+    }
+
+    fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+        use notify::Watcher;
+
+        match self {
+            Self::Recommended(watcher) => watcher.unwatch(path),
+            Self::Poll(watcher) => watcher.unwatch(path),
+            Self::Disabled => Ok(()),
+        }
+    }
+}
+
+impl notify::Watcher for FileWatcher {
+    fn new_raw(tx: mpsc::Sender<notify::RawEvent>) -> notify::Result<Self> {
+        RecommendedWatcher::new_raw(tx)
+            .map(WatcherBackend::Recommended)
+            .map(Self::from_backend)
+    }
+
+    fn new(
+        tx: mpsc::Sender<notify::DebouncedEvent>,
+        delay: time::Duration,
+    ) -> notify::Result<Self> {
+        RecommendedWatcher::new(tx, delay)
+            .map(WatcherBackend::Recommended)
+            .map(Self::from_backend)
+    }
+
+    fn watch<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        recursive_mode: notify::RecursiveMode,
+    ) -> notify::Result<()> {
+        self.watch_or_defer(path.as_ref(), recursive_mode)
+    }
+
+    fn unwatch<P: AsRef<Path>>(&mut self, path: P) -> notify::Result<()> {
+        let path = path.as_ref();
+
+        if let Some(i) = self.pending.iter().position(|x| x.path == path) {
+            let pending = self.pending.remove(i);
+            self.unwatch_parent(&pending.parent);
+            return Ok(());
+        }
+
+        // NOTE: a directory watched without its ignored directories has several watches
+        if self.split.iter().any(|x| x == path) {
+            for (watched, _) in self.watched.iter().filter(|(x, _)| x.starts_with(path)) {
+                self.backend.unwatch(watched)?;
+            }
+            self.watched.retain(|(x, _)| !x.starts_with(path));
+            self.split.retain(|x| !x.starts_with(path));
+            return Ok(());
+        }
+
+        self.backend.unwatch(path)?;
+        self.watched.retain(|(x, _)| x != path);
+        Ok(())
+    }
+}
+
+/// The watches of a directory watched recursively without its ignored directories: the directory
+/// itself if it contains no ignored directory, otherwise the directory non-recursively and the
+/// watches of its other subdirectories.
+fn watch_plan(filter: &WatchFilter, dir: &Path) -> Vec<(PathBuf, notify::RecursiveMode)> {
+    use notify::RecursiveMode;
+
+    let mut entries = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|x| x.file_name());
+
+    let mut split = false;
+    let mut watches = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => {}
+            // NOTE: the symbolic links are watched like the watcher of the platform does
+            Ok(file_type) if file_type.is_symlink() && path.is_dir() => {
+                if filter.is_ignored_dir(&path) {
+                    split = true;
+                } else {
+                    watches.push((path, RecursiveMode::Recursive));
+                }
+                continue;
+            }
+            _ => continue,
+        }
+        if filter.is_ignored_dir(&path) {
+            split = true;
+            continue;
         }
+        let plan = watch_plan(filter, &path);
+        split |= !matches!(plan.as_slice(), [(_, RecursiveMode::Recursive)]);
+        watches.extend(plan);
+    }
+
+    if split {
+        watches.insert(0, (dir.to_path_buf(), RecursiveMode::NonRecursive));
+        watches
+    } else {
+        vec![(dir.to_path_buf(), RecursiveMode::Recursive)]
     }
 }
 
-fn build(mut profile: BuildProfile, args: &dyn BuildArgs, hooks: &Hooks) -> Result<()> {
-    use wasm_bindgen_cli_support::Bindgen;
+/// Hooks.
+///
+/// Check the code of [`Hooks::default()`] implementation to see what they do by default.
+///
+/// If you don't provide your own hook, the default code will be executed. But if you do provide a
+/// hook, the code will be *replaced*. The `after_*` methods (like [`Hooks::after_post_build`])
+/// run a function after a hook instead and a replacement can still call the default code:
+/// `(Hooks::default().post_build)(context, wasm_js, wasm_bin)`.
+///
+/// The build hooks receive a [`BuildContext`] with the arguments, the profile and the timing of
+/// the build. The hooks of the `serve` command (`serve_init`, `serve`, `frontend_watch`,
+/// `backend_watch` and `backend_command`) only receive the arguments: they are deprecated in
+/// favor of the `run_*` hooks that receive a [`RunContext`] with the profile of the builds.
+///
+/// The hooks are plain functions but an async function can be used with [`block_on`]:
+///
+/// ```ignore
+/// let hooks = Hooks {
+///     post_build: Box::new(|context, wasm_js, wasm_bin| {
+///         wasm_run::block_on(upload_artifacts(context, wasm_js, wasm_bin))
+///     }),
+///     ..Hooks::default()
+/// };
+/// ```
+pub struct Hooks {
+    /// This hook will be run before the WASM is compiled. It does nothing by default.
+    /// You can tweak the command-line arguments of the build command here or create additional
+    /// files in the build directory.
+    #[allow(clippy::type_complexity)]
+    pub pre_build: Box<dyn Fn(&BuildContext, &mut Command) -> Result<()> + Send + Sync>,
+
+    /// This hook will be run after the WASM is optimized and before the `post_build` hook. It
+    /// receives the final WASM binary and returns it, possibly transformed (signing, custom
+    /// sections, ...). It does nothing by default. With [`ServeArgs::background_wasm_opt`], it is
+    /// run once the optimization is finished, after the `post_build` hook of the unoptimized
+    /// build.
+    #[allow(clippy::type_complexity)]
+    pub post_optimize: Box<dyn Fn(&BuildContext, Vec<u8>) -> Result<Vec<u8>> + Send + Sync>,
+
+    /// This hook will be run after the `post_optimize` hook and before the `post_build` hook. It
+    /// receives the JS generated by wasm-bindgen, after the replacements `js_replace` of the
+    /// configuration, and returns it, possibly transformed (a public path, a wrapper of `init`
+    /// reporting the errors, ...). The `post_build` hook receives the JS it returns, so a
+    /// minification done there always applies to the transformed JS. It does nothing by default.
+    #[allow(clippy::type_complexity)]
+    pub js_transform: Box<dyn Fn(&BuildContext, String) -> Result<String> + Send + Sync>,
+
+    /// This hook will be run after the WASM is compiled and optimized.
+    /// By default it copies the static files to the build directory.
+    #[allow(clippy::type_complexity)]
+    pub post_build: Box<dyn Fn(&BuildContext, String, Vec<u8>) -> Result<()> + Send + Sync>,
+
+    /// This hook will be run before the `serve` hook to initialize the state shared by the
+    /// endpoints of the development server (see [`ServeContext`]). It does nothing by default.
+    #[cfg(feature = "dev-server")]
+    #[deprecated(note = "use `run_serve_init`: it receives a `RunContext` with the profile")]
+    #[allow(clippy::type_complexity)]
+    pub serve_init: Box<dyn Fn(&dyn ServeArgs, &mut ServeContext) -> Result<()> + Send + Sync>,
+
+    /// This hook will be run before running the HTTP server.
+    /// By default it will add routes to the files in the build directory.
+    #[cfg(feature = "dev-server")]
+    #[deprecated(note = "use `run_serve`: it receives a `RunContext` with the profile")]
+    #[allow(clippy::type_complexity)]
+    pub serve: Box<dyn Fn(&dyn ServeArgs, &mut Server<()>) -> Result<()> + Send + Sync>,
+
+    /// This hook will be run before starting to watch for changes in files.
+    /// By default it will add all the `src/` directories and `Cargo.toml` files of all the crates
+    /// in the workspace plus the `static/` directory if it exists in the frontend crate.
+    #[deprecated(note = "use `run_frontend_watch`: it receives a `RunContext` with the profile")]
+    #[allow(clippy::type_complexity)]
+    pub frontend_watch: Box<dyn Fn(&dyn ServeArgs, &mut FileWatcher) -> Result<()> + Send + Sync>,
+
+    /// This hook will be run before starting to watch for changes in files.
+    /// By default it will add the backend crate directory and all its dependencies. But it
+    /// excludes the target directory.
+    #[deprecated(note = "use `run_backend_watch`: it receives a `RunContext` with the profile")]
+    #[allow(clippy::type_complexity)]
+    pub backend_watch: Box<dyn Fn(&dyn ServeArgs, &mut FileWatcher) -> Result<()> + Send + Sync>,
+
+    /// This hook will be run before (re-)starting the backend.
+    /// You can tweak the cargo command that is run here: adding/removing environment variables or
+    /// adding arguments.
+    /// By default it will do `cargo build -p <backend_crate>`. The executable produced by cargo is
+    /// then run with the environment variables of the command and it is not restarted if it has
+    /// not changed. If the command is a `cargo run`, it is run as is.
+    #[deprecated(note = "use `run_backend_command`: it receives a `RunContext` with the profile")]
+    #[allow(clippy::type_complexity)]
+    pub backend_command: Box<dyn Fn(&dyn ServeArgs, &mut Command) -> Result<()> + Send + Sync>,
+
+    /// Replaces the hook `serve_init` when set: it receives a [`RunContext`] instead of the
+    /// arguments. The hook `serve_init` (the default code) is run if it is `None` (the default).
+    #[cfg(feature = "dev-server")]
+    #[allow(clippy::type_complexity)]
+    pub run_serve_init:
+        Option<Box<dyn Fn(&RunContext, &mut ServeContext) -> Result<()> + Send + Sync>>,
+
+    /// Replaces the hook `serve` when set (check [`Hooks::run_serve_init`]).
+    #[cfg(feature = "dev-server")]
+    #[allow(clippy::type_complexity)]
+    pub run_serve: Option<Box<dyn Fn(&RunContext, &mut Server<()>) -> Result<()> + Send + Sync>>,
+
+    /// Replaces the hook `frontend_watch` when set (check [`Hooks::run_serve_init`]).
+    #[allow(clippy::type_complexity)]
+    pub run_frontend_watch:
+        Option<Box<dyn Fn(&RunContext, &mut FileWatcher) -> Result<()> + Send + Sync>>,
+
+    /// Replaces the hook `backend_watch` when set (check [`Hooks::run_serve_init`]).
+    #[allow(clippy::type_complexity)]
+    pub run_backend_watch:
+        Option<Box<dyn Fn(&RunContext, &mut FileWatcher) -> Result<()> + Send + Sync>>,
+
+    /// Replaces the hook `backend_command` when set (check [`Hooks::run_serve_init`]).
+    #[allow(clippy::type_complexity)]
+    pub run_backend_command:
+        Option<Box<dyn Fn(&RunContext, &mut Command) -> Result<()> + Send + Sync>>,
+
+    /// Tasks run by the `serve` command when the files of some paths change (check
+    /// [`WatchTask`]). There is none by default.
+    pub watch_tasks: Vec<WatchTask>,
+
+    /// This hook runs once when the process stops: at the end of the command (build, serve or
+    /// any other command, successful or not) or when it is interrupted (Ctrl-C) or terminated.
+    /// It does not run on a hard exit (`SIGKILL`, a second Ctrl-C, an abort, ...).
+    ///
+    /// The default implementation does nothing.
+    pub shutdown: Box<dyn Fn(ShutdownReason) -> Result<()> + Send + Sync>,
+}
+
+/// Composition of the hooks: the `after_*` methods run a function after the current hook instead
+/// of replacing it. The methods of the hooks of the `serve` command compose the `run_*` hook (or
+/// the deprecated hook if it is not set).
+///
+/// ```ignore
+/// let hooks = Hooks::default().after_post_build(|context, _wasm_js, _wasm_bin| {
+///     fs::copy("robots.txt", context.build_path().join("robots.txt"))?;
+///     Ok(())
+/// });
+/// ```
+#[allow(deprecated)]
+impl Hooks {
+    /// Run `f` after the `pre_build` hook.
+    pub fn after_pre_build(
+        mut self,
+        f: impl Fn(&BuildContext, &mut Command) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        let previous = self.pre_build;
+        self.pre_build = Box::new(move |context, command| {
+            previous(context, command)?;
+            f(context, command)
+        });
+        self
+    }
+
+    /// Run `f` after the `post_optimize` hook: it receives the WASM binary returned by the
+    /// previous hook.
+    pub fn after_post_optimize(
+        mut self,
+        f: impl Fn(&BuildContext, Vec<u8>) -> Result<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self {
+        let previous = self.post_optimize;
+        self.post_optimize = Box::new(move |context, wasm_bin| {
+            let wasm_bin = previous(context, wasm_bin)?;
+            f(context, wasm_bin)
+        });
+        self
+    }
+
+    /// Run `f` after the `js_transform` hook: it receives the JS returned by the previous hook.
+    pub fn after_js_transform(
+        mut self,
+        f: impl Fn(&BuildContext, String) -> Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        let previous = self.js_transform;
+        self.js_transform = Box::new(move |context, wasm_js| {
+            let wasm_js = previous(context, wasm_js)?;
+            f(context, wasm_js)
+        });
+        self
+    }
+
+    /// Run `f` after the `post_build` hook (for example to copy more files after the default hook
+    /// copied the static files).
+    pub fn after_post_build(
+        mut self,
+        f: impl Fn(&BuildContext, String, Vec<u8>) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        let previous = self.post_build;
+        self.post_build = Box::new(move |context, wasm_js, wasm_bin| {
+            previous(context, wasm_js.clone(), wasm_bin.clone())?;
+            f(context, wasm_js, wasm_bin)
+        });
+        self
+    }
+
+    /// Run `f` after the `serve_init` hook.
+    #[cfg(feature = "dev-server")]
+    pub fn after_serve_init(
+        mut self,
+        f: impl Fn(&dyn ServeArgs, &mut ServeContext) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        let previous = self.run_serve_init.take().unwrap_or_else(|| {
+            let previous = mem::replace(&mut self.serve_init, Box::new(|_, _| Ok(())));
+            Box::new(move |context, serve_context| previous(context.args(), serve_context))
+        });
+        self.run_serve_init = Some(Box::new(move |context, serve_context| {
+            previous(context, serve_context)?;
+            f(context.args(), serve_context)
+        }));
+        self
+    }
+
+    /// Run `f` after the `serve` hook (for example to add endpoints next to the files of the build
+    /// directory).
+    #[cfg(feature = "dev-server")]
+    pub fn after_serve(
+        mut self,
+        f: impl Fn(&dyn ServeArgs, &mut Server<()>) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        let previous = self.run_serve.take().unwrap_or_else(|| {
+            let previous = mem::replace(&mut self.serve, Box::new(|_, _| Ok(())));
+            Box::new(move |context, server| previous(context.args(), server))
+        });
+        self.run_serve = Some(Box::new(move |context, server| {
+            previous(context, server)?;
+            f(context.args(), server)
+        }));
+        self
+    }
+
+    /// Run `f` after the `frontend_watch` hook (for example to watch more paths).
+    pub fn after_frontend_watch(
+        mut self,
+        f: impl Fn(&dyn ServeArgs, &mut FileWatcher) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        let previous = self.run_frontend_watch.take().unwrap_or_else(|| {
+            let previous = mem::replace(&mut self.frontend_watch, Box::new(|_, _| Ok(())));
+            Box::new(move |context, watcher| previous(context.args(), watcher))
+        });
+        self.run_frontend_watch = Some(Box::new(move |context, watcher| {
+            previous(context, watcher)?;
+            f(context.args(), watcher)
+        }));
+        self
+    }
+
+    /// Run `f` after the `backend_watch` hook.
+    pub fn after_backend_watch(
+        mut self,
+        f: impl Fn(&dyn ServeArgs, &mut FileWatcher) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        let previous = self.run_backend_watch.take().unwrap_or_else(|| {
+            let previous = mem::replace(&mut self.backend_watch, Box::new(|_, _| Ok(())));
+            Box::new(move |context, watcher| previous(context.args(), watcher))
+        });
+        self.run_backend_watch = Some(Box::new(move |context, watcher| {
+            previous(context, watcher)?;
+            f(context.args(), watcher)
+        }));
+        self
+    }
+
+    /// Run `f` after the `backend_command` hook (for example to add an environment variable to
+    /// the default command).
+    pub fn after_backend_command(
+        mut self,
+        f: impl Fn(&dyn ServeArgs, &mut Command) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        let previous = self.run_backend_command.take().unwrap_or_else(|| {
+            let previous = mem::replace(&mut self.backend_command, Box::new(|_, _| Ok(())));
+            Box::new(move |context, command| previous(context.args(), command))
+        });
+        self.run_backend_command = Some(Box::new(move |context, command| {
+            previous(context, command)?;
+            f(context.args(), command)
+        }));
+        self
+    }
+
+    /// Run `f` after the `shutdown` hook.
+    pub fn after_shutdown(
+        mut self,
+        f: impl Fn(ShutdownReason) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        let previous = self.shutdown;
+        self.shutdown = Box::new(move |reason| {
+            previous(reason)?;
+            f(reason)
+        });
+        self
+    }
+}
+
+/// The hooks of the `serve` command: the `run_*` hook if it is set or the deprecated hook.
+#[allow(deprecated)]
+impl Hooks {
+    #[cfg(feature = "dev-server")]
+    fn call_serve_init(
+        &self,
+        context: &RunContext,
+        serve_context: &mut ServeContext,
+    ) -> Result<()> {
+        match &self.run_serve_init {
+            Some(hook) => hook(context, serve_context),
+            None => (self.serve_init)(context.args(), serve_context),
+        }
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn call_serve(&self, context: &RunContext, server: &mut Server<()>) -> Result<()> {
+        match &self.run_serve {
+            Some(hook) => hook(context, server),
+            None => (self.serve)(context.args(), server),
+        }
+    }
+
+    fn call_frontend_watch(&self, context: &RunContext, watcher: &mut FileWatcher) -> Result<()> {
+        match &self.run_frontend_watch {
+            Some(hook) => hook(context, watcher),
+            None => (self.frontend_watch)(context.args(), watcher),
+        }
+    }
+
+    #[cfg(not(feature = "dev-server"))]
+    fn call_backend_watch(&self, context: &RunContext, watcher: &mut FileWatcher) -> Result<()> {
+        match &self.run_backend_watch {
+            Some(hook) => hook(context, watcher),
+            None => (self.backend_watch)(context.args(), watcher),
+        }
+    }
+
+    #[cfg(not(feature = "dev-server"))]
+    fn call_backend_command(&self, context: &RunContext, command: &mut Command) -> Result<()> {
+        match &self.run_backend_command {
+            Some(hook) => hook(context, command),
+            None => (self.backend_command)(context.args(), command),
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl Default for Hooks {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "dev-server")]
+            run_serve_init: None,
+            #[cfg(feature = "dev-server")]
+            run_serve: None,
+            run_frontend_watch: None,
+            run_backend_watch: None,
+            run_backend_command: None,
+            backend_command: Box::new(|args, command| {
+                command.args([
+                    "build",
+                    "-p",
+                    &args
+                        .build_args()
+                        .backend_package()
+                        .context("missing backend crate name")?
+                        .name,
+                ]);
+                Ok(())
+            }),
+            backend_watch: Box::new(|args, watcher| {
+                use notify::{RecursiveMode, Watcher};
+
+                let metadata = args.build_args().metadata();
+                let backend = args
+                    .build_args()
+                    .backend_package()
+                    .context("missing backend crate name")?;
+                let packages: HashMap<_, _> = metadata
+                    .packages
+                    .iter()
+                    .map(|x| (x.name.as_str(), x))
+                    .collect();
+                let members: HashSet<_> = HashSet::from_iter(&metadata.workspace_members);
+
+                backend
+                    .dependencies
+                    .iter()
+                    .map(|x| packages.get(x.name.as_str()).unwrap())
+                    .filter(|x| members.contains(&x.id))
+                    .map(|x| x.manifest_path.parent().unwrap())
+                    .chain(iter::once(backend.manifest_path.parent().unwrap()))
+                    .try_for_each(|x| watcher.watch(x, RecursiveMode::Recursive))?;
+
+                for file_name in WORKSPACE_FILES {
+                    watcher.watch(
+                        metadata.workspace_root.join(file_name),
+                        RecursiveMode::NonRecursive,
+                    )?;
+                }
+
+                Ok(())
+            }),
+            watch_tasks: Vec::new(),
+            shutdown: Box::new(|_| Ok(())),
+            frontend_watch: Box::new(|args, watcher| {
+                use notify::{RecursiveMode, Watcher};
+
+                let metadata = args.build_args().metadata();
+                let frontend = args.build_args().frontend_package();
+                let packages: HashMap<_, _> = metadata
+                    .packages
+                    .iter()
+                    .map(|x| (x.name.as_str(), x))
+                    .collect();
+                let members: HashSet<_> = HashSet::from_iter(&metadata.workspace_members);
+
+                frontend
+                    .dependencies
+                    .iter()
+                    .filter_map(|x| packages.get(x.name.as_str()))
+                    .filter(|x| members.contains(&x.id))
+                    .map(|x| x.manifest_path.parent().unwrap())
+                    .chain(iter::once(frontend.manifest_path.parent().unwrap()))
+                    .try_for_each(|x| watcher.watch(x, RecursiveMode::Recursive))?;
+
+                for file_name in WORKSPACE_FILES {
+                    watcher.watch(
+                        metadata.workspace_root.join(file_name),
+                        RecursiveMode::NonRecursive,
+                    )?;
+                }
+
+                if let Some(index) = args.build_args().index_source() {
+                    if !index.starts_with(frontend.manifest_path.parent().unwrap()) {
+                        watcher.watch(&index, RecursiveMode::NonRecursive)?;
+                    }
+                }
+
+                // NOTE: the translations can be outside of the frontend package
+                #[cfg(feature = "i18n")]
+                {
+                    let frontend_dir = frontend.manifest_path.parent().unwrap();
+                    let options = args.build_args().i18n_options(args.profile());
+                    let source = frontend_dir.join(options.source);
+                    if !source.starts_with(frontend_dir) {
+                        watcher.watch(&source, RecursiveMode::Recursive)?;
+                    }
+                }
+
+                Ok(())
+            }),
+            pre_build: Box::new(|_, _| Ok(())),
+            post_optimize: Box::new(|_, wasm_bin| Ok(wasm_bin)),
+            js_transform: Box::new(|_, wasm_js| Ok(wasm_js)),
+            post_build: Box::new(
+                // NOTE: this hook writes the artifacts: a hook that replaces it must write them too
+                |context, wasm_js, wasm_bin| {
+                    let args = context.args();
+                    let profile = context.profile();
+                    let build_path = args.build_path();
+                    let artifact_path = args.artifact_path();
+                    let wasm_js_path = artifact_path.join("app.js");
+                    let wasm_bin_path = artifact_path.join("app_bg.wasm");
+
+                    fs::create_dir_all(&artifact_path).with_context(|| {
+                        format!("could not create artifact directory `{}`", artifact_path)
+                    })?;
+
+                    fs::write(&wasm_js_path, wasm_js).with_context(|| {
+                        format!("could not write JS file to `{}`", wasm_js_path)
+                    })?;
+                    fs::write(&wasm_bin_path, wasm_bin).with_context(|| {
+                        format!("could not write WASM file to `{}`", wasm_bin_path)
+                    })?;
+
+                    let index_path = build_path.join("index.html");
+
+                    if let Some(source) = args.index_source() {
+                        if !source.is_file() {
+                            bail!(
+                                "the index `{}` does not exist (`index` of the configuration is \
+                                relative to the file that declares it, `--index` to the current \
+                                directory)",
+                                source
+                            );
+                        }
+                        timings::stage("assets", || assets::copy_assets(args))?;
+                        fs::copy(&source, &index_path).with_context(|| {
+                            format!("could not copy `{}` to `{}`", source, index_path)
+                        })?;
+                    } else if index_path.exists() {
+                        // NOTE: the index.html has been written in the build directory by a hook
+                        log::debug!("Using the index.html of the build directory");
+                    } else {
+                        timings::stage("assets", || assets::copy_assets(args))?;
+
+                        // NOTE: the default index.html is used if the assets don't provide one
+                        if !index_path.exists() {
+                            let mut index = if args.loader_checks(profile) {
+                                DEFAULT_INDEX_WITH_CHECKS
+                            } else {
+                                DEFAULT_INDEX
+                            }
+                            .replace(
+                                "\"/app.js\"",
+                                &format!(
+                                    "\"{}{}\"",
+                                    args.profile_config(profile)
+                                        .base_url
+                                        .as_deref()
+                                        .unwrap_or("")
+                                        .trim_end_matches('/'),
+                                    args.artifact_url("app.js")
+                                ),
+                            );
+                            // NOTE: the service worker would serve stale files during development
+                            if let (BuildProfile::Release, Some(pwa)) = (profile, args.pwa()?) {
+                                index = index
+                                    .replace("</head>", &format!("{}</head>", pwa.head_tags()));
+                            }
+                            fs::write(&index_path, index).with_context(|| {
+                                format!("could not write default index.html to `{}`", index_path)
+                            })?;
+                        }
+                    }
+
+                    #[cfg(feature = "sass")]
+                    timings::stage("sass", || -> Result<()> {
+                        let options = args.sass_options(profile);
+                        for style_path in args.sass_lookup_directories(profile) {
+                            args.build_sass_from_dir(&style_path, options.clone())?;
+                        }
+                        Ok(())
+                    })?;
+
+                    #[cfg(feature = "i18n")]
+                    timings::stage("i18n", || i18n::compile(args, &args.i18n_options(profile)))?;
+
+                    Ok(())
+                },
+            ),
+            #[cfg(feature = "dev-server")]
+            serve_init: Box::new(|_, _| Ok(())),
+            #[cfg(feature = "dev-server")]
+            serve: Box::new(|args, server| {
+                use tide::Request;
+
+                let preview = args.release();
+                let build_path = args.build_args().build_path().to_owned();
+                let index = IndexOptions {
+                    index_path: build_path.join("index.html").into(),
+                    wasm_path: args.build_args().artifact_path().join("app_bg.wasm").into(),
+                    cache_bust: args.cache_bust(),
+                    overlay: args.overlay(),
+                    shims: !args.host_globals()?.is_empty(),
+                    fingerprint: args.build_args().fingerprint()?,
+                    external: if args.cache_external() {
+                        args.build_args()
+                            .external_dependencies()?
+                            .into_iter()
+                            .map(|x| {
+                                let local = format!("{}/{}", external::CACHE_URL, x.cache_name());
+                                (x.url, local)
+                            })
+                            .collect()
+                    } else {
+                        Vec::new()
+                    },
+                };
+
+                server.at("/").serve_dir(args.build_args().build_path())?;
+                server.at("/").get({
+                    let index = index.clone();
+                    move |req: Request<()>| {
+                        let index = index.clone();
+                        async move { serve_index(&index, &req).await }
+                    }
+                });
+                let overlay = Arc::clone(&args.build_args().state().overlay);
+                server.at("/*path").get(move |req: Request<()>| {
+                    let build_path = build_path.clone();
+                    let index = index.clone();
+                    let overlay = Arc::clone(&overlay);
+                    async move {
+                        let path = req.param("path").unwrap();
+                        match static_files::serve(build_path.as_std_path(), path, &req, preview)
+                            .await
+                        {
+                            Some(res) => Ok(res),
+                            None => {
+                                match static_files::missing_artifact(&build_path, path, &overlay) {
+                                    Some(res) => Ok(res),
+                                    None => serve_index(&index, &req).await,
+                                }
+                            }
+                        }
+                    }
+                });
+
+                Ok(())
+            }),
+        }
+    }
+}
+
+/// Error returned when a hook panicked.
+#[derive(Debug)]
+pub struct HookPanic {
+    /// Name of the hook.
+    pub hook: &'static str,
+    /// Message of the panic.
+    pub message: String,
+}
+
+impl fmt::Display for HookPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "hook `{}` panicked: {}", self.hook, self.message)
+    }
+}
+
+impl std::error::Error for HookPanic {}
+
+/// Run a future to completion on the current thread. This is used to call async hooks: the
+/// macro [`main`](crate::main) uses it for the hooks given with `async` (`post_build = async
+/// my_post_build`).
+///
+/// The runtime of `async-std` (the runtime of the development server) is used when the feature
+/// `dev-server` is enabled.
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    #[cfg(feature = "dev-server")]
+    {
+        async_std::task::block_on(future)
+    }
+    #[cfg(not(feature = "dev-server"))]
+    {
+        futures::executor::block_on(future)
+    }
+}
+
+fn call_hook<T>(hook: &'static str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    match timings::stage(format!("hook {}", hook), || {
+        panic::catch_unwind(panic::AssertUnwindSafe(f))
+    }) {
+        Ok(res) => res,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|x| x.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic payload".to_string());
+
+            Err(HookPanic { hook, message }.into())
+        }
+    }
+}
+
+fn build(profile: BuildProfile, args: &dyn BuildArgs, hooks: &Hooks) -> Result<BuildOutput> {
+    build_with(profile, args, hooks, false, false).map(|(output, _)| output)
+}
+
+/// The WASM of a build written before running wasm-opt, to be optimized afterwards.
+struct PendingOptimization {
+    profile: BuildProfile,
+    wasm_bin: Vec<u8>,
+    started: time::Instant,
+    rebuild: bool,
+}
+
+/// Build the frontend and return the files it emitted. If `defer_optimization` is enabled, the
+/// artifacts are written without running wasm-opt and they must be optimized afterwards with
+/// [`finish_optimization`]. `rebuild` tells the hooks if the build was triggered by a change of
+/// the watched files.
+fn build_with(
+    profile: BuildProfile,
+    args: &dyn BuildArgs,
+    hooks: &Hooks,
+    defer_optimization: bool,
+    rebuild: bool,
+) -> Result<(BuildOutput, Option<PendingOptimization>)> {
+    stale::record_build_started(args);
+    let started = time::Instant::now();
+    timings::start();
+    let res = build_steps(profile, args, hooks, defer_optimization, rebuild);
+    if res.is_ok() {
+        timings::report(args, "build", started.elapsed());
+    }
+    #[cfg(any(feature = "dev-server", feature = "webhooks"))]
+    if !cancel::is_cancelled(&res) {
+        let profile = if args.profiling() {
+            BuildProfile::Profiling
+        } else {
+            profile
+        };
+        #[cfg(feature = "dev-server")]
+        args.state()
+            .history
+            .record_build(profile, rebuild, started.elapsed(), &res);
+        #[cfg(feature = "webhooks")]
+        webhooks::notify(args, profile, rebuild, started, &res);
+    }
+    res
+}
+
+fn build_steps(
+    mut profile: BuildProfile,
+    args: &dyn BuildArgs,
+    hooks: &Hooks,
+    defer_optimization: bool,
+    rebuild: bool,
+) -> Result<(BuildOutput, Option<PendingOptimization>)> {
+    if args.profiling() {
+        profile = BuildProfile::Profiling;
+    }
+
+    let context = BuildContext::new(args, profile, time::Instant::now(), rebuild);
+
+    toolchain::check(args)?;
+
+    let frontend_package = args.frontend_package();
+    let profile_name = format!("{:?}", profile).to_lowercase();
+
+    events::emit(
+        args,
+        "build-started",
+        json!({ "package": frontend_package.name, "profile": profile_name }),
+    );
+
+    let build_path = args.build_path();
+    let index_path = build_path.join("index.html");
+    let previous_index = if log::log_enabled!(log::Level::Debug) {
+        fs::read_to_string(&index_path).ok()
+    } else {
+        None
+    };
+    out_dir::prepare(build_path, args.metadata())?;
+
+    let target = FrontendTarget::resolve(args)?;
+    let mut command = Command::new("cargo");
+
+    command
+        .arg("build")
+        .args(target.cargo_args())
+        .args(["--target", "wasm32-unknown-unknown", "--manifest-path"])
+        .arg(&frontend_package.manifest_path)
+        .args(match profile {
+            BuildProfile::Profiling => &["--release"] as &[&str],
+            BuildProfile::Release => &["--release"],
+            BuildProfile::Dev => &[],
+        });
+
+    if args.offline() {
+        command.arg("--offline");
+    }
+
+    let config = args.profile_config(profile);
+
+    if !config.features.is_empty() {
+        command.arg("--features").arg(config.features.join(","));
+    }
+
+    command
+        .envs(config.env.iter().map(|(k, v)| (k, v)))
+        .env("CARGO_TARGET_DIR", args.target_path());
+
+    if args.dwarf() && !matches!(profile, BuildProfile::Dev) {
+        command.env("CARGO_PROFILE_RELEASE_DEBUG", "true");
+    }
+
+    let optimizes = !defer_optimization && !matches!(profile, BuildProfile::Dev) && !args.dwarf();
+    let mut progress = ui::Progress::new(if optimizes { 5 } else { 4 }, args.verbosity());
+
+    log::debug!("Running pre-build hook");
+    call_hook("pre_build", || (hooks.pre_build)(&context, &mut command))?;
+
+    let step = progress.step("cargo build", true);
+    let artifacts = timings::stage("cargo build", || diagnostics::run_cargo(args, &mut command))?;
+    step.finish();
+    cancel::check()?;
+
+    // NOTE: the path reported by cargo accounts for the target directory and the name of the
+    //       library; it is only guessed if the command of the hook `pre_build` doesn't report it
+    let wasm_path = match target.wasm_artifact(frontend_package, &artifacts) {
+        Some(path) => path,
+        None => {
+            let path = cargo_wasm_path(args, &target, profile);
+            log::debug!(
+                "cargo did not report the WASM of the frontend: using `{}`",
+                path
+            );
+            path
+        }
+    };
+
+    let cache_key = if args.build_cache() && !matches!(profile, BuildProfile::Dev) && !args.dwarf()
+    {
+        Some(build_cache::key(args, profile, &wasm_path)?)
+    } else {
+        None
+    };
+    let cached = cache_key
+        .as_deref()
+        .and_then(|key| build_cache::load(args, key));
+    let optimized = cached.is_some();
+
+    let step = progress.step(
+        if optimized {
+            "wasm-bindgen (build cache)"
+        } else {
+            "wasm-bindgen"
+        },
+        false,
+    );
+    let bindgen_started = time::Instant::now();
+    let ((wasm_js, wasm_bin, snippets), bindgen_warnings) = if let Some(cached) = cached {
+        log::info!("Reusing the outputs of wasm-bindgen and wasm-opt from the build cache");
+        // NOTE: the warnings are replayed for the events and `--deny-bindgen-warnings`
+        for warning in &cached.warnings {
+            log::warn!("wasm-bindgen: {}", warning);
+        }
+        (
+            (cached.wasm_js, cached.wasm_bin, cached.snippets),
+            cached.warnings,
+        )
+    } else {
+        let (res, warnings) =
+            diagnostics::capture_bindgen_warnings(|| generate_bindings(args, &wasm_path, profile));
+        (res?, warnings)
+    };
+
+    timings::record(
+        if optimized {
+            "build cache"
+        } else {
+            "wasm-bindgen"
+        },
+        bindgen_started.elapsed(),
+    );
+    step.finish();
+
+    // NOTE: the JS snippets are imported by the JS relatively to its location
+    let snippets_path = args.artifact_path().join("snippets");
+    for (path, contents) in &snippets {
+        let path = snippets_path.join(path);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, contents)
+            .with_context(|| format!("could not write snippet `{}`", path))?;
+    }
+
+    for warning in &bindgen_warnings {
+        events::emit(args, "bindgen-warning", json!({ "message": warning }));
+    }
+    if args.deny_bindgen_warnings() && !bindgen_warnings.is_empty() {
+        bail!(
+            "wasm-bindgen emitted {} warning(s) and `--deny-bindgen-warnings` is used",
+            bindgen_warnings.len()
+        );
+    }
+
+    events::emit(
+        args,
+        "bindgen-finished",
+        json!({ "input": wasm_path, "size": wasm_bin.len() }),
+    );
+    cancel::check()?;
+
+    let pending = if !optimized
+        && defer_optimization
+        && !matches!(profile, BuildProfile::Dev)
+        && !args.dwarf()
+    {
+        log::info!("Skipping wasm-opt for now: the optimized WASM will replace this build");
+        Some(PendingOptimization {
+            profile,
+            wasm_bin: wasm_bin.clone(),
+            started: context.started(),
+            rebuild,
+        })
+    } else {
+        None
+    };
+    let wasm_bin = if pending.is_some() {
+        wasm_bin
+    } else if optimized {
+        if optimizes {
+            progress.step("wasm-opt (build cache)", false).finish();
+        }
+        wasm_bin
+    } else {
+        let step = optimizes.then(|| progress.step("wasm-opt", false));
+        let wasm_bin = optimize(profile, args, wasm_bin)?;
+        if let Some(key) = cache_key.as_deref() {
+            build_cache::store(args, key, &wasm_js, &wasm_bin, &snippets, &bindgen_warnings);
+        }
+        if let Some(step) = step {
+            step.finish();
+        }
+        wasm_bin
+    };
+    cancel::check()?;
+
+    let step = progress.step("hooks", false);
+    // NOTE: the hook `post_optimize` of a deferred optimization receives the optimized WASM
+    let wasm_bin = if pending.is_some() {
+        wasm_bin
+    } else {
+        log::debug!("Running post-optimize hook");
+        call_hook("post_optimize", || {
+            (hooks.post_optimize)(&context, wasm_bin)
+        })?
+    };
+
+    // NOTE: the replacements of the configuration are applied first, in the order of
+    //       declaration, then the hook
+    let wasm_js = js_replace::apply(
+        wasm_js,
+        &js_replace::from_config(&args.profile_config(profile))?,
+    );
+    log::debug!("Running js-transform hook");
+    let wasm_js = call_hook("js_transform", || (hooks.js_transform)(&context, wasm_js))?;
+
+    log::debug!("Running post-build hook");
+    call_hook("post_build", || {
+        (hooks.post_build)(&context, wasm_js, wasm_bin)
+    })?;
+    step.finish();
+
+    let step = progress.step("finishing", false);
+
+    let external = args.external_dependencies()?;
+    if !external.is_empty() {
+        timings::stage("external dependencies", || {
+            external::inject(args, &external)
+        })?;
+    }
+
+    if let Some(critical_css) = args.critical_css()? {
+        timings::stage("critical css", || critical_css::inline(args, &critical_css))?;
+    }
+
+    redirects::write(args, &args.redirects()?)?;
+
+    let mut artifacts = None;
+    if args.fingerprint_assets(profile) {
+        if pending.is_some() {
+            log::warn!("The artifacts are not fingerprinted when wasm-opt runs in background");
+        } else {
+            let fingerprint = args.fingerprint()?;
+            artifacts = Some(timings::stage("fingerprint", || {
+                fingerprint::fingerprint_artifacts(args, &fingerprint)
+            })?);
+        }
+    }
+
+    if let Some(pwa) = args.pwa()? {
+        timings::stage("pwa", || pwa::generate(args, &pwa))?;
+    }
+
+    if let (Some(previous), Ok(current)) = (previous_index, fs::read_to_string(&index_path)) {
+        index_diff::log_diff(&previous, &current);
+    }
+
+    events::emit(
+        args,
+        "artifacts-written",
+        json!({
+            "profile": profile_name,
+            "build_path": build_path,
+            "artifact_path": args.artifact_path(),
+        }),
+    );
+
+    embed::write_manifest(args, profile);
+
+    // NOTE: a build waiting for wasm-opt is retained once optimized
+    if pending.is_none() {
+        history::record(args, profile)?;
+    }
+    let output = BuildOutput::collect(args, profile, artifacts, context.started().elapsed())?;
+    step.finish();
+
+    Ok((output, pending))
+}
+
+/// Optimize the WASM of a build written with the optimization deferred and replace the file
+/// `app_bg.wasm` in the artifact directory. Only the hook `post_optimize` is run: it was skipped
+/// by the build.
+fn finish_optimization(
+    pending: PendingOptimization,
+    args: &dyn BuildArgs,
+    hooks: &Hooks,
+) -> Result<()> {
+    let PendingOptimization {
+        profile,
+        wasm_bin,
+        started,
+        rebuild,
+    } = pending;
+    let context = BuildContext::new(args, profile, started, rebuild);
+    let optimization_started = time::Instant::now();
+    timings::start();
+
+    log::info!("Optimizing the WASM in the background");
+    let wasm_bin = optimize(profile, args, wasm_bin)?;
+    cancel::check()?;
+
+    log::info!("Running post-optimize hook");
+    let wasm_bin = call_hook("post_optimize", || {
+        (hooks.post_optimize)(&context, wasm_bin)
+    })?;
+
+    let wasm_bin_path = args.artifact_path().join("app_bg.wasm");
+    fs::write(&wasm_bin_path, wasm_bin)
+        .with_context(|| format!("could not write WASM file to `{}`", wasm_bin_path))?;
+    log::info!("The optimized WASM replaced the unoptimized build");
+
+    // NOTE: the cache of the service worker must be renewed with the optimized WASM
+    if let Some(pwa) = args.pwa()? {
+        pwa::generate(args, &pwa)?;
+    }
+
+    history::record(args, profile)?;
+    timings::report(
+        args,
+        "background optimization",
+        optimization_started.elapsed(),
+    );
+
+    #[cfg(feature = "dev-server")]
+    args.state().overlay.notify_optimized();
+
+    Ok(())
+}
+
+/// Path of the WASM produced by cargo for the target `target` of the frontend, guessed from the
+/// target directory: the builds use the path reported by cargo when it is available.
+fn cargo_wasm_path(
+    args: &dyn BuildArgs,
+    target: &FrontendTarget,
+    profile: BuildProfile,
+) -> Utf8PathBuf {
+    args.target_path()
+        .join("wasm32-unknown-unknown")
+        .join(match profile {
+            BuildProfile::Profiling => "release",
+            BuildProfile::Release => "release",
+            BuildProfile::Dev => "debug",
+        })
+        .join(target.wasm_file_name())
+}
+
+/// Run wasm-bindgen (the library or the CLI with [`BuildArgs::external_bindgen`]) on the WASM
+/// produced by cargo. Returns the JS, the WASM and the JS snippets.
+#[allow(clippy::type_complexity)]
+fn generate_bindings(
+    args: &dyn BuildArgs,
+    wasm_path: &Utf8Path,
+    profile: BuildProfile,
+) -> Result<(String, Vec<u8>, Vec<(Utf8PathBuf, String)>)> {
+    use wasm_bindgen_cli_support::Bindgen;
+
+    if args.external_bindgen() {
+        return bindgen_cli::generate(args, wasm_path, profile);
+    }
+
+    let settings = args.bindgen_settings();
+    let mut bindgen = Bindgen::new();
+    bindgen
+        .input_path(wasm_path)
+        .out_name("app")
+        .web(true)
+        .expect("fails only if multiple modes specified; qed")
+        .debug(!matches!(profile, BuildProfile::Release))
+        .omit_default_module_path(settings.omit_default_module_path)
+        .remove_name_section(settings.remove_name_section)
+        .demangle(settings.demangle)
+        .keep_debug(args.dwarf());
+    if settings.reference_types {
+        // NOTE: otherwise it is detected from the target features of the WASM
+        #[allow(deprecated)]
+        bindgen.reference_types(true);
+    }
+    let mut output = bindgen
+        .generate_output()
+        .context("could not generate WASM bindgen file")?;
+
+    let mut snippets = Vec::new();
+    for (identifier, list) in output.snippets().iter() {
+        for (i, js) in list.iter().enumerate() {
+            snippets.push((
+                Utf8Path::new(identifier).join(format!("inline{}.js", i)),
+                js.to_owned(),
+            ));
+        }
+    }
+    for (path, contents) in output.local_modules().iter() {
+        snippets.push((Utf8PathBuf::from(path), contents.to_owned()));
+    }
+
+    Ok((
+        output.js().to_owned(),
+        output.wasm_mut().emit_wasm(),
+        snippets,
+    ))
+}
+
+fn optimize(profile: BuildProfile, args: &dyn BuildArgs, wasm_bin: Vec<u8>) -> Result<Vec<u8>> {
+    let size_before = wasm_bin.len();
+    let wasm_bin = match profile {
+        BuildProfile::Dev => wasm_bin,
+        _ if args.dwarf() => {
+            log::info!("Skipping wasm-opt to keep the DWARF debugging information");
+            wasm_bin
+        }
+        BuildProfile::Profiling | BuildProfile::Release => {
+            let config = args.profile_config(profile);
+            let debug_info = matches!(profile, BuildProfile::Profiling);
+            let shrink_level =
+                config
+                    .wasm_opt_shrink_level
+                    .unwrap_or(if debug_info { 0 } else { 1 });
+            let optimization_level = config.wasm_opt_level.unwrap_or(2);
+            timings::stage("wasm-opt", || {
+                build_cache::optimized(
+                    args,
+                    wasm_bin,
+                    (shrink_level, optimization_level, debug_info),
+                    |wasm_bin| {
+                        wasm_opt(wasm_bin, shrink_level, optimization_level, debug_info, args)
+                    },
+                )
+            })?
+        }
+    };
+
+    if !matches!(profile, BuildProfile::Dev) && !args.dwarf() {
+        events::emit(
+            args,
+            "wasm-opt-finished",
+            json!({ "size_before": size_before, "size_after": wasm_bin.len() }),
+        );
+    }
+
+    Ok(wasm_bin)
+}
+
+fn serve<A: ServeArgs + Sync + 'static>(args: A, hooks: Arc<Hooks>) -> Result<()> {
+    #[cfg(feature = "dev-server")]
+    if let Some(id) = args.history_build() {
+        history::restore(args.build_args(), id)?;
+        return async_std::task::block_on(async { serve_frontend(&args, &hooks)?.1.await });
+    }
+
+    if args.full_restart() && !full_restart::is_child() {
+        return full_restart::supervise(&args, &hooks);
+    }
+
+    // NOTE: the first step for serving is to call `build` a first time. The build directory
+    //       must be present before we start watching files there.
+    let (_, pending) = build_with(
+        args.profile(),
+        args.build_args(),
+        &hooks,
+        args.background_wasm_opt(),
+        false,
+    )?;
+    let args = Arc::new(args);
+    if args.watch() {
+        spawn_watch_tasks(&args, &hooks, None)?;
+    }
+    #[cfg(feature = "dev-server")]
+    {
+        async_std::task::block_on(async {
+            let t1 = async_std::task::spawn(serve_frontend(&*args, &hooks)?.1);
+            let t2 =
+                async_std::task::spawn_blocking(move || watch_frontend(&*args, &hooks, pending));
+            futures::try_join!(t1, t2)?;
+            Err(anyhow!("server and watcher unexpectedly exited"))
+        })
+    }
+    #[cfg(not(feature = "dev-server"))]
+    {
+        use std::thread;
+
+        if args.build_args().backend_package().is_none() {
+            bail!("missing backend crate name");
+        }
+
+        let t1 = {
+            let args = Arc::clone(&args);
+            let hooks = Arc::clone(&hooks);
+            thread::spawn(move || watch_frontend(&*args, &hooks, pending))
+        };
+        let t2 = thread::spawn(move || watch_backend(&*args, &hooks));
+        let _ = t1.join();
+        let _ = t2.join();
+
+        Err(anyhow!("server and watcher unexpectedly exited"))
+    }
+}
+
+#[cfg(feature = "dev-server")]
+#[allow(clippy::type_complexity)]
+fn serve_frontend(
+    args: &dyn ServeArgs,
+    hooks: &Hooks,
+) -> Result<(
+    std::net::SocketAddr,
+    Pin<Box<impl std::future::Future<Output = Result<()>> + Send + 'static>>,
+)> {
+    use futures::TryFutureExt;
+
+    if args.log() {
+        tide::log::start();
+    }
+    let mut app = tide::new();
+
+    let mut context = ServeContext::default();
+    let run_context = RunContext::from_args(args);
+    call_hook("serve_init", || {
+        hooks.call_serve_init(&run_context, &mut context)
+    })?;
+    let wrap = context::register(&mut app, context);
+
+    if args.overlay() {
+        overlay::register(&mut app, Arc::clone(&args.build_args().state().overlay));
+    }
+
+    if args.status_page() {
+        status_page::register(
+            &mut app,
+            Arc::clone(&args.build_args().state().history),
+            args.build_args().build_path().to_owned().into(),
+        );
+    }
+
+    if args.file_listing() {
+        file_listing::register(&mut app, args.build_args().build_path().to_owned().into());
+    }
+
+    if args.cache_external() {
+        let cache_dir = external::cache_dir(args.build_args());
+        fs::create_dir_all(&cache_dir)
+            .with_context(|| format!("could not create `{}`", cache_dir))?;
+        app.at(external::CACHE_URL).serve_dir(cache_dir)?;
+    }
+
+    throttle::register(&mut app, args.latency()?, args.throttle()?);
+
+    let proxy = args.proxy()?;
+    if !proxy.is_empty() {
+        for rule in &proxy {
+            log::info!("Proxy: {} -> {}", rule.path, rule.target);
+        }
+        app.with(proxy::ProxyMiddleware(proxy));
+    }
+
+    let host_globals = args.host_globals()?;
+    if !host_globals.is_empty() {
+        shims::register(&mut app, host_globals);
+    }
+
+    call_hook("serve", || hooks.call_serve(&run_context, &mut app))?;
+    app = wrap(app);
+
+    // NOTE: the redirects are applied by an outer server so the rewritten paths are routed by the
+    //       server of the hooks
+    let redirects = args.build_args().redirects()?;
+    if !redirects.is_empty() {
+        let mut outer = tide::new();
+        outer.with(redirects::RedirectMiddleware(redirects));
+        outer.at("/").all(app.clone());
+        outer.at("/*").all(app);
+        app = outer;
+    }
+
+    let port = if args.bind_random_port() {
+        0
+    } else {
+        args.port()
+    };
+    let listener = port::bind(args.ip(), port, args.auto_port())?;
+    let addr = listener.local_addr()?;
+    let url = format!("http://{}", addr);
+
+    if let Some(port_file) = args.port_file() {
+        fs::write(port_file, format!("{}\n", url))
+            .with_context(|| format!("could not write port file `{}`", port_file))?;
+    }
+
+    let status = json!({
+        "pid": std::process::id(),
+        "ip": addr.ip().to_string(),
+        "port": addr.port(),
+        "url": url,
+        "build_path": args.build_args().build_path(),
+    });
+    let status_path = args.status_path();
+    if let Some(parent) = status_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&status_path, format!("{}\n", status))
+        .with_context(|| format!("could not write status file `{}`", status_path))?;
+
+    if args.bind_random_port() {
+        events::print("server-started", status);
+    } else {
+        events::emit(args.build_args(), "server-started", status);
+    }
+
+    log::info!("Development server started: {}", url);
+
+    if args.open() {
+        // NOTE: the browser cannot connect to an unspecified address
+        let addr = listener.local_addr()?;
+        let url = if addr.ip().is_unspecified() {
+            format!("http://localhost:{}", addr.port())
+        } else {
+            url
+        };
+        if let Err(err) = open_browser(&url) {
+            log::warn!("Could not open the browser: {}", err);
+        }
+    }
+
+    Ok((addr, Box::pin(app.listen(listener).map_err(Into::into))))
+}
+
+#[cfg(feature = "dev-server")]
+fn open_browser(url: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+
+    command
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("could not start `{:?}`", command.get_program()))?;
+
+    Ok(())
+}
+
+/// Add the headers used by the browser DevTools to the debugging files: the source maps are JSON
+/// and a WASM file advertises the source map next to it (`<file>.wasm.map`) if there is one.
+#[cfg(feature = "dev-server")]
+fn debug_headers(build_path: &Path, path: &str, mut res: tide::Response) -> tide::Response {
+    if path.ends_with(".map") {
+        res.set_content_type(tide::http::mime::JSON);
+    } else if path.ends_with(".wasm") && build_path.join(format!("{}.map", path)).exists() {
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        res.insert_header("SourceMap", format!("{}.map", file_name));
+    }
+    res
+}
+
+#[cfg(feature = "dev-server")]
+#[derive(Clone)]
+struct IndexOptions {
+    index_path: PathBuf,
+    wasm_path: PathBuf,
+    cache_bust: bool,
+    overlay: bool,
+    shims: bool,
+    fingerprint: Fingerprint,
+    /// URLs of the external dependencies and the paths of their local copies.
+    external: Vec<(String, String)>,
+}
+
+/// Serve the `index.html` of the build directory (for `/` and the paths that are not files).
+///
+/// The HEAD requests are routed here by tide and the body is dropped by the HTTP server. The
+/// response has a `Last-Modified` header (the modification of the WASM file is taken into account
+/// when the links are cache busted) so the browsers and the proxies can revalidate it with
+/// `If-Modified-Since`.
+#[cfg(feature = "dev-server")]
+async fn serve_index(options: &IndexOptions, req: &tide::Request<()>) -> tide::Result {
+    use tide::http::conditional::{IfModifiedSince, LastModified};
+    use tide::{Response, StatusCode};
+
+    let modified = match async_std::fs::metadata(&options.index_path).await {
+        Ok(metadata) => metadata.modified().ok(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Response::builder(StatusCode::NotFound)
+                .body("index.html not found in the build directory")
+                .build());
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let modified = if options.cache_bust {
+        let wasm_modified = async_std::fs::metadata(&options.wasm_path)
+            .await
+            .ok()
+            .and_then(|x| x.modified().ok());
+        modified.max(wasm_modified)
+    } else {
+        modified
+    };
+    // NOTE: the HTTP dates don't have the fractions of second
+    let modified = modified
+        .and_then(|x| x.duration_since(time::UNIX_EPOCH).ok())
+        .map(|x| time::UNIX_EPOCH + time::Duration::from_secs(x.as_secs()));
+
+    let mut res = if modified.is_some_and(|modified| {
+        IfModifiedSince::from_headers(req)
+            .ok()
+            .flatten()
+            .is_some_and(|x| modified <= x.modified())
+    }) {
+        Response::new(StatusCode::NotModified)
+    } else {
+        serve_index_body(options).await?
+    };
+    res.insert_header("Cache-Control", "no-cache");
+    if let Some(modified) = modified {
+        LastModified::new(modified).apply(&mut res);
+    }
+    Ok(res)
+}
+
+#[cfg(feature = "dev-server")]
+async fn serve_index_body(options: &IndexOptions) -> tide::Result {
+    use tide::{Body, Response};
+
+    if !options.cache_bust && !options.overlay && !options.shims && options.external.is_empty() {
+        return Ok(Response::from(Body::from_file(&options.index_path).await?));
+    }
+
+    let mut html = async_std::fs::read_to_string(&options.index_path).await?;
+
+    if options.cache_bust {
+        let build_id = async_std::fs::read(&options.wasm_path)
+            .await
+            .map(|x| options.fingerprint.digest(&x))
+            .unwrap_or_default();
+        html = fingerprint::cache_bust_index(&html, &build_id);
+    }
+
+    if options.overlay {
+        match html.rfind("</body>") {
+            Some(i) => html.insert_str(i, overlay::SCRIPT),
+            None => html.push_str(overlay::SCRIPT),
+        }
+    }
+
+    if options.shims {
+        shims::inject(&mut html);
+    }
+
+    // NOTE: the integrity of the import map is keyed by URL too
+    for (url, local) in &options.external {
+        html = html.replace(&format!("\"{}\"", url), &format!("\"{}\"", local));
+    }
+
+    let mut res = Response::from(html);
+    res.set_content_type(tide::http::mime::HTML);
+    Ok(res)
+}
+
+/// Files of the root of the workspace watched by the default `frontend_watch` and `backend_watch`
+/// hooks: a change of the dependencies or of the toolchain triggers a full rebuild.
+const WORKSPACE_FILES: &[&str] = &["Cargo.lock", "rust-toolchain", "rust-toolchain.toml"];
+
+const WATCH_ERROR_HINT: &str = "could not watch files (on Linux you may need to increase \
+    `fs.inotify.max_user_watches` or you can use a polling watcher with `--poll <interval>`)";
+
+#[cfg(not(feature = "dev-server"))]
+fn watch_backend(args: &dyn ServeArgs, hooks: &Hooks) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
 
-    if args.profiling() {
-        profile = BuildProfile::Profiling;
-    }
+    let mut watcher = FileWatcher::for_serve(args, tx)?;
 
-    let frontend_package = args.frontend_package();
+    call_hook("backend_watch", || {
+        hooks.call_backend_watch(&RunContext::from_args(args), &mut watcher)
+    })
+    .context(WATCH_ERROR_HINT)?;
 
-    let build_path = args.build_path();
-    let _ = fs::remove_dir_all(build_path);
-    fs::create_dir_all(build_path).with_context(|| {
-        format!(
-            "could not create build directory `{}`",
-            build_path.display()
-        )
-    })?;
+    use backend::BackgroundProcess;
 
-    let mut command = Command::new("cargo");
+    let started = |process: &mut BackgroundProcess| {
+        let ready = match args.backend_health_check() {
+            Some(check) => {
+                let timeout = args.backend_health_timeout();
+                let ready = process.wait_ready(&check, timeout);
+                if ready {
+                    log::info!("Backend ready");
+                } else {
+                    log::warn!("The backend is not ready after {:?} ({:?})", timeout, check);
+                }
+                ready
+            }
+            None => {
+                log::info!("Backend started");
+                true
+            }
+        };
+        events::emit(
+            args.build_args(),
+            "backend-started",
+            json!({ "pid": process.id(), "ready": ready }),
+        );
+    };
 
-    command
-        .args(&[
-            "build",
-            "--lib",
-            "--target",
-            "wasm32-unknown-unknown",
-            "--manifest-path",
-        ])
-        .arg(&frontend_package.manifest_path)
-        .args(match profile {
-            BuildProfile::Profiling => &["--release"] as &[&str],
-            BuildProfile::Release => &["--release"],
-            BuildProfile::Dev => &[],
-        });
+    let spawn = |process_guard: &mut Option<BackgroundProcess>,
+                 command: &Command,
+                 executable: &Path|
+     -> Result<()> {
+        drop(process_guard.take());
 
-    log::info!("Running pre-build hook");
-    (hooks.pre_build)(args, profile, &mut command)?;
+        let mut server = Command::new(executable);
+        if let Some(dir) = command.get_current_dir() {
+            server.current_dir(dir);
+        }
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => server.env(key, value),
+                None => server.env_remove(key),
+            };
+        }
+        let process = BackgroundProcess::spawn(&mut server, args.backend_kill_timeout())
+            .with_context(|| format!("could not start `{}`", executable.display()))?;
+        started(process_guard.insert(process));
 
-    log::info!("Building frontend");
-    let status = command.status().context("could not start build process")?;
+        Ok(())
+    };
 
-    if !status.success() {
-        if let Some(code) = status.code() {
-            bail!("build process exit with code {}", code);
-        } else {
-            bail!("build process has been terminated by a signal");
+    // NOTE: the executable of the last build and its inputs, with `fast_backend_restart`
+    let run_server = |process_guard: &mut Option<BackgroundProcess>,
+                      built: &mut Option<(PathBuf, HashSet<PathBuf>)>,
+                      changed: Option<&Path>|
+     -> Result<()> {
+        let mut command = Command::new("cargo");
+        command
+            .envs(args.build_args().config().env.iter().map(|(k, v)| (k, v)))
+            .env("CARGO_TARGET_DIR", args.build_args().target_path())
+            .env(embed::BUILD_PATH_ENV, args.build_args().build_path())
+            .env(
+                embed::FRONTEND_ENV,
+                &args.build_args().frontend_package().name,
+            );
+        call_hook("backend_command", || {
+            hooks.call_backend_command(&RunContext::from_args(args), &mut command)
+        })?;
+
+        if command
+            .get_args()
+            .next()
+            .map(|x| x == "run")
+            .unwrap_or(false)
+        {
+            drop(process_guard.take());
+            *built = None;
+            let process = BackgroundProcess::spawn(&mut command, args.backend_kill_timeout())
+                .context("could not start the backend")?;
+            started(process_guard.insert(process));
+            return Ok(());
         }
-    }
 
-    let wasm_path = args
-        .target_path()
-        .join("wasm32-unknown-unknown")
-        .join(match profile {
-            BuildProfile::Profiling => "release",
-            BuildProfile::Release => "release",
-            BuildProfile::Dev => "debug",
-        })
-        .join(frontend_package.name.replace("-", "_"))
-        .with_extension("wasm");
+        if let (Some(path), Some((executable, inputs))) = (changed, built.as_ref()) {
+            if !inputs.contains(path) && path.file_name().is_some_and(|x| x != "Cargo.toml") {
+                log::info!(
+                    "{} is not a source of the backend: restarting it without cargo",
+                    path.display()
+                );
+                return spawn(process_guard, &command, executable);
+            }
+        }
 
-    let mut output = Bindgen::new()
-        .input_path(wasm_path)
-        .out_name("app")
-        .web(true)
-        .expect("fails only if multiple modes specified; qed")
-        .debug(!matches!(profile, BuildProfile::Release))
-        .generate_output()
-        .context("could not generate WASM bindgen file")?;
+        // NOTE: a running executable cannot be overwritten on Windows
+        if cfg!(windows) {
+            drop(process_guard.take());
+        }
 
-    let wasm_js = output.js().to_owned();
-    let wasm_bin = output.wasm_mut().emit_wasm();
+        let (executable, fresh) = build_backend(&mut command)?;
+        *built = if args.fast_backend_restart() {
+            backend_inputs(&executable).map(|inputs| (executable.clone(), inputs))
+        } else {
+            None
+        };
+        let running = process_guard
+            .as_mut()
+            .is_some_and(BackgroundProcess::is_running);
 
-    let wasm_bin = match profile {
-        BuildProfile::Profiling => wasm_opt(wasm_bin, 0, 2, true, args.target_path())?,
-        BuildProfile::Release => wasm_opt(wasm_bin, 1, 2, false, args.target_path())?,
-        BuildProfile::Dev => wasm_bin,
+        if fresh && running {
+            log::info!("Backend unchanged, keeping the running process");
+            return Ok(());
+        }
+
+        spawn(process_guard, &command, &executable)
     };
 
-    log::info!("Running post-build hook");
-    (hooks.post_build)(args, profile, wasm_js, wasm_bin)?;
+    let mut process_guard = None;
+    let mut built = None;
+    run_server(&mut process_guard, &mut built, None)?;
+
+    watch_loop(args, hooks, &mut watcher, rx, |path, full| {
+        let changed = (args.fast_backend_restart() && !full).then_some(path);
+        report(
+            args.build_args().state(),
+            run_server(&mut process_guard, &mut built, changed),
+        )
+    });
 
     Ok(())
 }
 
-#[cfg(feature = "dev-server")]
-fn serve_frontend(
-    args: &dyn ServeArgs,
-    hooks: &Hooks,
-) -> Result<Pin<Box<impl std::future::Future<Output = Result<()>> + Send + 'static>>> {
-    use futures::TryFutureExt;
+/// Run a `cargo build` command and return the path to the executable it produced and whether it
+/// was already up-to-date.
+#[cfg(not(feature = "dev-server"))]
+fn build_backend(command: &mut Command) -> Result<(PathBuf, bool)> {
+    use cargo_metadata::Message;
 
-    if args.log() {
-        tide::log::start();
+    command
+        .arg("--message-format=json-render-diagnostics")
+        .stdout(Stdio::piped());
+
+    log::info!("Building backend");
+    let mut child = command.spawn().context("could not start cargo")?;
+    let reader = BufReader::new(child.stdout.take().unwrap());
+    let mut executable = None;
+
+    for message in Message::parse_stream(reader) {
+        if let Message::CompilerArtifact(artifact) = message? {
+            if let Some(path) = artifact.executable {
+                executable = Some((path, artifact.fresh));
+            }
+        }
     }
-    let mut app = tide::new();
-
-    (hooks.serve)(args, &mut app)?;
 
-    log::info!(
-        "Development server started: http://{}:{}",
-        args.ip(),
-        args.port()
-    );
+    if !child.wait()?.success() {
+        bail!("could not build the backend");
+    }
 
-    Ok(Box::pin(
-        app.listen(format!("{}:{}", args.ip(), args.port()))
-            .map_err(Into::into),
-    ))
+    executable.context("cargo did not produce any executable for the backend")
 }
 
+/// The files the executable has been built from, read from the dep-info file written by cargo
+/// next to it (`<executable>: <input> <input> ...`, the spaces of the paths are escaped).
 #[cfg(not(feature = "dev-server"))]
-fn watch_backend(args: &dyn ServeArgs, hooks: &Hooks) -> Result<()> {
+fn backend_inputs(executable: &Path) -> Option<HashSet<PathBuf>> {
+    let mut file_name = executable.file_name()?.to_str()?;
+    if cfg!(windows) {
+        file_name = file_name.strip_suffix(".exe").unwrap_or(file_name);
+    }
+    let dep_info = match fs::read_to_string(executable.with_file_name(format!("{}.d", file_name))) {
+        Ok(dep_info) => dep_info,
+        Err(err) => {
+            log::warn!("Could not read the dep-info file of the backend: {}", err);
+            return None;
+        }
+    };
+    parse_dep_info(&dep_info)
+}
+
+/// The inputs of the first rule of a dep-info file.
+#[cfg(any(test, not(feature = "dev-server")))]
+fn parse_dep_info(dep_info: &str) -> Option<HashSet<PathBuf>> {
+    let (_, inputs) = dep_info
+        .lines()
+        .find(|x| !x.starts_with('#') && !x.trim().is_empty())?
+        .split_once(": ")?;
+
+    let mut paths = HashSet::new();
+    let mut path = String::new();
+    let mut chars = inputs.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.as_str().starts_with(' ') => {
+                path.push(' ');
+                chars.next();
+            }
+            ' ' => {
+                if !path.is_empty() {
+                    paths.insert(PathBuf::from(mem::take(&mut path)));
+                }
+            }
+            c => path.push(c),
+        }
+    }
+    if !path.is_empty() {
+        paths.insert(PathBuf::from(path));
+    }
+
+    Some(paths)
+}
+
+fn watch_frontend(
+    args: &(dyn ServeArgs + Sync),
+    hooks: &Hooks,
+    pending: Option<PendingOptimization>,
+) -> Result<()> {
     let (tx, rx) = mpsc::channel();
 
-    let mut watcher: RecommendedWatcher = notify::Watcher::new(tx, time::Duration::from_secs(2))
-        .context("could not initialize watcher")?;
+    let mut watcher = FileWatcher::for_serve(args, tx)?;
 
-    (hooks.backend_watch)(args, &mut watcher)?;
+    call_hook("frontend_watch", || {
+        hooks.call_frontend_watch(&RunContext::from_args(args), &mut watcher)
+    })
+    .context(WATCH_ERROR_HINT)?;
 
-    struct BackgroundProcess(std::process::Child);
+    // NOTE: the builds run on a worker so a change that happens in the meantime cancels the build
+    //       of the stale sources (at the end of cargo, wasm-bindgen or while wasm-opt runs)
+    std::thread::scope(|scope| {
+        let mut worker = cancel::Worker::new(args.build_args().state());
 
-    impl Drop for BackgroundProcess {
-        fn drop(&mut self) {
-            // TODO: cleaner exit on Unix
-            let _ = self.0.kill();
-            let _ = self.0.wait();
+        if let Some(pending) = pending {
+            serve_handle::build_started();
+            worker.spawn(scope, move || {
+                finish_optimization(pending, args.build_args(), hooks)
+            });
         }
-    }
 
-    let run_server = || -> Result<BackgroundProcess> {
-        let mut command = Command::new("cargo");
-        (hooks.backend_command)(args, &mut command)?;
-        Ok(command.spawn().map(BackgroundProcess)?)
-    };
+        watch_loop(args, hooks, &mut watcher, rx, |path, full| {
+            let build_args = args.build_args();
+            let failed = !worker.cancel();
+            let stage = if full || failed || !args.partial_rebuilds() {
+                rebuild::Stage::Full
+            } else {
+                rebuild::Stage::of(build_args, args.profile(), path)
+            };
+            if stage != rebuild::Stage::Full {
+                log::info!("Rebuilding the {} only", stage.name());
+                events::emit(
+                    build_args,
+                    "partial-rebuild",
+                    json!({ "stage": stage.name(), "path": path }),
+                );
+                report(build_args.state(), stage.run(build_args, args.profile()));
+                return;
+            }
 
-    let mut process_guard = Some(run_server()?);
+            worker.spawn(scope, move || {
+                let build_args = args.build_args();
+                let (_, pending) = build_with(
+                    args.profile(),
+                    build_args,
+                    hooks,
+                    args.background_wasm_opt(),
+                    true,
+                )?;
+                if args.test_on_change() {
+                    run_tests(args)?;
+                }
+                if let Some(pending) = pending {
+                    // NOTE: the unoptimized build is reported as finished like the first build,
+                    //       the optimization is reported as another build
+                    report(build_args.state(), Ok(()));
+                    serve_handle::build_started();
+                    finish_optimization(pending, build_args, hooks)?;
+                }
+                Ok(())
+            });
+        });
 
-    watch_loop(args, rx, || {
-        drop(process_guard.take());
-        process_guard.replace(run_server()?);
         Ok(())
-    });
+    })
 }
 
-fn watch_frontend(args: &dyn ServeArgs, hooks: &Hooks) -> Result<()> {
-    let (tx, rx) = mpsc::channel();
+fn default_test_command(args: &dyn BuildArgs) -> Command {
+    let mut command = Command::new("cargo");
+    command
+        .env("CARGO_TARGET_DIR", args.target_path())
+        .arg("test")
+        .arg("--manifest-path")
+        .arg(&args.frontend_package().manifest_path);
+    args.verbosity().cargo_args(&mut command);
+    command
+}
 
-    let mut watcher: RecommendedWatcher = notify::Watcher::new(tx, time::Duration::from_secs(2))
-        .context("could not initialize watcher")?;
+fn run_tests(args: &dyn ServeArgs) -> Result<()> {
+    log::info!("Running tests");
+    let status =
+        cancel::status(&mut args.test_command()).context("could not start test process")?;
+
+    if status.success() {
+        log::info!("Tests passed");
+        Ok(())
+    } else if let Some(code) = status.code() {
+        bail!("tests failed: test process exit with code {}", code);
+    } else {
+        bail!("tests failed: test process has been terminated by a signal");
+    }
+}
+
+/// Start the watchers of the watch tasks (check [`WatchTask`]) on their own threads. They stop
+/// with `control` if it is given.
+fn spawn_watch_tasks<A: ServeArgs + Sync + 'static>(
+    args: &Arc<A>,
+    hooks: &Arc<Hooks>,
+    control: Option<&serve_handle::Control>,
+) -> Result<()> {
+    use notify::Watcher;
+
+    for (i, task) in hooks.watch_tasks.iter().enumerate() {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = FileWatcher::from_config(args.watcher_config(), tx)?;
+        let workspace_root = &args.build_args().metadata().workspace_root;
+        for path in &task.paths {
+            watcher
+                .watch(workspace_root.join(path), notify::RecursiveMode::Recursive)
+                .with_context(|| {
+                    format!(
+                        "could not watch `{}` for the watch task `{}`",
+                        path.display(),
+                        task.name
+                    )
+                })
+                .context(WATCH_ERROR_HINT)?;
+        }
+
+        let args = Arc::clone(args);
+        let hooks = Arc::clone(hooks);
+        let run = move || watch_task_loop(&*args, &hooks.watch_tasks[i], watcher, rx);
+        match control {
+            Some(control) => serve_handle::spawn(control, run),
+            None => std::thread::spawn(run),
+        };
+    }
+
+    Ok(())
+}
+
+/// Run a watch task once no change happened during its delay.
+fn watch_task_loop(
+    args: &dyn ServeArgs,
+    task: &WatchTask,
+    mut watcher: FileWatcher,
+    rx: mpsc::Receiver<notify::DebouncedEvent>,
+) {
+    use notify::DebouncedEvent::*;
 
-    (hooks.frontend_watch)(args, &mut watcher)?;
+    let filter = WatchFilter::new(args);
+    let mut changed = Vec::new();
 
-    let build_args = args.build_args();
+    loop {
+        let message = if changed.is_empty() {
+            match serve_handle::recv(&rx, false) {
+                serve_handle::Next::Message(Ok(message)) => Some(message),
+                _ => return,
+            }
+        } else if serve_handle::is_shut_down() {
+            return;
+        } else {
+            match rx.recv_timeout(task.delay) {
+                Ok(message) => Some(message),
+                Err(mpsc::RecvTimeoutError::Timeout) => None,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        };
 
-    watch_loop(args, rx, || build(BuildProfile::Dev, build_args, hooks));
+        match message {
+            Some(Create(path)) | Some(Remove(path)) | Some(Rename(_, path))
+                if watcher.update_pending(&path) => {}
+            Some(Create(path)) | Some(Write(path)) | Some(Remove(path)) | Some(Rename(_, path)) => {
+                if !filter.is_ignored(&path) && !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+            Some(Error(err, _)) => {
+                log::error!("Watch error: {}", err);
+            }
+            Some(_) => {}
+            None => {
+                let paths = std::mem::take(&mut changed);
+                log::info!("Running watch task `{}`", task.name);
+                events::emit(
+                    args.build_args(),
+                    "watch-task-triggered",
+                    json!({ "name": task.name, "paths": paths }),
+                );
+                if let Err(err) = call_hook("watch_tasks", || (task.run)(args, &paths)) {
+                    log::error!("Watch task `{}` failed: {:#}", task.name, err);
+                }
+            }
+        }
+    }
 }
 
 fn watch_loop(
     args: &dyn ServeArgs,
+    hooks: &Hooks,
+    watcher: &mut FileWatcher,
     rx: mpsc::Receiver<notify::DebouncedEvent>,
-    mut callback: impl FnMut() -> Result<()>,
-) -> ! {
+    mut callback: impl FnMut(&Path, bool),
+) {
+    // NOTE: the command restarted with `--full-restart` is restarted by its supervisor instead
+    if full_restart::is_child() {
+        loop {
+            std::thread::park();
+        }
+    }
+
+    let mut filter = WatchFilter::new(args);
+    // NOTE: the exclusive watch tasks handle the changes of their paths on their own
+    filter.exclude(
+        hooks
+            .watch_tasks
+            .iter()
+            .filter(|x| x.exclusive)
+            .flat_map(|x| &x.paths)
+            .map(|x| args.build_args().metadata().workspace_root.join(x)),
+    );
+
+    serve_handle::watching();
+    if !args.watch() {
+        return stale::monitor(args, watcher, &filter, callback);
+    }
+
     loop {
         use notify::DebouncedEvent::*;
 
-        let message = rx.recv();
+        let message = match serve_handle::recv(&rx, true) {
+            serve_handle::Next::Message(message) => message,
+            serve_handle::Next::Rebuild => {
+                log::info!("Rebuild requested");
+                let path = &args.build_args().metadata().workspace_root;
+                events::emit(
+                    args.build_args(),
+                    "rebuild-triggered",
+                    json!({ "path": path, "full": true }),
+                );
+                callback(path, true);
+                continue;
+            }
+            serve_handle::Next::Stop => return,
+        };
+        if let Ok(Create(path)) | Ok(Remove(path)) | Ok(Rename(_, path)) = &message {
+            if watcher.update_pending(path) {
+                continue;
+            }
+        }
+        #[cfg(feature = "dev-server")]
+        if let Ok(Create(path)) | Ok(Write(path)) | Ok(Remove(path)) | Ok(Rename(_, path)) =
+            &message
+        {
+            // NOTE: the changes of the build and target directories are generated by the builds
+            if !filter.is_excluded(path) {
+                args.build_args()
+                    .state()
+                    .history
+                    .record_watch_event(path, filter.is_ignored(path));
+            }
+        }
         match &message {
             Ok(Create(path)) | Ok(Write(path)) | Ok(Remove(path)) | Ok(Rename(_, path))
-                if !path.starts_with(args.build_args().build_path())
-                    && !path.starts_with(args.build_args().target_path())
-                    && !path
-                        .file_name()
-                        .and_then(|x| x.to_str())
-                        .map(|x| x.starts_with('.'))
-                        .unwrap_or(false) =>
+                if !filter.is_ignored(path) =>
             {
-                if let Err(err) = callback() {
-                    log::error!("{}", err);
+                let workspace_file = filter.is_workspace_file(path);
+                if workspace_file {
+                    log::info!("{} changed: full rebuild", path.display());
+                    toolchain::invalidate(args.build_args());
+                } else {
+                    log::debug!("Change detected: {}", path.display());
                 }
+                // NOTE: the files removed from the sources must be removed from the build
+                //       directory too
+                let full = workspace_file || matches!(message, Ok(Remove(_)) | Ok(Rename(..)));
+                events::emit(
+                    args.build_args(),
+                    "rebuild-triggered",
+                    json!({ "path": path, "full": full }),
+                );
+                serve_handle::build_started();
+                callback(path, full);
+            }
+            Ok(NoticeWrite(path)) | Ok(NoticeRemove(path)) if !filter.is_ignored(path) => {
+                serve_handle::change_noticed();
+            }
+            Ok(Error(err, _)) => {
+                log::error!("Watch error: {}", err);
             }
             Ok(_) => {}
-            Err(e) => log::error!("Watch error: {}", e),
+            Err(_) => {
+                log::error!("The file watcher stopped: the changes are not watched anymore");
+                return stale::monitor(args, watcher, &filter, callback);
+            }
+        }
+    }
+}
+
+/// Log the error of a rebuild and display it in the overlay (or hide the overlay).
+fn report(state: &State, res: Result<()>) {
+    serve_handle::build_finished(&res);
+    if cancel::is_cancelled(&res) {
+        log::info!("The build has been cancelled by a newer change");
+        return;
+    }
+    if let Err(err) = &res {
+        log::error!("{}", err);
+    }
+    #[cfg(feature = "dev-server")]
+    state.overlay.set_error(
+        res.err()
+            .map(|err| match err.downcast_ref::<CompileError>() {
+                Some(err) => err.rendered(),
+                None => format!("{:#}", err),
+            }),
+    );
+    #[cfg(not(feature = "dev-server"))]
+    let _ = state;
+}
+
+struct WatchFilter {
+    excluded_paths: Vec<PathBuf>,
+    workspace_files: Vec<PathBuf>,
+    gitignores: Vec<ignore::gitignore::Gitignore>,
+}
+
+impl WatchFilter {
+    fn new(args: &dyn ServeArgs) -> Self {
+        use ignore::gitignore::GitignoreBuilder;
+
+        let build_args = args.build_args();
+        let metadata = build_args.metadata();
+        let members: HashSet<_> = HashSet::from_iter(&metadata.workspace_members);
+        let roots = iter::once(metadata.workspace_root.as_path()).chain(
+            metadata
+                .packages
+                .iter()
+                .filter(|x| members.contains(&x.id))
+                .map(|x| x.manifest_path.parent().unwrap())
+                .filter(|x| *x != metadata.workspace_root),
+        );
+
+        let mut gitignores = Vec::new();
+        for root in roots {
+            let mut builder = GitignoreBuilder::new(root);
+            for file_name in &[".gitignore", ".ignore"] {
+                let path = root.join(file_name);
+                if path.exists() {
+                    if let Some(err) = builder.add(&path) {
+                        log::warn!("Could not read `{}`: {}", path.display(), err);
+                    }
+                }
+            }
+            if root == metadata.workspace_root {
+                for pattern in build_args
+                    .config()
+                    .watch_ignore
+                    .iter()
+                    .chain(args.watch_ignore())
+                {
+                    if let Err(err) = builder.add_line(None, pattern) {
+                        log::warn!("Invalid watch ignore pattern `{}`: {}", pattern, err);
+                    }
+                }
+            }
+            match builder.build() {
+                Ok(gitignore) => gitignores.push(gitignore),
+                Err(err) => {
+                    log::warn!(
+                        "Could not load ignore files in `{}`: {}",
+                        root.display(),
+                        err
+                    );
+                }
+            }
+        }
+
+        let mut filter = Self {
+            excluded_paths: Vec::new(),
+            workspace_files: WORKSPACE_FILES
+                .iter()
+                .map(|x| normalize_path(&metadata.workspace_root.join(x)))
+                .collect(),
+            gitignores,
+        };
+        filter.exclude(args.watch_excluded_paths().into_iter().map(Into::into));
+        filter
+    }
+
+    /// Ignore the changes in these paths.
+    fn exclude(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        for path in paths {
+            let path = normalize_path(&path);
+            if let Some(resolved) = resolve_path(&path).filter(|x| *x != path) {
+                self.excluded_paths.push(resolved);
+            }
+            self.excluded_paths.push(path);
+        }
+    }
+
+    /// The path is in the build or the target directories (check
+    /// [`ServeArgs::watch_excluded_paths`]).
+    fn is_excluded(&self, path: &Path) -> bool {
+        let normalized = normalize_path(path);
+        let candidates = iter::once(normalized.clone())
+            .chain(resolve_path(&normalized))
+            .collect::<Vec<_>>();
+        if self.is_in_excluded_path(&candidates) {
+            return true;
+        }
+
+        // NOTE: the polling watcher reports the changes on the parent directories of the build
+        //       and target directories.
+        path.is_dir()
+            && candidates
+                .iter()
+                .any(|path| self.excluded_paths.iter().any(|x| x.starts_with(path)))
+    }
+
+    fn is_in_excluded_path(&self, candidates: &[PathBuf]) -> bool {
+        candidates
+            .iter()
+            .any(|path| self.excluded_paths.iter().any(|x| path.starts_with(x)))
+    }
+
+    /// The directory and its content are ignored: it is not watched at all.
+    fn is_ignored_dir(&self, dir: &Path) -> bool {
+        let normalized = normalize_path(dir);
+        let candidates = iter::once(normalized.clone())
+            .chain(resolve_path(&normalized))
+            .collect::<Vec<_>>();
+        self.is_in_excluded_path(&candidates) || self.matches_ignore_patterns(dir)
+    }
+
+    /// The path is one of the [`WORKSPACE_FILES`].
+    fn is_workspace_file(&self, path: &Path) -> bool {
+        self.workspace_files.contains(&normalize_path(path))
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        self.is_excluded(path) || self.matches_ignore_patterns(path)
+    }
+
+    /// The path is a temporary file or it is ignored by the ignore files or the patterns
+    /// [`ServeArgs::watch_ignore`].
+    fn matches_ignore_patterns(&self, path: &Path) -> bool {
+        // NOTE: `Cargo.lock` is often ignored by git
+        if self.is_workspace_file(path) {
+            return false;
+        }
+
+        let is_temporary_file = path
+            .file_name()
+            .and_then(|x| x.to_str())
+            .map(|x| {
+                x.starts_with('.')
+                    || x.starts_with('#')
+                    || x.ends_with('~')
+                    || x.ends_with(".swp")
+                    || x.ends_with(".swx")
+                    || x.ends_with(".tmp")
+                    || x == "4913"
+            })
+            .unwrap_or(false);
+        if is_temporary_file {
+            return true;
+        }
+
+        self.gitignores
+            .iter()
+            .filter(|x| path.starts_with(x.path()))
+            .any(|x| {
+                x.matched_path_or_any_parents(path, path.is_dir())
+                    .is_ignore()
+            })
+    }
+}
+
+/// Make a path absolute (relative to the current directory) and remove its `.` and `..`
+/// components without touching the file system.
+fn normalize_path(path: &Path) -> PathBuf {
+    use std::path::Component;
+
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map(|x| x.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
         }
     }
+    normalized
+}
+
+/// Resolve the symbolic links of a path that may not exist (anymore): the deepest existing
+/// ancestor is canonicalized and the rest of the path is appended to it.
+fn resolve_path(path: &Path) -> Option<PathBuf> {
+    let ancestor = path.ancestors().find(|x| x.exists())?;
+    let rest = path.strip_prefix(ancestor).ok()?;
+    Some(ancestor.canonicalize().ok()?.join(rest))
+}
+
+/// A path of the metadata of cargo as a UTF-8 path: cargo_metadata uses [`PathBuf`] but the
+/// paths are deserialized from the JSON of `cargo metadata`.
+pub(crate) fn metadata_path(path: &Path) -> &Utf8Path {
+    Utf8Path::from_path(path).expect("the paths of the metadata come from JSON strings; qed")
+}
+
+/// Escape a text or an attribute value inserted in the HTML generated by wasm-run (status page,
+/// listings, reports, `<script>` tags).
+pub(crate) fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Byte offset of the first occurrence of the ASCII `needle` in `haystack`, ignoring the case (to
+/// find the HTML tags).
+pub(crate) fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|x| x.eq_ignore_ascii_case(needle.as_bytes()))
 }
 
 #[allow(unused_variables, unreachable_code)]
@@ -869,7 +4742,7 @@ fn wasm_opt(
     shrink_level: u32,
     optimization_level: u32,
     debug_info: bool,
-    target_path: impl AsRef<Path>,
+    args: &dyn BuildArgs,
 ) -> Result<Vec<u8>> {
     #[cfg(feature = "binaryen")]
     return match binaryen::Module::read(&binary) {
@@ -884,33 +4757,86 @@ fn wasm_opt(
         Err(()) => bail!("could not load WASM module"),
     };
 
+    // NOTE: the optimization runs in this process and cannot be cancelled
+    #[cfg(feature = "wasm-opt")]
+    return {
+        use std::io::Write;
+        use wasm_opt::{OptimizationOptions, OptimizeLevel, ShrinkLevel};
+
+        // NOTE: binaryen only reads and writes files
+        let mut input = tempfile::Builder::new().suffix(".wasm").tempfile()?;
+        input.write_all(&binary)?;
+        input.flush()?;
+        let input_path = input.into_temp_path();
+        let output_path = tempfile::Builder::new()
+            .suffix(".wasm")
+            .tempfile()?
+            .into_temp_path();
+
+        OptimizationOptions::new_opt_level_0()
+            .optimize_level(match optimization_level {
+                0 => OptimizeLevel::Level0,
+                1 => OptimizeLevel::Level1,
+                2 => OptimizeLevel::Level2,
+                3 => OptimizeLevel::Level3,
+                _ => OptimizeLevel::Level4,
+            })
+            .shrink_level(match shrink_level {
+                0 => ShrinkLevel::Level0,
+                1 => ShrinkLevel::Level1,
+                _ => ShrinkLevel::Level2,
+            })
+            .debug_info(debug_info)
+            .run(&input_path, &output_path)
+            .context("could not optimize the WASM")?;
+
+        fs::read(&output_path)
+            .with_context(|| format!("could not read `{}`", output_path.display()))
+    };
+
     #[cfg(feature = "prebuilt-wasm-opt")]
     return {
-        let wasm_opt = prebuilt_wasm_opt::install_wasm_opt(target_path)?;
+        let wasm_opt = prebuilt_wasm_opt::install_wasm_opt(args)?;
 
         let mut command = Command::new(&wasm_opt);
         command
             .stderr(Stdio::inherit())
-            .args(&["-o", "-", "-O"])
-            .args(&["-ol", &optimization_level.to_string()])
-            .args(&["-s", &shrink_level.to_string()]);
+            .arg("-O")
+            .args(["-ol", &optimization_level.to_string()])
+            .args(["-s", &shrink_level.to_string()]);
         if debug_info {
             command.arg("-g");
         }
 
         #[cfg(target_os = "macos")]
         {
-            command.env("DYLD_LIBRARY_PATH", wasm_opt.parent().unwrap());
+            command.env(
+                "DYLD_LIBRARY_PATH",
+                wasm_opt.parent().unwrap().with_file_name("lib"),
+            );
         }
 
+        // NOTE: the standard streams are in text mode on Windows and would corrupt the WASM: it
+        //       goes through temporary files instead, closed so wasm-opt can open them (they are
+        //       deleted when the paths are dropped)
         #[cfg(windows)]
-        let delete_guard = {
+        let (input_path, output_path) = {
             use std::io::Write;
 
-            let tmp = tempfile::NamedTempFile::new()?;
-            tmp.as_file().write_all(&binary)?;
-            command.arg(tmp.path());
-            tmp
+            let mut input = tempfile::Builder::new().suffix(".wasm").tempfile()?;
+            input.write_all(&binary)?;
+            input.flush()?;
+            let input_path = input.into_temp_path();
+            let output_path = tempfile::Builder::new()
+                .suffix(".wasm")
+                .tempfile()?
+                .into_temp_path();
+            command
+                .arg(&input_path)
+                .arg("-o")
+                .arg(&output_path)
+                .stdin(Stdio::null());
+            (input_path, output_path)
         };
 
         #[cfg(unix)]
@@ -920,14 +4846,20 @@ fn wasm_opt(
             let mut file = tempfile::tempfile()?;
             file.write_all(&binary)?;
             file.seek(SeekFrom::Start(0))?;
-            command.stdin(file);
+            command.stdin(file).args(["-o", "-"]);
         }
 
-        let output = command.output()?;
+        let output = cancel::output(&mut command)?;
         if !output.status.success() {
             bail!("command `wasm-opt` failed.");
         }
-        Ok(output.stdout)
+
+        #[cfg(windows)]
+        let optimized = fs::read(&output_path)
+            .with_context(|| format!("could not read `{}`", output_path.display()))?;
+        #[cfg(unix)]
+        let optimized = output.stdout;
+        Ok(optimized)
     };
 
     log::warn!("No optimization has been done on the WASM");
@@ -960,6 +4892,7 @@ impl PackageExt for Metadata {
         let mut command = Command::new("cargo");
         command
             .current_dir(&self.workspace_root)
+            .env("CARGO_TARGET_DIR", &self.target_directory)
             .stdout(Stdio::piped());
 
         builder(&mut command);
@@ -1021,13 +4954,15 @@ pub mod prelude {
     pub use anyhow;
     #[cfg(feature = "dev-server")]
     pub use async_std;
+    pub use camino;
+    pub use camino::{Utf8Path, Utf8PathBuf};
     pub use cargo_metadata;
     pub use cargo_metadata::{Message, Metadata, Package};
     pub use fs_extra;
     #[cfg(feature = "dev-server")]
     pub use futures;
     pub use notify;
-    pub use notify::RecommendedWatcher;
+    pub use notify::{RecommendedWatcher, Watcher};
     #[cfg(feature = "sass")]
     pub use sass_rs;
     #[cfg(feature = "dev-server")]
@@ -1035,8 +4970,111 @@ pub mod prelude {
     #[cfg(feature = "dev-server")]
     pub use tide::Server;
 
+    #[cfg(not(feature = "dev-server"))]
+    pub use super::HealthCheck;
+    #[cfg(feature = "i18n")]
+    pub use super::I18nOptions;
+    #[cfg(feature = "webhooks")]
+    pub use super::Webhook;
+    #[cfg(feature = "dev-server")]
+    pub use super::{PathRule, ServeContext};
+
     pub use super::{
-        BuildArgs, BuildProfile, CargoChild, DefaultBuildArgs, DefaultServeArgs, Hooks, PackageExt,
-        ServeArgs,
+        ArgsFactory, AssetRule, BindgenSettings, BuildArgs, BuildContext, BuildOutput,
+        BuildProfile, BuildWorkspaceArgs, Builder, CargoChild, CommandContext, CompareOptArgs,
+        CompileError, Config, ConfigArgs, ConfigIssue, CriticalCss, DefaultBuildArgs,
+        DefaultPackageArgs, DefaultServeArgs, ExternalDependency, FileWatcher, Fingerprint,
+        HashAlgorithm, HookPanic, Hooks, MessageFormat, PackageArgs, PackageExt, PwaConfig,
+        PwaIcon, Redirect, RunContext, ServeArgs, ServeHandle, ShutdownReason, SymlinkMode,
+        TestBuildArgs, TestServeArgs, Timings, WatchTask, WatcherConfig, WatcherKind,
+        WorkspaceContext,
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> HashSet<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn parse_dep_info_inputs() {
+        assert_eq!(
+            parse_dep_info(
+                "/target/debug/backend: /src/main.rs /src/api.rs\n\n/src/main.rs:\n/src/api.rs:\n"
+            ),
+            Some(paths(&["/src/main.rs", "/src/api.rs"]))
+        );
+        assert_eq!(
+            parse_dep_info("# comment\n\n/target/debug/backend:  /a.rs   /b.rs \n"),
+            Some(paths(&["/a.rs", "/b.rs"]))
+        );
+    }
+
+    #[test]
+    fn parse_dep_info_escaped_spaces() {
+        assert_eq!(
+            parse_dep_info("/my\\ app/backend: /my\\ app/src/main.rs /my\\ app/static\\ files/x\n"),
+            Some(paths(&["/my app/src/main.rs", "/my app/static files/x"]))
+        );
+        // NOTE: the backslashes of the Windows paths are not escapes
+        assert_eq!(
+            parse_dep_info("C:\\target\\backend.exe: C:\\src\\main.rs\n"),
+            Some(paths(&["C:\\src\\main.rs"]))
+        );
+    }
+
+    #[test]
+    fn parse_dep_info_without_inputs() {
+        assert_eq!(
+            parse_dep_info("/target/debug/backend: \n"),
+            Some(paths(&[]))
+        );
+        assert_eq!(parse_dep_info(""), None);
+        assert_eq!(parse_dep_info("# comment\n"), None);
+        assert_eq!(parse_dep_info("/target/debug/backend:\n"), None);
+    }
+
+    #[test]
+    fn watch_plan_skips_ignored_directories() {
+        use notify::RecursiveMode::{NonRecursive, Recursive};
+
+        let root = std::env::temp_dir().join(format!("wasm-run-watch-plan-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in [
+            "src/bin",
+            "target/debug",
+            "frontend/node_modules/x",
+            "frontend/src",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let root = normalize_path(&root);
+        let mut builder = ignore::gitignore::GitignoreBuilder::new(&root);
+        builder.add_line(None, "node_modules").unwrap();
+        let mut filter = WatchFilter {
+            excluded_paths: Vec::new(),
+            workspace_files: Vec::new(),
+            gitignores: vec![builder.build().unwrap()],
+        };
+        filter.exclude([root.join("target")]);
+
+        assert_eq!(
+            watch_plan(&filter, &root),
+            vec![
+                (root.clone(), NonRecursive),
+                (root.join("frontend"), NonRecursive),
+                (root.join("frontend/src"), Recursive),
+                (root.join("src"), Recursive),
+            ]
+        );
+        assert_eq!(
+            watch_plan(&filter, &root.join("src")),
+            vec![(root.join("src"), Recursive)]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
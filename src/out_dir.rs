@@ -0,0 +1,66 @@
+use crate::branch;
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::Metadata;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// The output directory of a build: the one of the command-line (`--out-dir`, formerly
+/// `--build-path`) relative to the current directory, or `default` (check
+/// [`BuildArgs::default_build_path`](crate::BuildArgs::default_build_path)). It is namespaced by
+/// git branch with `per_branch`.
+///
+/// The path is made absolute so it doesn't depend on the directory of the commands run later.
+pub(crate) fn resolve(
+    cli: Option<&Utf8Path>,
+    default: &Utf8Path,
+    per_branch: bool,
+    workspace_root: &Path,
+) -> Utf8PathBuf {
+    let path = match cli {
+        Some(path) if path.is_relative() => env::current_dir()
+            .ok()
+            .and_then(|x| Utf8PathBuf::from_path_buf(x).ok())
+            .map(|x| x.join(path))
+            .unwrap_or_else(|| path.to_owned()),
+        Some(path) => path.to_owned(),
+        None => default.to_owned(),
+    };
+
+    if per_branch {
+        branch::build_path(&path, workspace_root)
+    } else {
+        path
+    }
+}
+
+/// Empty the output directory `path` before it is written. This is the only place where an
+/// output directory is removed: it refuses to remove a directory that contains the workspace or
+/// one of its packages (like `--out-dir .`).
+pub(crate) fn prepare(path: &Utf8Path, metadata: &Metadata) -> Result<()> {
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let target = canonical(path.as_std_path());
+
+    let sources = std::iter::once(metadata.workspace_root.as_path()).chain(
+        metadata
+            .packages
+            .iter()
+            .filter(|x| x.source.is_none())
+            .filter_map(|x| x.manifest_path.parent()),
+    );
+    for source in sources {
+        if canonical(source).starts_with(&target) {
+            bail!(
+                "refusing to use `{}` as output directory: it contains `{}`, which would be \
+                deleted",
+                path,
+                source.display(),
+            );
+        }
+    }
+
+    let _ = fs::remove_dir_all(path);
+    fs::create_dir_all(path)
+        .with_context(|| format!("could not create output directory `{}`", path))
+}
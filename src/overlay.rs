@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tide::convert::json;
+use tide::{Body, Server};
+
+/// The messages displayed by the error overlay.
+#[derive(Debug, Default)]
+pub(crate) struct Overlay {
+    /// Error of the last build.
+    error: Mutex<Option<String>>,
+    /// Last request of a WASM or JavaScript file missing from the build directory, until the
+    /// next build.
+    missing: Mutex<Option<String>>,
+    /// Warning displayed when the build is out of date and the changes are not watched.
+    stale: Mutex<Option<String>>,
+    /// Number of optimized artifacts that replaced an unoptimized build.
+    optimized: AtomicUsize,
+}
+
+pub(crate) const STATUS_PATH: &str = "/__wasm_run/status";
+
+pub(crate) const SCRIPT: &str = r#"<script>(function () {
+    const overlay = document.createElement("pre");
+    overlay.style = "position: fixed; inset: 0; z-index: 2147483647; margin: 0; padding: 2em; overflow: auto; white-space: pre-wrap; background: rgba(0, 0, 0, 0.85); color: #f66; font: 14px monospace;";
+    const notification = document.createElement("div");
+    notification.style = "position: fixed; right: 1em; bottom: 1em; z-index: 2147483647; padding: 1em; cursor: pointer; background: #333; color: #fff; font: 14px sans-serif;";
+    notification.textContent = "The optimized build is ready: click to reload";
+    notification.onclick = () => location.reload();
+    const stale = document.createElement("div");
+    stale.style = "position: fixed; left: 1em; bottom: 1em; z-index: 2147483647; padding: 1em; background: #a60; color: #fff; font: 14px sans-serif;";
+    let optimized = null;
+    setInterval(async () => {
+        try {
+            const status = await (await fetch("/__wasm_run/status")).json();
+            if (optimized === null) {
+                optimized = status.optimized;
+            } else if (status.optimized !== optimized) {
+                document.body.appendChild(notification);
+            }
+            if (status.stale) {
+                stale.textContent = status.stale;
+                document.body.appendChild(stale);
+            } else {
+                stale.remove();
+            }
+            if (status.error || status.missing) {
+                overlay.textContent = status.error || status.missing;
+                document.body.appendChild(overlay);
+            } else {
+                overlay.remove();
+            }
+        } catch (_) {}
+    }, 1000);
+})();</script>"#;
+
+impl Overlay {
+    pub(crate) fn set_error(&self, error: Option<String>) {
+        *self.error.lock().unwrap() = error;
+        *self.missing.lock().unwrap() = None;
+    }
+
+    pub(crate) fn set_missing(&self, message: Option<String>) {
+        *self.missing.lock().unwrap() = message;
+    }
+
+    pub(crate) fn set_stale(&self, message: Option<String>) {
+        *self.stale.lock().unwrap() = message;
+    }
+
+    /// Notify the browser that the optimized artifact replaced the unoptimized one.
+    pub(crate) fn notify_optimized(&self) {
+        self.optimized.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+pub(crate) fn register(server: &mut Server<()>, overlay: Arc<Overlay>) {
+    server.at(STATUS_PATH).get(move |_| {
+        let overlay = Arc::clone(&overlay);
+        async move {
+            let error = overlay.error.lock().unwrap().clone();
+            let missing = overlay.missing.lock().unwrap().clone();
+            let stale = overlay.stale.lock().unwrap().clone();
+            let optimized = overlay.optimized.load(Ordering::SeqCst);
+            Body::from_json(&json!({
+                "error": error,
+                "missing": missing,
+                "stale": stale,
+                "optimized": optimized,
+            }))
+        }
+    });
+}
@@ -0,0 +1,197 @@
+use crate::{
+    build, BuildArgs, BuildProfile, DefaultBuildArgs, Hooks, PackageExt, WorkspaceContext,
+};
+use anyhow::{bail, Context, Result};
+use camino::Utf8PathBuf;
+use downcast_rs::*;
+use fs_extra::dir;
+use std::fs;
+use std::process::Command;
+use std::sync::Arc;
+use structopt::StructOpt;
+
+/// Package arguments.
+#[derive(StructOpt, Debug)]
+pub struct DefaultPackageArgs {
+    /// Base image of the container image.
+    #[structopt(long, default_value = "gcr.io/distroless/static")]
+    pub base_image: String,
+
+    /// Target triple used to build the backend.
+    #[structopt(long, default_value = "x86_64-unknown-linux-musl")]
+    pub target: String,
+
+    /// Tag of the container image (default: `<backend>:latest`).
+    #[structopt(long, short = "t")]
+    pub tag: Option<String>,
+
+    /// Do not add the build directory to the container image.
+    #[structopt(long)]
+    pub no_build_path: bool,
+
+    /// Build arguments.
+    #[structopt(flatten)]
+    pub build_args: DefaultBuildArgs,
+}
+
+impl DefaultPackageArgs {
+    /// Use this context instead of the global context initialized by the macro
+    /// [`main`](crate::main).
+    pub fn with_context(mut self, context: Arc<WorkspaceContext>) -> Self {
+        self.build_args = self.build_args.with_context(context);
+        self
+    }
+}
+
+/// A trait that allows overriding the `package-image` command.
+pub trait PackageArgs: Downcast {
+    /// Base image of the container image.
+    fn base_image(&self) -> &str;
+
+    /// Target triple used to build the backend.
+    fn target(&self) -> &str;
+
+    /// Tag of the container image. When `None`, `<backend>:latest` is used.
+    fn tag(&self) -> Option<&str>;
+
+    /// Add the build directory to the container image.
+    fn include_build_path(&self) -> bool;
+
+    /// Build arguments.
+    fn build_args(&self) -> &dyn BuildArgs;
+
+    /// Path to the directory used as context for the container image build.
+    fn context_path(&self) -> Utf8PathBuf {
+        self.build_args().target_path().join("wasm-run-package")
+    }
+
+    /// Content of the `Dockerfile` given the name of the backend binary.
+    fn dockerfile(&self, backend_bin: &str) -> String {
+        let mut dockerfile = format!("FROM {}\n", self.base_image());
+        dockerfile.push_str(&format!("ADD {0} /{0}\n", backend_bin));
+        if self.include_build_path() {
+            dockerfile.push_str("ADD build /build\n");
+        }
+        dockerfile.push_str(&format!("ENTRYPOINT [\"/{}\"]\n", backend_bin));
+        dockerfile
+    }
+
+    /// Run the `package-image` command.
+    fn run(self) -> Result<()>
+    where
+        Self: Sized + 'static,
+    {
+        let hooks = self.build_args().context().hooks();
+        package_image(&self, hooks)
+    }
+}
+
+impl_downcast!(PackageArgs);
+
+impl PackageArgs for DefaultPackageArgs {
+    fn base_image(&self) -> &str {
+        &self.base_image
+    }
+
+    fn target(&self) -> &str {
+        &self.target
+    }
+
+    fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    fn include_build_path(&self) -> bool {
+        !self.no_build_path
+    }
+
+    fn build_args(&self) -> &dyn BuildArgs {
+        &self.build_args
+    }
+}
+
+fn package_image(args: &dyn PackageArgs, hooks: &Hooks) -> Result<()> {
+    let build_args = args.build_args();
+    let backend = build_args
+        .backend_package()
+        .context("missing backend crate name")?;
+    let backend_bin = backend
+        .targets
+        .iter()
+        .find(|x| x.kind.iter().any(|x| x == "bin"))
+        .map(|x| x.name.as_str())
+        .unwrap_or_else(|| backend.name.as_str());
+
+    if args.include_build_path() {
+        log::info!("Building frontend");
+        build(BuildProfile::Release, build_args, hooks)?;
+    }
+
+    log::info!("Building backend");
+    build_args
+        .metadata()
+        .cargo(|command| {
+            command
+                .args(["build", "--release", "-p", &backend.name, "--target"])
+                .arg(args.target())
+                .env(crate::embed::BUILD_PATH_ENV, build_args.build_path())
+                .env(
+                    crate::embed::FRONTEND_ENV,
+                    &build_args.frontend_package().name,
+                );
+        })?
+        .wait_success()?;
+
+    let context_path = args.context_path();
+    crate::out_dir::prepare(&context_path, build_args.metadata())?;
+
+    let backend_bin_path = build_args
+        .target_path()
+        .join(args.target())
+        .join("release")
+        .join(backend_bin);
+    fs::copy(&backend_bin_path, context_path.join(backend_bin))
+        .with_context(|| format!("could not copy backend binary `{}`", backend_bin_path))?;
+
+    if args.include_build_path() {
+        let build_path = build_args.build_path();
+        let context_build_path = context_path.join("build");
+        fs::create_dir_all(&context_build_path)?;
+        dir::copy(
+            build_path,
+            &context_build_path,
+            &dir::CopyOptions {
+                content_only: true,
+                ..dir::CopyOptions::new()
+            },
+        )
+        .with_context(|| {
+            format!(
+                "could not copy build directory `{}` to `{}`",
+                build_path, context_build_path
+            )
+        })?;
+    }
+
+    let dockerfile_path = context_path.join("Dockerfile");
+    fs::write(&dockerfile_path, args.dockerfile(backend_bin))
+        .with_context(|| format!("could not write Dockerfile to `{}`", dockerfile_path))?;
+
+    let tag = args
+        .tag()
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| format!("{}:latest", backend.name));
+
+    log::info!("Building container image {}", tag);
+    let status = Command::new("docker")
+        .args(["build", "-t", &tag])
+        .arg(&context_path)
+        .status()
+        .context("could not start docker")?;
+
+    if !status.success() {
+        bail!("failed to build container image");
+    }
+
+    Ok(())
+}
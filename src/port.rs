@@ -0,0 +1,89 @@
+use anyhow::{bail, Result};
+use std::io;
+use std::net::TcpListener;
+
+pub(crate) fn bind(ip: &str, port: u16, auto_port: bool) -> Result<TcpListener> {
+    match TcpListener::bind((ip, port)) {
+        Ok(listener) => Ok(listener),
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse && auto_port => {
+            log::warn!("Port {} is already in use, picking a free port", port);
+            Ok(TcpListener::bind((ip, 0))?)
+        }
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse => match port_owner(port) {
+            Some(owner) => bail!(
+                "could not bind {}:{}: the port is already used by {} (use `--port 0` or \
+                `--auto-port` to pick a free port)",
+                ip,
+                port,
+                owner,
+            ),
+            None => bail!(
+                "could not bind {}:{}: the port is already in use (use `--port 0` or \
+                `--auto-port` to pick a free port)",
+                ip,
+                port,
+            ),
+        },
+        Err(err) => bail!("could not bind {}:{}: {}", ip, port, err),
+    }
+}
+
+/// Find the process listening on a TCP port by looking up the socket's inode in `/proc`.
+#[cfg(target_os = "linux")]
+fn port_owner(port: u16) -> Option<String> {
+    use std::fs;
+
+    let inodes: Vec<String> = ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|x| fs::read_to_string(x).ok())
+        .flat_map(|table| {
+            table
+                .lines()
+                .skip(1)
+                .filter_map(|line| {
+                    let fields: Vec<_> = line.split_whitespace().collect();
+                    let local_port = fields.get(1)?.rsplit(':').next()?;
+                    // NOTE: 0A is the state LISTEN
+                    if u16::from_str_radix(local_port, 16).ok()? == port && fields.get(3)? == &"0A"
+                    {
+                        Some(format!("socket:[{}]", fields.get(9)?))
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    if inodes.is_empty() {
+        return None;
+    }
+
+    fs::read_dir("/proc")
+        .ok()?
+        .filter_map(|x| x.ok())
+        .filter(|x| x.file_name().to_string_lossy().parse::<u32>().is_ok())
+        .find_map(|process| {
+            let has_socket = fs::read_dir(process.path().join("fd"))
+                .ok()?
+                .filter_map(|x| x.ok())
+                .filter_map(|x| fs::read_link(x.path()).ok())
+                .any(|x| inodes.iter().any(|inode| x.as_os_str() == inode.as_str()));
+
+            if has_socket {
+                let name = fs::read_to_string(process.path().join("comm")).ok()?;
+                Some(format!(
+                    "`{}` (PID {})",
+                    name.trim(),
+                    process.file_name().to_string_lossy()
+                ))
+            } else {
+                None
+            }
+        })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn port_owner(_port: u16) -> Option<String> {
+    None
+}
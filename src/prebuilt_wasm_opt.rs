@@ -1,28 +1,191 @@
-use anyhow::{Context, Result};
-use binary_install::Cache;
-use std::path::{Path, PathBuf};
+use crate::{BuildArgs, Config};
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
 
-pub(crate) fn install_wasm_opt(target_path: impl AsRef<Path>) -> Result<PathBuf> {
-    let cache = Cache::at(target_path.as_ref());
+const DEFAULT_VERSION: &str = "97";
 
-    let url = format!(
-        "https://github.com/WebAssembly/binaryen/releases/download/version_{version}/binaryen-version_{version}-{arch}-{os}.tar.gz",
-        version = "97",
-        arch = platforms::TARGET_ARCH,
-        os = platforms::TARGET_OS,
-    );
+const RELEASES_URL: &str = "https://github.com/WebAssembly/binaryen/releases/download";
 
-    #[cfg(target_os = "macos")]
-    let binaries = &["wasm-opt", "libbinaryen"];
-    #[cfg(not(target_os = "macos"))]
-    let binaries = &["wasm-opt"];
+/// The binaries of binaryen (`binaryen-version_<version>-<platform>.tar.gz`) that can run on a
+/// host (architecture and OS of `platforms`), by order of preference. The Linux binaries are
+/// linked statically: they run on the glibc and musl hosts alike.
+const PLATFORMS: &[(&str, &str, &[&str])] = &[
+    ("x86_64", "linux", &["x86_64-linux"]),
+    ("aarch64", "linux", &["aarch64-linux"]),
+    ("x86_64", "macos", &["x86_64-macos"]),
+    // NOTE: the x86_64 binaries run with Rosetta 2 for the versions without arm64 binaries
+    ("aarch64", "macos", &["arm64-macos", "x86_64-macos"]),
+    ("x86_64", "windows", &["x86_64-windows"]),
+];
+
+pub(crate) fn default_version(config: &Config) -> String {
+    env::var("WASM_RUN_WASM_OPT_VERSION")
+        .ok()
+        .or_else(|| config.wasm_opt_version.clone())
+        .unwrap_or_else(|| DEFAULT_VERSION.to_string())
+}
+
+pub(crate) fn default_sha256(config: &Config) -> Option<String> {
+    env::var("WASM_RUN_WASM_OPT_SHA256")
+        .ok()
+        .or_else(|| config.wasm_opt_sha256.clone())
+}
+
+pub(crate) fn default_mirror(config: &Config) -> Option<String> {
+    env::var("WASM_RUN_WASM_OPT_MIRROR")
+        .ok()
+        .or_else(|| config.wasm_opt_mirror.clone())
+}
+
+/// The binaries of binaryen that can run on this host.
+fn host_platforms() -> Result<&'static [&'static str]> {
+    let arch = platforms::TARGET_ARCH.as_str();
+    let os = platforms::TARGET_OS.as_str();
+    PLATFORMS
+        .iter()
+        .find(|(x, y, _)| *x == arch && *y == os)
+        .map(|(_, _, platforms)| *platforms)
+        .with_context(|| {
+            format!(
+                "binaryen does not publish binaries for {}-{}: install wasm-opt and set \
+                `WASM_RUN_WASM_OPT` to its path, or use the feature `binaryen` instead of \
+                `prebuilt-wasm-opt` to build it from source",
+                arch, os
+            )
+        })
+}
+
+pub(crate) fn install_wasm_opt(args: &dyn BuildArgs) -> Result<PathBuf> {
+    if let Some(wasm_opt) = env::var_os("WASM_RUN_WASM_OPT") {
+        return Ok(PathBuf::from(wasm_opt));
+    }
+
+    let version = args.wasm_opt_version();
+    let cache_path = args
+        .target_path()
+        .join("wasm-opt")
+        .join(format!("version_{}", version));
+    let wasm_opt = cache_path
+        .join(format!("binaryen-version_{}", version))
+        .join("bin")
+        .join(format!("wasm-opt{}", env::consts::EXE_SUFFIX));
+
+    if wasm_opt.exists() {
+        return Ok(wasm_opt.into());
+    }
+
+    if args.offline() {
+        bail!(
+            "wasm-opt version {} is not in the cache (`{}`) and the offline mode is enabled",
+            version,
+            cache_path,
+        );
+    }
+
+    let platforms = host_platforms()?;
+    let mut base_urls = vec![RELEASES_URL.to_string()];
+    base_urls.extend(args.wasm_opt_mirror());
+
+    let mut errors = Vec::new();
+    let archive = platforms.iter().find_map(|platform| {
+        let file_name = format!("binaryen-version_{}-{}.tar.gz", version, platform);
+        base_urls.iter().find_map(|base_url| {
+            let url = format!(
+                "{}/version_{}/{}",
+                base_url.trim_end_matches('/'),
+                version,
+                file_name
+            );
+            match download_verified(&url, args.wasm_opt_sha256()) {
+                Ok(archive) => Some(archive),
+                Err(err) => {
+                    log::warn!("Could not download wasm-opt from {}: {:#}", url, err);
+                    errors.push(url);
+                    None
+                }
+            }
+        })
+    });
+    let archive = match archive {
+        Some(archive) => archive,
+        None => bail!(
+            "could not download binaryen: {} (the version {} may not have a binary for {}: try \
+            another version with `wasm_opt.version` or set `WASM_RUN_WASM_OPT` to the path of \
+            wasm-opt)",
+            errors.join(", "),
+            version,
+            platforms.join(" or "),
+        ),
+    };
+
+    // NOTE: the archive is extracted in a temporary directory first so an interrupted extraction
+    //       is never taken for a complete installation
+    let tmp_path = cache_path.with_extension("tmp");
+    let _ = fs::remove_dir_all(&tmp_path);
+    fs::create_dir_all(&tmp_path)?;
+    tar::Archive::new(flate2::read::GzDecoder::new(archive.as_slice()))
+        .unpack(&tmp_path)
+        .context("could not extract binaryen")?;
+    let _ = fs::remove_dir_all(&cache_path);
+    fs::rename(&tmp_path, &cache_path)?;
+
+    if !wasm_opt.exists() {
+        bail!(
+            "could not find wasm-opt in the archive of binaryen: `{}` does not exist",
+            wasm_opt
+        );
+    }
+
+    Ok(wasm_opt.into())
+}
+
+/// Download a file and verify its SHA-256 checksum. If no checksum is provided, the checksum file
+/// published alongside (`<url>.sha256`) is used.
+fn download_verified(url: &str, sha256: Option<String>) -> Result<Vec<u8>> {
+    let expected = match sha256 {
+        Some(sha256) => sha256,
+        None => {
+            let checksum_file = download(&format!("{}.sha256", url))
+                .context("could not download the checksum (provide it explicitly to skip this)")?;
+            String::from_utf8_lossy(&checksum_file)
+                .split_whitespace()
+                .next()
+                .context("invalid checksum file")?
+                .to_string()
+        }
+    };
 
     eprintln!("Downloading wasm-opt...");
-    Ok(cache
-        .download(true, "wasm-opt", binaries, &url)
-        .map_err(|err| err.compat())
-        .with_context(|| format!("could not download binaryen: {}", url))?
-        .expect("install is permitted; qed")
-        .binary("wasm-opt")
-        .map_err(|err| err.compat())?)
+    let data = download(url)?;
+    let actual = format!("{:x}", Sha256::digest(&data));
+
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        bail!(
+            "checksum mismatch: expected SHA-256 {}, got {}",
+            expected.trim(),
+            actual
+        );
+    }
+
+    Ok(data)
+}
+
+fn download(url: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut easy = curl::easy::Easy::new();
+    easy.url(url)?;
+    easy.follow_location(true)?;
+    easy.fail_on_error(true)?;
+    {
+        let mut transfer = easy.transfer();
+        transfer.write_function(|chunk| {
+            data.extend_from_slice(chunk);
+            Ok(chunk.len())
+        })?;
+        transfer.perform()?;
+    }
+    Ok(data)
 }
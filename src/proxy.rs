@@ -0,0 +1,127 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+/// A path prefix of the development server forwarded to another HTTP server (typically the API of
+/// the backend during the development of the frontend).
+///
+/// The requests whose path is the prefix or starts with the prefix followed by `/` are forwarded
+/// with their method, headers, body and query. The path of the target, if any, is prepended to the
+/// path of the request: with `"/api" = "http://localhost:8000/v1"`, `/api/users` is forwarded to
+/// `http://localhost:8000/v1/api/users`. Only `http://` targets are supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyRule {
+    /// Path prefix of the forwarded requests (it starts with `/`).
+    pub path: String,
+    /// URL of the server that receives the requests (`http://<host>[:<port>][/<path>]`).
+    pub target: String,
+}
+
+impl ProxyRule {
+    /// Create a rule and check its path and its target.
+    pub fn new(path: impl Into<String>, target: impl Into<String>) -> Result<Self> {
+        let rule = Self {
+            path: path.into().trim_end_matches('/').to_string(),
+            target: target.into().trim_end_matches('/').to_string(),
+        };
+        if !rule.path.starts_with('/') && !rule.path.is_empty() {
+            bail!(
+                "invalid proxy `{}`: the path must start with `/`",
+                rule.path
+            );
+        }
+        match rule.target.strip_prefix("http://") {
+            Some(rest) if !rest.is_empty() && !rest.starts_with('/') => {}
+            Some(_) => bail!("invalid proxy target `{}`: missing host", rule.target),
+            None => bail!(
+                "invalid proxy target `{}`: only `http://` targets are supported",
+                rule.target
+            ),
+        }
+        Ok(rule)
+    }
+
+    /// Read the rules from the table `proxy` of the configuration (the path prefixes mapped to
+    /// their targets). The longest prefixes are matched first.
+    pub(crate) fn from_value(value: &Value) -> Result<Vec<Self>> {
+        if value.is_null() {
+            return Ok(Vec::new());
+        }
+        let error = "invalid `proxy`: expected a table of paths and `http://` URLs";
+        let mut rules = value
+            .as_object()
+            .context(error)?
+            .iter()
+            .map(|(path, target)| Self::new(path.as_str(), target.as_str().context(error)?))
+            .collect::<Result<Vec<_>>>()?;
+        rules.sort_by_key(|x| std::cmp::Reverse(x.path.len()));
+        Ok(rules)
+    }
+
+    /// The request path is forwarded by this rule.
+    pub fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(self.path.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+
+    /// Host and port of the target (`80` by default).
+    #[cfg(feature = "dev-server")]
+    fn authority(&self) -> String {
+        let rest = &self.target["http://".len()..];
+        let authority = rest.split('/').next().unwrap_or(rest);
+        if authority.ends_with(']') || !authority.contains(':') {
+            format!("{}:80", authority)
+        } else {
+            authority.to_string()
+        }
+    }
+
+    /// URL of the forwarded request.
+    pub fn url(&self, path: &str, query: Option<&str>) -> String {
+        let mut url = format!("{}{}", self.target, path);
+        if let Some(query) = query {
+            url.push('?');
+            url.push_str(query);
+        }
+        url
+    }
+}
+
+/// Middleware of the development server that forwards the requests of the proxy rules before the
+/// routing.
+#[cfg(feature = "dev-server")]
+pub(crate) struct ProxyMiddleware(pub(crate) Vec<ProxyRule>);
+
+#[cfg(feature = "dev-server")]
+#[tide::utils::async_trait]
+impl tide::Middleware<()> for ProxyMiddleware {
+    async fn handle(&self, req: tide::Request<()>, next: tide::Next<'_, ()>) -> tide::Result {
+        let rule = match self.0.iter().find(|x| x.matches(req.url().path())) {
+            Some(rule) => rule,
+            None => return Ok(next.run(req).await),
+        };
+
+        let url = rule.url(req.url().path(), req.url().query());
+        log::debug!("Proxy {} to {}", req.url().path(), url);
+        let mut inner: tide::http::Request = req.into();
+        *inner.url_mut() = tide::http::Url::parse(&url)?;
+
+        let res = match async_std::net::TcpStream::connect(rule.authority()).await {
+            Ok(stream) => async_h1::connect(stream, inner).await,
+            Err(err) => Err(err.into()),
+        };
+        match res {
+            Ok(res) => Ok(res.into()),
+            Err(err) => {
+                log::warn!("Could not forward the request to {}: {}", url, err);
+                Ok(tide::Response::builder(502)
+                    .body(format!(
+                        "could not forward the request to `{}`: {}",
+                        url, err
+                    ))
+                    .build())
+            }
+        }
+    }
+}
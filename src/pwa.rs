@@ -0,0 +1,357 @@
+use crate::{BuildArgs, Config};
+use anyhow::{Context, Result};
+use cargo_metadata::Package;
+use globset::{Glob, GlobSetBuilder};
+use serde_json::{json, Map, Value};
+use std::fs;
+
+const MANIFEST: &str = "manifest.webmanifest";
+const SERVICE_WORKER: &str = "service-worker.js";
+
+const SERVICE_WORKER_TEMPLATE: &str = r#"const CACHE = "wasm-run-__VERSION__";
+const FILES = __FILES__;
+
+self.addEventListener("install", (event) => {
+    event.waitUntil(caches.open(CACHE).then((cache) => cache.addAll(FILES)).then(() => self.skipWaiting()));
+});
+
+self.addEventListener("activate", (event) => {
+    event.waitUntil(
+        caches.keys()
+            .then((keys) => Promise.all(keys.filter((key) => key !== CACHE).map((key) => caches.delete(key))))
+            .then(() => self.clients.claim())
+    );
+});
+
+self.addEventListener("fetch", (event) => {
+    if (event.request.method !== "GET") {
+        return;
+    }
+    if (event.request.mode === "navigate") {
+        event.respondWith(fetch(event.request).catch(() => caches.match("/")));
+        return;
+    }
+    event.respondWith(caches.match(event.request).then((res) => res || fetch(event.request)));
+});
+"#;
+
+/// Configuration of the Progressive Web App: the web app manifest and the service worker
+/// generated in the build directory.
+///
+/// It is read from `[pwa]` in `WasmRun.toml` or from `[package.metadata.wasm-run.pwa]` in the
+/// `Cargo.toml` of the frontend:
+///
+/// ```toml
+/// [package.metadata.wasm-run.pwa]
+/// name = "My App"
+/// short_name = "App"
+/// theme_color = "#3367d6"
+/// icons = [{ src = "/icons/192.png", sizes = "192x192", type = "image/png" }]
+/// precache_exclude = ["videos/**"]
+/// ```
+///
+/// Or built by overriding [`BuildArgs::pwa`](crate::BuildArgs::pwa):
+///
+/// ```ignore
+/// fn pwa(&self) -> anyhow::Result<Option<PwaConfig>> {
+///     Ok(Some(
+///         PwaConfig::new("My App")
+///             .theme_color("#3367d6")
+///             .icon(PwaIcon::new("/icons/192.png", "192x192")),
+///     ))
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PwaConfig {
+    /// Name of the application.
+    pub name: String,
+    /// Short name of the application (home screen).
+    pub short_name: Option<String>,
+    /// Description of the application.
+    pub description: Option<String>,
+    /// URL loaded when the application is launched. `/` by default.
+    pub start_url: String,
+    /// Display mode: `fullscreen`, `standalone` (default), `minimal-ui` or `browser`.
+    pub display: String,
+    /// Background color of the splash screen.
+    pub background_color: Option<String>,
+    /// Color of the toolbar of the browser.
+    pub theme_color: Option<String>,
+    /// Icons of the application.
+    pub icons: Vec<PwaIcon>,
+    /// The files matching one of these glob patterns (relative to the build directory) are not
+    /// precached by the service worker.
+    pub precache_exclude: Vec<String>,
+}
+
+/// An icon of the web app manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PwaIcon {
+    /// URL of the icon.
+    pub src: String,
+    /// Sizes of the icon (`192x192`).
+    pub sizes: String,
+    /// MIME type of the icon (`image/png`).
+    pub mime_type: Option<String>,
+}
+
+impl PwaConfig {
+    /// Create a configuration with the defaults of the manifest.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            short_name: None,
+            description: None,
+            start_url: "/".to_string(),
+            display: "standalone".to_string(),
+            background_color: None,
+            theme_color: None,
+            icons: Vec::new(),
+            precache_exclude: Vec::new(),
+        }
+    }
+
+    /// Read the configuration from `[package.metadata.wasm-run.pwa]`. It is `None` if the table
+    /// is missing. The name of the application is the name of the package by default.
+    pub fn from_package(package: &Package) -> Result<Option<Self>> {
+        Self::parse(
+            &package.metadata["wasm-run"]["pwa"],
+            &package.name,
+            "package.metadata.wasm-run.pwa",
+        )
+    }
+
+    /// Read the configuration from `pwa` in `WasmRun.toml` or in the metadata of the frontend
+    /// package (check [`Config`]). It is `None` if the table is missing. The name of the
+    /// application is `package_name` by default.
+    pub fn from_config(config: &Config, package_name: &str) -> Result<Option<Self>> {
+        Self::parse(config.get("pwa"), package_name, "pwa")
+    }
+
+    /// Parse the table `pwa`, named `table` in the errors.
+    fn parse(pwa: &Value, package_name: &str, table: &str) -> Result<Option<Self>> {
+        if pwa.is_null() {
+            return Ok(None);
+        }
+        let pwa = pwa
+            .as_object()
+            .with_context(|| format!("`{}` must be a table", table))?;
+
+        let string = |key: &str| -> Result<Option<String>> {
+            match pwa.get(key) {
+                None => Ok(None),
+                Some(value) => value
+                    .as_str()
+                    .map(|x| Some(x.to_string()))
+                    .with_context(|| format!("`{}.{}` must be a string", table, key)),
+            }
+        };
+
+        let mut config = Self::new(string("name")?.unwrap_or_else(|| package_name.to_string()));
+        config.short_name = string("short_name")?;
+        config.description = string("description")?;
+        if let Some(start_url) = string("start_url")? {
+            config.start_url = start_url;
+        }
+        if let Some(display) = string("display")? {
+            config.display = display;
+        }
+        config.background_color = string("background_color")?;
+        config.theme_color = string("theme_color")?;
+
+        if let Some(icons) = pwa.get("icons") {
+            let error = || {
+                format!(
+                    "`{}.icons` must be an array of tables with `src`, `sizes` and optionally \
+                    `type`",
+                    table
+                )
+            };
+            for icon in icons.as_array().with_context(error)? {
+                let field = |key: &str| icon.get(key).and_then(|x| x.as_str());
+                config.icons.push(PwaIcon {
+                    src: field("src").with_context(error)?.to_string(),
+                    sizes: field("sizes").with_context(error)?.to_string(),
+                    mime_type: field("type").map(|x| x.to_string()),
+                });
+            }
+        }
+
+        if let Some(exclude) = pwa.get("precache_exclude") {
+            let error = || format!("`{}.precache_exclude` must be an array of strings", table);
+            for pattern in exclude.as_array().with_context(error)? {
+                config
+                    .precache_exclude
+                    .push(pattern.as_str().with_context(error)?.to_string());
+            }
+        }
+
+        Ok(Some(config))
+    }
+
+    /// Set the short name of the application.
+    pub fn short_name(mut self, short_name: impl Into<String>) -> Self {
+        self.short_name = Some(short_name.into());
+        self
+    }
+
+    /// Set the description of the application.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the URL loaded when the application is launched.
+    pub fn start_url(mut self, start_url: impl Into<String>) -> Self {
+        self.start_url = start_url.into();
+        self
+    }
+
+    /// Set the display mode.
+    pub fn display(mut self, display: impl Into<String>) -> Self {
+        self.display = display.into();
+        self
+    }
+
+    /// Set the background color of the splash screen.
+    pub fn background_color(mut self, color: impl Into<String>) -> Self {
+        self.background_color = Some(color.into());
+        self
+    }
+
+    /// Set the color of the toolbar of the browser.
+    pub fn theme_color(mut self, color: impl Into<String>) -> Self {
+        self.theme_color = Some(color.into());
+        self
+    }
+
+    /// Add an icon.
+    pub fn icon(mut self, icon: PwaIcon) -> Self {
+        self.icons.push(icon);
+        self
+    }
+
+    /// Do not precache the files matching this glob pattern.
+    pub fn precache_exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.precache_exclude.push(pattern.into());
+        self
+    }
+
+    /// Content of the web app manifest.
+    pub fn manifest(&self) -> Value {
+        let mut manifest = Map::new();
+        manifest.insert("name".to_string(), json!(self.name));
+        let optional = [
+            ("short_name", &self.short_name),
+            ("description", &self.description),
+            ("background_color", &self.background_color),
+            ("theme_color", &self.theme_color),
+        ];
+        for (key, value) in optional.iter() {
+            if let Some(value) = value {
+                manifest.insert(key.to_string(), json!(value));
+            }
+        }
+        manifest.insert("start_url".to_string(), json!(self.start_url));
+        manifest.insert("display".to_string(), json!(self.display));
+        let icons = self
+            .icons
+            .iter()
+            .map(|x| {
+                let mut icon = json!({ "src": x.src, "sizes": x.sizes });
+                if let Some(mime_type) = &x.mime_type {
+                    icon["type"] = json!(mime_type);
+                }
+                icon
+            })
+            .collect();
+        manifest.insert("icons".to_string(), Value::Array(icons));
+
+        Value::Object(manifest)
+    }
+
+    /// Tags added to the `<head>` of the default `index.html` to link the manifest and register
+    /// the service worker.
+    pub(crate) fn head_tags(&self) -> String {
+        let mut tags = format!(r#"<link rel="manifest" href="/{}"/>"#, MANIFEST);
+        if let Some(color) = &self.theme_color {
+            tags.push_str(&format!(
+                r#"<meta name="theme-color" content="{}"/>"#,
+                color
+            ));
+        }
+        tags.push_str(&format!(
+            r#"<script>if ("serviceWorker" in navigator) navigator.serviceWorker.register("/{}");</script>"#,
+            SERVICE_WORKER
+        ));
+        tags
+    }
+}
+
+impl PwaIcon {
+    /// Create an icon. The MIME type is left to the browser.
+    pub fn new(src: impl Into<String>, sizes: impl Into<String>) -> Self {
+        Self {
+            src: src.into(),
+            sizes: sizes.into(),
+            mime_type: None,
+        }
+    }
+
+    /// Set the MIME type of the icon.
+    pub fn mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+}
+
+/// Write the web app manifest and the service worker to the build directory. The service worker
+/// precaches all the files of the build directory; its cache is named after the fingerprint of
+/// their content ([`BuildArgs::fingerprint`]) so a new build replaces the cache of the previous
+/// one.
+pub(crate) fn generate(args: &dyn BuildArgs, config: &PwaConfig) -> Result<()> {
+    let build_path = args.build_path();
+
+    let manifest_path = build_path.join(MANIFEST);
+    crate::generated::write_json(&manifest_path, &config.manifest(), true)?;
+
+    let mut exclude = GlobSetBuilder::new();
+    for pattern in &config.precache_exclude {
+        exclude.add(Glob::new(pattern).with_context(|| {
+            format!("invalid glob pattern `{}` in `precache_exclude`", pattern)
+        })?);
+    }
+    let exclude = exclude.build()?;
+
+    let files = crate::generated::files(build_path)
+        .with_context(|| format!("could not list `{}`", build_path))?;
+
+    let mut hasher = args.fingerprint()?.hasher();
+    let mut urls = vec!["/".to_string()];
+    for (relative, file) in files {
+        // NOTE: the file of the redirects is read by the hosting, it is not served
+        if relative == SERVICE_WORKER
+            || relative == crate::redirects::REDIRECTS_FILE
+            || exclude.is_match(&relative)
+        {
+            continue;
+        }
+        let url = format!("/{}", relative);
+        hasher.update(url.as_bytes());
+        hasher.update(&fs::read(&file).with_context(|| format!("could not read `{}`", file))?);
+        urls.push(url);
+    }
+
+    let service_worker = SERVICE_WORKER_TEMPLATE
+        .replace("__VERSION__", &hasher.finish())
+        .replace("__FILES__", &serde_json::to_string(&urls)?);
+    let service_worker_path = build_path.join(SERVICE_WORKER);
+    crate::generated::write(&service_worker_path, &service_worker)?;
+
+    log::info!(
+        "Generated the web app manifest and a service worker precaching {} file(s)",
+        urls.len() - 1
+    );
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use crate::{assets, BuildArgs, BuildProfile};
+use anyhow::Result;
+use std::path::Path;
+
+/// Stage of the build of the frontend fed by a changed file. A change that only feeds one of the
+/// last stages of the pipeline doesn't need cargo, wasm-bindgen and wasm-opt again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stage {
+    /// The SASS and SCSS files are transpiled again.
+    #[cfg(feature = "sass")]
+    Styles,
+    /// The translations are compiled again.
+    #[cfg(feature = "i18n")]
+    Translations,
+    /// The static files are copied again.
+    Assets,
+    /// The whole pipeline: cargo, wasm-bindgen, wasm-opt and the hooks.
+    Full,
+}
+
+impl Stage {
+    /// The stage fed by the file `path`. The whole pipeline is used when the other stages rewrite
+    /// the files of the build directory afterwards (fingerprints, critical CSS, service worker).
+    pub(crate) fn of(args: &dyn BuildArgs, profile: BuildProfile, path: &Path) -> Self {
+        let rewritten = args.fingerprint_assets(profile)
+            || !matches!(args.critical_css(), Ok(None))
+            || !matches!(args.pwa(), Ok(None));
+        if rewritten {
+            return Self::Full;
+        }
+
+        let frontend_dir = args.frontend_package().manifest_path.parent().unwrap();
+
+        #[cfg(feature = "sass")]
+        if matches!(
+            path.extension().and_then(|x| x.to_str()),
+            Some("sass") | Some("scss")
+        ) && args
+            .sass_lookup_directories(profile)
+            .iter()
+            .any(|x| path.starts_with(x))
+        {
+            return Self::Styles;
+        }
+
+        #[cfg(feature = "i18n")]
+        if path.starts_with(frontend_dir.join(args.i18n_options(profile).source)) {
+            return Self::Translations;
+        }
+
+        // NOTE: the index.html written by the full pipeline is not a plain copy
+        if path.file_name().is_some_and(|x| x != "index.html")
+            && args
+                .asset_rules()
+                .iter()
+                .any(|x| path.starts_with(frontend_dir.join(&x.source)))
+        {
+            return Self::Assets;
+        }
+
+        Self::Full
+    }
+
+    /// Run the stage on the existing build directory. The whole pipeline is run by `build_with`
+    /// instead.
+    #[cfg_attr(not(any(feature = "sass", feature = "i18n")), allow(unused_variables))]
+    pub(crate) fn run(self, args: &dyn BuildArgs, profile: BuildProfile) -> Result<()> {
+        match self {
+            #[cfg(feature = "sass")]
+            Self::Styles => {
+                let options = args.sass_options(profile);
+                for style_path in args.sass_lookup_directories(profile) {
+                    args.build_sass_from_dir(&style_path, options.clone())?;
+                }
+            }
+            #[cfg(feature = "i18n")]
+            Self::Translations => crate::i18n::compile(args, &args.i18n_options(profile))?,
+            Self::Assets => assets::copy_assets(args)?,
+            Self::Full => unreachable!("the whole pipeline is run by `build_with`"),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "sass")]
+            Self::Styles => "styles",
+            #[cfg(feature = "i18n")]
+            Self::Translations => "translations",
+            Self::Assets => "assets",
+            Self::Full => "full",
+        }
+    }
+}
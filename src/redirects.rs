@@ -0,0 +1,305 @@
+use crate::BuildArgs;
+use anyhow::Result;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+/// File of the redirects in the build directory, read by Netlify and Cloudflare Pages.
+pub(crate) const REDIRECTS_FILE: &str = "_redirects";
+
+/// First line of the files `_redirects` generated from [`BuildArgs::redirects`].
+const GENERATED_HEADER: &str = "# Generated by wasm-run from `redirects`";
+
+/// A redirect or a rewrite of the path of a request.
+///
+/// It is written `<from> -> <to> [status]` like `/old -> /new` or
+/// `/docs/* -> https://docs.example.com/* 302`. A `*` at the end of `<from>` matches the rest of
+/// the path, which replaces the `*` of `<to>`. The status is `301` (permanent redirect) by default;
+/// `200` rewrites the path of the request without redirecting the browser.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    /// Path matched (it can end with `*`).
+    pub from: String,
+    /// Path or URL of the destination (the `*` is replaced by the rest of the path).
+    pub to: String,
+    /// HTTP status: `301`, `302`, `303`, `307`, `308` or `200` for a rewrite.
+    pub status: u16,
+}
+
+impl Redirect {
+    /// Destination of a request to `path` if it matches this redirect.
+    pub fn target(&self, path: &str) -> Option<String> {
+        match self.from.strip_suffix('*') {
+            Some(prefix) => path
+                .strip_prefix(prefix)
+                .map(|rest| self.to.replacen('*', rest, 1)),
+            None if path == self.from => Some(self.to.clone()),
+            None => None,
+        }
+    }
+
+    /// The path is rewritten instead of redirecting the browser.
+    pub fn is_rewrite(&self) -> bool {
+        self.status == 200
+    }
+
+    /// Rule of the file `_redirects` of Netlify and Cloudflare Pages.
+    pub fn to_netlify(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.from,
+            self.to.replacen('*', ":splat", 1),
+            self.status
+        )
+    }
+}
+
+impl FromStr for Redirect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, rest) = s
+            .split_once("->")
+            .ok_or_else(|| format!("invalid redirect `{}`: expected `<from> -> <to>`", s))?;
+        let from = from.trim();
+        let mut rest = rest.split_whitespace();
+        let to = rest
+            .next()
+            .ok_or_else(|| format!("invalid redirect `{}`: missing destination", s))?;
+        let status = match rest.next() {
+            Some(status) => status
+                .parse()
+                .map_err(|_| format!("invalid redirect `{}`: invalid status", s))?,
+            None => 301,
+        };
+
+        if !from.starts_with('/') {
+            return Err(format!(
+                "invalid redirect `{}`: the path must start with `/`",
+                s
+            ));
+        }
+        if from.strip_suffix('*').unwrap_or(from).contains('*') {
+            return Err(format!(
+                "invalid redirect `{}`: `*` is only allowed at the end of the path",
+                s
+            ));
+        }
+        if !matches!(status, 200 | 301 | 302 | 303 | 307 | 308) {
+            return Err(format!(
+                "invalid redirect `{}`: unsupported status {}",
+                s, status
+            ));
+        }
+        if status == 200 && !to.starts_with('/') {
+            return Err(format!(
+                "invalid redirect `{}`: a rewrite must target a local path",
+                s
+            ));
+        }
+        if let Some(extra) = rest.next() {
+            return Err(format!("invalid redirect `{}`: unexpected `{}`", s, extra));
+        }
+
+        Ok(Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            status,
+        })
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {} {}", self.from, self.to, self.status)
+    }
+}
+
+/// Write the redirects to the file `_redirects` of the build directory so the hosting applies the
+/// same redirects as the development server. A `_redirects` provided by the assets or by a hook
+/// is kept; the file generated by a previous build is removed if there is no redirect anymore.
+pub(crate) fn write(args: &dyn BuildArgs, redirects: &[Redirect]) -> Result<()> {
+    let path = args.build_path().join(REDIRECTS_FILE);
+    let is_generated = match fs::read_to_string(&path) {
+        Ok(contents) => contents.starts_with(GENERATED_HEADER),
+        Err(_) => !path.exists(),
+    };
+    if !is_generated {
+        if !redirects.is_empty() {
+            log::warn!(
+                "The redirects are not written to `{}`: the file is provided by the assets or by \
+                a hook",
+                path
+            );
+        }
+        return Ok(());
+    }
+
+    if redirects.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        return Ok(());
+    }
+
+    crate::generated::write(&path, &netlify_file(redirects))
+}
+
+/// Contents of the file `_redirects` of the redirects (check [`Redirect::to_netlify`]).
+fn netlify_file(redirects: &[Redirect]) -> String {
+    let mut contents = GENERATED_HEADER.to_string();
+    for redirect in redirects {
+        contents.push('\n');
+        contents.push_str(&redirect.to_netlify());
+    }
+    contents
+}
+
+/// Middleware of the development server that applies the redirects before the routing.
+#[cfg(feature = "dev-server")]
+pub(crate) struct RedirectMiddleware(pub(crate) Vec<Redirect>);
+
+#[cfg(feature = "dev-server")]
+#[tide::utils::async_trait]
+impl tide::Middleware<()> for RedirectMiddleware {
+    async fn handle(&self, mut req: tide::Request<()>, next: tide::Next<'_, ()>) -> tide::Result {
+        let path = req.url().path().to_string();
+        let (redirect, target) = match self
+            .0
+            .iter()
+            .find_map(|x| x.target(&path).map(|target| (x, target)))
+        {
+            Some(found) => found,
+            None => return Ok(next.run(req).await),
+        };
+
+        if redirect.is_rewrite() {
+            log::debug!("Rewrite {} to {}", path, target);
+            let inner: &mut tide::http::Request = req.as_mut();
+            inner.url_mut().set_path(&target);
+            return Ok(next.run(req).await);
+        }
+
+        let mut location = target;
+        if let Some(query) = req.url().query() {
+            if !location.contains('?') {
+                location.push('?');
+                location.push_str(query);
+            }
+        }
+
+        Ok(tide::Response::builder(redirect.status)
+            .header("Location", location)
+            .build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirect(s: &str) -> Redirect {
+        s.parse().unwrap()
+    }
+
+    fn error(s: &str) -> String {
+        s.parse::<Redirect>().unwrap_err()
+    }
+
+    #[test]
+    fn parse_redirects() {
+        assert_eq!(
+            redirect("/old -> /new"),
+            Redirect {
+                from: "/old".to_string(),
+                to: "/new".to_string(),
+                status: 301,
+            }
+        );
+        assert_eq!(
+            redirect("  /docs/*->https://docs.example.com/*   302 "),
+            Redirect {
+                from: "/docs/*".to_string(),
+                to: "https://docs.example.com/*".to_string(),
+                status: 302,
+            }
+        );
+        assert!(redirect("/app/* -> /index.html 200").is_rewrite());
+        assert_eq!(redirect("/café -> /cafe").from, "/café");
+    }
+
+    #[test]
+    fn parse_invalid_redirects() {
+        assert_eq!(
+            error("/old /new"),
+            "invalid redirect `/old /new`: expected `<from> -> <to>`"
+        );
+        assert_eq!(
+            error("/old ->  "),
+            "invalid redirect `/old ->  `: missing destination"
+        );
+        assert_eq!(
+            error("/old -> /new permanent"),
+            "invalid redirect `/old -> /new permanent`: invalid status"
+        );
+        assert_eq!(
+            error("old -> /new"),
+            "invalid redirect `old -> /new`: the path must start with `/`"
+        );
+        assert_eq!(
+            error("/*/old -> /new"),
+            "invalid redirect `/*/old -> /new`: `*` is only allowed at the end of the path"
+        );
+        assert_eq!(
+            error("/old -> /new 404"),
+            "invalid redirect `/old -> /new 404`: unsupported status 404"
+        );
+        assert_eq!(
+            error("/old -> https://example.com 200"),
+            "invalid redirect `/old -> https://example.com 200`: a rewrite must target a local \
+            path"
+        );
+        assert_eq!(
+            error("/old -> /new 302 now"),
+            "invalid redirect `/old -> /new 302 now`: unexpected `now`"
+        );
+    }
+
+    #[test]
+    fn target() {
+        assert_eq!(
+            redirect("/old -> /new").target("/old"),
+            Some("/new".to_string())
+        );
+        assert_eq!(redirect("/old -> /new").target("/old/page"), None);
+        let splat = redirect("/docs/* -> https://docs.example.com/*");
+        assert_eq!(
+            splat.target("/docs/a/b"),
+            Some("https://docs.example.com/a/b".to_string())
+        );
+        assert_eq!(
+            splat.target("/docs/"),
+            Some("https://docs.example.com/".to_string())
+        );
+        assert_eq!(splat.target("/doc"), None);
+    }
+
+    #[test]
+    fn display_and_netlify() {
+        let splat = redirect("/docs/* -> /guide/*");
+        assert_eq!(splat.to_string(), "/docs/* -> /guide/* 301");
+        assert_eq!(redirect(&splat.to_string()), splat);
+        assert_eq!(splat.to_netlify(), "/docs/* /guide/:splat 301");
+    }
+
+    #[test]
+    fn netlify_file_of_redirects() {
+        assert_eq!(
+            netlify_file(&[
+                redirect("/old -> /new"),
+                redirect("/app/* -> /index.html 200")
+            ]),
+            "# Generated by wasm-run from `redirects`\n/old /new 301\n/app/* /index.html 200"
+        );
+    }
+}
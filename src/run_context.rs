@@ -0,0 +1,101 @@
+use crate::{BuildArgs, BuildProfile, ServeArgs};
+use camino::Utf8Path;
+use cargo_metadata::{Metadata, Package};
+use std::fmt;
+
+/// Information about the `serve` command given to its hooks (`run_serve_init`, `run_serve`,
+/// `run_frontend_watch`, `run_backend_watch` and `run_backend_command`).
+///
+/// The hooks of the macro [`main`](crate::main) receive a context with the arguments of the
+/// command (`RunContext<DefaultServeArgs>` by default):
+///
+/// ```ignore
+/// fn run_backend_watch(
+///     context: &RunContext<DefaultServeArgs>,
+///     watcher: &mut FileWatcher,
+/// ) -> anyhow::Result<()> {
+///     if context.profile() == BuildProfile::Profiling {
+///         watcher.watch(context.metadata().workspace_root.join("flamegraphs"), Recursive)?;
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct RunContext<'a, A: ?Sized + 'a = dyn ServeArgs> {
+    args: &'a A,
+    profile: BuildProfile,
+}
+
+impl<'a, A: ?Sized> Clone for RunContext<'a, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, A: ?Sized> Copy for RunContext<'a, A> {}
+
+impl<'a, A: ?Sized> fmt::Debug for RunContext<'a, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RunContext")
+            .field("profile", &self.profile)
+            .finish()
+    }
+}
+
+impl<'a> RunContext<'a> {
+    /// The same context with the concrete type of the arguments, if they are of this type.
+    pub fn downcast<A: ServeArgs>(&self) -> Option<RunContext<'a, A>> {
+        Some(RunContext {
+            args: self.args.downcast_ref::<A>()?,
+            profile: self.profile,
+        })
+    }
+}
+
+impl<'a, A: ServeArgs + ?Sized> RunContext<'a, A> {
+    /// Context of the `serve` command run with these arguments. The profile is
+    /// [`BuildProfile::Profiling`] if [`BuildArgs::profiling`] is enabled and
+    /// [`ServeArgs::profile`] otherwise, like the builds of the command.
+    pub fn from_args(args: &'a A) -> Self {
+        let profile = if args.build_args().profiling() {
+            BuildProfile::Profiling
+        } else {
+            args.profile()
+        };
+        Self { args, profile }
+    }
+
+    /// Arguments of the command.
+    pub fn args(&self) -> &'a A {
+        self.args
+    }
+
+    /// Arguments of the builds of the command.
+    pub fn build_args(&self) -> &'a dyn BuildArgs {
+        self.args.build_args()
+    }
+
+    /// Profile of the builds of the frontend.
+    pub fn profile(&self) -> BuildProfile {
+        self.profile
+    }
+
+    /// Build directory.
+    pub fn build_path(&self) -> &'a Utf8Path {
+        self.build_args().build_path()
+    }
+
+    /// Metadata of the workspace.
+    pub fn metadata(&self) -> &'a Metadata {
+        self.build_args().metadata()
+    }
+
+    /// Frontend package.
+    pub fn frontend_package(&self) -> &'a Package {
+        self.build_args().frontend_package()
+    }
+
+    /// Backend package, if any.
+    pub fn backend_package(&self) -> Option<&'a Package> {
+        self.build_args().backend_package()
+    }
+}
@@ -0,0 +1,419 @@
+#[cfg(feature = "dev-server")]
+use crate::WatcherKind;
+use crate::{Hooks, ServeArgs};
+use anyhow::{anyhow, Result};
+use std::cell::RefCell;
+use std::fmt;
+#[cfg(feature = "dev-server")]
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Interval between two checks of the shutdown and of the rebuild requests while waiting for a
+/// change.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time given to the file watcher to report a change after its debounce delay.
+#[cfg(feature = "dev-server")]
+const REPORT_MARGIN: Duration = Duration::from_millis(500);
+
+thread_local! {
+    static CURRENT: RefCell<Option<Control>> = const { RefCell::new(None) };
+}
+
+/// Control of the watchers started by [`ServeArgs::start`]: their shutdown, the rebuilds
+/// requested by [`ServeHandle`] and the builds in progress.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Control(Arc<Shared>);
+
+#[derive(Debug, Default)]
+struct Shared {
+    shutdown: AtomicBool,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Rebuilds requested and rebuilds started by the watchers for these requests.
+    requested: u64,
+    picked: u64,
+    /// Builds started and finished (or cancelled) since the start of the watchers.
+    started: u64,
+    finished: u64,
+    /// Error of the last build that was not cancelled.
+    error: Option<String>,
+    /// Last change of a watched file noticed (not reported yet) by a file watcher.
+    last_change: Option<Instant>,
+    /// Watchers that watch the changes.
+    watching: usize,
+}
+
+impl Control {
+    fn request_shutdown(&self) {
+        self.0.shutdown.store(true, Ordering::SeqCst);
+    }
+
+    fn is_shut_down(&self) -> bool {
+        self.0.shutdown.load(Ordering::SeqCst)
+    }
+
+    /// Use this control for the watchers and the builds run by the current thread.
+    pub(crate) fn install(self) {
+        CURRENT.with(|x| x.replace(Some(self)));
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        f(&mut self.0.state.lock().unwrap());
+        self.0.changed.notify_all();
+    }
+
+    /// Take a rebuild request, if any. The rebuild is considered started.
+    fn take_request(&self) -> bool {
+        let mut state = self.0.state.lock().unwrap();
+        if state.picked < state.requested {
+            // NOTE: the build is started with the request so `wait_idle` cannot see the request
+            //       taken before the build started
+            state.picked += 1;
+            state.started += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Wait until `count` watchers watch the changes or one of `threads` stopped.
+    fn wait_watching(&self, count: usize, threads: &[thread::JoinHandle<Result<()>>]) {
+        let mut state = self.0.state.lock().unwrap();
+        while state.watching < count && !threads.iter().any(|x| x.is_finished()) {
+            state = self.0.changed.wait_timeout(state, POLL_INTERVAL).unwrap().0;
+        }
+    }
+
+    /// Wait until no build runs and the changes made before `since` have been reported by the
+    /// file watchers: they report them after `quiet`. Returns the error of the last build.
+    #[cfg(feature = "dev-server")]
+    fn wait_idle(&self, since: Instant, quiet: Duration, request: Option<u64>) -> Result<()> {
+        let mut state = self.0.state.lock().unwrap();
+        loop {
+            if self.is_shut_down() {
+                return Err(anyhow!("the server has been shut down"));
+            }
+            let last = state.last_change.map_or(since, |x| x.max(since));
+            let remaining = quiet.saturating_sub(last.elapsed());
+            let idle = state.started == state.finished
+                && request.is_none_or(|x| state.picked >= x)
+                && remaining.is_zero();
+            if idle {
+                return match &state.error {
+                    Some(err) => Err(anyhow!("{}", err)),
+                    None => Ok(()),
+                };
+            }
+            let timeout = if remaining.is_zero() {
+                POLL_INTERVAL
+            } else {
+                remaining
+            };
+            state = self.0.changed.wait_timeout(state, timeout).unwrap().0;
+        }
+    }
+}
+
+/// The control of the watchers run by the current thread, if they have been started by a
+/// [`ServeHandle`].
+pub(crate) fn current() -> Option<Control> {
+    CURRENT.with(|x| x.borrow().clone())
+}
+
+/// Whether the watchers of the current thread must stop.
+pub(crate) fn is_shut_down() -> bool {
+    current().is_some_and(|x| x.is_shut_down())
+}
+
+/// The watcher of the current thread watches the changes.
+pub(crate) fn watching() {
+    if let Some(control) = current() {
+        control.update(|state| state.watching += 1);
+    }
+}
+
+/// The file watcher of the current thread noticed a change of a watched file: it is reported
+/// after its debounce delay.
+pub(crate) fn change_noticed() {
+    if let Some(control) = current() {
+        control.update(|state| state.last_change = Some(Instant::now()));
+    }
+}
+
+/// A build of the current thread started.
+pub(crate) fn build_started() {
+    if let Some(control) = current() {
+        control.update(|state| state.started += 1);
+    }
+}
+
+/// A build of the current thread finished with `res`.
+pub(crate) fn build_finished(res: &Result<()>) {
+    if let Some(control) = current() {
+        let cancelled = crate::cancel::is_cancelled(res);
+        control.update(|state| {
+            state.finished += 1;
+            if !cancelled {
+                state.error = res.as_ref().err().map(|err| format!("{:#}", err));
+            }
+        });
+    }
+}
+
+/// What the watcher of the current thread must do next.
+pub(crate) enum Next<T> {
+    /// A message of the file watcher (an error if the watcher stopped).
+    Message(Result<T, mpsc::RecvError>),
+    /// A rebuild requested by [`ServeHandle::rebuild`]. It is considered started.
+    Rebuild,
+    /// Stop, the [`ServeHandle`] is shut down.
+    Stop,
+}
+
+/// Wait for the next message of `rx`, the shutdown or, if `rebuild` is set, a rebuild request.
+pub(crate) fn recv<T>(rx: &mpsc::Receiver<T>, rebuild: bool) -> Next<T> {
+    let Some(control) = current() else {
+        return Next::Message(rx.recv());
+    };
+    loop {
+        if control.is_shut_down() {
+            return Next::Stop;
+        }
+        if rebuild && control.take_request() {
+            return Next::Rebuild;
+        }
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(message) => return Next::Message(Ok(message)),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Next::Message(Err(mpsc::RecvError))
+            }
+        }
+    }
+}
+
+/// Take a rebuild requested by [`ServeHandle::rebuild`] for the watchers of the current thread,
+/// if any. The rebuild is considered started.
+pub(crate) fn take_request() -> bool {
+    current().is_some_and(|x| x.take_request())
+}
+
+/// The `serve` command running in the background, returned by [`ServeArgs::start`] (and by
+/// `Cli::serve()` of the macro [`main`](crate::main)).
+///
+/// It is shut down with [`ServeHandle::shutdown`] or when it is dropped: the development server
+/// stops listening, the watchers stop and the backend is stopped.
+///
+/// ```ignore
+/// let server = Cli::serve_with_args(&["--bind-random-port"])?;
+/// let index = reqwest::blocking::get(server.url())?.text()?;
+/// assert!(index.contains("app.js"));
+/// server.shutdown()?;
+/// ```
+pub struct ServeHandle {
+    #[cfg(feature = "dev-server")]
+    addr: SocketAddr,
+    #[cfg(feature = "dev-server")]
+    server: Option<async_std::task::JoinHandle<Result<()>>>,
+    control: Control,
+    /// Time for a change to be reported by the file watcher.
+    #[cfg(feature = "dev-server")]
+    quiet: Duration,
+    threads: Vec<thread::JoinHandle<Result<()>>>,
+}
+
+impl fmt::Debug for ServeHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("ServeHandle");
+        #[cfg(feature = "dev-server")]
+        debug.field("addr", &self.addr);
+        debug.finish()
+    }
+}
+
+impl ServeHandle {
+    /// Address of the development server. The port is the one actually bound (with
+    /// `--bind-random-port` or `--auto-port`).
+    #[cfg(feature = "dev-server")]
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// URL of the development server (on `localhost` if it listens on all the interfaces).
+    #[cfg(feature = "dev-server")]
+    pub fn url(&self) -> String {
+        if self.addr.ip().is_unspecified() {
+            format!("http://localhost:{}", self.addr.port())
+        } else {
+            format!("http://{}", self.addr)
+        }
+    }
+
+    /// Rebuild the frontend (like a change of a file of the workspace) and wait for the end of
+    /// the build. Returns the error of the build if it failed.
+    ///
+    /// ```ignore
+    /// let server = Cli::serve_with_args(&["--bind-random-port"])?;
+    /// fs::write("frontend/static/index.html", "<h1>Changed</h1>")?;
+    /// async_std::task::block_on(server.wait_idle())?;
+    /// assert!(get(server.url())?.contains("Changed"));
+    ///
+    /// async_std::task::block_on(server.rebuild())?;
+    /// ```
+    #[cfg(feature = "dev-server")]
+    pub async fn rebuild(&self) -> Result<()> {
+        let control = self.control.clone();
+        let mut request = 0;
+        control.update(|state| {
+            state.requested += 1;
+            request = state.requested;
+        });
+        async_std::task::spawn_blocking(move || {
+            control.wait_idle(Instant::now(), Duration::ZERO, Some(request))
+        })
+        .await
+    }
+
+    /// Wait until the changes made before the call have been handled: the file watcher reported
+    /// them (after its debounce delay) and no build is running. Returns the error of the last
+    /// build if it failed.
+    #[cfg(feature = "dev-server")]
+    pub async fn wait_idle(&self) -> Result<()> {
+        let control = self.control.clone();
+        let (since, quiet) = (Instant::now(), self.quiet);
+        async_std::task::spawn_blocking(move || control.wait_idle(since, quiet, None)).await
+    }
+
+    /// Stop the development server, the watchers and the backend and wait for them. Returns the
+    /// error of the server or of a watcher if one of them stopped on error.
+    pub fn shutdown(mut self) -> Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.control.request_shutdown();
+        let mut res = Ok(());
+        #[cfg(feature = "dev-server")]
+        if let Some(server) = self.server.take() {
+            if let Some(Err(err)) = async_std::task::block_on(server.cancel()) {
+                res = Err(err);
+            }
+        }
+        for thread in self.threads.drain(..) {
+            let outcome = thread
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("a watcher panicked")));
+            if res.is_ok() {
+                res = outcome;
+            }
+        }
+        res
+    }
+}
+
+impl Drop for ServeHandle {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop() {
+            log::error!("{:#}", err);
+        }
+    }
+}
+
+/// Build the frontend and start the development server (or the backend without the feature
+/// `dev-server`) and the watchers in the background.
+pub(crate) fn start<A: ServeArgs + Sync + 'static>(
+    args: A,
+    hooks: Arc<Hooks>,
+) -> Result<ServeHandle> {
+    let control = Control::default();
+    #[cfg(feature = "dev-server")]
+    let quiet = {
+        let watcher_config = args.watcher_config();
+        let delay = match watcher_config.kind {
+            WatcherKind::Poll => watcher_config.delay + watcher_config.poll_interval,
+            _ => watcher_config.delay,
+        };
+        delay + REPORT_MARGIN
+    };
+
+    #[cfg(feature = "dev-server")]
+    if let Some(id) = args.history_build() {
+        crate::history::restore(args.build_args(), id)?;
+        let (addr, server) = crate::serve_frontend(&args, &hooks)?;
+        return Ok(ServeHandle {
+            addr,
+            server: Some(async_std::task::spawn(server)),
+            control,
+            quiet,
+            threads: Vec::new(),
+        });
+    }
+
+    let (_, pending) = crate::build_with(
+        args.profile(),
+        args.build_args(),
+        &hooks,
+        args.background_wasm_opt(),
+        false,
+    )?;
+    let args = Arc::new(args);
+    if args.watch() {
+        crate::spawn_watch_tasks(&args, &hooks, Some(&control))?;
+    }
+
+    #[cfg(feature = "dev-server")]
+    {
+        let (addr, server) = crate::serve_frontend(&*args, &hooks)?;
+        let server = async_std::task::spawn(server);
+        let watcher = spawn(&control, move || {
+            crate::watch_frontend(&*args, &hooks, pending)
+        });
+        // NOTE: the changes made after the start must be seen by the watcher
+        control.wait_watching(1, std::slice::from_ref(&watcher));
+        Ok(ServeHandle {
+            addr,
+            server: Some(server),
+            control,
+            quiet,
+            threads: vec![watcher],
+        })
+    }
+    #[cfg(not(feature = "dev-server"))]
+    {
+        if args.build_args().backend_package().is_none() {
+            anyhow::bail!("missing backend crate name");
+        }
+
+        let frontend = {
+            let args = Arc::clone(&args);
+            let hooks = Arc::clone(&hooks);
+            spawn(&control, move || {
+                crate::watch_frontend(&*args, &hooks, pending)
+            })
+        };
+        let backend = spawn(&control, move || crate::watch_backend(&*args, &hooks));
+        let threads = vec![frontend, backend];
+        control.wait_watching(2, &threads);
+        Ok(ServeHandle { control, threads })
+    }
+}
+
+/// Run the watcher `f` on a new thread controlled by `control`.
+pub(crate) fn spawn<T: Send + 'static>(
+    control: &Control,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> thread::JoinHandle<T> {
+    let control = control.clone();
+    thread::spawn(move || {
+        control.install();
+        f()
+    })
+}
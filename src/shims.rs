@@ -0,0 +1,62 @@
+use tide::Server;
+
+pub(crate) const SHIMS_PATH: &str = "/__wasm_run/shims.js";
+
+pub(crate) const SCRIPT: &str = r#"<script src="/__wasm_run/shims.js"></script>"#;
+
+const SHIM_JS: &str = r#"(function () {
+    function shim(path) {
+        const children = new Map();
+        const target = function () {
+            console.warn(`[wasm-run] ${path}() called: this is a development shim of a global provided by the host page`);
+        };
+        return new Proxy(target, {
+            get(target, key) {
+                if (typeof key === "symbol" || key === "then" || key in target) {
+                    return target[key];
+                }
+                if (!children.has(key)) {
+                    children.set(key, shim(`${path}.${key}`));
+                }
+                return children.get(key);
+            },
+        });
+    }
+    for (const path of GLOBALS) {
+        let object = window;
+        let name = "";
+        for (const key of path.split(".")) {
+            name = name ? `${name}.${key}` : key;
+            if (object[key] === undefined) {
+                console.warn(`[wasm-run] \`${name}\` is not provided by the host page: using a development shim`);
+                object[key] = shim(name);
+            }
+            object = object[key];
+        }
+    }
+})();
+"#;
+
+/// Insert the script of the shims in the `index.html`, before its first script so the globals
+/// exist when the app starts.
+pub(crate) fn inject(html: &mut String) {
+    match html.find("<script").or_else(|| html.find("</head>")) {
+        Some(i) => html.insert_str(i, SCRIPT),
+        None => html.insert_str(0, SCRIPT),
+    }
+}
+
+pub(crate) fn register(server: &mut Server<()>, globals: Vec<String>) {
+    let js = SHIM_JS.replace(
+        "GLOBALS",
+        &serde_json::to_string(&globals).expect("strings can be serialized; qed"),
+    );
+    server.at(SHIMS_PATH).get(move |_| {
+        let js = js.clone();
+        async move {
+            let mut res = tide::Response::from(js);
+            res.set_content_type(tide::http::mime::JAVASCRIPT);
+            Ok(res)
+        }
+    });
+}
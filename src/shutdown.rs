@@ -0,0 +1,147 @@
+use crate::WorkspaceContext;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the handlers have been installed.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the hook `shutdown` has already run: the process ends only once.
+static DONE: AtomicBool = AtomicBool::new(false);
+
+/// The reason of the end of the process given to the hook `shutdown`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ShutdownReason {
+    /// The command finished, successfully or not.
+    Exit,
+    /// The command was interrupted with Ctrl-C (`SIGINT`, or Ctrl-Break on Windows).
+    Interrupted,
+    /// The command was terminated (`SIGTERM` or `SIGHUP`, or its console was closed on
+    /// Windows).
+    Terminated,
+}
+
+impl ShutdownReason {
+    /// Exit code of the process after the hook, like a shell reports the signals.
+    fn exit_code(self) -> i32 {
+        match self {
+            Self::Exit => 0,
+            Self::Interrupted => 130,
+            Self::Terminated => 143,
+        }
+    }
+}
+
+/// Run the hook `shutdown` of the global context when the process is interrupted or terminated,
+/// then forward the signal to the background processes on Unix. A second interruption exits
+/// immediately.
+pub(crate) fn install() {
+    if !INSTALLED.swap(true, Ordering::SeqCst) {
+        imp::install();
+    }
+}
+
+/// Run the hook `shutdown` of the global context, only once per process.
+pub(crate) fn run(reason: ShutdownReason) {
+    if DONE.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    if let Some(context) = WorkspaceContext::try_global() {
+        let hooks = context.hooks();
+        log::debug!("Running shutdown hook ({:?})", reason);
+        if let Err(err) = crate::call_hook("shutdown", || (hooks.shutdown)(reason)) {
+            log::error!("{:#}", err);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::ShutdownReason;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+    pub(super) fn install() {
+        // NOTE: the signal handlers only write the signal on a socket: the hook runs on a thread
+        let (mut reader, writer) = match UnixStream::pair() {
+            Ok(x) => x,
+            Err(err) => {
+                log::warn!("Could not install the signal handlers: {}", err);
+                return;
+            }
+        };
+        let _ = writer.set_nonblocking(true);
+        let writer = Arc::new(writer);
+
+        for (signal, code) in [(libc::SIGINT, 1), (libc::SIGTERM, 2), (libc::SIGHUP, 2)] {
+            let writer = Arc::clone(&writer);
+            let handler = move || {
+                if RECEIVED.swap(true, Ordering::SeqCst) {
+                    // SAFETY: `_exit` is async-signal-safe
+                    unsafe { libc::_exit(if code == 1 { 130 } else { 143 }) };
+                }
+                let _ = (&*writer).write(&[code]);
+            };
+            // SAFETY: the handler only uses async-signal-safe operations (an atomic, a write on a
+            //         socket and `_exit`)
+            if let Err(err) = unsafe { signal_hook_registry::register(signal, handler) } {
+                log::warn!(
+                    "Could not install the handler of signal {}: {}",
+                    signal,
+                    err
+                );
+            }
+        }
+
+        thread::spawn(move || {
+            let mut code = [0];
+            if reader.read_exact(&mut code).is_ok() {
+                let (reason, signal) = if code[0] == 1 {
+                    (ShutdownReason::Interrupted, libc::SIGINT)
+                } else {
+                    (ShutdownReason::Terminated, libc::SIGTERM)
+                };
+                super::run(reason);
+                // NOTE: the background processes are not in the foreground process group of the
+                //       terminal and they are not dropped by `exit`
+                crate::backend::forward_signal(signal);
+                std::process::exit(reason.exit_code());
+            }
+        });
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::ShutdownReason;
+    use windows_sys::Win32::Foundation::BOOL;
+    use windows_sys::Win32::System::Console::{
+        SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT,
+    };
+
+    /// The handler runs on its own thread.
+    unsafe extern "system" fn handler(event: u32) -> BOOL {
+        let reason = if event == CTRL_C_EVENT || event == CTRL_BREAK_EVENT {
+            ShutdownReason::Interrupted
+        } else {
+            ShutdownReason::Terminated
+        };
+        super::run(reason);
+        std::process::exit(reason.exit_code());
+    }
+
+    pub(super) fn install() {
+        // SAFETY: the handler is a valid function for the lifetime of the process
+        if unsafe { SetConsoleCtrlHandler(Some(handler), 1) } == 0 {
+            log::warn!("Could not install the console control handler");
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    pub(super) fn install() {}
+}
@@ -0,0 +1,122 @@
+use crate::{events, BuildArgs, FileWatcher, ServeArgs, WatchFilter};
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Interval between two checks of the modification times of the sources.
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The monitors waiting for the user to press Enter, whatever their context: the standard input
+/// belongs to the process and is read by a single thread started with the first monitor.
+static SUBSCRIBERS: Lazy<Mutex<Vec<mpsc::Sender<()>>>> = Lazy::new(|| {
+    thread::spawn(|| {
+        for _ in std::io::stdin().lock().lines() {
+            SUBSCRIBERS.lock().unwrap().retain(|x| x.send(()).is_ok());
+        }
+    });
+    Default::default()
+});
+
+pub(crate) fn record_build_started(args: &dyn BuildArgs) {
+    *args.state().build_started.lock().unwrap() = SystemTime::now();
+}
+
+/// Check the sources periodically when the changes are not watched (`--no-watch` or the watcher
+/// stopped): a source modified since the last build is reported in the logs and in the overlay
+/// and `callback` rebuilds when the user presses Enter.
+pub(crate) fn monitor(
+    args: &dyn ServeArgs,
+    watcher: &FileWatcher,
+    filter: &WatchFilter,
+    mut callback: impl FnMut(&Path, bool),
+) {
+    let (tx, rx) = mpsc::channel();
+    SUBSCRIBERS.lock().unwrap().push(tx);
+    let build_args = args.build_args();
+    let mut since = *build_args.state().build_started.lock().unwrap();
+    let mut stale = None;
+
+    loop {
+        if crate::serve_handle::is_shut_down() {
+            return;
+        }
+        let requested = crate::serve_handle::take_request();
+        if requested || rx.recv_timeout(CHECK_INTERVAL).is_ok() {
+            log::info!("Rebuild requested");
+            set_stale(build_args, None);
+            let path = stale
+                .take()
+                .unwrap_or_else(|| build_args.metadata().workspace_root.clone());
+            since = SystemTime::now();
+            if !requested {
+                crate::serve_handle::build_started();
+            }
+            callback(&path, true);
+            continue;
+        }
+
+        if stale.is_some() {
+            continue;
+        }
+        let changed = watcher
+            .watched
+            .iter()
+            .map(|(path, mode)| (path.as_path(), *mode == notify::RecursiveMode::Recursive))
+            .chain(watcher.pending_paths().map(|x| (x, true)))
+            .find_map(|(path, recursive)| changed_since(path, true, recursive, filter, since));
+        if let Some(path) = changed {
+            let message = format!(
+                "The build is out of date: {} changed since the last build (the changes are not \
+                watched). Press Enter to rebuild.",
+                path.display()
+            );
+            log::warn!("{}", message);
+            set_stale(build_args, Some(message));
+            events::emit(build_args, "stale-build", json!({ "path": path }));
+            stale = Some(path);
+        }
+    }
+}
+
+/// The first file in `path` (its entries if `descend` is set, recursively if `recursive` is set)
+/// modified after `since`.
+fn changed_since(
+    path: &Path,
+    descend: bool,
+    recursive: bool,
+    filter: &WatchFilter,
+    since: SystemTime,
+) -> Option<PathBuf> {
+    if filter.is_ignored(path) {
+        return None;
+    }
+    let metadata = fs::metadata(path).ok()?;
+    if metadata.is_file() {
+        return metadata
+            .modified()
+            .ok()
+            .filter(|x| *x > since)
+            .map(|_| path.to_owned());
+    }
+    if !metadata.is_dir() || !descend {
+        return None;
+    }
+
+    fs::read_dir(path)
+        .ok()?
+        .flatten()
+        .find_map(|entry| changed_since(&entry.path(), recursive, recursive, filter, since))
+}
+
+#[cfg(feature = "dev-server")]
+fn set_stale(args: &dyn BuildArgs, message: Option<String>) {
+    args.state().overlay.set_stale(message);
+}
+
+#[cfg(not(feature = "dev-server"))]
+fn set_stale(_args: &dyn BuildArgs, _message: Option<String>) {}
@@ -0,0 +1,37 @@
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "dev-server")]
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The state shared by the builds, the watchers and the development server of the same arguments:
+/// it belongs to their [`WorkspaceContext`](crate::WorkspaceContext) (or to their
+/// [`BuildConfig`](crate::tasks::BuildConfig)) so several projects can be built and served in the
+/// same process without sharing it.
+#[derive(Debug)]
+pub struct State {
+    /// The toolchain has been checked since the last change of the toolchain file or of the lock
+    /// file.
+    pub(crate) toolchain_checked: AtomicBool,
+    /// Start of the last build of the frontend.
+    pub(crate) build_started: Mutex<SystemTime>,
+    /// Messages displayed by the error overlay.
+    #[cfg(feature = "dev-server")]
+    pub(crate) overlay: Arc<crate::overlay::Overlay>,
+    /// Last builds and watch events displayed by the status page.
+    #[cfg(feature = "dev-server")]
+    pub(crate) history: Arc<crate::status_page::History>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            toolchain_checked: AtomicBool::new(false),
+            build_started: Mutex::new(SystemTime::now()),
+            #[cfg(feature = "dev-server")]
+            overlay: Default::default(),
+            #[cfg(feature = "dev-server")]
+            history: Default::default(),
+        }
+    }
+}
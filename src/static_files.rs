@@ -0,0 +1,400 @@
+use crate::fingerprint::ASSET_MANIFEST;
+use crate::overlay::Overlay;
+use async_std::io::{prelude::SeekExt, BufReader, ReadExt, SeekFrom};
+use camino::Utf8Path;
+use serde_json::{json, Value};
+use std::path::Path;
+use tide::http::{mime, Mime};
+use tide::{Body, Request, Response, StatusCode};
+
+/// Encodings of the precompressed files by order of preference: `<file>.br` and `<file>.gz` are
+/// served instead of `<file>` to the browsers that accept them.
+const ENCODINGS: &[(&str, &str)] = &[("br", "br"), ("gzip", "gz")];
+
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Serve a file of the build directory.
+///
+/// The unhashed paths (`app.js`) are resolved to the fingerprinted files (`app-<hash>.js`) with
+/// the manifest of the build directory and a precompressed variant of the file is preferred if
+/// the browser accepts it. The fingerprinted files are cached forever when `preview` is set (the
+/// server runs the release build) as their content can't change.
+///
+/// The requests with a `Range` header (the media elements of the browsers) get the part of the
+/// file requested (`206 Partial Content`). Only one range is supported: the whole file is served
+/// otherwise.
+///
+/// Returns `None` if the file doesn't exist.
+pub(crate) async fn serve(
+    build_path: &Path,
+    path: &str,
+    req: &Request<()>,
+    preview: bool,
+) -> Option<Response> {
+    let manifest = read_manifest(build_path).await;
+    let files = manifest.get("files").and_then(Value::as_object);
+    let resolved = files.and_then(|x| x.get(path)).and_then(Value::as_str);
+    let hashed = files.is_some_and(|x| x.values().any(|x| x.as_str() == Some(path)));
+    let path = resolved.unwrap_or(path);
+
+    let accept_encoding = req
+        .header("Accept-Encoding")
+        .map(|x| x.as_str().to_ascii_lowercase())
+        .unwrap_or_default();
+    let range = req.header("Range").map(|x| x.as_str().to_owned());
+    let mut res = None;
+    // NOTE: the ranges are applied to the uncompressed files
+    for (encoding, extension) in ENCODINGS.iter().filter(|_| range.is_none()) {
+        if !accepts(&accept_encoding, encoding) {
+            continue;
+        }
+        if let Ok(body) = Body::from_file(build_path.join(format!("{}.{}", path, extension))).await
+        {
+            let mut compressed = Response::from(body);
+            compressed.insert_header("Content-Encoding", *encoding);
+            compressed.insert_header("Vary", "Accept-Encoding");
+            if let Some(mime) = mime(path) {
+                compressed.set_content_type(mime);
+            }
+            res = Some(compressed);
+            break;
+        }
+    }
+    let mut res = match res {
+        Some(res) => res,
+        None => match range {
+            Some(range) => serve_range(&build_path.join(path), path, &range).await?,
+            None => {
+                let mut res = Response::from(Body::from_file(build_path.join(path)).await.ok()?);
+                res.insert_header("Accept-Ranges", "bytes");
+                res
+            }
+        },
+    };
+
+    if resolved.is_some() {
+        // NOTE: the unhashed path points to another file after every rebuild
+        res.insert_header("Cache-Control", "no-cache");
+    } else if hashed && preview {
+        res.insert_header("Cache-Control", IMMUTABLE);
+    }
+
+    Some(crate::debug_headers(build_path, path, res))
+}
+
+/// Serve the part of the file requested by the header `Range` (`bytes=<start>-<end>`,
+/// `bytes=<start>-` or `bytes=-<suffix length>`).
+async fn serve_range(file_path: &Path, path: &str, range: &str) -> Option<Response> {
+    let mut file = async_std::fs::File::open(file_path).await.ok()?;
+    let metadata = file.metadata().await.ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    let len = metadata.len();
+
+    let (start, end) = match parse_range(range, len) {
+        Some(Ok(range)) => range,
+        Some(Err(())) => {
+            let mut res = Response::new(StatusCode::RequestedRangeNotSatisfiable);
+            res.insert_header("Content-Range", format!("bytes */{}", len));
+            return Some(res);
+        }
+        None => {
+            let mut res = Response::from(Body::from_file(file_path).await.ok()?);
+            res.insert_header("Accept-Ranges", "bytes");
+            return Some(res);
+        }
+    };
+
+    file.seek(SeekFrom::Start(start)).await.ok()?;
+    let part_len = end - start + 1;
+    let mut body = Body::from_reader(BufReader::new(file.take(part_len)), Some(part_len as usize));
+    if let Some(mime) = mime(path) {
+        body.set_mime(mime);
+    }
+    let mut res = Response::new(StatusCode::PartialContent);
+    res.set_body(body);
+    res.insert_header("Accept-Ranges", "bytes");
+    res.insert_header("Content-Range", format!("bytes {}-{}/{}", start, end, len));
+    Some(res)
+}
+
+/// Parse a header `Range` for a file of `len` bytes: the first and last bytes of the range are
+/// returned, or an error if it can't be satisfied.
+///
+/// Returns `None` if the header is not a single range of bytes.
+fn parse_range(range: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let range = range.trim().strip_prefix("bytes=")?;
+    if range.contains(',') {
+        return None;
+    }
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix = suffix.parse::<u64>().ok()?;
+            if suffix == 0 || len == 0 {
+                return Some(Err(()));
+            }
+            (len.saturating_sub(suffix), len - 1)
+        }
+        (start, "") => (start.parse().ok()?, len.saturating_sub(1)),
+        (start, end) => {
+            let (start, end) = (start.parse().ok()?, end.parse::<u64>().ok()?);
+            if end < start {
+                return None;
+            }
+            (start, end.min(len.saturating_sub(1)))
+        }
+    };
+    if start >= len {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end)))
+}
+
+fn mime(path: &str) -> Option<Mime> {
+    // NOTE: `WebAssembly.instantiateStreaming` requires `application/wasm`, also for the
+    //       precompressed files whose type can't be sniffed
+    match Path::new(path).extension().and_then(|x| x.to_str()) {
+        Some("wasm") => Some(mime::WASM),
+        extension => extension.and_then(Mime::from_extension),
+    }
+}
+
+/// The response to a request of a WASM or JavaScript file that doesn't exist in the build
+/// directory (the `index.html` would be served instead and the application would fail with an
+/// opaque error). The closest file of the build directory is logged and given in a JSON body
+/// (`error`, `path` and `closest`), also displayed by the overlay.
+///
+/// Returns `None` for the other files.
+pub(crate) fn missing_artifact(
+    build_path: &Utf8Path,
+    path: &str,
+    overlay: &Overlay,
+) -> Option<Response> {
+    let extension = Path::new(path).extension().and_then(|x| x.to_str())?;
+    if !["wasm", "js", "mjs"].contains(&extension) {
+        return None;
+    }
+
+    let mut candidates = crate::generated::files(build_path)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(relative, _)| relative)
+        .collect::<Vec<_>>();
+    let manifest = std::fs::read(build_path.join(ASSET_MANIFEST))
+        .ok()
+        .and_then(|x| serde_json::from_slice::<Value>(&x).ok());
+    if let Some(files) = manifest.as_ref().and_then(|x| x["files"].as_object()) {
+        candidates.extend(files.keys().cloned());
+    }
+    let closest = candidates
+        .into_iter()
+        .filter(|x| x.ends_with(&format!(".{}", extension)))
+        .min_by_key(|x| (distance(x, path), x.len()));
+
+    let error = match closest.as_deref() {
+        Some(closest) => format!(
+            "`/{}` does not exist in the build directory: the closest file is `/{}` (check the \
+            `out_name` and the fingerprint of the artifacts)",
+            path, closest
+        ),
+        None => format!(
+            "`/{}` does not exist in the build directory and it contains no `.{}` file",
+            path, extension
+        ),
+    };
+    log::warn!("{}", error);
+    overlay.set_missing(Some(error.clone()));
+
+    let mut res = Response::new(StatusCode::NotFound);
+    res.set_body(json!({ "error": error, "path": path, "closest": closest }));
+    res.insert_header("Cache-Control", "no-store");
+    Some(res)
+}
+
+/// Edit distance between two paths.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, x) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = (previous + usize::from(x != *y))
+                .min(row[j] + 1)
+                .min(current + 1);
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+async fn read_manifest(build_path: &Path) -> Value {
+    match async_std::fs::read(build_path.join(ASSET_MANIFEST)).await {
+        Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|err| {
+            log::warn!("Could not parse `{}`: {}", ASSET_MANIFEST, err);
+            Value::Null
+        }),
+        Err(_) => Value::Null,
+    }
+}
+
+/// Check if the header `Accept-Encoding` accepts the encoding (without `q=0`).
+fn accepts(accept_encoding: &str, encoding: &str) -> bool {
+    accept_encoding.split(',').any(|x| {
+        let mut parts = x.split(';').map(str::trim);
+        parts.next() == Some(encoding)
+            && parts.all(|x| {
+                x.strip_prefix("q=")
+                    .and_then(|x| x.parse::<f32>().ok())
+                    .is_none_or(|x| x > 0.0)
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_range_bounded() {
+        assert_eq!(parse_range("bytes=0-9", 100), Some(Ok((0, 9))));
+        assert_eq!(parse_range(" bytes=10-10 ", 100), Some(Ok((10, 10))));
+        // NOTE: the end is clamped to the last byte
+        assert_eq!(parse_range("bytes=90-200", 100), Some(Ok((90, 99))));
+    }
+
+    #[test]
+    fn parse_range_open_ended() {
+        assert_eq!(parse_range("bytes=40-", 100), Some(Ok((40, 99))));
+        assert_eq!(parse_range("bytes=0-", 1), Some(Ok((0, 0))));
+    }
+
+    #[test]
+    fn parse_range_suffix() {
+        assert_eq!(parse_range("bytes=-10", 100), Some(Ok((90, 99))));
+        assert_eq!(parse_range("bytes=-500", 100), Some(Ok((0, 99))));
+        assert_eq!(parse_range("bytes=-0", 100), Some(Err(())));
+        assert_eq!(parse_range("bytes=-10", 0), Some(Err(())));
+    }
+
+    #[test]
+    fn parse_range_not_satisfiable() {
+        assert_eq!(parse_range("bytes=100-", 100), Some(Err(())));
+        assert_eq!(parse_range("bytes=150-200", 100), Some(Err(())));
+        assert_eq!(parse_range("bytes=0-", 0), Some(Err(())));
+    }
+
+    #[test]
+    fn parse_range_multiple_ranges() {
+        // NOTE: the whole file is served
+        assert_eq!(parse_range("bytes=0-9,20-29", 100), None);
+        assert_eq!(parse_range("bytes=-5, 0-1", 100), None);
+    }
+
+    #[test]
+    fn parse_range_malformed() {
+        assert_eq!(parse_range("0-9", 100), None);
+        assert_eq!(parse_range("items=0-9", 100), None);
+        assert_eq!(parse_range("bytes=", 100), None);
+        assert_eq!(parse_range("bytes=-", 100), None);
+        assert_eq!(parse_range("bytes=9", 100), None);
+        assert_eq!(parse_range("bytes=a-9", 100), None);
+        assert_eq!(parse_range("bytes=0-b", 100), None);
+        assert_eq!(parse_range("bytes=9-0", 100), None);
+        assert_eq!(parse_range("bytes=-1-2", 100), None);
+    }
+
+    #[test]
+    fn distance_counts_the_edits() {
+        assert_eq!(distance("app.js", "app.js"), 0);
+        assert_eq!(distance("app.js", "app.mjs"), 1);
+        assert_eq!(distance("app_bg.wasm", "app-bg.wasm"), 1);
+        assert_eq!(distance("", "app.js"), 6);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+
+    fn build_dir(name: &str, files: &[&str]) -> camino::Utf8PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "wasm-run-static-files-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let path = camino::Utf8PathBuf::from_path_buf(path).unwrap();
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        for file in files {
+            let file = path.join(file);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, "").unwrap();
+        }
+        path
+    }
+
+    fn body(mut res: Response) -> Value {
+        async_std::task::block_on(res.take_body().into_json()).unwrap()
+    }
+
+    fn body_closest(res: Response) -> Value {
+        body(res)["closest"].clone()
+    }
+
+    #[test]
+    fn missing_artifact_ignores_the_other_files() {
+        let path = build_dir("other", &["app.js"]);
+        let overlay = Overlay::default();
+        assert!(missing_artifact(&path, "index.html", &overlay).is_none());
+        assert!(missing_artifact(&path, "style.css", &overlay).is_none());
+        assert!(missing_artifact(&path, "favicon", &overlay).is_none());
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_artifact_suggests_the_closest_file() {
+        let path = build_dir(
+            "closest",
+            &["app.js", "app_bg.wasm", "other_bg.wasm", "snippets/x.js"],
+        );
+        let overlay = Overlay::default();
+        let res = missing_artifact(&path, "app-bg.wasm", &overlay).unwrap();
+        assert_eq!(res.status(), StatusCode::NotFound);
+        assert_eq!(res["Cache-Control"], "no-store");
+        let body = body(res);
+        assert_eq!(body["path"], "app-bg.wasm");
+        assert_eq!(body["closest"], "app_bg.wasm");
+        assert!(body["error"].as_str().unwrap().contains("`/app_bg.wasm`"));
+
+        let res = missing_artifact(&path, "apps.js", &overlay).unwrap();
+        assert_eq!(body_closest(res), "app.js");
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_artifact_reads_the_manifest() {
+        let path = build_dir("manifest", &[]);
+        std::fs::write(
+            path.join(ASSET_MANIFEST),
+            r#"{"files": {"app-0123.js": "app.js", "app_bg-0123.wasm": "app_bg.wasm"}}"#,
+        )
+        .unwrap();
+        let overlay = Overlay::default();
+        let res = missing_artifact(&path, "app-4567.js", &overlay).unwrap();
+        assert_eq!(body_closest(res), "app-0123.js");
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_artifact_without_candidate() {
+        let path = build_dir("empty", &["app.js"]);
+        let overlay = Overlay::default();
+        let body = body(missing_artifact(&path, "app_bg.wasm", &overlay).unwrap());
+        assert_eq!(body["closest"], Value::Null);
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .contains("it contains no `.wasm` file"));
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{escape_html, BuildProfile, CompileError};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tide::{Response, Server};
+
+pub(crate) const PAGE_PATH: &str = "/__wasm_run/";
+
+/// Number of builds and of watch events kept for the page.
+const HISTORY_LEN: usize = 50;
+
+/// The last builds and watch events displayed by the page.
+#[derive(Debug, Default)]
+pub(crate) struct History {
+    builds: Mutex<VecDeque<BuildRecord>>,
+    watch_events: Mutex<VecDeque<WatchEvent>>,
+}
+
+#[derive(Debug)]
+struct BuildRecord {
+    finished: SystemTime,
+    duration: Duration,
+    profile: BuildProfile,
+    rebuild: bool,
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+struct WatchEvent {
+    time: SystemTime,
+    path: PathBuf,
+    ignored: bool,
+}
+
+fn push<T>(history: &Mutex<VecDeque<T>>, item: T) {
+    let mut history = history.lock().unwrap();
+    if history.len() == HISTORY_LEN {
+        history.pop_front();
+    }
+    history.push_back(item);
+}
+
+impl History {
+    pub(crate) fn record_build<T>(
+        &self,
+        profile: BuildProfile,
+        rebuild: bool,
+        duration: Duration,
+        res: &anyhow::Result<T>,
+    ) {
+        let error = res
+            .as_ref()
+            .err()
+            .map(|err| match err.downcast_ref::<CompileError>() {
+                Some(err) => err.rendered(),
+                None => format!("{:#}", err),
+            });
+        push(
+            &self.builds,
+            BuildRecord {
+                finished: SystemTime::now(),
+                duration,
+                profile,
+                rebuild,
+                error,
+            },
+        );
+    }
+
+    pub(crate) fn record_watch_event(&self, path: &Path, ignored: bool) {
+        push(
+            &self.watch_events,
+            WatchEvent {
+                time: SystemTime::now(),
+                path: path.to_owned(),
+                ignored,
+            },
+        );
+    }
+}
+
+/// Register the page `/__wasm_run/` that shows the last builds, the files of the build directory
+/// and the last events of the watcher.
+pub(crate) fn register(server: &mut Server<()>, history: Arc<History>, build_path: PathBuf) {
+    for path in &[PAGE_PATH, PAGE_PATH.trim_end_matches('/')] {
+        let history = Arc::clone(&history);
+        let build_path = build_path.clone();
+        server.at(path).get(move |_| {
+            let history = Arc::clone(&history);
+            let build_path = build_path.clone();
+            async move {
+                let mut res = Response::from(render(&history, &build_path));
+                res.set_content_type(tide::http::mime::HTML);
+                res.insert_header("Cache-Control", "no-store");
+                Ok(res)
+            }
+        });
+    }
+}
+
+fn render(history: &History, build_path: &Path) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>wasm-run</title><style>\
+        body { font: 14px sans-serif; margin: 2em; } \
+        table { border-collapse: collapse; margin-bottom: 2em; } \
+        td, th { padding: 0.2em 1em 0.2em 0; text-align: left; vertical-align: top; } \
+        pre { margin: 0; color: #c00; white-space: pre-wrap; } \
+        .ignored { color: #999; } .number { text-align: right; }\
+        </style></head><body>",
+    );
+
+    let builds = history.builds.lock().unwrap();
+    html.push_str("<h1>Builds</h1>");
+    match builds.back() {
+        Some(last) => {
+            let _ = write!(
+                html,
+                "<p>Last build: {} ({:?} profile, {} ms){}</p>",
+                time(last.finished),
+                last.profile,
+                last.duration.as_millis(),
+                if last.error.is_some() { ": failed" } else { "" },
+            );
+        }
+        None => html.push_str("<p>No build yet.</p>"),
+    }
+    html.push_str(
+        "<table><tr><th>Finished</th><th>Trigger</th><th>Duration</th><th>Result</th></tr>",
+    );
+    for build in builds.iter().rev() {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td class=\"number\">{} ms</td><td>{}</td></tr>",
+            time(build.finished),
+            if build.rebuild { "watcher" } else { "initial" },
+            build.duration.as_millis(),
+            match &build.error {
+                Some(error) => format!("<pre>{}</pre>", escape_html(error)),
+                None => "ok".to_owned(),
+            },
+        );
+    }
+    html.push_str("</table>");
+    drop(builds);
+
+    let mut files = Vec::new();
+    list_files(build_path, &mut files);
+    files.sort();
+    let _ = write!(
+        html,
+        "<h1>Build directory</h1><p>{}</p><table><tr><th>File</th><th>Size</th></tr>",
+        escape_html(&build_path.display().to_string()),
+    );
+    for (path, size) in files {
+        let path = path.strip_prefix(build_path).unwrap_or(&path);
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td class=\"number\">{}</td></tr>",
+            escape_html(&path.display().to_string()),
+            size,
+        );
+    }
+    html.push_str("</table>");
+
+    html.push_str("<h1>Watch events</h1><table><tr><th>Time</th><th>Path</th><th></th></tr>");
+    for event in history.watch_events.lock().unwrap().iter().rev() {
+        let _ = write!(
+            html,
+            "<tr{}><td>{}</td><td>{}</td><td>{}</td></tr>",
+            if event.ignored {
+                " class=\"ignored\""
+            } else {
+                ""
+            },
+            time(event.time),
+            escape_html(&event.path.display().to_string()),
+            if event.ignored { "ignored" } else { "rebuild" },
+        );
+    }
+    html.push_str("</table></body></html>");
+
+    html
+}
+
+fn list_files(dir: &Path, files: &mut Vec<(PathBuf, u64)>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => list_files(&path, files),
+            Ok(metadata) if metadata.is_file() => files.push((path, metadata.len())),
+            _ => {}
+        }
+    }
+}
+
+/// Time of the day (UTC) with the milliseconds.
+pub(crate) fn time(time: SystemTime) -> String {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let secs = millis / 1000;
+    format!(
+        "{:02}:{:02}:{:02}.{:03} UTC",
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60,
+        millis % 1000,
+    )
+}
@@ -0,0 +1,645 @@
+//! Plain functions to build, watch and serve a project without the CLI.
+//!
+//! This is meant for projects that use the [xtask](https://github.com/matklad/cargo-xtask)
+//! pattern: the xtask binary calls the pipeline of `wasm-run` directly instead of using the macro
+//! [`main`](crate::main). The configuration is provided explicitly with [`BuildConfig`] and
+//! [`ServeConfig`] and the hooks are passed as argument. The commands generated by the macro run
+//! the same pipeline as these functions, with the configuration parsed from the command line.
+//!
+//! Nothing is initialized by these functions: you need to set up a logger yourself if you want to
+//! see the logs. [`init_logger`] installs the same logger as the CLI.
+//!
+//! ```ignore
+//! use wasm_run::prelude::*;
+//! use wasm_run::tasks::{self, BuildConfig, ServeConfig};
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     let config = BuildConfig::new("my-frontend")?;
+//!     match std::env::args().nth(1).as_deref() {
+//!         Some("build") => {
+//!             let output = tasks::build(BuildProfile::Release, &config, &Hooks::default())?;
+//!             println!("{}", output.build_path);
+//!             Ok(())
+//!         }
+//!         _ => tasks::serve(ServeConfig::new(config), Hooks::default()),
+//!     }
+//! }
+//! ```
+
+use crate::state::State;
+#[cfg(not(feature = "dev-server"))]
+use crate::HealthCheck;
+#[cfg(feature = "dev-server")]
+use crate::PathRule;
+use crate::{
+    BindgenSettings, BuildArgs, BuildOutput, BuildProfile, Config, CriticalCss, Fingerprint, Hooks,
+    MessageFormat, PwaConfig, ServeArgs, Timings, Verbosity, WatcherConfig,
+};
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::{Metadata, MetadataCommand, Package};
+use std::sync::Arc;
+#[cfg(not(feature = "dev-server"))]
+use std::time::Duration;
+
+/// Build configuration. This is the equivalent of [`DefaultBuildArgs`](crate::DefaultBuildArgs)
+/// without the command-line parsing.
+#[derive(Debug)]
+pub struct BuildConfig {
+    metadata: Metadata,
+    frontend_package: usize,
+    backend_package: Option<usize>,
+    default_build_path: Utf8PathBuf,
+    state: State,
+
+    /// Build directory output.
+    pub build_path: Utf8PathBuf,
+
+    /// Subdirectory of the build directory where the WASM and JS files are written.
+    pub artifact_subdir: Option<Utf8PathBuf>,
+
+    /// Enable optimization in the development profile.
+    pub profiling: bool,
+
+    /// Do not add the error reporting to the loader of the default `index.html`.
+    pub no_loader_checks: bool,
+
+    /// Always run wasm-bindgen and wasm-opt instead of reusing their outputs from the cache.
+    pub no_build_cache: bool,
+
+    /// Report the duration of every stage of the builds.
+    pub timings: Option<Timings>,
+
+    /// Format of the messages printed on the standard output.
+    pub message_format: MessageFormat,
+
+    /// Verbosity of the output of cargo and of the steps of the builds.
+    pub verbosity: Verbosity,
+
+    /// Install the target `wasm32-unknown-unknown` with rustup if it is missing.
+    pub auto_install_target: bool,
+
+    /// Fail the build if wasm-bindgen emits warnings.
+    pub deny_bindgen_warnings: bool,
+
+    /// Binary of the frontend package compiled instead of its library.
+    pub frontend_bin: Option<String>,
+
+    /// Do not access the network.
+    pub offline: bool,
+
+    /// Use the `wasm-bindgen` CLI of the exact version used by the frontend.
+    pub external_bindgen: bool,
+
+    /// Options of the generation of the JS bindings by `wasm-bindgen`.
+    pub bindgen_settings: BindgenSettings,
+
+    /// Keep the DWARF debugging information in the WASM and skip wasm-opt.
+    pub dwarf: bool,
+
+    /// Number of builds retained in the history.
+    pub keep_builds: usize,
+
+    /// Web app manifest and service worker. It is read from the configuration by default.
+    pub pwa: Option<PwaConfig>,
+
+    /// Inlining of the critical CSS. It is read from the configuration by default.
+    pub critical_css: Option<CriticalCss>,
+
+    /// Hashing of the fingerprints. It is read from the configuration by default.
+    pub fingerprint: Fingerprint,
+
+    /// Settings of `WasmRun.toml` and of the metadata of the frontend.
+    pub config: Config,
+}
+
+impl BuildConfig {
+    /// Load the metadata of the workspace of the current directory and use the package
+    /// `frontend_pkg_name` as frontend.
+    pub fn new(frontend_pkg_name: &str) -> Result<Self> {
+        let metadata = MetadataCommand::new()
+            .exec()
+            .context("could not load the metadata of the workspace")?;
+        Self::from_metadata(metadata, frontend_pkg_name)
+    }
+
+    /// Use the package `frontend_pkg_name` of the workspace `metadata` as frontend.
+    pub fn from_metadata(mut metadata: Metadata, frontend_pkg_name: &str) -> Result<Self> {
+        let frontend_package = find_package(&metadata, frontend_pkg_name)?;
+        let config = Config::load(&metadata, &metadata.packages[frontend_package])?;
+        metadata.target_directory = crate::workspace::target_directory(&metadata, &config);
+        let default_build_path = config
+            .build_path
+            .clone()
+            .unwrap_or_else(|| crate::metadata_path(&metadata.workspace_root).join("build"));
+        let pwa = PwaConfig::from_config(&config, &metadata.packages[frontend_package].name)?;
+        let critical_css = CriticalCss::from_config(&config)?;
+        let fingerprint = Fingerprint::from_config(&config)?;
+
+        Ok(Self {
+            metadata,
+            frontend_package,
+            backend_package: None,
+            build_path: default_build_path.clone(),
+            default_build_path,
+            state: State::default(),
+            artifact_subdir: None,
+            profiling: false,
+            no_loader_checks: false,
+            no_build_cache: false,
+            timings: None,
+            message_format: MessageFormat::Human,
+            verbosity: Verbosity::default(),
+            auto_install_target: false,
+            deny_bindgen_warnings: false,
+            frontend_bin: None,
+            offline: false,
+            external_bindgen: false,
+            bindgen_settings: BindgenSettings::default(),
+            dwarf: false,
+            keep_builds: 0,
+            pwa,
+            critical_css,
+            fingerprint,
+            config,
+        })
+    }
+
+    /// Use the package `backend_pkg_name` of the workspace as backend.
+    pub fn backend(mut self, backend_pkg_name: &str) -> Result<Self> {
+        self.backend_package = Some(find_package(&self.metadata, backend_pkg_name)?);
+        Ok(self)
+    }
+
+    /// Namespace the build directory by git branch (`<build_path>/<branch>`). The build directory
+    /// must be set before calling this.
+    pub fn per_branch(mut self) -> Self {
+        self.build_path =
+            crate::branch::build_path(&self.build_path, &self.metadata.workspace_root);
+        self
+    }
+}
+
+fn find_package(metadata: &Metadata, name: &str) -> Result<usize> {
+    metadata
+        .packages
+        .iter()
+        .position(|x| x.name == name)
+        .with_context(|| format!("package `{}` not found in the workspace", name))
+}
+
+impl BuildArgs for BuildConfig {
+    fn build_path(&self) -> &Utf8PathBuf {
+        &self.build_path
+    }
+
+    fn artifact_subdir(&self) -> Option<&Utf8Path> {
+        self.artifact_subdir.as_deref()
+    }
+
+    fn default_build_path(&self) -> &Utf8PathBuf {
+        &self.default_build_path
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn frontend_package(&self) -> &Package {
+        &self.metadata.packages[self.frontend_package]
+    }
+
+    fn backend_package(&self) -> Option<&Package> {
+        self.backend_package.map(|x| &self.metadata.packages[x])
+    }
+
+    fn profiling(&self) -> bool {
+        self.profiling
+    }
+
+    fn loader_checks(&self, profile: BuildProfile) -> bool {
+        !self.no_loader_checks && !matches!(profile, BuildProfile::Release)
+    }
+
+    fn build_cache(&self) -> bool {
+        !self.no_build_cache
+    }
+
+    fn timings(&self) -> Option<Timings> {
+        self.timings
+    }
+
+    fn message_format(&self) -> MessageFormat {
+        self.message_format
+    }
+
+    fn auto_install_target(&self) -> bool {
+        self.auto_install_target
+    }
+
+    fn deny_bindgen_warnings(&self) -> bool {
+        self.deny_bindgen_warnings
+    }
+
+    fn frontend_bin(&self) -> Option<String> {
+        self.frontend_bin.clone()
+    }
+
+    fn offline(&self) -> bool {
+        self.offline
+    }
+
+    fn external_bindgen(&self) -> bool {
+        self.external_bindgen
+    }
+
+    fn bindgen_settings(&self) -> BindgenSettings {
+        self.bindgen_settings.clone()
+    }
+
+    fn dwarf(&self) -> bool {
+        self.dwarf
+    }
+
+    fn keep_builds(&self) -> usize {
+        self.keep_builds
+    }
+
+    fn pwa(&self) -> Result<Option<PwaConfig>> {
+        Ok(self.pwa.clone())
+    }
+
+    fn critical_css(&self) -> Result<Option<CriticalCss>> {
+        Ok(self.critical_css.clone())
+    }
+
+    fn fingerprint(&self) -> Result<Fingerprint> {
+        Ok(self.fingerprint)
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn state(&self) -> &State {
+        &self.state
+    }
+
+    fn verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+}
+
+/// Serve configuration. This is the equivalent of [`DefaultServeArgs`](crate::DefaultServeArgs)
+/// without the command-line parsing.
+#[derive(Debug)]
+pub struct ServeConfig {
+    /// Build configuration.
+    pub build: BuildConfig,
+
+    /// Activate HTTP logs.
+    #[cfg(feature = "dev-server")]
+    pub log: bool,
+
+    /// IP address to bind.
+    #[cfg(feature = "dev-server")]
+    pub ip: String,
+
+    /// Port number. Use 0 to pick a free port.
+    #[cfg(feature = "dev-server")]
+    pub port: u16,
+
+    /// Pick a free port if the port is already in use.
+    #[cfg(feature = "dev-server")]
+    pub auto_port: bool,
+
+    /// Bind a random free port and announce it on the standard output with a JSON line.
+    #[cfg(feature = "dev-server")]
+    pub bind_random_port: bool,
+
+    /// Write the URL of the development server to this file once the port is bound.
+    #[cfg(feature = "dev-server")]
+    pub port_file: Option<Utf8PathBuf>,
+
+    /// Open the development server in the default browser once it has started.
+    #[cfg(feature = "dev-server")]
+    pub open: bool,
+
+    /// Append a `?v=<build-id>` query string to the script and WASM URLs of the served
+    /// `index.html`.
+    #[cfg(feature = "dev-server")]
+    pub cache_bust: bool,
+
+    /// Display the build errors in an overlay in the browser. It is read from `overlay` of the
+    /// configuration by default (enabled if missing).
+    #[cfg(feature = "dev-server")]
+    pub overlay: bool,
+
+    /// Serve a status page at `/__wasm_run/`.
+    #[cfg(feature = "dev-server")]
+    pub status_page: bool,
+
+    /// Serve a listing of the build directory at `/__wasm_run/files`.
+    #[cfg(feature = "dev-server")]
+    pub file_listing: bool,
+
+    /// Serve the external dependencies from their local copies.
+    #[cfg(feature = "dev-server")]
+    pub cache_external: bool,
+
+    /// Artificial latency (in milliseconds) of the responses, per path glob pattern.
+    #[cfg(feature = "dev-server")]
+    pub latency: Vec<PathRule<u64>>,
+
+    /// Bandwidth limit (in kilobits per second) of the responses, per path glob pattern.
+    #[cfg(feature = "dev-server")]
+    pub throttle: Vec<PathRule<u64>>,
+
+    /// Patterns (`.gitignore` syntax) of the files whose changes are ignored when watching.
+    pub watch_ignore: Vec<String>,
+
+    /// Configuration of the file watchers.
+    pub watcher: WatcherConfig,
+
+    /// Only run the stages of the build fed by the files that changed (the `post_build` hook is
+    /// not run by these rebuilds).
+    pub partial_rebuilds: bool,
+
+    /// Run the tests after every successful rebuild of the frontend.
+    pub test_on_change: bool,
+
+    /// Build and rebuild the frontend with the release profile.
+    pub release: bool,
+
+    /// Serve the unoptimized build immediately and replace it when wasm-opt is finished.
+    pub background_wasm_opt: bool,
+
+    /// Check that the backend is ready after (re-)starting it.
+    #[cfg(not(feature = "dev-server"))]
+    pub backend_health_check: Option<HealthCheck>,
+
+    /// Maximum time waited for the health check of the backend.
+    #[cfg(not(feature = "dev-server"))]
+    pub backend_health_timeout: Duration,
+
+    /// Time given to the backend to exit after `SIGTERM` before it is killed.
+    #[cfg(not(feature = "dev-server"))]
+    pub backend_kill_timeout: Duration,
+
+    /// Watch the sources and rebuild when they change.
+    pub watch: bool,
+
+    /// Restart the whole command when a change is detected.
+    pub full_restart: bool,
+
+    /// Restart the backend without cargo when a changed file is not one of its sources.
+    #[cfg(not(feature = "dev-server"))]
+    pub fast_backend_restart: bool,
+
+    /// Serve this build of the history instead of building the frontend.
+    #[cfg(feature = "dev-server")]
+    pub history_build: Option<u64>,
+}
+
+impl ServeConfig {
+    /// Create a serve configuration with the same defaults as the `serve` command.
+    pub fn new(build: BuildConfig) -> Self {
+        #[cfg(feature = "dev-server")]
+        let port = build.config.port.unwrap_or(3000);
+        #[cfg(feature = "dev-server")]
+        let overlay = build.config.get("overlay").as_bool() != Some(false);
+        Self {
+            build,
+            #[cfg(feature = "dev-server")]
+            log: false,
+            #[cfg(feature = "dev-server")]
+            ip: "127.0.0.1".to_string(),
+            #[cfg(feature = "dev-server")]
+            port,
+            #[cfg(feature = "dev-server")]
+            auto_port: false,
+            #[cfg(feature = "dev-server")]
+            bind_random_port: false,
+            #[cfg(feature = "dev-server")]
+            port_file: None,
+            #[cfg(feature = "dev-server")]
+            open: false,
+            #[cfg(feature = "dev-server")]
+            cache_bust: false,
+            #[cfg(feature = "dev-server")]
+            overlay,
+            #[cfg(feature = "dev-server")]
+            status_page: false,
+            #[cfg(feature = "dev-server")]
+            file_listing: false,
+            #[cfg(feature = "dev-server")]
+            cache_external: false,
+            #[cfg(feature = "dev-server")]
+            latency: Vec::new(),
+            #[cfg(feature = "dev-server")]
+            throttle: Vec::new(),
+            watch_ignore: Vec::new(),
+            watcher: WatcherConfig::default(),
+            partial_rebuilds: false,
+            test_on_change: false,
+            release: false,
+            background_wasm_opt: false,
+            #[cfg(not(feature = "dev-server"))]
+            backend_health_check: None,
+            #[cfg(not(feature = "dev-server"))]
+            backend_health_timeout: Duration::from_secs(30),
+            #[cfg(not(feature = "dev-server"))]
+            backend_kill_timeout: Duration::from_secs(5),
+            watch: true,
+            full_restart: false,
+            #[cfg(not(feature = "dev-server"))]
+            fast_backend_restart: false,
+            #[cfg(feature = "dev-server")]
+            history_build: None,
+        }
+    }
+}
+
+impl ServeArgs for ServeConfig {
+    #[cfg(feature = "dev-server")]
+    fn log(&self) -> bool {
+        self.log
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn ip(&self) -> &str {
+        &self.ip
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn port(&self) -> u16 {
+        self.port
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn default_port(&self) -> u16 {
+        self.build.config.port.unwrap_or(3000)
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn auto_port(&self) -> bool {
+        self.auto_port
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn bind_random_port(&self) -> bool {
+        self.bind_random_port
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn port_file(&self) -> Option<&Utf8Path> {
+        self.port_file.as_deref()
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn open(&self) -> bool {
+        self.open
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn cache_bust(&self) -> bool {
+        self.cache_bust
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn overlay(&self) -> bool {
+        self.overlay
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn status_page(&self) -> bool {
+        self.status_page
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn file_listing(&self) -> bool {
+        self.file_listing
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn cache_external(&self) -> bool {
+        self.cache_external
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn latency(&self) -> Result<Vec<PathRule<u64>>> {
+        Ok(self.latency.clone())
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn throttle(&self) -> Result<Vec<PathRule<u64>>> {
+        Ok(self.throttle.clone())
+    }
+
+    fn watch_ignore(&self) -> &[String] {
+        &self.watch_ignore
+    }
+
+    fn watcher_config(&self) -> WatcherConfig {
+        self.watcher
+    }
+
+    fn partial_rebuilds(&self) -> bool {
+        self.partial_rebuilds
+    }
+
+    fn test_on_change(&self) -> bool {
+        self.test_on_change
+    }
+
+    fn release(&self) -> bool {
+        self.release
+    }
+
+    fn background_wasm_opt(&self) -> bool {
+        self.background_wasm_opt
+    }
+
+    #[cfg(not(feature = "dev-server"))]
+    fn backend_health_check(&self) -> Option<HealthCheck> {
+        self.backend_health_check.clone()
+    }
+
+    #[cfg(not(feature = "dev-server"))]
+    fn backend_health_timeout(&self) -> Duration {
+        self.backend_health_timeout
+    }
+
+    #[cfg(not(feature = "dev-server"))]
+    fn backend_kill_timeout(&self) -> Duration {
+        self.backend_kill_timeout
+    }
+
+    fn watch(&self) -> bool {
+        self.watch
+    }
+
+    fn full_restart(&self) -> bool {
+        self.full_restart
+    }
+
+    #[cfg(not(feature = "dev-server"))]
+    fn fast_backend_restart(&self) -> bool {
+        self.fast_backend_restart
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn history_build(&self) -> Option<u64> {
+        self.history_build
+    }
+
+    fn build_args(&self) -> &dyn BuildArgs {
+        &self.build
+    }
+}
+
+/// Install the logger used by the CLI: `env_logger` (info level by default, configurable with
+/// `RUST_LOG`). It also captures the warnings of wasm-bindgen for
+/// [`BuildArgs::deny_bindgen_warnings`].
+pub fn init_logger() {
+    crate::diagnostics::init_logger(Verbosity::default());
+}
+
+/// Install the logger used by the CLI with the default filter of a [`Verbosity`] instead of the
+/// default one (the output of cargo is set by [`BuildConfig::verbosity`]). Only the first call
+/// has an effect.
+pub fn init_logger_with(verbosity: Verbosity) {
+    crate::diagnostics::init_logger(verbosity);
+}
+
+/// Build the frontend with the given profile and return the files it emitted. It returns once the
+/// webhooks of the build have been sent.
+pub fn build(profile: BuildProfile, args: &dyn BuildArgs, hooks: &Hooks) -> Result<BuildOutput> {
+    let output = crate::build(profile, args, hooks);
+    #[cfg(feature = "webhooks")]
+    crate::webhooks::wait();
+    output
+}
+
+/// Build the frontend (with the profile [`ServeArgs::profile`]) and rebuild it every time a change
+/// is detected.
+///
+/// This function only returns on error.
+pub fn watch(args: &(dyn ServeArgs + Sync), hooks: &Hooks) -> Result<()> {
+    let (_, pending) = crate::build_with(
+        args.profile(),
+        args.build_args(),
+        hooks,
+        args.background_wasm_opt(),
+        false,
+    )?;
+    crate::watch_frontend(args, hooks, pending)
+}
+
+/// Build the frontend, serve it (or run the backend without the feature `dev-server`) and
+/// rebuild every time a change is detected.
+///
+/// This function only returns on error.
+pub fn serve<A: ServeArgs + Sync + 'static>(args: A, hooks: Hooks) -> Result<()> {
+    crate::serve(args, Arc::new(hooks))
+}
@@ -0,0 +1,171 @@
+use crate::{BuildArgs, Hooks, ServeArgs, WorkspaceContext};
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+use cargo_metadata::Metadata;
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Build arguments that don't need the macro [`main`](crate::main), to unit test the hooks.
+///
+/// They describe a fake workspace: a frontend package `app` at the root of a directory (usually
+/// a temporary directory), built in `<root>/build`. The hooks tested must be generic over the
+/// arguments or take a [`BuildContext`](crate::BuildContext) of `dyn BuildArgs`.
+///
+/// ```ignore
+/// #[test]
+/// fn post_build_writes_the_manifest() {
+///     let args = TestBuildArgs::new(tempdir.path()).unwrap();
+///     let context = BuildContext::from_args(&args, BuildProfile::Release);
+///     post_build(&context, String::new(), Vec::new()).unwrap();
+///     assert!(args.build_path().join("manifest.json").exists());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TestBuildArgs {
+    context: Arc<WorkspaceContext>,
+    build_path: Utf8PathBuf,
+    profiling: bool,
+}
+
+impl TestBuildArgs {
+    /// Arguments of the fake workspace at `root`. The `WasmRun.toml` of `root` is loaded if
+    /// there is one.
+    pub fn new(root: impl AsRef<Path>) -> Result<Self> {
+        let metadata = fake_metadata(root.as_ref())?;
+        let context = WorkspaceContext::new(metadata, Some("app"), None, Hooks::default())?;
+        Ok(Self::with_context(Arc::new(context)))
+    }
+
+    /// Arguments of an existing context, built in its default build directory.
+    pub fn with_context(context: Arc<WorkspaceContext>) -> Self {
+        Self {
+            build_path: context.default_build_path().to_owned(),
+            context,
+            profiling: false,
+        }
+    }
+
+    /// Use this build directory.
+    pub fn with_build_path(mut self, build_path: impl Into<Utf8PathBuf>) -> Self {
+        self.build_path = build_path.into();
+        self
+    }
+
+    /// Make a profiling build.
+    pub fn with_profiling(mut self, profiling: bool) -> Self {
+        self.profiling = profiling;
+        self
+    }
+}
+
+impl BuildArgs for TestBuildArgs {
+    fn context(&self) -> &WorkspaceContext {
+        &self.context
+    }
+
+    fn build_path(&self) -> &Utf8PathBuf {
+        &self.build_path
+    }
+
+    fn profiling(&self) -> bool {
+        self.profiling
+    }
+}
+
+/// Serve arguments that don't need the macro [`main`](crate::main), to unit test the hooks (check
+/// [`TestBuildArgs`]).
+#[derive(Debug, Clone)]
+pub struct TestServeArgs {
+    build_args: TestBuildArgs,
+    #[cfg(feature = "dev-server")]
+    ip: String,
+    #[cfg(feature = "dev-server")]
+    port: u16,
+    #[cfg(feature = "dev-server")]
+    log: bool,
+}
+
+impl TestServeArgs {
+    /// Serve the build of `build_args` on `127.0.0.1` and the default port of the workspace.
+    pub fn new(build_args: TestBuildArgs) -> Self {
+        Self {
+            #[cfg(feature = "dev-server")]
+            port: build_args.context.default_port(),
+            build_args,
+            #[cfg(feature = "dev-server")]
+            ip: "127.0.0.1".to_owned(),
+            #[cfg(feature = "dev-server")]
+            log: false,
+        }
+    }
+
+    /// Bind the server to this IP address.
+    #[cfg(feature = "dev-server")]
+    pub fn with_ip(mut self, ip: impl Into<String>) -> Self {
+        self.ip = ip.into();
+        self
+    }
+
+    /// Bind the server to this port.
+    #[cfg(feature = "dev-server")]
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Log the requests.
+    #[cfg(feature = "dev-server")]
+    pub fn with_log(mut self, log: bool) -> Self {
+        self.log = log;
+        self
+    }
+}
+
+impl ServeArgs for TestServeArgs {
+    #[cfg(feature = "dev-server")]
+    fn log(&self) -> bool {
+        self.log
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn ip(&self) -> &str {
+        &self.ip
+    }
+
+    #[cfg(feature = "dev-server")]
+    fn port(&self) -> u16 {
+        self.port
+    }
+
+    fn build_args(&self) -> &dyn BuildArgs {
+        &self.build_args
+    }
+}
+
+/// Metadata of a workspace containing only the frontend package `app` at `root`.
+fn fake_metadata(root: &Path) -> Result<Metadata> {
+    let id = format!("app 0.1.0 (path+file://{})", root.display());
+    let metadata = json!({
+        "packages": [{
+            "name": "app",
+            "version": "0.1.0",
+            "id": id,
+            "dependencies": [],
+            "targets": [{
+                "name": "app",
+                "kind": ["cdylib"],
+                "crate_types": ["cdylib"],
+                "src_path": root.join("src").join("lib.rs"),
+            }],
+            "features": {},
+            "manifest_path": root.join("Cargo.toml"),
+        }],
+        "workspace_members": [id],
+        "resolve": null,
+        "workspace_root": root,
+        "target_directory": root.join("target"),
+        "version": 1,
+    });
+    serde_json::from_value(metadata).context("could not create the metadata of the workspace")
+}
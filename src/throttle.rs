@@ -0,0 +1,271 @@
+use futures::io::{AsyncRead, BufReader};
+use globset::{Glob, GlobMatcher};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tide::{Body, Middleware, Next, Request, Server};
+
+/// Interval at which the bandwidth of a throttled response is distributed.
+const TICK: Duration = Duration::from_millis(100);
+
+/// A value applied to the requests whose path matches a glob pattern, or to all the requests.
+///
+/// It is written `<value>` or `<pattern>=<value>` like `500` or `/api/*=500`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathRule<T> {
+    /// Glob pattern matched against the path of the request. All the paths match if `None`.
+    pub pattern: Option<String>,
+    /// Value applied to the matching requests.
+    pub value: T,
+}
+
+impl<T: FromStr> FromStr for PathRule<T>
+where
+    T::Err: fmt::Display,
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, value) = match s.rsplit_once('=') {
+            Some((pattern, value)) => (Some(pattern.to_string()), value),
+            None => (None, s),
+        };
+        if let Some(pattern) = pattern.as_deref() {
+            Glob::new(pattern).map_err(|err| format!("invalid glob `{}`: {}", pattern, err))?;
+        }
+        let value = value
+            .parse()
+            .map_err(|err| format!("invalid value `{}`: {}", value, err))?;
+
+        Ok(Self { pattern, value })
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for PathRule<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.pattern {
+            Some(pattern) => write!(f, "{}={}", pattern, self.value),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+struct Matcher<T> {
+    pattern: Option<GlobMatcher>,
+    value: T,
+}
+
+fn matchers<T>(rules: Vec<PathRule<T>>) -> Vec<Matcher<T>> {
+    rules
+        .into_iter()
+        .map(|rule| Matcher {
+            // NOTE: the patterns are validated when the rules are parsed
+            pattern: rule
+                .pattern
+                .and_then(|x| Glob::new(&x).ok())
+                .map(|x| x.compile_matcher()),
+            value: rule.value,
+        })
+        .collect()
+}
+
+fn find<'a, T>(matchers: &'a [Matcher<T>], path: &str) -> Option<&'a T> {
+    matchers
+        .iter()
+        .find(|x| x.pattern.as_ref().is_none_or(|x| x.is_match(path)))
+        .map(|x| &x.value)
+}
+
+/// Middleware that delays the responses (latency in milliseconds) and limits their bandwidth
+/// (kilobits per second).
+struct ThrottleMiddleware {
+    latency: Vec<Matcher<u64>>,
+    throttle: Vec<Matcher<u64>>,
+}
+
+#[tide::utils::async_trait]
+impl Middleware<()> for ThrottleMiddleware {
+    async fn handle(&self, req: Request<()>, next: Next<'_, ()>) -> tide::Result {
+        let path = req.url().path().to_string();
+        let latency = find(&self.latency, &path).copied();
+        let kbps = find(&self.throttle, &path).copied();
+
+        if let Some(latency) = latency {
+            async_std::task::sleep(Duration::from_millis(latency)).await;
+        }
+
+        let mut res = next.run(req).await;
+
+        if let Some(kbps) = kbps {
+            let data = res.take_body().into_bytes().await?;
+            let len = data.len();
+            let reader = ThrottledReader {
+                data,
+                pos: 0,
+                bytes_per_tick: ((kbps * 1000 / 8) as f64 * TICK.as_secs_f64()).max(1.0) as usize,
+                budget: 0,
+                delay: Mutex::new(None),
+            };
+            res.set_body(Body::from_reader(BufReader::new(reader), Some(len)));
+        }
+
+        Ok(res)
+    }
+}
+
+type Delay = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Reader that only gives a fixed amount of bytes per tick.
+struct ThrottledReader {
+    data: Vec<u8>,
+    pos: usize,
+    bytes_per_tick: usize,
+    budget: usize,
+    // NOTE: the mutex makes the reader `Sync` as required by tide; it is never contended
+    delay: Mutex<Option<Delay>>,
+}
+
+impl AsyncRead for ThrottledReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            if self.pos >= self.data.len() {
+                return Poll::Ready(Ok(0));
+            }
+
+            let delay = self.delay.get_mut().unwrap();
+            if let Some(future) = delay.as_mut() {
+                if future.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                *delay = None;
+                self.budget = self.bytes_per_tick;
+            }
+
+            if self.budget == 0 {
+                *self.delay.get_mut().unwrap() = Some(Box::pin(async_std::task::sleep(TICK)));
+                continue;
+            }
+
+            let n = buf.len().min(self.budget).min(self.data.len() - self.pos);
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            self.budget -= n;
+            return Poll::Ready(Ok(n));
+        }
+    }
+}
+
+pub(crate) fn register(
+    server: &mut Server<()>,
+    latency: Vec<PathRule<u64>>,
+    throttle: Vec<PathRule<u64>>,
+) {
+    if latency.is_empty() && throttle.is_empty() {
+        return;
+    }
+
+    for rule in &latency {
+        log::info!(
+            "Latency: {} ms ({})",
+            rule.value,
+            rule.pattern.as_deref().unwrap_or("all paths")
+        );
+    }
+    for rule in &throttle {
+        log::info!(
+            "Throttle: {} kbps ({})",
+            rule.value,
+            rule.pattern.as_deref().unwrap_or("all paths")
+        );
+    }
+
+    server.with(ThrottleMiddleware {
+        latency: matchers(latency),
+        throttle: matchers(throttle),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(s: &str) -> PathRule<u64> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_path_rules() {
+        assert_eq!(
+            rule("500"),
+            PathRule {
+                pattern: None,
+                value: 500,
+            }
+        );
+        assert_eq!(
+            rule("/api/*=500"),
+            PathRule {
+                pattern: Some("/api/*".to_string()),
+                value: 500,
+            }
+        );
+        // NOTE: the value is after the last `=`
+        assert_eq!(rule("/a=b/*=1").pattern.as_deref(), Some("/a=b/*"));
+    }
+
+    #[test]
+    fn parse_invalid_path_rules() {
+        let err = "/api/[*=500".parse::<PathRule<u64>>().unwrap_err();
+        assert!(err.starts_with("invalid glob `/api/[*`: "), "{}", err);
+        let err = "/api/*=fast".parse::<PathRule<u64>>().unwrap_err();
+        assert!(err.starts_with("invalid value `fast`: "), "{}", err);
+        assert!("".parse::<PathRule<u64>>().is_err());
+        assert!("/api/*=".parse::<PathRule<u64>>().is_err());
+    }
+
+    #[test]
+    fn display_path_rules() {
+        for s in ["500", "/api/*=500"] {
+            assert_eq!(rule(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn find_first_matching_rule() {
+        let matchers = matchers(vec![
+            rule("/api/slow/*=3000"),
+            rule("/api/*=500"),
+            rule("50"),
+        ]);
+        assert_eq!(find(&matchers, "/api/slow/x"), Some(&3000));
+        assert_eq!(find(&matchers, "/api/users"), Some(&500));
+        assert_eq!(find(&matchers, "/index.html"), Some(&50));
+        assert_eq!(find(&matchers[..2], "/index.html"), None);
+    }
+
+    #[test]
+    fn throttled_reader_returns_all_the_data() {
+        use futures::io::AsyncReadExt;
+
+        let data = (0..=255).collect::<Vec<u8>>();
+        let mut reader = ThrottledReader {
+            data: data.clone(),
+            pos: 0,
+            bytes_per_tick: 100,
+            budget: 0,
+            delay: Mutex::new(None),
+        };
+        let mut read = Vec::new();
+        async_std::task::block_on(reader.read_to_end(&mut read)).unwrap();
+        assert_eq!(read, data);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{escape_html, events, BuildArgs};
+use serde_json::json;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Report of the durations of the stages of the builds (`--timings`).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Timings {
+    /// A summary is logged after every build.
+    Summary,
+    /// The summary is logged and an HTML report is written in `wasm-run/timings` in the target
+    /// directory (`wasm-run-timing.html` is the last one).
+    Html,
+}
+
+impl FromStr for Timings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "summary" => Ok(Self::Summary),
+            "html" => Ok(Self::Html),
+            _ => Err(format!("invalid timings report `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Summary => write!(f, "summary"),
+            Self::Html => write!(f, "html"),
+        }
+    }
+}
+
+/// A stage of a build. The stages run by another one (the assets copied by the hook
+/// `post_build` for example) have a greater depth.
+struct Stage {
+    name: String,
+    depth: usize,
+    duration: Duration,
+}
+
+// NOTE: a build runs on a single thread; the stages are only recorded between `start` and
+//       `report`
+thread_local! {
+    static STAGES: RefCell<Option<Vec<Stage>>> = const { RefCell::new(None) };
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Start recording the stages of a build on this thread.
+pub(crate) fn start() {
+    STAGES.with(|x| *x.borrow_mut() = Some(Vec::new()));
+    DEPTH.with(|x| x.set(0));
+}
+
+/// Run the stage `name` and record its duration.
+pub(crate) fn stage<T>(name: impl Into<String>, f: impl FnOnce() -> T) -> T {
+    // NOTE: the stage is recorded when it starts so the stages are listed in order of execution,
+    //       every stage followed by the ones it runs
+    let depth = DEPTH.with(|x| x.replace(x.get() + 1));
+    let index = push(name.into(), depth, Duration::default());
+    let started = Instant::now();
+    let res = f();
+    DEPTH.with(|x| x.set(depth));
+    STAGES.with(|x| {
+        if let (Some(stages), Some(index)) = (x.borrow_mut().as_mut(), index) {
+            stages[index].duration = started.elapsed();
+        }
+    });
+    res
+}
+
+/// Record the duration of the stage `name` measured by the caller.
+pub(crate) fn record(name: impl Into<String>, duration: Duration) {
+    push(name.into(), DEPTH.with(Cell::get), duration);
+}
+
+fn push(name: String, depth: usize, duration: Duration) -> Option<usize> {
+    STAGES.with(|x| {
+        let mut stages = x.borrow_mut();
+        let stages = stages.as_mut()?;
+        stages.push(Stage {
+            name,
+            depth,
+            duration,
+        });
+        Some(stages.len() - 1)
+    })
+}
+
+/// Stop recording and report the stages of the build `title` that took `total`.
+pub(crate) fn report(args: &dyn BuildArgs, title: &str, total: Duration) {
+    let stages = match STAGES.with(|x| x.borrow_mut().take()) {
+        Some(stages) => stages,
+        None => return,
+    };
+
+    events::emit(
+        args,
+        "timings",
+        json!({
+            "build": title,
+            "total": total.as_secs_f64(),
+            "stages": stages
+                .iter()
+                .map(|x| {
+                    json!({
+                        "name": x.name,
+                        "depth": x.depth,
+                        "duration": x.duration.as_secs_f64(),
+                    })
+                })
+                .collect::<Vec<_>>(),
+        }),
+    );
+
+    let timings = match args.timings() {
+        Some(timings) => timings,
+        None => {
+            log::debug!("{} in {:.2?}", title, total);
+            return;
+        }
+    };
+
+    log::info!("Timings of the {} ({:.2}s):", title, total.as_secs_f64());
+    let width = stages
+        .iter()
+        .map(|x| x.name.len() + 2 * x.depth)
+        .max()
+        .unwrap_or(0);
+    for stage in &stages {
+        log::info!(
+            "  {:width$}  {:>7.2}s  {:>3.0}%",
+            format!("{}{}", "  ".repeat(stage.depth), stage.name),
+            stage.duration.as_secs_f64(),
+            percent(stage.duration, total),
+            width = width,
+        );
+    }
+
+    if timings == Timings::Html {
+        if let Err(err) = write_html(args, title, total, &stages) {
+            log::warn!("Could not write the timings report: {}", err);
+        }
+    }
+}
+
+fn percent(duration: Duration, total: Duration) -> f64 {
+    if total.is_zero() {
+        0.0
+    } else {
+        100.0 * duration.as_secs_f64() / total.as_secs_f64()
+    }
+}
+
+fn write_html(
+    args: &dyn BuildArgs,
+    title: &str,
+    total: Duration,
+    stages: &[Stage],
+) -> std::io::Result<()> {
+    let mut rows = String::new();
+    for stage in stages {
+        rows.push_str(&format!(
+            "<tr><td style=\"padding-left: {}em\">{}</td><td>{:.2}s</td><td><div class=\"bar\" \
+            style=\"width: {:.1}%\"></div></td></tr>",
+            0.5 + 1.5 * stage.depth as f64,
+            escape_html(&stage.name),
+            stage.duration.as_secs_f64(),
+            percent(stage.duration, total),
+        ));
+    }
+    let html = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>wasm-run timings</title>\
+        <style>body {{ font-family: sans-serif; }} \
+        table {{ border-collapse: collapse; width: 100%; }} \
+        td {{ padding: 0.2em 0.5em; border-bottom: 1px solid #ddd; white-space: nowrap; }} \
+        td:last-child {{ width: 60%; }} .bar {{ background: #4a90d9; height: 1em; }}</style>\
+        </head><body><h1>Timings of the {} of {}</h1><p>Total: {:.2}s</p>\
+        <table><tr><th>Stage</th><th>Duration</th><th></th></tr>{}</table></body></html>",
+        escape_html(title),
+        escape_html(&args.frontend_package().name),
+        total.as_secs_f64(),
+        rows,
+    );
+
+    let path = args.target_path().join("wasm-run").join("timings");
+    fs::create_dir_all(&path)?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let report_path = path.join(format!("wasm-run-timing-{}.html", timestamp));
+    fs::write(&report_path, &html)?;
+    fs::write(path.join("wasm-run-timing.html"), &html)?;
+    log::info!("Timings report written to `{}`", report_path);
+    Ok(())
+}
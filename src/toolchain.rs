@@ -0,0 +1,101 @@
+use crate::BuildArgs;
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::Ordering;
+
+const TARGET: &str = "wasm32-unknown-unknown";
+
+/// Check (once) that the toolchain can build the frontend: the WASM target must be installed and
+/// the version of `wasm-bindgen` used by the frontend should match the one of `wasm-run`.
+pub(crate) fn check(args: &dyn BuildArgs) -> Result<()> {
+    let checked = &args.state().toolchain_checked;
+    if checked.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    check_target(args)?;
+    check_bindgen_version(args);
+    checked.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Check the toolchain again at the next build (the toolchain file or the lock file changed).
+pub(crate) fn invalidate(args: &dyn BuildArgs) {
+    args.state()
+        .toolchain_checked
+        .store(false, Ordering::SeqCst);
+}
+
+fn check_target(args: &dyn BuildArgs) -> Result<()> {
+    let frontend_dir = args.frontend_package().manifest_path.parent().unwrap();
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+
+    // NOTE: rustc is run in the frontend directory so rustup picks its toolchain file
+    let output = Command::new(&rustc)
+        .args(["--print", "sysroot"])
+        .current_dir(frontend_dir)
+        .output()
+        .context("could not run rustc")?;
+
+    if !output.status.success() {
+        // NOTE: let cargo report the error
+        return Ok(());
+    }
+
+    let sysroot = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    if sysroot.join("lib").join("rustlib").join(TARGET).exists() {
+        return Ok(());
+    }
+
+    if !args.auto_install_target() {
+        bail!(
+            "the target `{0}` is not installed for the toolchain `{1}`: install it with \
+            `rustup target add {0}` or use `--auto-install-target`",
+            TARGET,
+            sysroot.display(),
+        );
+    }
+
+    log::info!("Installing target {}", TARGET);
+    let status = Command::new("rustup")
+        .args(["target", "add", TARGET])
+        .current_dir(frontend_dir)
+        .status()
+        .context("could not run rustup")?;
+
+    if !status.success() {
+        bail!("could not install the target `{}`", TARGET);
+    }
+
+    Ok(())
+}
+
+fn check_bindgen_version(args: &dyn BuildArgs) {
+    if args.external_bindgen() {
+        return;
+    }
+
+    let packages = &args.metadata().packages;
+    let cli_support = match packages
+        .iter()
+        .find(|x| x.name == "wasm-bindgen-cli-support")
+    {
+        Some(x) => x,
+        None => return,
+    };
+
+    for bindgen in packages
+        .iter()
+        .filter(|x| x.name == "wasm-bindgen" && x.version != cli_support.version)
+    {
+        log::warn!(
+            "wasm-bindgen {} does not match the version of wasm-bindgen-cli-support used by \
+            wasm-run ({}): the build will probably fail. Use the same version with \
+            `cargo update -p wasm-bindgen --precise {}` or use `--external-bindgen`",
+            bindgen.version,
+            cli_support.version,
+            cli_support.version,
+        );
+    }
+}
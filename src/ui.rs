@@ -0,0 +1,223 @@
+use crate::Verbosity;
+use once_cell::sync::{Lazy, OnceCell};
+use std::fmt;
+use std::io::{IsTerminal, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static COLOR: OnceCell<ColorChoice> = OnceCell::new();
+
+/// Line of the spinner currently displayed on the terminal, without its frame.
+static SPINNER: Lazy<Mutex<Option<String>>> = Lazy::new(Default::default);
+
+const FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(80);
+
+/// Use of the colors in the output (`--color`).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ColorChoice {
+    /// Colors are used if the standard error is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    /// Colors are always used.
+    Always,
+    /// Colors are never used.
+    Never,
+}
+
+impl ColorChoice {
+    /// The choice of the command line. [`ColorChoice::Auto`] if it was not set.
+    pub fn global() -> Self {
+        COLOR.get().copied().unwrap_or_default()
+    }
+
+    /// Set the choice of the process. It can only be set once and before the logger is
+    /// installed, otherwise it is ignored.
+    pub(crate) fn set_global(self) {
+        let _ = COLOR.set(self);
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!("invalid color choice `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Always => write!(f, "always"),
+            Self::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// Whether the output on the standard error uses colors.
+pub(crate) fn colored() -> bool {
+    match ColorChoice::global() {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|x| x.is_empty())
+                && std::io::stderr().is_terminal()
+        }
+    }
+}
+
+/// Style of the logs of `env_logger`.
+pub(crate) fn write_style() -> env_logger::WriteStyle {
+    if colored() {
+        env_logger::WriteStyle::Always
+    } else {
+        env_logger::WriteStyle::Never
+    }
+}
+
+/// The text with the terminal color `code` (an SGR parameter) if the colors are used.
+pub(crate) fn paint(code: &str, text: &str) -> String {
+    if colored() {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Run `f` (that writes on the standard error) with the spinner hidden, so its line is not
+/// mixed with the output of `f`.
+pub(crate) fn suspend<T>(f: impl FnOnce() -> T) -> T {
+    let spinner = SPINNER.lock().unwrap();
+    if spinner.is_some() {
+        eprint!("\r\x1b[2K");
+    }
+    let res = f();
+    if let Some(line) = spinner.as_deref() {
+        eprint!("{} {}", paint("36", &FRAMES[0].to_string()), line);
+        let _ = std::io::stderr().flush();
+    }
+    res
+}
+
+/// The steps of a build, displayed as `[1/5] cargo build`. Nothing is displayed with
+/// [`Verbosity::Quiet`].
+///
+/// On a terminal, a spinner runs until the step is finished. Otherwise a line is printed when the
+/// step starts.
+pub(crate) struct Progress {
+    total: usize,
+    current: usize,
+    verbosity: Verbosity,
+}
+
+impl Progress {
+    pub(crate) fn new(total: usize, verbosity: Verbosity) -> Self {
+        Self {
+            total,
+            current: 0,
+            verbosity,
+        }
+    }
+
+    /// Start the next step. The spinner is not used if `streamed` (the step writes on the
+    /// terminal, like cargo).
+    pub(crate) fn step(&mut self, name: &str, streamed: bool) -> Step {
+        self.current += 1;
+        let line = format!(
+            "{} {}",
+            paint("1;36", &format!("[{}/{}]", self.current, self.total)),
+            name
+        );
+        Step::start(line, streamed, self.verbosity)
+    }
+}
+
+/// A step in progress. It is displayed as failed if it is dropped without [`Step::finish`].
+pub(crate) struct Step {
+    line: String,
+    started: Instant,
+    spinner: Option<(Arc<AtomicBool>, thread::JoinHandle<()>)>,
+    finished: bool,
+}
+
+impl Step {
+    fn start(line: String, streamed: bool, verbosity: Verbosity) -> Self {
+        let enabled = verbosity >= Verbosity::Normal;
+        let mut spinner = None;
+        if enabled {
+            let mut current = SPINNER.lock().unwrap();
+            // NOTE: only one spinner is displayed, the steps of the builds that run at the same
+            //       time are printed as lines
+            if !streamed && current.is_none() && std::io::stderr().is_terminal() {
+                *current = Some(line.clone());
+                let stop = Arc::new(AtomicBool::new(false));
+                let handle = {
+                    let stop = Arc::clone(&stop);
+                    thread::spawn(move || spin(&stop))
+                };
+                spinner = Some((stop, handle));
+            } else {
+                eprintln!("{}", line);
+            }
+        }
+
+        Self {
+            line,
+            started: Instant::now(),
+            spinner,
+            finished: false,
+        }
+    }
+
+    /// Mark the step as succeeded.
+    pub(crate) fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for Step {
+    fn drop(&mut self) {
+        if let Some((stop, handle)) = self.spinner.take() {
+            stop.store(true, Ordering::SeqCst);
+            handle.thread().unpark();
+            let _ = handle.join();
+            let mut current = SPINNER.lock().unwrap();
+            *current = None;
+            let mark = if self.finished {
+                paint("32", "✓")
+            } else {
+                paint("31", "✗")
+            };
+            eprintln!(
+                "\r\x1b[2K{} {} {}",
+                mark,
+                self.line,
+                paint("2", &format!("({:.2?})", self.started.elapsed())),
+            );
+        }
+    }
+}
+
+fn spin(stop: &AtomicBool) {
+    for frame in FRAMES.iter().cycle() {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        if let Some(line) = SPINNER.lock().unwrap().as_deref() {
+            eprint!("\r\x1b[2K{} {}", paint("36", &frame.to_string()), line);
+            let _ = std::io::stderr().flush();
+        }
+        thread::park_timeout(FRAME_INTERVAL);
+    }
+}
@@ -0,0 +1,60 @@
+use std::process::Command;
+
+/// Verbosity of the commands (`-q`, `-qq`, `-v` and `-vv`). It sets the default filter of the
+/// logger (`RUST_LOG` still takes precedence) and the output of the cargo commands.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+pub enum Verbosity {
+    /// `-qq`: only the errors are logged and the output of cargo is hidden.
+    Silent,
+    /// `-q`: only the warnings and the errors are logged. cargo runs with `--quiet`, the errors
+    /// of the compiler are displayed and its warnings are summarized.
+    Quiet,
+    /// The info messages are logged and the output of cargo is streamed.
+    #[default]
+    Normal,
+    /// `-v`: the debug messages of wasm-run are logged and cargo runs with `--verbose`.
+    Verbose,
+    /// `-vv`: everything is logged and cargo runs with `-vv`.
+    VeryVerbose,
+}
+
+impl Verbosity {
+    /// The verbosity of the occurrences of `-v` and `-q` of the command line.
+    pub fn from_flags(verbose: u8, quiet: u8) -> Self {
+        match (verbose, quiet) {
+            (0, 0) => Self::Normal,
+            (0, 1) => Self::Quiet,
+            (0, _) => Self::Silent,
+            (1, _) => Self::Verbose,
+            _ => Self::VeryVerbose,
+        }
+    }
+
+    /// Default filter of `env_logger`.
+    pub(crate) fn default_filter(self) -> &'static str {
+        match self {
+            Self::Silent => "error",
+            Self::Quiet => "warn",
+            Self::Normal => "info",
+            Self::Verbose => "info,wasm_run=debug",
+            Self::VeryVerbose => "trace",
+        }
+    }
+
+    /// Add the verbosity flag matching this one to a cargo command. It must be called after the
+    /// subcommand of cargo has been added.
+    pub(crate) fn cargo_args(self, command: &mut Command) {
+        match self {
+            Self::Silent | Self::Quiet => {
+                command.arg("--quiet");
+            }
+            Self::Normal => {}
+            Self::Verbose => {
+                command.arg("--verbose");
+            }
+            Self::VeryVerbose => {
+                command.arg("-vv");
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::ServeArgs;
+use anyhow::Result;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A task run by the `serve` command when the files of some paths change, instead of rebuilding
+/// the frontend (and restarting the backend).
+///
+/// Every task has its own watcher: the changes are grouped until no change happened for `delay`
+/// and the task receives the paths that changed. The tasks are added to
+/// [`Hooks::watch_tasks`](crate::Hooks::watch_tasks) (or returned by the function given to the
+/// argument `watch_tasks` of the macro [`main`](crate::main)):
+///
+/// ```ignore
+/// fn watch_tasks() -> Vec<WatchTask> {
+///     vec![
+///         WatchTask::new("graphql", vec!["graphql".into()], |_args, _paths| {
+///             run_codegen()
+///         }),
+///         WatchTask::new("styles", vec!["frontend/styles".into()], |args, _paths| {
+///             build_css(args.build_args().build_path())
+///         }),
+///     ]
+/// }
+/// ```
+pub struct WatchTask {
+    /// Name of the task in the logs.
+    pub name: String,
+    /// Paths watched (recursively), relative to the root of the workspace.
+    pub paths: Vec<PathBuf>,
+    /// Time without change before the task is run.
+    pub delay: Duration,
+    /// The changes of the paths don't trigger the rebuild of the frontend nor the restart of the
+    /// backend (`true` by default).
+    pub exclusive: bool,
+    /// Function run with the paths that changed.
+    #[allow(clippy::type_complexity)]
+    pub run: Box<dyn Fn(&dyn ServeArgs, &[PathBuf]) -> Result<()> + Send + Sync>,
+}
+
+impl WatchTask {
+    /// A task that runs `run` when the files of `paths` change, exclusively, with a delay of
+    /// 200 milliseconds.
+    pub fn new(
+        name: impl Into<String>,
+        paths: Vec<PathBuf>,
+        run: impl Fn(&dyn ServeArgs, &[PathBuf]) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            paths,
+            delay: Duration::from_millis(200),
+            exclusive: true,
+            run: Box::new(run),
+        }
+    }
+}
+
+impl fmt::Debug for WatchTask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WatchTask")
+            .field("name", &self.name)
+            .field("paths", &self.paths)
+            .field("delay", &self.delay)
+            .field("exclusive", &self.exclusive)
+            .finish()
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{BuildArgs, BuildProfile, Config};
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::env;
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The threads that send the notifications, waited for when the command ends.
+static PENDING: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// A URL notified of the end of the builds with an HTTP POST of a JSON summary:
+///
+/// ```json
+/// {
+///     "event": "build-succeeded",
+///     "package": "my-frontend",
+///     "profile": "release",
+///     "rebuild": false,
+///     "duration_ms": 4210,
+///     "build_path": "/home/me/my-project/build",
+///     "error": null
+/// }
+/// ```
+///
+/// The event is `build-failed` and `error` is the message of the error when the build fails.
+/// Check [`BuildArgs::webhooks`] for the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Webhook {
+    /// URL of the POST request.
+    pub url: String,
+    /// Notify the successful builds.
+    pub on_success: bool,
+    /// Notify the failed builds.
+    pub on_failure: bool,
+}
+
+impl Webhook {
+    /// A webhook notified of all the builds.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            on_success: true,
+            on_failure: true,
+        }
+    }
+
+    pub(crate) fn from_config(config: &Config) -> Result<Vec<Self>> {
+        let mut webhooks = match config.get("webhooks") {
+            Value::Null => Vec::new(),
+            Value::Array(values) => values.iter().map(Self::from_value).collect::<Result<_>>()?,
+            _ => bail!("invalid `webhooks`: expected an array"),
+        };
+
+        if let Ok(urls) = env::var("WASM_RUN_WEBHOOKS") {
+            webhooks.extend(
+                urls.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|x| !x.is_empty())
+                    .map(Self::new),
+            );
+        }
+
+        Ok(webhooks)
+    }
+
+    fn from_value(value: &Value) -> Result<Self> {
+        let table = match value {
+            Value::String(url) => return Ok(Self::new(url.as_str())),
+            Value::Object(table) => table,
+            _ => bail!("invalid `webhooks`: expected URLs or tables"),
+        };
+
+        let mut webhook = Self::new(
+            table
+                .get("url")
+                .and_then(Value::as_str)
+                .context("invalid `webhooks`: `url` must be a string")?,
+        );
+        if let Some(on) = table.get("on") {
+            let on = on
+                .as_array()
+                .and_then(|x| x.iter().map(Value::as_str).collect::<Option<Vec<_>>>())
+                .context("invalid `webhooks`: `on` must be an array of strings")?;
+            webhook.on_success = false;
+            webhook.on_failure = false;
+            for event in on {
+                match event {
+                    "success" => webhook.on_success = true,
+                    "failure" => webhook.on_failure = true,
+                    _ => bail!(
+                        "invalid `webhooks`: unknown event `{}` (expected `success` or \
+                        `failure`)",
+                        event
+                    ),
+                }
+            }
+        }
+
+        Ok(webhook)
+    }
+}
+
+/// Send the summary of a build to the webhooks on a background thread. The errors are only logged:
+/// a webhook can't make the build fail or slow it down.
+pub(crate) fn notify<T>(
+    args: &dyn BuildArgs,
+    profile: BuildProfile,
+    rebuild: bool,
+    started: Instant,
+    res: &Result<T>,
+) {
+    let webhooks = match args.webhooks() {
+        Ok(webhooks) => webhooks,
+        Err(err) => {
+            log::warn!("Could not read the webhooks: {:#}", err);
+            return;
+        }
+    };
+    let webhooks = webhooks
+        .iter()
+        .filter(|x| match res {
+            Ok(_) => x.on_success,
+            Err(_) => x.on_failure,
+        })
+        .collect::<Vec<_>>();
+    if webhooks.is_empty() {
+        return;
+    }
+
+    let summary = json!({
+        "event": if res.is_ok() { "build-succeeded" } else { "build-failed" },
+        "package": args.frontend_package().name,
+        "profile": format!("{:?}", profile).to_lowercase(),
+        "rebuild": rebuild,
+        "duration_ms": started.elapsed().as_millis() as u64,
+        "build_path": args.build_path(),
+        "error": res.as_ref().err().map(|err| format!("{:#}", err)),
+    })
+    .to_string();
+
+    let urls = webhooks
+        .into_iter()
+        .map(|x| x.url.clone())
+        .collect::<Vec<_>>();
+    let handle = thread::spawn(move || {
+        for url in urls {
+            if let Err(err) = post(&url, summary.as_bytes()) {
+                log::warn!("Could not notify the webhook `{}`: {:#}", url, err);
+            }
+        }
+    });
+
+    let mut pending = PENDING.lock().unwrap();
+    pending.retain(|x| !x.is_finished());
+    pending.push(handle);
+}
+
+/// Wait for the notifications still being sent: the process must not exit before them.
+pub(crate) fn wait() {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+    if pending.iter().any(|x| !x.is_finished()) {
+        log::debug!("Waiting for the webhooks");
+    }
+    for handle in pending {
+        let _ = handle.join();
+    }
+}
+
+fn post(url: &str, body: &[u8]) -> Result<()> {
+    let mut headers = curl::easy::List::new();
+    headers.append("Content-Type: application/json")?;
+
+    let mut easy = curl::easy::Easy::new();
+    easy.url(url)?;
+    easy.post(true)?;
+    easy.post_fields_copy(body)?;
+    easy.http_headers(headers)?;
+    easy.timeout(TIMEOUT)?;
+    easy.fail_on_error(true)?;
+    {
+        let mut transfer = easy.transfer();
+        transfer.write_function(|chunk| Ok(chunk.len()))?;
+        transfer.perform()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(value: Value) -> Config {
+        let mut config = Config::default();
+        config.values.insert("webhooks".to_string(), value);
+        config
+    }
+
+    fn error(value: Value) -> String {
+        format!("{:#}", Webhook::from_config(&config(value)).unwrap_err())
+    }
+
+    #[test]
+    fn from_config() {
+        // NOTE: the only test that reads the environment variable
+        env::remove_var("WASM_RUN_WEBHOOKS");
+        assert_eq!(
+            Webhook::from_config(&Config::default()).unwrap(),
+            Vec::new()
+        );
+        assert_eq!(
+            Webhook::from_config(&config(json!([
+                "https://example.com/all",
+                { "url": "https://example.com/failures", "on": ["failure"] },
+                { "url": "https://example.com/none", "on": [] },
+            ])))
+            .unwrap(),
+            vec![
+                Webhook::new("https://example.com/all"),
+                Webhook {
+                    url: "https://example.com/failures".to_string(),
+                    on_success: false,
+                    on_failure: true,
+                },
+                Webhook {
+                    url: "https://example.com/none".to_string(),
+                    on_success: false,
+                    on_failure: false,
+                },
+            ]
+        );
+
+        env::set_var(
+            "WASM_RUN_WEBHOOKS",
+            " https://a.example.com,https://b.example.com\n",
+        );
+        let webhooks = Webhook::from_config(&config(json!(["https://example.com"])));
+        env::remove_var("WASM_RUN_WEBHOOKS");
+        assert_eq!(
+            webhooks.unwrap(),
+            vec![
+                Webhook::new("https://example.com"),
+                Webhook::new("https://a.example.com"),
+                Webhook::new("https://b.example.com"),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_invalid_config() {
+        assert_eq!(
+            error(json!("https://example.com")),
+            "invalid `webhooks`: expected an array"
+        );
+        assert_eq!(
+            error(json!([1])),
+            "invalid `webhooks`: expected URLs or tables"
+        );
+        assert_eq!(
+            error(json!([{ "on": ["success"] }])),
+            "invalid `webhooks`: `url` must be a string"
+        );
+        assert_eq!(
+            error(json!([{ "url": "https://example.com", "on": "success" }])),
+            "invalid `webhooks`: `on` must be an array of strings"
+        );
+        assert_eq!(
+            error(json!([{ "url": "https://example.com", "on": ["start"] }])),
+            "invalid `webhooks`: unknown event `start` (expected `success` or `failure`)"
+        );
+    }
+}
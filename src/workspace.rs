@@ -0,0 +1,256 @@
+use crate::state::State;
+use crate::{package_port, runner_packages, select_frontend, Config, Hooks, Verbosity};
+use anyhow::{bail, Context, Result};
+use camino::Utf8PathBuf;
+use cargo_metadata::{Metadata, Package};
+use once_cell::sync::OnceCell;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Context of the macro [`main`](crate::main): it is initialized by `wasm_run_init()` and used by
+/// the arguments that don't have their own context.
+static GLOBAL: OnceCell<WorkspaceContext> = OnceCell::new();
+
+/// Everything the commands need to know about the workspace: its metadata, the frontend and
+/// backend packages, the defaults of the arguments, the configuration, the verbosity and the
+/// hooks. It also holds the state of the builds, the watchers and the development server (like
+/// the last error displayed by the overlay).
+///
+/// The macro [`main`](crate::main) initializes a global context that is used by the arguments
+/// by default. A context can also be created explicitly and given to the arguments (check
+/// [`DefaultBuildArgs::with_context`](crate::DefaultBuildArgs::with_context)) so several
+/// projects can be built in the same process, for example in a test harness.
+pub struct WorkspaceContext {
+    metadata: Metadata,
+    frontend_package: usize,
+    backend_package: Option<usize>,
+    default_build_path: Utf8PathBuf,
+    default_port: u16,
+    config: Config,
+    verbosity: Verbosity,
+    hooks: Arc<Hooks>,
+    state: State,
+}
+
+impl fmt::Debug for WorkspaceContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WorkspaceContext")
+            .field("frontend_package", &self.frontend_package().name)
+            .field("backend_package", &self.backend_package().map(|x| &x.name))
+            .field("default_build_path", &self.default_build_path)
+            .field("default_port", &self.default_port)
+            .finish()
+    }
+}
+
+impl WorkspaceContext {
+    /// Create the context of the workspace `metadata` with the package `frontend_pkg_name` as
+    /// frontend (or the detected one if it is `None`) and the package `backend_pkg_name` as
+    /// backend.
+    pub fn new(
+        metadata: Metadata,
+        frontend_pkg_name: Option<&str>,
+        backend_pkg_name: Option<&str>,
+        hooks: Hooks,
+    ) -> Result<Self> {
+        Self::init(
+            metadata,
+            frontend_pkg_name,
+            backend_pkg_name,
+            None,
+            false,
+            hooks,
+        )
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn init(
+        metadata: Metadata,
+        pkg_name: Option<&str>,
+        backend_pkg_name: Option<&str>,
+        default_build_path: Option<Box<dyn FnOnce(&Metadata, &Package) -> Utf8PathBuf>>,
+        per_package_defaults: bool,
+        hooks: Hooks,
+    ) -> Result<Self> {
+        let frontend_package = select_frontend(&metadata, pkg_name)?;
+        let config = Config::load(&metadata, frontend_package)?;
+
+        let backend_package = match backend_pkg_name {
+            Some(name) => Some(
+                metadata
+                    .packages
+                    .iter()
+                    .position(|x| x.name == name)
+                    .with_context(|| format!("package `{}` not found in the workspace", name))?,
+            ),
+            None => None,
+        };
+
+        let runners = runner_packages(&metadata);
+        if runners.len() > 1
+            && !per_package_defaults
+            && default_build_path.is_none()
+            && config.build_path.is_none()
+            && config.port.is_none()
+        {
+            log::warn!(
+                "The runner crates {} share the default build directory and the default port \
+                3000: running them at the same time will make them collide (use the argument \
+                `per_package_defaults` of the macro, or `build_path` and `port` in their \
+                configuration)",
+                runners
+                    .iter()
+                    .map(|x| format!("`{}`", x.name))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        }
+        let per_package_defaults = per_package_defaults && runners.len() > 1;
+
+        let default_build_path = if let Some(build_path) = config.build_path.clone() {
+            build_path
+        } else if let Some(default_build_path) = default_build_path {
+            default_build_path(&metadata, frontend_package)
+        } else if per_package_defaults {
+            crate::metadata_path(&metadata.workspace_root)
+                .join("build")
+                .join(&frontend_package.name)
+        } else {
+            crate::metadata_path(&metadata.workspace_root).join("build")
+        };
+
+        let default_port = if let Some(port) = config.port {
+            port
+        } else if per_package_defaults {
+            package_port(&frontend_package.name)
+        } else {
+            3000
+        };
+
+        let frontend_package = metadata
+            .packages
+            .iter()
+            .position(|x| x.id == frontend_package.id)
+            .expect("the frontend package comes from the metadata; qed");
+        let mut metadata = metadata;
+        metadata.target_directory = target_directory(&metadata, &config);
+
+        Ok(Self {
+            metadata,
+            frontend_package,
+            backend_package,
+            default_build_path,
+            default_port,
+            config,
+            verbosity: Verbosity::default(),
+            hooks: Arc::new(hooks),
+            state: State::default(),
+        })
+    }
+
+    /// Use the verbosity `verbosity` for the commands ([`Verbosity::Normal`] by default).
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// The global context initialized by the macro [`main`](crate::main).
+    ///
+    /// # Panics
+    ///
+    /// Panics if it has not been initialized.
+    pub fn global() -> &'static Self {
+        GLOBAL.get().expect("wasm_run_init() has not been called")
+    }
+
+    /// The global context if it has been initialized.
+    pub(crate) fn try_global() -> Option<&'static Self> {
+        GLOBAL.get()
+    }
+
+    pub(crate) fn set_global(context: Self) -> Result<&'static Self> {
+        if GLOBAL.set(context).is_err() {
+            bail!("the global context of wasm-run has already been initialized");
+        }
+        Ok(Self::global())
+    }
+
+    /// Metadata of the workspace.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Frontend package.
+    pub fn frontend_package(&self) -> &Package {
+        &self.metadata.packages[self.frontend_package]
+    }
+
+    /// Backend package.
+    pub fn backend_package(&self) -> Option<&Package> {
+        self.backend_package.map(|x| &self.metadata.packages[x])
+    }
+
+    /// Default build directory.
+    pub fn default_build_path(&self) -> &Utf8PathBuf {
+        &self.default_build_path
+    }
+
+    /// Default port of the development server.
+    pub fn default_port(&self) -> u16 {
+        self.default_port
+    }
+
+    /// Settings of `WasmRun.toml` and `[package.metadata.wasm-run]`.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Verbosity of the commands.
+    pub fn verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+
+    /// Hooks of the commands.
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    /// Hooks of the commands, for the development server and the watchers that use them on their
+    /// own threads.
+    pub(crate) fn shared_hooks(&self) -> Arc<Hooks> {
+        Arc::clone(&self.hooks)
+    }
+
+    /// Use the hooks of another context.
+    pub(crate) fn with_shared_hooks(mut self, hooks: Arc<Hooks>) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    pub(crate) fn state(&self) -> &State {
+        &self.state
+    }
+}
+
+/// The target directory of the cargo commands run by wasm-run: `CARGO_TARGET_DIR` (or else
+/// `CARGO_BUILD_TARGET_DIR`) of the `env` of the configuration, relative to the root of the
+/// workspace, if it is set. Otherwise it is the one resolved by cargo for the metadata
+/// (`CARGO_TARGET_DIR`, `CARGO_BUILD_TARGET_DIR` or `build.target-dir` of the cargo
+/// configuration).
+///
+/// It replaces `target_directory` of the metadata and is given explicitly to the cargo commands
+/// so the artifacts are found whatever the current directory of the commands.
+pub(crate) fn target_directory(metadata: &Metadata, config: &Config) -> PathBuf {
+    let setting = |name: &str| {
+        config
+            .env
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, path)| path)
+    };
+    match setting("CARGO_TARGET_DIR").or_else(|| setting("CARGO_BUILD_TARGET_DIR")) {
+        Some(path) => metadata.workspace_root.join(path),
+        None => metadata.target_directory.clone(),
+    }
+}
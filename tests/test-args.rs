@@ -0,0 +1,166 @@
+use std::fs;
+use wasm_run::prelude::*;
+use wasm_run::structopt::StructOpt;
+
+fn temp_dir(name: &str) -> Utf8PathBuf {
+    let path = std::env::temp_dir().join(format!("wasm-run-{}-{}", name, std::process::id()));
+    let path = Utf8PathBuf::from_path_buf(path).unwrap();
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+/// A hook as it would be written by a user: it writes a file in the build directory.
+fn post_build(context: &BuildContext, _wasm_js: String, wasm_bin: Vec<u8>) -> anyhow::Result<()> {
+    fs::create_dir_all(context.build_path())?;
+    fs::write(
+        context.build_path().join("size.txt"),
+        format!("{} {:?}", wasm_bin.len(), context.profile()),
+    )?;
+    Ok(())
+}
+
+#[test]
+fn build_args() {
+    let root = temp_dir("build-args");
+    let args = TestBuildArgs::new(&root).unwrap();
+    assert_eq!(args.build_path(), &root.join("build"));
+    assert_eq!(args.frontend_package().name, "app");
+    assert!(!args.profiling());
+
+    let args = args.with_build_path(root.join("public"));
+    post_build(
+        &BuildContext::from_args(&args, BuildProfile::Release),
+        String::new(),
+        vec![0; 42],
+    )
+    .unwrap();
+    assert_eq!(
+        fs::read_to_string(root.join("public").join("size.txt")).unwrap(),
+        "42 Release",
+    );
+
+    let context = BuildContext::from_args(&args, BuildProfile::Dev);
+    assert!(!context.is_rebuild());
+    assert!(context.with_rebuild(true).is_rebuild());
+}
+
+#[cfg(feature = "dev-server")]
+#[test]
+fn serve_args_with_config() {
+    let root = temp_dir("serve-args");
+    fs::write(root.join("WasmRun.toml"), "port = 4000\n").unwrap();
+    let args = TestServeArgs::new(TestBuildArgs::new(&root).unwrap());
+    assert_eq!(args.port(), 4000);
+    assert_eq!(args.ip(), "127.0.0.1");
+    assert_eq!(args.build_args().build_path(), &root.join("build"));
+    assert_eq!(args.with_port(8080).port(), 8080);
+}
+
+#[test]
+fn run_context_profile() {
+    let root = temp_dir("run-context");
+    let build_args = TestBuildArgs::new(&root).unwrap();
+
+    let args = TestServeArgs::new(build_args.clone());
+    let context = RunContext::from_args(&args);
+    assert_eq!(context.profile(), BuildProfile::Dev);
+    assert_eq!(context.build_path(), root.join("build"));
+    assert!(context.backend_package().is_none());
+
+    let args = TestServeArgs::new(build_args.with_profiling(true));
+    assert_eq!(
+        RunContext::from_args(&args).profile(),
+        BuildProfile::Profiling
+    );
+}
+
+#[test]
+fn out_dir_aliases() {
+    let root = temp_dir("out-dir");
+    fs::write(root.join("WasmRun.toml"), "out_dir = \"dist\"\n").unwrap();
+    let args = TestBuildArgs::new(&root).unwrap();
+    assert_eq!(args.build_path(), &root.join("dist"));
+
+    for flag in &["--out-dir", "--build-path"] {
+        let args = DefaultBuildArgs::from_iter_safe(&["build", flag, "public"]).unwrap();
+        assert_eq!(args.build_path, Some(Utf8PathBuf::from("public")));
+    }
+
+    fs::write(
+        root.join("WasmRun.toml"),
+        "out_dir = \"dist\"\nbuild_path = \"public\"\n",
+    )
+    .unwrap();
+    assert!(TestBuildArgs::new(&root).is_err());
+}
+
+#[test]
+fn frontend_bin() {
+    let args = DefaultBuildArgs::from_iter_safe(&["build"]).unwrap();
+    assert_eq!(BuildArgs::frontend_bin(&args), None);
+
+    let args = DefaultBuildArgs::from_iter_safe(&["build", "--frontend-bin", "app"]).unwrap();
+    assert_eq!(BuildArgs::frontend_bin(&args), Some("app".to_string()));
+}
+
+#[test]
+fn relocated_target_dir() {
+    let root = temp_dir("target-dir");
+    let args = TestBuildArgs::new(&root).unwrap();
+    assert_eq!(args.target_path(), &root.join("target"));
+
+    fs::write(
+        root.join("WasmRun.toml"),
+        "[env]\nCARGO_TARGET_DIR = \"../shared-target\"\n",
+    )
+    .unwrap();
+    let args = TestBuildArgs::new(&root).unwrap();
+    let target_path = root.join("../shared-target");
+    assert_eq!(args.target_path(), &target_path);
+    assert_eq!(&args.metadata().target_directory, &target_path);
+    assert!(TestServeArgs::new(args)
+        .watch_excluded_paths()
+        .contains(&target_path));
+}
+
+#[test]
+fn build_target_dir_setting() {
+    let root = temp_dir("build-target-dir");
+    fs::write(
+        root.join("WasmRun.toml"),
+        "[env]\nCARGO_BUILD_TARGET_DIR = \"custom-target\"\n",
+    )
+    .unwrap();
+    let args = TestBuildArgs::new(&root).unwrap();
+    assert_eq!(args.target_path(), &root.join("custom-target"));
+
+    // NOTE: `CARGO_TARGET_DIR` takes precedence, like for cargo
+    fs::write(
+        root.join("WasmRun.toml"),
+        "[env]\nCARGO_BUILD_TARGET_DIR = \"custom-target\"\nCARGO_TARGET_DIR = \"other\"\n",
+    )
+    .unwrap();
+    let args = TestBuildArgs::new(&root).unwrap();
+    let target_path = root.join("other");
+    assert_eq!(args.target_path(), &target_path);
+    #[cfg(feature = "dev-server")]
+    assert!(TestServeArgs::new(args)
+        .status_path()
+        .starts_with(&target_path));
+
+    fs::write(
+        root.join("WasmRun.toml"),
+        "[release.env]\nCARGO_BUILD_TARGET_DIR = \"custom-target\"\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join("Cargo.toml"),
+        "[package]\nname = \"app\"\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+    let args = TestBuildArgs::new(&root).unwrap();
+    let issues = Config::validate(args.metadata(), args.frontend_package()).unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].key, "release.env.CARGO_BUILD_TARGET_DIR");
+}
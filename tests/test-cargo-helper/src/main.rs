@@ -31,33 +31,43 @@ fn other_cli_commands(cli: Cli, metadata: &Metadata, package: &Package) -> anyho
             };
 
             let mut cargo = package.cargo(|command| {
-                command.args(&["build", "--message-format=json"]);
+                command.args(["build", "--message-format=json"]);
             })?;
 
             read_messages(&mut cargo);
             cargo.wait_success()?;
 
             let mut cargo = metadata.cargo(|command| {
-                command.args(&["build", "--message-format=json"]);
+                command.args(["build", "--message-format=json"]);
             })?;
 
             read_messages(&mut cargo);
             cargo.wait_success()?;
 
-            let build_path = Cli::build()?;
+            let output = Cli::build()?;
 
-            if !build_path.exists() {
+            if !output.build_path.exists() {
                 anyhow::bail!("build path must exist");
             }
 
-            std::fs::remove_dir_all(build_path)?;
+            if !output.assets.contains(&output.wasm_path)
+                || !output.assets.contains(&output.js_path)
+            {
+                anyhow::bail!("the artifacts must be listed in the assets");
+            }
+
+            std::fs::remove_dir_all(output.build_path)?;
 
-            let build_path = Cli::build_with_args(&["--profiling"])?;
+            let output = Cli::build_with_args(&["--profiling"])?;
 
-            if !build_path.exists() {
+            if !output.build_path.exists() {
                 anyhow::bail!("build path must exist");
             }
 
+            if output.profile != BuildProfile::Profiling {
+                anyhow::bail!("the profile must be `Profiling`");
+            }
+
             Ok(())
         }
     }
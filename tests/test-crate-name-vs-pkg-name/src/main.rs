@@ -1,5 +0,0 @@
-use structopt::StructOpt;
-
-#[wasm_run::main]
-#[derive(StructOpt, Debug)]
-enum Cli {}
@@ -1,11 +1,15 @@
 use std::fs;
+use std::io::{BufRead, BufReader};
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 fn run_crate(path: &Path, args: &[&str]) {
     let output = Command::new("cargo")
         .current_dir(path)
-        .args(&["run"])
+        .args(["run"])
         .arg("--")
         .args(args)
         .output()
@@ -27,15 +31,6 @@ fn run_crate(path: &Path, args: &[&str]) {
 fn run_test_crates() {
     let tests = Path::new("tests");
 
-    let crate_path = tests.join("test-crate-name-vs-pkg-name");
-    let build_path = Path::new("build");
-    let _ = fs::remove_dir_all(build_path);
-    run_crate(&crate_path, &["build"]);
-    assert!(
-        build_path.exists(),
-        "test for `crate-name-vs-pkg-name` failed"
-    );
-
     let crate_path = tests.join("test-cargo-helper");
     run_crate(&crate_path, &["test"]);
 
@@ -45,6 +40,12 @@ fn run_test_crates() {
     run_crate(&crate_path, &["build"]);
     assert!(build_path.exists(), "test for `default_build_path` failed");
     assert!(build_path.join("fancy.css").exists());
+    // NOTE: the WASM optimized by wasm-opt must be intact (it goes through files on Windows)
+    let wasm = fs::read(build_path.join("app_bg.wasm")).unwrap();
+    assert!(
+        wasm.starts_with(b"\0asm\x01\0\0\0"),
+        "the WASM optimized by wasm-opt is not a valid module"
+    );
 
     let crate_path = tests.join("test-binaryen");
     let build_path = crate_path.join("build");
@@ -55,8 +56,15 @@ fn run_test_crates() {
     let crate_path = tests.join("test-no-serve");
     let build_path = crate_path.join("build");
     let _ = fs::remove_dir_all(&build_path);
-    run_crate(&crate_path, &["build"]);
+    // NOTE: the index.html of the configuration doesn't depend on the current directory
+    run_crate(&crate_path.join("src"), &["build"]);
     assert!(build_path.exists(), "test for `no-serve` failed");
+    assert!(
+        fs::read_to_string(build_path.join("index.html"))
+            .unwrap()
+            .contains("<title>test-no-serve</title>"),
+        "the index.html of the configuration has not been used"
+    );
 
     let crate_path = tests.join("test-sass");
     let build_path = crate_path.join("build");
@@ -71,3 +79,90 @@ fn run_test_crates() {
     assert!(!build_path.join("subdirectory").join("_test6.css").exists());
     assert!(build_path.join("subdirectory").join("test7.css").exists());
 }
+
+#[test]
+fn serve_handle() {
+    // NOTE: the crate serves itself with `Cli::serve_with_args()`, changes a static file and
+    //       waits for the rebuild with `ServeHandle::wait_idle()`
+    let crate_path = Path::new("tests").join("test-default-build-path");
+    let _ = fs::remove_dir_all(crate_path.join("public"));
+    run_crate(&crate_path, &["test-serve"]);
+}
+
+/// Serve the crate, change a file once and return the number of rebuilds.
+fn count_rebuilds(path: &Path, binary: &str, args: &[&str]) -> usize {
+    let status = Command::new("cargo")
+        .args(["build", "-p", binary])
+        .status()
+        .unwrap();
+    assert!(status.success(), "build failed: {}", binary);
+
+    let binary_path = std::env::current_dir()
+        .unwrap()
+        .join("target")
+        .join("debug")
+        .join(binary);
+    let mut child = Command::new(binary_path)
+        .current_dir(path)
+        .args(["serve", "--message-format", "json", "--bind-random-port"])
+        .args(args)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let (tx, rx) = mpsc::channel();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    thread::spawn(move || {
+        for line in stdout.lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let started = rx
+        .iter()
+        .any(|x| x.contains("\"reason\":\"wasm-run-server-started\""));
+    assert!(started, "the server of `{}` did not start", binary);
+    // NOTE: the watcher is started right after the server
+    thread::sleep(Duration::from_secs(3));
+
+    let changed_path = path.join("src").join("changed.rs");
+    fs::write(&changed_path, "").unwrap();
+    let deadline = Instant::now() + Duration::from_secs(20);
+    let mut rebuilds = 0;
+    while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+        match rx.recv_timeout(timeout) {
+            Ok(line) if line.contains("\"reason\":\"wasm-run-rebuild-triggered\"") => rebuilds += 1,
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = fs::remove_file(&changed_path);
+    rebuilds
+}
+
+#[test]
+fn watch_nested_build_path() {
+    let crate_path = Path::new("tests").join("test-default-build-path");
+
+    // NOTE: the default build path is inside the crate, which is watched
+    let _ = fs::remove_dir_all(crate_path.join("public"));
+    assert_eq!(
+        count_rebuilds(&crate_path, "test-default-build-path", &[]),
+        1
+    );
+
+    // NOTE: a relative build path is resolved from the current directory
+    let _ = fs::remove_dir_all(crate_path.join("public"));
+    assert_eq!(
+        count_rebuilds(
+            &crate_path,
+            "test-default-build-path",
+            &["--build-path", "./static/../public"],
+        ),
+        1,
+    );
+}
@@ -1,11 +1,67 @@
-use std::path::PathBuf;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
 use structopt::StructOpt;
 use wasm_run::prelude::*;
 
-#[wasm_run::main(default_build_path)]
+#[wasm_run::main(default_build_path, other_cli_commands)]
 #[derive(StructOpt, Debug)]
-enum Cli {}
+enum Cli {
+    TestServe,
+}
+
+fn default_build_path(_metadata: &Metadata, package: &Package) -> Utf8PathBuf {
+    Utf8PathBuf::from_path_buf(package.manifest_path.parent().unwrap().join("public")).unwrap()
+}
+
+fn other_cli_commands(cli: Cli, _metadata: &Metadata, package: &Package) -> anyhow::Result<()> {
+    match cli {
+        Cli::TestServe => {
+            let changed_path = package
+                .manifest_path
+                .parent()
+                .unwrap()
+                .join("static")
+                .join("changed.txt");
+            fs::write(&changed_path, "before")?;
+
+            let res = (|| {
+                let server = Cli::serve_with_args(["--bind-random-port"])?;
+                if get(server.addr(), "/changed.txt")? != "before" {
+                    anyhow::bail!("the static file must be served");
+                }
+
+                fs::write(&changed_path, "after")?;
+                async_std::task::block_on(server.wait_idle())?;
+                if get(server.addr(), "/changed.txt")? != "after" {
+                    anyhow::bail!("the changed static file must be served after `wait_idle()`");
+                }
+
+                async_std::task::block_on(server.rebuild())?;
+                server.shutdown()
+            })();
+
+            fs::remove_file(&changed_path)?;
+            res
+        }
+    }
+}
 
-fn default_build_path(_metadata: &Metadata, package: &Package) -> PathBuf {
-    package.manifest_path.parent().unwrap().join("public")
+/// Body of the response of the development server to a GET of `path`.
+fn get(mut addr: SocketAddr, path: &str) -> anyhow::Result<String> {
+    if addr.ip().is_unspecified() {
+        addr.set_ip(Ipv4Addr::LOCALHOST.into());
+    }
+    let mut stream = TcpStream::connect(addr)?;
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, addr
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    match response.split_once("\r\n\r\n") {
+        Some((head, body)) if head.starts_with("HTTP/1.1 200") => Ok(body.to_string()),
+        _ => anyhow::bail!("unexpected response to GET {}: {}", path, response),
+    }
 }
@@ -1,20 +1,53 @@
 use syn::parse::{Error, ParseStream, Result};
+use syn::spanned::Spanned;
 use syn::{Ident, LitStr, Path, Token};
 
+/// A function given to a hook argument: `pre_build = my_fn` or `pre_build = async my_fn`.
+pub struct Hook {
+    pub path: Path,
+    pub is_async: bool,
+}
+
 pub struct Attr {
     pub other_cli_commands: Option<Path>,
-    pub pre_build: Option<Path>,
-    pub post_build: Option<Path>,
-    #[cfg(feature = "dev-server")]
-    pub serve: Option<Path>,
-    pub frontend_watch: Option<Path>,
+    pub pre_build: Option<Hook>,
+    pub post_optimize: Option<Hook>,
+    pub js_transform: Option<Hook>,
+    pub post_build: Option<Hook>,
+    #[cfg(feature = "serve")]
+    pub serve_init: Option<Hook>,
+    #[cfg(feature = "serve")]
+    pub serve: Option<Hook>,
+    pub frontend_watch: Option<Hook>,
+    #[cfg(feature = "serve")]
+    pub run_serve_init: Option<Hook>,
+    #[cfg(feature = "serve")]
+    pub run_serve: Option<Hook>,
+    pub run_frontend_watch: Option<Hook>,
+    pub watch_tasks: Option<Path>,
+    pub shutdown: Option<Hook>,
     pub frontend_pkg_name: Option<LitStr>,
-    #[cfg(not(feature = "dev-server"))]
-    pub backend_watch: Option<Path>,
+    #[cfg(not(feature = "serve"))]
+    pub backend_watch: Option<Hook>,
+    #[cfg(not(feature = "serve"))]
+    pub run_backend_watch: Option<Hook>,
     pub backend_pkg_name: Option<LitStr>,
     pub default_build_path: Option<Path>,
     pub build_args: Option<Path>,
     pub serve_args: Option<Path>,
+    pub package_args: Option<Path>,
+    pub default_command: Option<Path>,
+    pub build_aliases: Vec<LitStr>,
+    pub serve_aliases: Vec<LitStr>,
+    pub build_about: Option<LitStr>,
+    pub build_long_about: Option<LitStr>,
+    pub serve_about: Option<LitStr>,
+    pub serve_long_about: Option<LitStr>,
+    pub per_package_defaults: bool,
+    pub build_workspace: bool,
+    pub args_factory: bool,
+    pub command_context: bool,
+    pub detect_frontend: bool,
 }
 
 impl Attr {
@@ -33,37 +66,141 @@ impl Attr {
 
         let mut other_cli_commands = None;
         let mut pre_build = None;
+        let mut post_optimize = None;
+        let mut js_transform = None;
         let mut post_build = None;
-        #[cfg(feature = "dev-server")]
+        #[cfg(feature = "serve")]
+        let mut serve_init = None;
+        #[cfg(feature = "serve")]
         let mut serve = None;
         let mut frontend_watch = None;
-        #[cfg(not(feature = "dev-server"))]
+        #[cfg(feature = "serve")]
+        let mut run_serve_init = None;
+        #[cfg(feature = "serve")]
+        let mut run_serve = None;
+        let mut run_frontend_watch = None;
+        let mut watch_tasks = None;
+        let mut shutdown = None;
+        #[cfg(not(feature = "serve"))]
         let mut backend_watch = None;
+        #[cfg(not(feature = "serve"))]
+        let mut run_backend_watch = None;
         let mut default_build_path = None;
         let mut build_args = None;
         let mut serve_args = None;
+        let mut package_args = None;
+        let mut default_command = None;
+        let mut build_aliases = Vec::new();
+        let mut serve_aliases = Vec::new();
+        let mut build_about = None;
+        let mut build_long_about = None;
+        let mut serve_about = None;
+        let mut serve_long_about = None;
+        let mut per_package_defaults = false;
+        let mut build_workspace = false;
+        let mut args_factory = false;
+        let mut command_context = false;
+        let mut detect_frontend = false;
 
         while !input.is_empty() {
             let ident: Ident = input.parse()?;
-            let path: Path = if input.parse::<Token![=]>().is_ok() {
-                input.parse()?
+            let has_value = input.parse::<Token![=]>().is_ok();
+
+            if ident == "per_package_defaults"
+                || ident == "build_workspace"
+                || ident == "detect_frontend"
+                || ident == "args_factory"
+                || ident == "command_context"
+            {
+                if has_value {
+                    return Err(Error::new(
+                        ident.span(),
+                        "this argument does not take a value",
+                    ));
+                }
+                if ident == "per_package_defaults" {
+                    per_package_defaults = true;
+                } else if ident == "build_workspace" {
+                    build_workspace = true;
+                } else if ident == "args_factory" {
+                    args_factory = true;
+                } else if ident == "command_context" {
+                    command_context = true;
+                } else if frontend_pkg_name.is_some() {
+                    return Err(Error::new(
+                        ident.span(),
+                        "`detect_frontend` cannot be used with a frontend package name",
+                    ));
+                } else {
+                    detect_frontend = true;
+                }
+            } else if has_value && input.peek(LitStr) {
+                let value: LitStr = input.parse()?;
+
+                match ident.to_string().as_str() {
+                    "build_alias" => build_aliases.push(value),
+                    "serve_alias" => serve_aliases.push(value),
+                    "build_about" => build_about = Some(value),
+                    "build_long_about" => build_long_about = Some(value),
+                    "serve_about" => serve_about = Some(value),
+                    "serve_long_about" => serve_long_about = Some(value),
+                    _ => return Err(Error::new(value.span(), "expected a path")),
+                }
             } else {
-                ident.clone().into()
-            };
+                let async_token = if has_value {
+                    input.parse::<Option<Token![async]>>()?
+                } else {
+                    None
+                };
+                let path: Path = if has_value {
+                    input.parse()?
+                } else {
+                    ident.clone().into()
+                };
+                let hook = Hook {
+                    path: path.clone(),
+                    is_async: async_token.is_some(),
+                };
 
-            match ident.to_string().as_str() {
-                "other_cli_commands" => other_cli_commands = Some(path),
-                "pre_build" => pre_build = Some(path),
-                "post_build" => post_build = Some(path),
-                #[cfg(feature = "dev-server")]
-                "serve" => serve = Some(path),
-                #[cfg(not(feature = "dev-server"))]
-                "backend_watch" => backend_watch = Some(path),
-                "frontend_watch" => frontend_watch = Some(path),
-                "default_build_path" => default_build_path = Some(path),
-                "build_args" => build_args = Some(path),
-                "serve_args" => serve_args = Some(path),
-                _ => return Err(Error::new(ident.span(), "invalid argument")),
+                match ident.to_string().as_str() {
+                    "pre_build" => pre_build = Some(hook),
+                    "post_optimize" => post_optimize = Some(hook),
+                    "js_transform" => js_transform = Some(hook),
+                    "post_build" => post_build = Some(hook),
+                    #[cfg(feature = "serve")]
+                    "serve_init" => serve_init = Some(hook),
+                    #[cfg(feature = "serve")]
+                    "serve" => serve = Some(hook),
+                    #[cfg(not(feature = "serve"))]
+                    "backend_watch" => backend_watch = Some(hook),
+                    "frontend_watch" => frontend_watch = Some(hook),
+                    #[cfg(feature = "serve")]
+                    "run_serve_init" => run_serve_init = Some(hook),
+                    #[cfg(feature = "serve")]
+                    "run_serve" => run_serve = Some(hook),
+                    #[cfg(not(feature = "serve"))]
+                    "run_backend_watch" => run_backend_watch = Some(hook),
+                    "run_frontend_watch" => run_frontend_watch = Some(hook),
+                    "shutdown" => shutdown = Some(hook),
+                    _ if async_token.is_some() => {
+                        return Err(Error::new(
+                            async_token.span(),
+                            "only the hooks can be async functions",
+                        ))
+                    }
+                    "other_cli_commands" => other_cli_commands = Some(path),
+                    "watch_tasks" => watch_tasks = Some(path),
+                    "default_build_path" => default_build_path = Some(path),
+                    "build_args" => build_args = Some(path),
+                    "serve_args" => serve_args = Some(path),
+                    "package_args" => package_args = Some(path),
+                    "default_command" => default_command = Some(path),
+                    "build_alias" | "serve_alias" | "build_about" | "build_long_about"
+                    | "serve_about" | "serve_long_about" => {
+                        return Err(Error::new(ident.span(), "expected a string literal"))
+                    }
+                    _ => return Err(Error::new(ident.span(), "invalid argument")),
+                }
             }
 
             let _comma_token: Token![,] = match input.parse() {
@@ -76,17 +213,43 @@ impl Attr {
         Ok(Self {
             other_cli_commands,
             pre_build,
+            post_optimize,
+            js_transform,
             post_build,
-            #[cfg(feature = "dev-server")]
+            #[cfg(feature = "serve")]
+            serve_init,
+            #[cfg(feature = "serve")]
             serve,
             frontend_watch,
+            #[cfg(feature = "serve")]
+            run_serve_init,
+            #[cfg(feature = "serve")]
+            run_serve,
+            run_frontend_watch,
+            watch_tasks,
+            shutdown,
             frontend_pkg_name,
-            #[cfg(not(feature = "dev-server"))]
+            #[cfg(not(feature = "serve"))]
             backend_watch,
+            #[cfg(not(feature = "serve"))]
+            run_backend_watch,
             backend_pkg_name,
             default_build_path,
             build_args,
             serve_args,
+            package_args,
+            default_command,
+            build_aliases,
+            serve_aliases,
+            build_about,
+            build_long_about,
+            serve_about,
+            serve_long_about,
+            per_package_defaults,
+            build_workspace,
+            args_factory,
+            command_context,
+            detect_frontend,
         })
     }
 }
@@ -5,7 +5,10 @@ mod main_generator;
 
 use cargo_metadata::MetadataCommand;
 use proc_macro::TokenStream;
-use syn::{parse_macro_input, ItemEnum};
+use quote::quote;
+use syn::parse::Parser;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Error, ItemEnum};
 
 /// Makes an entrypoint to your binary (not WASM).
 ///
@@ -26,23 +29,95 @@ use syn::{parse_macro_input, ItemEnum};
 /// There are a number of named arguments you can provide to the macro:
 ///  -  `other_cli_commands`: a function that is called if you have added new commands to the
 ///     `enum`;
+///  -  `args_factory`: (takes no value) give an `ArgsFactory` to `other_cli_commands` as fourth
+///     argument: it parses the arguments of the configured `build_args` and `serve_args` types
+///     from any command-line so the custom commands can run a build;
+///  -  `command_context`: (takes no value) call `other_cli_commands` with the command and a
+///     `&CommandContext<B, S>` instead of the metadata and the package: it gives the workspace,
+///     the frontend and backend packages, the hooks, and runs the build pipeline with the
+///     configured `build_args` and `serve_args` types changed programmatically;
 ///  -  `pre_build`: a function that is called when the build has not yet started (you can tweak
 ///     the command-line arguments of the build command);
+///  -  `post_optimize`: a function that is called after the optimization with `wasm-opt` and
+///     before `post_build`: it receives the final WASM binary and returns it (possibly
+///     transformed);
+///  -  `js_transform`: a function that is called after `post_optimize` and before `post_build`:
+///     it receives the JS generated by wasm-bindgen (after the replacements `js_replace` of the
+///     configuration) and returns it, possibly transformed (a public path, a wrapper of `init`...);
 ///  -  `post_build`: a function that is called when the build is finished (after the optimization
-///     with `wasm-opt`);
+///     with `wasm-opt`). The build hooks receive a `BuildContext` with the arguments of the build
+///     command (`&BuildContext<DefaultBuildArgs>` by default), the profile and the timing of the
+///     build;
 ///  -  `frontend_watch`: a function that is called when the watcher is being initialized (allowing
-///      you to add extra things to watch for example);
+///     you to add extra things to watch for example);
 ///  -  `backend_watch`: a function that is called when the watcher is being initialized (allowing
-///      you to add extra things to watch for example);
+///     you to add extra things to watch for example);
+///  -  `watch_tasks`: a function that returns the tasks (`Vec<WatchTask>`) run by the `serve`
+///     command when the files of some paths change instead of rebuilding the frontend;
+///  -  `shutdown`: a function that is called once when the process stops, with the
+///     `ShutdownReason`: at the end of any command or on Ctrl-C and termination (to stop
+///     external processes or flush some state). It is not called on a hard exit;
+///  -  `serve_init`: (only if built with the `serve` feature): a function that is called before
+///     `serve` to initialize the state shared by the endpoints (`ServeContext`);
 ///  -  `serve`: (only if built with the `serve` feature): a function that is called when the HTTP
 ///     serve is getting configured;
+///  -  `run_serve_init`, `run_serve`, `run_frontend_watch` and `run_backend_watch`: the same
+///     hooks but the function receives a `RunContext` (`&RunContext<DefaultServeArgs>` by default)
+///     with the profile of the builds instead of the arguments. They cannot be used with the hook
+///     they replace;
 ///  -  `default_build_path`: a function that is called that provides the default directory path
-///     when the user didn't provide it through the command-line arguments (the default is
-///     `workspace root/build`);
+///     (a `Utf8PathBuf`) when the user didn't provide it through the command-line arguments (the
+///     default is `workspace root/build`, or `workspace root/build/<frontend package>` when the
+///     workspace contains several crates depending on `wasm-run`);
 ///  -  `build_args`: allow you to override the `build` command when providing a custom argument
 ///     (the default is `DefaultBuildArgs`);
 ///  -  `serve_args`: allow you to override the `serve` command when providing a custom argument
-///     (the default is `DefaultServeArgs`).
+///     (the default is `DefaultServeArgs`);
+///  -  `package_args`: allow you to override the `package-image` command when providing a custom
+///     argument (the default is `DefaultPackageArgs`);
+///  -  `default_command`: the command that is run when no command is provided on the command-line:
+///     `build` or `serve` (the default is `serve`);
+///  -  `build_alias` and `serve_alias`: a string literal that adds an alias to the `build` or
+///     `serve` command. They can be provided multiple times;
+///  -  `build_about`, `build_long_about`, `serve_about` and `serve_long_about`: a string literal
+///     that replaces the help text of the `build` or `serve` command (`--help` shows the long
+///     one);
+///  -  `per_package_defaults`: (takes no value) derive the default build directory
+///     (`build/<frontend package>/`) and the default port (between 3000 and 3999) from the name
+///     of the frontend package when the workspace contains several crates depending on
+///     `wasm-run`, so the runners don't collide. By default, they are `build/` and 3000.
+///  -  `build_workspace`: (takes no value) add the command `build-workspace` that builds all the
+///     packages of the workspace with a `[package.metadata.wasm-run]` section, one at a time and
+///     with the hooks of the runner (check `BuildWorkspaceArgs`). It cannot be used with
+///     `build_args`;
+///  -  `detect_frontend`: (takes no value) detect the frontend package at runtime instead of using
+///     the package of the binary: the only member of the workspace that can be built to WASM (a
+///     library with the crate type `cdylib` or depending on `wasm-bindgen`) is used. If there are
+///     several candidates, the frontend is selected with `--package <name>`. It cannot be used
+///     with a frontend package name.
+///
+/// ```ignore
+/// #[wasm_run::main(default_command = build, build_alias = "b", serve_alias = "s")]
+/// enum Cli {}
+/// ```
+///
+/// The hooks (`pre_build`, `post_optimize`, `js_transform`, `post_build`, `serve_init`, `serve`,
+/// `frontend_watch`, `backend_watch`, `shutdown` and their `run_*` versions) can be async
+/// functions if they are prefixed with `async`: they are then run to completion with
+/// `wasm_run::block_on`.
+///
+/// ```ignore
+/// #[wasm_run::main(post_build = async upload_artifacts)]
+/// enum Cli {}
+///
+/// async fn upload_artifacts(
+///     context: &BuildContext<DefaultBuildArgs>,
+///     wasm_js: String,
+///     wasm_bin: Vec<u8>,
+/// ) -> anyhow::Result<()> {
+///     todo!()
+/// }
+/// ```
 ///
 /// You can also change the frontend package that is built by providing its name in the first
 /// positional argument:
@@ -75,3 +150,56 @@ pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
         .unwrap_or_else(|err| err.to_compile_error())
         .into()
 }
+
+/// Expands the macro [`main`] for the tests of its arguments.
+///
+/// It takes an `enum` with the attribute `#[wasm_run::main(...)]` and returns a
+/// `Result<&'static str, &'static str>`: the generated code (formatted like a token stream) or
+/// the message of the compile error. The test suite of the crate checks the expansions and the
+/// compile errors of the arguments with `trybuild` (`tests/ui`).
+///
+/// ```
+/// let result = wasm_run_proc_macro::expand_for_test! {
+///     #[wasm_run::main(serve_args = MyServeArgs)]
+///     enum Cli {}
+/// };
+/// assert_eq!(
+///     result,
+///     Err("if you use a custom ServeArgs, you must use a custom BuildArgs"),
+/// );
+/// ```
+#[proc_macro]
+pub fn expand_for_test(item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemEnum);
+
+    match expand(item) {
+        Ok(code) => {
+            let code = code.to_string();
+            quote! { ::std::result::Result::<&'static str, &'static str>::Ok(#code) }
+        }
+        Err(err) => {
+            let message = err.to_string();
+            quote! { ::std::result::Result::<&'static str, &'static str>::Err(#message) }
+        }
+    }
+    .into()
+}
+
+fn expand(mut item: ItemEnum) -> syn::Result<proc_macro2::TokenStream> {
+    let i = item
+        .attrs
+        .iter()
+        .position(|x| x.path.segments.last().is_some_and(|x| x.ident == "main"))
+        .ok_or_else(|| Error::new(item.span(), "missing attribute `#[wasm_run::main]`"))?;
+    let attr = item.attrs.remove(i);
+    let attr = if attr.tokens.is_empty() {
+        attr_parser::Attr::parse.parse2(proc_macro2::TokenStream::new())?
+    } else {
+        attr.parse_args_with(attr_parser::Attr::parse)?
+    };
+    let metadata = MetadataCommand::new()
+        .exec()
+        .expect("could not get metadata");
+
+    main_generator::generate(item, attr, &metadata)
+}
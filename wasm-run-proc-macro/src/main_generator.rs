@@ -1,7 +1,7 @@
-use crate::attr_parser::Attr;
+use crate::attr_parser::{Attr, Hook};
 use cargo_metadata::Metadata;
 use proc_macro2::TokenStream;
-use quote::{quote, quote_spanned};
+use quote::{format_ident, quote, quote_spanned};
 use syn::spanned::Spanned;
 use syn::{Error, ItemEnum};
 
@@ -10,16 +10,43 @@ pub fn generate(item: ItemEnum, attr: Attr, metadata: &Metadata) -> syn::Result<
     let Attr {
         other_cli_commands,
         pre_build,
+        post_optimize,
+        js_transform,
         post_build,
-        #[cfg(feature = "dev-server")]
+        #[cfg(feature = "serve")]
+        serve_init,
+        #[cfg(feature = "serve")]
         serve,
         frontend_watch,
+        #[cfg(feature = "serve")]
+        run_serve_init,
+        #[cfg(feature = "serve")]
+        run_serve,
+        run_frontend_watch,
+        watch_tasks,
+        shutdown,
         frontend_pkg_name,
+        #[cfg(not(feature = "serve"))]
         backend_watch,
+        #[cfg(not(feature = "serve"))]
+        run_backend_watch,
         backend_pkg_name,
         default_build_path,
         build_args,
         serve_args,
+        package_args,
+        default_command,
+        build_aliases,
+        serve_aliases,
+        build_about,
+        build_long_about,
+        serve_about,
+        serve_long_about,
+        per_package_defaults,
+        build_workspace,
+        args_factory,
+        command_context,
+        detect_frontend,
     } = attr;
 
     if let Some(serve_args) = serve_args.as_ref() {
@@ -31,6 +58,23 @@ pub fn generate(item: ItemEnum, attr: Attr, metadata: &Metadata) -> syn::Result<
         }
     }
 
+    if let Some(package_args) = package_args.as_ref() {
+        if build_args.is_none() {
+            return Err(Error::new(
+                package_args.span(),
+                "if you use a custom PackageArgs, you must use a custom BuildArgs",
+            ));
+        }
+    }
+
+    if let Some(build_args) = build_args.as_ref().filter(|_| build_workspace) {
+        return Err(Error::new(
+            build_args.span(),
+            "`build_workspace` cannot be used with a custom BuildArgs: the packages are built with \
+            `DefaultBuildArgs`",
+        ));
+    }
+
     let build_ty = if let Some(ty) = build_args {
         quote! { #ty }
     } else {
@@ -43,11 +87,86 @@ pub fn generate(item: ItemEnum, attr: Attr, metadata: &Metadata) -> syn::Result<
         quote! { ::wasm_run::DefaultServeArgs }
     };
 
+    let package_ty = if let Some(ty) = package_args {
+        quote! { #ty }
+    } else {
+        quote! { ::wasm_run::DefaultPackageArgs }
+    };
+
+    let default_command = match default_command {
+        Some(path) if path.is_ident("build") => quote_spanned! {path.span()=>
+            #build_ty::from_args().run()?;
+        },
+        Some(path) if path.is_ident("serve") => quote_spanned! {path.span()=>
+            #serve_ty::from_args().run()?;
+        },
+        Some(path) => {
+            return Err(Error::new(
+                path.span(),
+                "invalid default command: expected `build` or `serve`",
+            ))
+        }
+        None => quote! {
+            #serve_ty::from_args().run()?;
+        },
+    };
+
+    let build_about = build_about.map(|x| quote! { #[structopt(about = #x)] });
+    let build_long_about = build_long_about.map(|x| quote! { #[structopt(long_about = #x)] });
+    let serve_about = serve_about.map(|x| quote! { #[structopt(about = #x)] });
+    let serve_long_about = serve_long_about.map(|x| quote! { #[structopt(long_about = #x)] });
+
+    // NOTE: the binary of the runner package (the frontend package can be another package)
+    let bin_name = quote! {
+        ::std::option_env!("CARGO_BIN_NAME").unwrap_or(::std::env!("CARGO_PKG_NAME"))
+    };
+
+    if args_factory && other_cli_commands.is_none() {
+        return Err(Error::new(
+            item.ident.span(),
+            "`args_factory` requires `other_cli_commands`",
+        ));
+    }
+    if command_context && other_cli_commands.is_none() {
+        return Err(Error::new(
+            item.ident.span(),
+            "`command_context` requires `other_cli_commands`",
+        ));
+    }
+    if command_context && args_factory {
+        return Err(Error::new(
+            item.ident.span(),
+            "`command_context` and `args_factory` cannot be used together (the context has an \
+            `ArgsFactory`)",
+        ));
+    }
+
     let span = other_cli_commands.span();
     let other_cli_commands = other_cli_commands
         .map(|x| {
-            quote_spanned! {span=>
-                WasmRunCliCommand::Other(cli) => #x(cli, metadata, package)?,
+            if command_context {
+                quote_spanned! {span=>
+                    WasmRunCliCommand::Other(cli) => #x(
+                        cli,
+                        &::wasm_run::CommandContext::<#build_ty, #serve_ty>::new(
+                            ::wasm_run::WorkspaceContext::global(),
+                            #bin_name,
+                        ),
+                    )?,
+                }
+            } else if args_factory {
+                quote_spanned! {span=>
+                    WasmRunCliCommand::Other(cli) => #x(
+                        cli,
+                        metadata,
+                        package,
+                        &::wasm_run::ArgsFactory::<#build_ty, #serve_ty>::new(#bin_name),
+                    )?,
+                }
+            } else {
+                quote_spanned! {span=>
+                    WasmRunCliCommand::Other(cli) => #x(cli, metadata, package)?,
+                }
             }
         })
         .unwrap_or_else(|| {
@@ -64,61 +183,106 @@ pub fn generate(item: ItemEnum, attr: Attr, metadata: &Metadata) -> syn::Result<
             }
         });
 
-    let pre_build = pre_build.map(|path| {
-        quote_spanned! {path.span()=>
-            pre_build: Box::new(|args, profile, command| {
-                let args = args.downcast_ref::<#build_ty>()
+    let pre_build = pre_build.map(|hook| {
+        let call = hook.call(quote! { &context, command });
+        quote_spanned! {hook.path.span()=>
+            pre_build: Box::new(|context, command| {
+                let context = context.downcast::<#build_ty>()
                     .expect("invalid type for `Build` command: the type in the command enum \
                         must be the same than the type returned by `build_args()` \
                         in the implementation of the trait `ServeArgs`");
-                #path(args, profile, command)
+                #call
             }),
         }
     });
 
-    let post_build = post_build.map(|path| {
-        quote_spanned! {path.span()=>
-            post_build: Box::new(|args, profile, wasm_js, wasm_bin| {
-                let args = args.downcast_ref::<#build_ty>()
+    let post_optimize = post_optimize.map(|hook| {
+        let call = hook.call(quote! { &context, wasm_bin });
+        quote_spanned! {hook.path.span()=>
+            post_optimize: Box::new(|context, wasm_bin| {
+                let context = context.downcast::<#build_ty>()
                     .expect("invalid type for `Build` command: the type in the command enum \
                         must be the same than the type returned by `build_args()` \
                         in the implementation of the trait `ServeArgs`");
-                #path(args, profile, wasm_js, wasm_bin)
+                #call
             }),
         }
     });
 
-    #[cfg(feature = "dev-server")]
-    let serve = serve.map(|path| {
-        quote_spanned! {path.span()=>
-            serve: Box::new(|args, app| {
-                let args = args.downcast_ref::<#serve_ty>().unwrap();
-                #path(args, app)
+    let js_transform = js_transform.map(|hook| {
+        let call = hook.call(quote! { &context, wasm_js });
+        quote_spanned! {hook.path.span()=>
+            js_transform: Box::new(|context, wasm_js| {
+                let context = context.downcast::<#build_ty>()
+                    .expect("invalid type for `Build` command: the type in the command enum \
+                        must be the same than the type returned by `build_args()` \
+                        in the implementation of the trait `ServeArgs`");
+                #call
             }),
         }
     });
-    #[cfg(not(feature = "dev-server"))]
-    let serve = quote! {};
 
-    let frontend_watch = frontend_watch.map(|path| {
-        quote_spanned! {path.span()=>
-            frontend_watch: Box::new(|args, watcher| {
-                let args = args.downcast_ref::<#serve_ty>().unwrap();
-                #path(args, watcher)
+    let post_build = post_build.map(|hook| {
+        let call = hook.call(quote! { &context, wasm_js, wasm_bin });
+        quote_spanned! {hook.path.span()=>
+            post_build: Box::new(|context, wasm_js, wasm_bin| {
+                let context = context.downcast::<#build_ty>()
+                    .expect("invalid type for `Build` command: the type in the command enum \
+                        must be the same than the type returned by `build_args()` \
+                        in the implementation of the trait `ServeArgs`");
+                #call
             }),
         }
     });
 
-    #[cfg(not(feature = "dev-server"))]
-    let backend_watch = backend_watch.map(|path| {
+    #[cfg(feature = "serve")]
+    let serve_init = run_hook(
+        "serve_init",
+        serve_init,
+        run_serve_init,
+        quote! { serve_context },
+        &serve_ty,
+    )?;
+    #[cfg(not(feature = "serve"))]
+    let serve_init = quote! {};
+
+    #[cfg(feature = "serve")]
+    let serve = run_hook("serve", serve, run_serve, quote! { app }, &serve_ty)?;
+    #[cfg(not(feature = "serve"))]
+    let serve = quote! {};
+
+    let frontend_watch = run_hook(
+        "frontend_watch",
+        frontend_watch,
+        run_frontend_watch,
+        quote! { watcher },
+        &serve_ty,
+    )?;
+
+    let watch_tasks = watch_tasks.map(|path| {
         quote_spanned! {path.span()=>
-            backend_watch: Box::new(|args, watcher| {
-                let args = args.downcast_ref::<#serve_ty>().unwrap();
-                #path(args, watcher)
-            }),
+            watch_tasks: #path(),
+        }
+    });
+
+    let shutdown = shutdown.map(|hook| {
+        let call = hook.call(quote! { reason });
+        quote_spanned! {hook.path.span()=>
+            shutdown: Box::new(|reason| #call),
         }
     });
 
+    #[cfg(not(feature = "serve"))]
+    let backend_watch = run_hook(
+        "backend_watch",
+        backend_watch,
+        run_backend_watch,
+        quote! { watcher },
+        &serve_ty,
+    )?;
+    #[cfg(feature = "serve")]
+    let backend_watch = quote! {};
+
     if let Some(pkg_name) = frontend_pkg_name.as_ref() {
         let span = pkg_name.span();
         let pkg_name = pkg_name.value();
@@ -152,6 +316,29 @@ pub fn generate(item: ItemEnum, attr: Attr, metadata: &Metadata) -> syn::Result<
             quote! { None }
         });
 
+    let (package_arg, frontend_selection) = if detect_frontend {
+        (
+            quote! {
+                #[doc = "Frontend package (detected if only one package of the workspace can be \
+                    built to WASM)"]
+                #[structopt(long, global = true)]
+                package: Option<String>,
+            },
+            quote! { cli.package.as_deref() },
+        )
+    } else {
+        (quote! {}, quote! { Some(#frontend_pkg_name) })
+    };
+
+    let (build_workspace_variant, build_workspace_arm) = if build_workspace {
+        (
+            quote! { BuildWorkspace(::wasm_run::BuildWorkspaceArgs), },
+            quote! { WasmRunCliCommand::BuildWorkspace(args) => args.run()?, },
+        )
+    } else {
+        (quote! {}, quote! {})
+    };
+
     let default_build_path = if let Some(path) = default_build_path {
         quote_spanned! {path.span()=>
             Some(Box::new(|metadata, package| {
@@ -166,25 +353,49 @@ pub fn generate(item: ItemEnum, attr: Attr, metadata: &Metadata) -> syn::Result<
         #item
 
         impl #ident {
-            fn build() -> ::wasm_run::prelude::anyhow::Result<::std::path::PathBuf>
+            fn build() -> ::wasm_run::prelude::anyhow::Result<::wasm_run::BuildOutput>
             {
                 use ::wasm_run::BuildArgs;
+                use ::wasm_run::structopt::StructOpt;
                 let build_args = #build_ty::from_iter_safe(&[#frontend_pkg_name])?;
                 build_args.run()
             }
 
             fn build_with_args<I>(iter: I)
-            -> ::wasm_run::prelude::anyhow::Result<::std::path::PathBuf>
+            -> ::wasm_run::prelude::anyhow::Result<::wasm_run::BuildOutput>
             where
                 I: ::std::iter::IntoIterator,
                 I::Item: ::std::convert::Into<::std::ffi::OsString> + Clone,
             {
                 use ::wasm_run::BuildArgs;
+                use ::wasm_run::structopt::StructOpt;
                 let iter = ::std::iter::once(::std::ffi::OsString::from(#frontend_pkg_name))
                     .chain(iter.into_iter().map(|x| x.into()));
                 let build_args = #build_ty::from_iter_safe(iter)?;
                 build_args.run()
             }
+
+            fn serve() -> ::wasm_run::prelude::anyhow::Result<::wasm_run::ServeHandle>
+            {
+                use ::wasm_run::ServeArgs;
+                use ::wasm_run::structopt::StructOpt;
+                let serve_args = #serve_ty::from_iter_safe(&[#frontend_pkg_name])?;
+                serve_args.start()
+            }
+
+            fn serve_with_args<I>(iter: I)
+            -> ::wasm_run::prelude::anyhow::Result<::wasm_run::ServeHandle>
+            where
+                I: ::std::iter::IntoIterator,
+                I::Item: ::std::convert::Into<::std::ffi::OsString> + Clone,
+            {
+                use ::wasm_run::ServeArgs;
+                use ::wasm_run::structopt::StructOpt;
+                let iter = ::std::iter::once(::std::ffi::OsString::from(#frontend_pkg_name))
+                    .chain(iter.into_iter().map(|x| x.into()));
+                let serve_args = #serve_ty::from_iter_safe(iter)?;
+                serve_args.start()
+            }
         }
 
         fn main() -> ::wasm_run::prelude::anyhow::Result<()> {
@@ -194,50 +405,154 @@ pub fn generate(item: ItemEnum, attr: Attr, metadata: &Metadata) -> syn::Result<
 
             #[derive(::wasm_run::structopt::StructOpt)]
             struct WasmRunCli {
+                #package_arg
+                #[doc = "Log more details and run cargo with `--verbose` (`-vv` for everything)"]
+                #[structopt(short, long, global = true, parse(from_occurrences))]
+                verbose: u8,
+                #[doc = "Log only the warnings and summarize the output of cargo (`-qq` to only \
+                    log the errors and hide the output of cargo)"]
+                #[structopt(short, long, global = true, parse(from_occurrences))]
+                quiet: u8,
+                #[doc = "Use of the colors: `auto` (when the output is a terminal and `NO_COLOR` \
+                    is not set), `always` or `never`"]
+                #[structopt(
+                    long,
+                    global = true,
+                    default_value = "auto",
+                    possible_values = &["auto", "always", "never"],
+                )]
+                color: ::wasm_run::ColorChoice,
                 #[structopt(subcommand)]
                 command: Option<WasmRunCliCommand>,
             }
 
             #[derive(::wasm_run::structopt::StructOpt)]
             enum WasmRunCliCommand {
+                #(#[structopt(visible_alias = #build_aliases)])*
+                #build_about
+                #build_long_about
                 Build(#build_ty),
+                #(#[structopt(visible_alias = #serve_aliases)])*
+                #serve_about
+                #serve_long_about
                 Serve(#serve_ty),
+                PackageImage(#package_ty),
+                #build_workspace_variant
+                CompareOpt(::wasm_run::CompareOptArgs),
+                Config(::wasm_run::ConfigArgs),
                 #[structopt(flatten)]
                 Other(#ident),
             }
 
             let cli = WasmRunCli::from_args();
+            let verbosity = ::wasm_run::Verbosity::from_flags(cli.verbose, cli.quiet);
+            ::wasm_run::wasm_run_init_logger(verbosity, cli.color);
+
+            // NOTE: the initialization fails on the first invalid setting of the configuration
+            if let Some(WasmRunCliCommand::Config(::wasm_run::ConfigArgs {
+                command: ::wasm_run::ConfigCommand::Validate,
+            })) = &cli.command
+            {
+                return ::wasm_run::wasm_run_validate_config(#frontend_selection);
+            }
 
             #[allow(clippy::needless_update)]
             let hooks = Hooks {
                 #pre_build
+                #post_optimize
+                #js_transform
                 #post_build
+                #serve_init
                 #serve
                 #frontend_watch
                 #backend_watch
+                #watch_tasks
+                #shutdown
                 .. Hooks::default()
             };
 
             let (metadata, package) = ::wasm_run::wasm_run_init(
-                #frontend_pkg_name,
+                #frontend_selection,
                 #backend_pkg_name,
                 #default_build_path,
+                #per_package_defaults,
+                verbosity,
                 hooks,
             )?;
 
-            if let Some(cli) = cli.command {
-                match cli {
-                    WasmRunCliCommand::Build(args) => {
-                        args.run()?;
-                    },
-                    WasmRunCliCommand::Serve(args) => args.run()?,
-                    #other_cli_commands
+            #[allow(clippy::redundant_closure_call)]
+            let res = (|| -> ::wasm_run::prelude::anyhow::Result<()> {
+                if let Some(cli) = cli.command {
+                    match cli {
+                        WasmRunCliCommand::Build(args) => {
+                            args.run()?;
+                        },
+                        WasmRunCliCommand::Serve(args) => args.run()?,
+                        WasmRunCliCommand::PackageImage(args) => args.run()?,
+                        #build_workspace_arm
+                        WasmRunCliCommand::CompareOpt(args) => args.run()?,
+                        WasmRunCliCommand::Config(args) => args.run()?,
+                        #other_cli_commands
+                    }
+                } else {
+                    #default_command
                 }
-            } else {
-                #serve_ty::from_args().run()?;
-            }
 
-            Ok(())
+                Ok(())
+            })();
+            ::wasm_run::wasm_run_shutdown();
+
+            res
         }
     })
 }
+
+impl Hook {
+    /// Call the function of the hook, running it to completion if it is async.
+    fn call(&self, args: TokenStream) -> TokenStream {
+        let path = &self.path;
+        if self.is_async {
+            quote_spanned! {path.span()=> ::wasm_run::block_on(#path(#args)) }
+        } else {
+            quote_spanned! {path.span()=> #path(#args) }
+        }
+    }
+}
+
+/// The field `run_<name>` of a hook of the `serve` command given with the argument `<name>` (a
+/// function that receives the arguments) or with the argument `run_<name>` (a function that
+/// receives a `RunContext`).
+fn run_hook(
+    name: &str,
+    hook: Option<Hook>,
+    run_hook: Option<Hook>,
+    param: TokenStream,
+    serve_ty: &TokenStream,
+) -> syn::Result<TokenStream> {
+    let field = format_ident!("run_{}", name);
+    match (hook, run_hook) {
+        (Some(_), Some(run_hook)) => Err(Error::new(
+            run_hook.path.span(),
+            format!("`{}` and `run_{}` cannot be used together", name, name),
+        )),
+        (Some(hook), None) => {
+            let call = hook.call(quote! { args, #param });
+            Ok(quote_spanned! {hook.path.span()=>
+                #field: Some(Box::new(|context, #param| {
+                    let args = context.args().downcast_ref::<#serve_ty>().unwrap();
+                    #call
+                })),
+            })
+        }
+        (None, Some(hook)) => {
+            let call = hook.call(quote! { &context, #param });
+            Ok(quote_spanned! {hook.path.span()=>
+                #field: Some(Box::new(|context, #param| {
+                    let context = context.downcast::<#serve_ty>().unwrap();
+                    #call
+                })),
+            })
+        }
+        (None, None) => Ok(quote! {}),
+    }
+}
@@ -0,0 +1,5 @@
+#[wasm_run::main(build_alias = b)]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
@@ -0,0 +1,5 @@
+#[wasm_run::main(args_factory)]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
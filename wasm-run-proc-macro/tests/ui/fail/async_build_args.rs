@@ -0,0 +1,5 @@
+#[wasm_run::main(build_args = async BuildCommand)]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
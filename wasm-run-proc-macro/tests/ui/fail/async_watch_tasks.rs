@@ -0,0 +1,5 @@
+#[wasm_run::main(watch_tasks = async my_watch_tasks)]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
@@ -0,0 +1,5 @@
+#[wasm_run::main("wasm-run-proc-macro-tests", detect_frontend)]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
@@ -0,0 +1,5 @@
+#[wasm_run::main(per_package_defaults = true)]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
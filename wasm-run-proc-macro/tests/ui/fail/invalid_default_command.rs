@@ -0,0 +1,5 @@
+#[wasm_run::main(default_command = package)]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
@@ -0,0 +1,5 @@
+#[wasm_run::main(package_args = PackageCommand)]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
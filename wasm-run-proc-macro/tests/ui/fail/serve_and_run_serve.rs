@@ -0,0 +1,5 @@
+#[wasm_run::main(serve = my_serve, run_serve = my_run_serve)]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
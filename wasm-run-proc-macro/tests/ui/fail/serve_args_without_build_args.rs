@@ -0,0 +1,5 @@
+#[wasm_run::main(serve_args = ServeCommand)]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
@@ -0,0 +1,5 @@
+#[wasm_run::main(unknown = foo)]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
@@ -0,0 +1,5 @@
+#[wasm_run::main("unknown-frontend")]
+#[derive(structopt::StructOpt, Debug)]
+enum Cli {}
+
+fn main() {}
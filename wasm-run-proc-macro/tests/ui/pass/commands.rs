@@ -0,0 +1,34 @@
+#[allow(dead_code)]
+mod cli {
+    use structopt::StructOpt;
+    use wasm_run::prelude::*;
+
+    #[wasm_run::main(
+        other_cli_commands,
+        args_factory,
+        default_command = build,
+        build_alias = "b",
+        serve_alias = "s",
+        build_about = "Build the app",
+        serve_long_about = "Serve the app",
+        per_package_defaults,
+        build_workspace,
+    )]
+    #[derive(StructOpt, Debug)]
+    enum Cli {
+        Hello,
+    }
+
+    fn other_cli_commands(
+        cli: Cli,
+        _metadata: &Metadata,
+        _package: &Package,
+        _factory: &ArgsFactory<DefaultBuildArgs, DefaultServeArgs>,
+    ) -> anyhow::Result<()> {
+        match cli {
+            Cli::Hello => Ok(()),
+        }
+    }
+}
+
+fn main() {}
@@ -0,0 +1,53 @@
+#[allow(dead_code)]
+mod cli {
+    use structopt::StructOpt;
+    use wasm_run::prelude::*;
+
+    #[wasm_run::main(build_args = BuildCommand, serve_args = ServeCommand)]
+    #[derive(StructOpt, Debug)]
+    enum Cli {}
+
+    #[derive(StructOpt, Debug)]
+    struct BuildCommand {
+        #[structopt(flatten)]
+        base: DefaultBuildArgs,
+    }
+
+    impl BuildArgs for BuildCommand {
+        fn build_path(&self) -> &Utf8PathBuf {
+            self.base.build_path()
+        }
+
+        fn profiling(&self) -> bool {
+            self.base.profiling()
+        }
+    }
+
+    #[derive(StructOpt, Debug)]
+    struct ServeCommand {
+        #[structopt(flatten)]
+        base: DefaultServeArgs,
+        #[structopt(flatten)]
+        build_args: BuildCommand,
+    }
+
+    impl ServeArgs for ServeCommand {
+        fn log(&self) -> bool {
+            self.base.log()
+        }
+
+        fn ip(&self) -> &str {
+            self.base.ip()
+        }
+
+        fn port(&self) -> u16 {
+            self.base.port()
+        }
+
+        fn build_args(&self) -> &dyn BuildArgs {
+            &self.build_args
+        }
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+// NOTE: the expansion is checked in a module: the generated `main` is type-checked but trybuild
+//       runs the empty `main` of the crate
+#[allow(dead_code)]
+mod cli {
+    #[wasm_run::main]
+    #[derive(structopt::StructOpt, Debug)]
+    enum Cli {}
+}
+
+fn main() {}
@@ -0,0 +1,53 @@
+#[allow(dead_code)]
+mod cli {
+    use std::process::Command;
+    use structopt::StructOpt;
+    use wasm_run::prelude::*;
+
+    #[wasm_run::main(
+        pre_build = async my_pre_build,
+        post_build = my_post_build,
+        serve = my_serve,
+        run_frontend_watch = my_frontend_watch,
+        watch_tasks = my_watch_tasks,
+        shutdown = my_shutdown,
+    )]
+    #[derive(StructOpt, Debug)]
+    enum Cli {}
+
+    async fn my_pre_build(
+        _context: &BuildContext<'_, DefaultBuildArgs>,
+        _command: &mut Command,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn my_post_build(
+        _context: &BuildContext<DefaultBuildArgs>,
+        _wasm_js: String,
+        _wasm_bin: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn my_serve(_args: &DefaultServeArgs, _server: &mut Server<()>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn my_frontend_watch(
+        _context: &RunContext<DefaultServeArgs>,
+        _watcher: &mut FileWatcher,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn my_watch_tasks() -> Vec<WatchTask> {
+        Vec::new()
+    }
+
+    fn my_shutdown(_reason: ShutdownReason) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+fn main() {}